@@ -0,0 +1,33 @@
+//! Embeds build metadata, so that the engine can report exactly which build it is.
+
+use std::{env, process::Command};
+
+fn main() {
+    // Not in a git checkout, or git not installed: The version is still reported.
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map_or_else(|| "unknown".to_string(), |hash| hash.trim().to_string());
+    println!("cargo:rustc-env=KAIK_GIT_HASH={git_hash}");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(str::to_lowercase))
+        .collect();
+    features.sort();
+    let popcnt = env::var("CARGO_CFG_TARGET_FEATURE")
+        .unwrap_or_default()
+        .split(',')
+        .any(|feature| feature == "popcnt");
+    println!(
+        "cargo:rustc-env=KAIK_BUILD_INFO=target {}, profile {}, features [{}], popcnt {}",
+        env::var("TARGET").unwrap_or_default(),
+        env::var("PROFILE").unwrap_or_default(),
+        features.join(", "),
+        if popcnt { "yes" } else { "no" }
+    );
+}
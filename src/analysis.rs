@@ -0,0 +1,153 @@
+//! Human readable analysis of positions and games, for using the engine outside of a GUI.
+//! Scores are shown from White's point of view, as it is the convention in chess literature.
+
+use std::sync::{atomic::AtomicBool, Arc};
+
+use crate::{
+    board::Board,
+    common::Color,
+    engine::{
+        config::Config,
+        eval,
+        search::{self, Line},
+        transposition::TranspositionTable,
+    },
+    utils::pgn::PgnGame,
+};
+
+// Formats the score of a line from White's point of view, like "+0.35" or "#-2".
+pub fn format_score(line: &Line, side_to_move: Color) -> String {
+    let sign = if side_to_move == Color::White { 1 } else { -1 };
+    if let Some(mate) = line.score.mate_moves() {
+        format!("#{}", sign * mate)
+    } else {
+        format!("{:+.2}", f64::from(sign * line.score.score()) / 100.0)
+    }
+}
+
+// Prints the best lines of the position.
+pub fn analyse_position(board: &Board, depth: usize, lines_count: usize, config: &Config) {
+    board.print();
+    println!();
+    println!("FEN: {board}");
+
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let tt = TranspositionTable::new(config.search.hash);
+    let evaluator = eval::new_evaluator(&config.eval);
+    let lines = search::multi_pv(
+        board,
+        depth,
+        lines_count,
+        &stop_flag,
+        &tt,
+        evaluator.as_ref(),
+    );
+    if lines.is_empty() {
+        if board.in_check() {
+            println!("Checkmate");
+        } else {
+            println!("Stalemate");
+        }
+        return;
+    }
+
+    println!("Depth {depth}:");
+    for (i, line) in lines.iter().enumerate() {
+        println!(
+            "{:>3}. {:>7}  {}",
+            i + 1,
+            format_score(line, board.get_side_to_move()),
+            board.format_moves_as_san_string(&line.pv)
+        );
+    }
+}
+
+// Finds the shortest forced mate by the side to move, in up to that many moves.
+pub fn solve_mate(board: &Board, max_moves: usize, config: &Config) -> Option<Line> {
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let tt = TranspositionTable::new(config.search.hash);
+    let evaluator = eval::new_evaluator(&config.eval);
+    search::find_mate(board, max_moves, &stop_flag, &tt, evaluator.as_ref())
+}
+
+// Annotates each move of the game with the evaluation of the position following it.
+// Existing comments are kept, the evaluation is appended to them.
+pub fn annotate_game(game: &PgnGame, depth: usize, config: &Config) -> Result<PgnGame, String> {
+    let mut board = game
+        .get_fen()
+        .map_or_else(Board::initial_board, Board::from_fen);
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let tt = TranspositionTable::new(config.search.hash);
+    let evaluator = eval::new_evaluator(&config.eval);
+
+    let mut annotated = game.clone();
+    for pgn_move in &mut annotated.moves {
+        let mv = board
+            .new_move_from_san(&pgn_move.san)
+            .ok_or_else(|| format!("Invalid move {} in position {board}", pgn_move.san))?;
+        board.update_by_move(mv);
+
+        let lines = search::multi_pv(&board, depth, 1, &stop_flag, &tt, evaluator.as_ref());
+        let eval = if let Some(line) = lines.first() {
+            format!("{}/{depth}", format_score(line, board.get_side_to_move()))
+        } else if board.in_check() {
+            continue; // Checkmate is already indicated by the move itself.
+        } else {
+            "0.00".to_string()
+        };
+
+        pgn_move.comment = Some(match &pgn_move.comment {
+            Some(comment) => format!("{comment} {eval}"),
+            None => eval,
+        });
+    }
+    Ok(annotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{common::Value, utils::pgn};
+
+    use super::*;
+
+    #[test]
+    fn test_format_score() {
+        let line = Line {
+            score: Value::new(35),
+            pv: Vec::new(),
+        };
+        assert_eq!(format_score(&line, Color::White), "+0.35");
+        assert_eq!(format_score(&line, Color::Black), "-0.35");
+
+        let line = Line {
+            score: Value::mate_in(3),
+            pv: Vec::new(),
+        };
+        assert_eq!(format_score(&line, Color::White), "#2");
+        assert_eq!(format_score(&line, Color::Black), "#-2");
+    }
+
+    #[test]
+    fn test_annotate_game() {
+        let games = pgn::parse("1. e4 {Best by test} f5 2. exf5 g5 3. Qh5# 1-0");
+        let annotated = annotate_game(&games[0], 2, &Config::default()).unwrap();
+        assert_eq!(
+            annotated
+                .moves
+                .iter()
+                .map(|m| m.comment.as_deref())
+                .collect::<Vec<_>>(),
+            [
+                Some("Best by test +0.00/2"),
+                // A pawn up, with the knights and rooks worth a bit less with more pawns.
+                Some("+0.88/2"),
+                Some("+0.88/2"),
+                Some("#1/2"),
+                None
+            ]
+        );
+
+        let games = pgn::parse("1. e4 e4 *");
+        assert!(annotate_game(&games[0], 2, &Config::default()).is_err());
+    }
+}
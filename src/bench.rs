@@ -0,0 +1,140 @@
+//! Internal benchmarks, to measure the impact of performance changes.
+//! Each benchmark runs an operation on a fixed set of positions and reports the time per operation.
+//! Run with the release profile: cargo r --release -- bench-internal
+
+use std::{
+    fmt::Write,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    board::Board,
+    common::Move,
+    engine::{config::EvalConfig, eval},
+    perft,
+};
+
+// Standard positions from <https://www.chessprogramming.org/Perft_Results>, plus an endgame,
+// covering castling, en passant, promotions and checks.
+const POSITIONS: [&str; 6] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "8/8/4k3/3p4/3P4/4K3/8/8 w - - 0 1",
+];
+
+pub struct Measure {
+    pub name: &'static str,
+    pub operations: usize,
+    pub elapsed: Duration,
+}
+
+impl Measure {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn ns_per_operation(&self) -> f64 {
+        self.elapsed.as_nanos() as f64 / self.operations.max(1) as f64
+    }
+}
+
+// Runs the function the specified number of times. It returns how many operations it did.
+fn measure<F>(name: &'static str, iterations: usize, mut f: F) -> Measure
+where
+    F: FnMut() -> usize,
+{
+    let now = Instant::now();
+    let operations = (0..iterations).map(|_| f()).sum();
+    Measure {
+        name,
+        operations,
+        elapsed: now.elapsed(),
+    }
+}
+
+pub fn run(iterations: usize, perft_depth: usize) -> Vec<Measure> {
+    let boards: Vec<Board> = POSITIONS.iter().map(|fen| Board::from_fen(fen)).collect();
+    let moves: Vec<Vec<Move>> = boards.iter().map(Board::generate_moves).collect();
+    let moves_count = moves.iter().map(Vec::len).sum();
+    let evaluator = eval::new_evaluator(&EvalConfig::default());
+
+    vec![
+        measure("Move generation", iterations, || {
+            for board in &boards {
+                black_box(board.generate_moves());
+            }
+            boards.len()
+        }),
+        measure("Legal move generation", iterations, || {
+            for board in &boards {
+                black_box(board.generate_legal_moves());
+            }
+            boards.len()
+        }),
+        // There is no unmake, moves are applied on a copy of the board.
+        measure("Make move", iterations, || {
+            for (board, moves) in boards.iter().zip(&moves) {
+                for mv in moves {
+                    black_box(board.copy_with_move(*mv));
+                }
+            }
+            moves_count
+        }),
+        measure("Evaluation", iterations, || {
+            for board in &boards {
+                black_box(evaluator.eval(board));
+            }
+            boards.len()
+        }),
+        measure("Perft (per node)", 1, || {
+            boards
+                .iter()
+                .map(|board| perft::perft(black_box(board), perft_depth))
+                .sum()
+        }),
+    ]
+}
+
+pub fn report(measures: &[Measure]) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "{:<24}{:>12}{:>14}{:>12}",
+        "Benchmark", "Operations", "Time", "ns/op"
+    )
+    .unwrap();
+    for m in measures {
+        writeln!(
+            out,
+            "{:<24}{:>12}{:>14.2?}{:>12.1}",
+            m.name,
+            m.operations,
+            m.elapsed,
+            m.ns_per_operation()
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let measures = run(2, 1);
+        assert_eq!(measures.len(), 5);
+        assert!(measures.iter().all(|m| m.operations > 0));
+        // Perft at depth 1 is the number of legal moves.
+        let legal_moves: usize = POSITIONS
+            .iter()
+            .map(|fen| Board::from_fen(fen).generate_legal_moves().len())
+            .sum();
+        assert_eq!(measures[4].operations, legal_moves);
+
+        let report = report(&measures);
+        assert_eq!(report.lines().count(), 6);
+    }
+}
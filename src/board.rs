@@ -3,26 +3,43 @@
 
 use bitboard::BitBoard;
 
-use crate::common::{Color, Square};
+use crate::common::{Color, Piece, Square};
 
 mod attacks;
-mod bitboard;
+mod binary;
+pub mod bitboard;
 mod board_type;
 mod castling;
 mod display;
+mod edit;
+mod legality;
 mod move_gen;
+pub mod outcome;
+mod san;
+mod see;
 mod update;
 mod zobrist;
 
+pub use display::{set_theme, Theme};
+pub use edit::BoardEdit;
+pub use legality::IllegalReason;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 struct CastlingAbility(u8);
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+// Hash is implemented with the Zobrist key, see board_type.rs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Board {
     // Even indexes are white pieces, odd are black pieces.
     pieces: [BitBoard; 12],
     all: [BitBoard; 2],
     occupied: BitBoard,
+    // Redundant with the bitboards, for finding the piece on a square without scanning them.
+    mailbox: [Option<Piece>; 64],
+    // Square of the king of each color, None only for boards being edited without one.
+    kings: [Option<Square>; 2],
+    // Pieces giving check to the side to move, computed once per position.
+    checkers: BitBoard,
     side_to_move: Color,
     en_passant_target_square: Option<Square>,
     castling_ability: CastlingAbility,
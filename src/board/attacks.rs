@@ -4,15 +4,23 @@
 
 use crate::{
     board::bitboard::{self, movements, BitBoard},
-    common::{Color, Piece, Square},
+    common::{Color, Move, Piece, Square},
 };
 
 use super::Board;
 
+// Attacks of a sliding piece, given its square and the occupied squares.
+type SliderAttacks = fn(BitBoard, BitBoard) -> BitBoard;
+
 impl Board {
     // Is the side to play in check?
     pub fn in_check(&self) -> bool {
-        self.attacks_king(self.get_side_to_move()) != 0
+        self.checkers != 0
+    }
+
+    // The pieces giving check to the side to move.
+    pub fn checkers(&self) -> BitBoard {
+        self.checkers
     }
 
     // Returns a bitboard indicating which squares attack the king of the specified color.
@@ -20,7 +28,10 @@ impl Board {
         // From <https://www.chessprogramming.org/Checks_and_Pinned_Pieces_(Bitboards)>
         // Note that the example there doesn't check king creating checks.
 
-        let king_bb = self.pieces[Piece::get_king_of(king_color) as usize];
+        let Some(king_square) = self.kings[king_color as usize] else {
+            return 0;
+        };
+        let king_bb = bitboard::from_square(king_square);
         let opp_king_color = king_color.opposite();
 
         // Could be optimized a bit with things like:
@@ -47,8 +58,188 @@ impl Board {
             | (movements::get_rook_attacks(king_bb, self.occupied) & opposite_rooks_queens)
     }
 
+    // Returns all the squares attacked by the pieces of that color, whether they are empty or not.
+    // With the opponent pieces, it gives the threatened ones, for GUIs highlighting them.
+    pub fn attack_map(&self, color: Color) -> BitBoard {
+        let pieces = |piece: Piece| self.pieces[piece as usize];
+        let pawn_attacks = if color == Color::White {
+            movements::get_white_pawn_attacks(pieces(Piece::WhitePawn))
+        } else {
+            movements::get_black_pawn_attacks(pieces(Piece::BlackPawn))
+        };
+        let queens = pieces(Piece::get_queen_of(color));
+        let bishop_attacks = bitboard::into_iter(pieces(Piece::get_bishop_of(color)) | queens)
+            .fold(0, |attacks, bb| {
+                attacks | movements::get_bishop_attacks(bb, self.occupied)
+            });
+        let rook_attacks = bitboard::into_iter(pieces(Piece::get_rook_of(color)) | queens)
+            .fold(0, |attacks, bb| {
+                attacks | movements::get_rook_attacks(bb, self.occupied)
+            });
+
+        pawn_attacks
+            | movements::get_knight_attacks(pieces(Piece::get_knight_of(color)))
+            | movements::get_king_attacks(pieces(Piece::get_king_of(color)))
+            | bishop_attacks
+            | rook_attacks
+    }
+
+    // Returns the pieces of that color protected by another one of theirs, kings excluded as they
+    // can't be captured.
+    pub fn defended_pieces(&self, color: Color) -> BitBoard {
+        let king = self.pieces[Piece::get_king_of(color) as usize];
+        self.attack_map(color) & self.all[color as usize] & !king
+    }
+
+    // Returns the pieces of that color which are pinned to their king.
+    // From <https://www.chessprogramming.org/Checks_and_Pinned_Pieces_(Bitboards)>
+    pub fn pinned(&self, color: Color) -> BitBoard {
+        self.x_ray_blockers(color, color)
+    }
+
+    // Returns the pieces of that color whose move may discover a check on the opponent king.
+    pub fn discoverers(&self, color: Color) -> BitBoard {
+        self.x_ray_blockers(color.opposite(), color)
+    }
+
+    // The pieces of the blockers color that are alone between the king and a slider attacking it.
+    fn x_ray_blockers(&self, king_color: Color, blockers_color: Color) -> BitBoard {
+        let Some(king_square) = self.kings[king_color as usize] else {
+            return 0;
+        };
+        let king_bb = bitboard::from_square(king_square);
+        let blocking_pieces = self.all[blockers_color as usize];
+        let opp_color = king_color.opposite();
+        let opposite_queens = self.pieces[Piece::get_queen_of(opp_color) as usize];
+        let sliders: [(SliderAttacks, BitBoard); 2] = [
+            (
+                movements::get_rook_attacks,
+                opposite_queens | self.pieces[Piece::get_rook_of(opp_color) as usize],
+            ),
+            (
+                movements::get_bishop_attacks,
+                opposite_queens | self.pieces[Piece::get_bishop_of(opp_color) as usize],
+            ),
+        ];
+
+        let mut blockers = 0;
+        for (attacks, snipers) in sliders {
+            // The sliders seeing the king once the pieces in the way are removed are x-raying
+            // the piece between them and the king.
+            let king_attacks = attacks(king_bb, self.occupied);
+            let candidates = king_attacks & blocking_pieces;
+            let x_rays = attacks(king_bb, self.occupied ^ candidates) & snipers;
+            for sniper in bitboard::into_iter(x_rays) {
+                blockers |= attacks(sniper, self.occupied) & king_attacks & blocking_pieces;
+            }
+        }
+        blockers
+    }
+
+    // Does the move give check? Found without making the move: Either the moved piece attacks the
+    // king from its destination, or it leaves the line between the king and one of our sliders.
+    // Castling, en passant and promotions change more squares, so they take the slower path.
+    pub fn gives_check(&self, mv: Move) -> bool {
+        let color = self.get_side_to_move();
+        let Some(king_square) = self.kings[color.opposite() as usize] else {
+            return false;
+        };
+        if mv.is_castling() || mv.is_en_passant() || mv.get_promotion().is_some() {
+            return self.gives_check_after(mv, king_square);
+        }
+        let king_bb = bitboard::from_square(king_square);
+        let from_bb = bitboard::from_square(mv.get_from());
+        let to_bb = bitboard::from_square(mv.get_to());
+
+        // Direct check: The lines from the king are blocked by the pieces remaining in place.
+        let occupied = self.occupied & !from_bb;
+        let piece = mv.get_piece();
+        let direct = if piece.is_pawn() {
+            match color {
+                Color::White => movements::get_white_pawn_attacks(to_bb),
+                Color::Black => movements::get_black_pawn_attacks(to_bb),
+            }
+        } else if piece.is_knight() {
+            movements::get_knight_attacks(to_bb)
+        } else if piece.is_bishop() {
+            movements::get_bishop_attacks(to_bb, occupied)
+        } else if piece.is_rook() {
+            movements::get_rook_attacks(to_bb, occupied)
+        } else if piece.is_queen() {
+            movements::get_bishop_attacks(to_bb, occupied)
+                | movements::get_rook_attacks(to_bb, occupied)
+        } else {
+            0
+        };
+        if direct & king_bb != 0 {
+            return true;
+        }
+
+        // Discovered check: The piece moves out of the line.
+        self.discoverers(color) & from_bb != 0 && Self::pin_line(king_square, from_bb) & to_bb == 0
+    }
+
+    // Same as gives_check(), computing the attacks on the king with the pieces where the move
+    // puts them.
+    fn gives_check_after(&self, mv: Move, king_square: Square) -> bool {
+        let color = self.get_side_to_move();
+        let king_bb = bitboard::from_square(king_square);
+        let mut pieces = self.pieces;
+        let mut occupied = self.occupied;
+        let mut move_piece = |piece: Piece, from: Square, to: Square, promotion: Option<Piece>| {
+            let from_bb = bitboard::from_square(from);
+            let to_bb = bitboard::from_square(to);
+            pieces[piece as usize] &= !from_bb;
+            pieces[promotion.unwrap_or(piece) as usize] |= to_bb;
+            occupied = (occupied & !from_bb) | to_bb;
+        };
+        move_piece(
+            mv.get_piece(),
+            mv.get_from(),
+            mv.get_to(),
+            mv.get_promotion(),
+        );
+        if let Some(rook_move) = mv.get_castling_rook_move() {
+            move_piece(
+                rook_move.get_piece(),
+                rook_move.get_from(),
+                rook_move.get_to(),
+                None,
+            );
+        }
+        if mv.is_en_passant() {
+            let to_bb = bitboard::from_square(mv.get_to());
+            occupied &= !match color {
+                Color::White => to_bb >> 8,
+                Color::Black => to_bb << 8,
+            };
+        }
+
+        let own = |piece: Piece| pieces[piece as usize];
+        let pawn_attacks = match color {
+            // Squares from which our pawns would attack the king.
+            Color::White => movements::get_black_pawn_attacks(king_bb),
+            Color::Black => movements::get_white_pawn_attacks(king_bb),
+        };
+        let queens = own(Piece::get_queen_of(color));
+        (pawn_attacks & own(Piece::get_pawn_of(color)))
+            | (movements::get_knight_attacks(king_bb) & own(Piece::get_knight_of(color)))
+            | (movements::get_bishop_attacks(king_bb, occupied)
+                & (own(Piece::get_bishop_of(color)) | queens))
+            | (movements::get_rook_attacks(king_bb, occupied)
+                & (own(Piece::get_rook_of(color)) | queens))
+            != 0
+    }
+
     // Returns a bitboard indicating which squares attack that square.
     pub fn attacks_to(&self, square: Square) -> BitBoard {
+        self.attacks_to_with(square, self.occupied)
+    }
+
+    // Same as attacks_to(), with sliding pieces blocked only by the occupied squares.
+    // Removing pieces from the occupancy reveals the x-ray attackers behind them.
+    // Pieces not in the occupancy may still be included.
+    pub(super) fn attacks_to_with(&self, square: Square, occupied: BitBoard) -> BitBoard {
         // From <https://www.chessprogramming.org/Square_Attacked_By#AnyAttackBySide>
 
         let bb = bitboard::from_square(square);
@@ -70,14 +261,19 @@ impl Board {
             | (movements::get_black_pawn_attacks(bb) & white_pawns)
             | (movements::get_knight_attacks(bb) & knights)
             | (movements::get_king_attacks(bb) & kings)
-            | (movements::get_bishop_attacks(bb, self.occupied) & bishops_queens)
-            | (movements::get_rook_attacks(bb, self.occupied) & rooks_queens)
+            | (movements::get_bishop_attacks(bb, occupied) & bishops_queens)
+            | (movements::get_rook_attacks(bb, occupied) & rooks_queens)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::common::Square;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{
+        common::Square,
+        utils::{fen, random_game::random_game},
+    };
 
     use super::*;
 
@@ -89,14 +285,167 @@ mod tests {
         assert_eq!(attacks_king_bb, attacks_bb);
     }
 
+    #[test]
+    fn test_attack_map() {
+        let board: Board = "4k3/8/8/8/8/8/1p6/R3K3 w - - 0 1".into();
+        assert_eq!(
+            board.attack_map(Color::White),
+            bitboard::from_str(
+                "1 . . . . . . .
+                 1 . . . . . . .
+                 1 . . . . . . .
+                 1 . . . . . . .
+                 1 . . . . . . .
+                 1 . . . . . . .
+                 1 . . 1 1 1 . .
+                 . 1 1 1 1 1 . ."
+            )
+        );
+        assert_eq!(
+            board.attack_map(Color::Black),
+            bitboard::from_str(
+                ". . . 1 . 1 . .
+                 . . . 1 1 1 . .
+                 . . . . . . . .
+                 . . . . . . . .
+                 . . . . . . . .
+                 . . . . . . . .
+                 . . . . . . . .
+                 1 . 1 . . . . ."
+            )
+        );
+    }
+
+    #[test]
+    fn test_defended_pieces() {
+        // The pawn and the bishop defend the knight, the king defends the bishop. Nothing defends
+        // the rook or the pawn.
+        let board: Board = "4k3/3p4/8/8/8/2N5/1P1B4/R3K3 w - - 0 1".into();
+        assert_eq!(
+            board.defended_pieces(Color::White),
+            bitboard::from_square(Square::C3) | bitboard::from_square(Square::D2)
+        );
+        assert_eq!(
+            board.defended_pieces(Color::Black),
+            bitboard::from_square(Square::D7)
+        );
+    }
+
+    #[test]
+    fn test_pinned() {
+        // The rook is pinned by the queen, the pawn by the bishop. The bishop on g1 isn't pinned
+        // as the knight is in front of it.
+        let board: Board = "4k3/4q3/8/b7/8/8/3PR3/4KNBr w - - 0 1".into();
+        assert_eq!(
+            board.pinned(Color::White),
+            bitboard::from_square(Square::D2) | bitboard::from_square(Square::E2)
+        );
+        // The queen is pinned too, by the rook it's pinning.
+        assert_eq!(
+            board.pinned(Color::Black),
+            bitboard::from_square(Square::E7)
+        );
+
+        // Two pieces in front of the bishop.
+        let board: Board = "4k3/4q3/8/b7/8/2N5/3PR3/4KNBr w - - 0 1".into();
+        assert_eq!(
+            board.pinned(Color::White),
+            bitboard::from_square(Square::E2)
+        );
+    }
+
+    #[test]
+    fn test_discoverers() {
+        // Two pieces between the bishop and the king.
+        let board: Board = "7k/8/8/8/3N4/8/1P6/B3K3 w - - 0 1".into();
+        assert_eq!(board.discoverers(Color::White), 0);
+        let board: Board = "7k/8/8/8/3N4/8/8/B3K3 w - - 0 1".into();
+        assert_eq!(
+            board.discoverers(Color::White),
+            bitboard::from_square(Square::D4)
+        );
+        // Black pieces can't discover a check on the black king.
+        let board: Board = "4k3/4n3/8/8/8/8/4P3/4RK2 w - - 0 1".into();
+        assert_eq!(board.discoverers(Color::White), 0);
+        let board: Board = "4k3/8/8/8/8/8/4P3/4RK2 w - - 0 1".into();
+        assert_eq!(
+            board.discoverers(Color::White),
+            bitboard::from_square(Square::E2)
+        );
+    }
+
+    #[test]
+    fn test_gives_check() {
+        for (fen, mv, check) in [
+            // Direct checks.
+            ("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", "a1a8", true),
+            ("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", "a1a7", false),
+            ("4k3/8/8/8/8/8/3P4/4K3 w - - 0 1", "d2d4", false),
+            ("4k3/8/3P4/8/8/8/8/4K3 w - - 0 1", "d6d7", true),
+            // Discovered by the bishop.
+            ("7k/8/8/8/8/8/1N6/B3K3 w - - 0 1", "b2d1", true),
+            // Promotion to a knight.
+            ("8/1P1k4/8/8/8/8/8/4K3 w - - 0 1", "b7b8n", true),
+            ("8/1P1k4/8/8/8/8/8/4K3 w - - 0 1", "b7b8q", false),
+            // By the rook when castling.
+            ("5k2/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1", true),
+            // En passant removes the pawn blocking the rook.
+            ("8/8/8/R2pP2k/8/8/8/4K3 w - d6 0 1", "e5d6", true),
+        ] {
+            let board: Board = fen.into();
+            let mv = board.new_move_from_pure(mv);
+            assert_eq!(board.gives_check(mv), check, "{fen} {}", mv.pure());
+        }
+    }
+
+    #[test]
+    fn test_gives_check_random_positions() {
+        let mut rng = StdRng::seed_from_u64(4);
+        for fen in [
+            fen::KIWIPETE,
+            fen::POSITION_3,
+            fen::POSITION_4,
+            fen::POSITION_5,
+        ] {
+            for board in random_game(Board::from_fen(fen), 100, &mut rng) {
+                for mv in board.generate_moves() {
+                    if let Some(board_after) = board.copy_with_move(mv) {
+                        assert_eq!(
+                            board.gives_check(mv),
+                            board_after.in_check(),
+                            "{} {}",
+                            board.as_fen(),
+                            mv.pure()
+                        );
+                    }
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_attacks_king_king_next_to_king() {
-        let board: Board = "8/2kp4/1K6/2P4r/8/8/8/8 w - - 1 2".into();
+        // Not a valid position, so the king is put after parsing the FEN.
+        let mut board: Board = "8/2kp4/8/2P4r/8/8/8/8 w - - 1 2".into();
+        board.put_piece(Square::B6, Piece::WhiteKing);
         let bb = board.attacks_king(Color::White);
         // Not allowed to move next to opponent king.
         assert_eq!(
             bb,
-            0b0000000000000100000000000000000000000000000000000000000000000000
+            0b0000_0000_0000_0100_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000
+        );
+    }
+
+    #[test]
+    fn test_checkers() {
+        let mut board: Board = "4k3/8/8/8/8/3n4/8/R3K2r w Q - 0 1".into();
+        assert_eq!(
+            board.checkers(),
+            bitboard::from_square(Square::D3) | bitboard::from_square(Square::H1)
         );
+        board.update_by_move(board.new_move_from_pure("e1d2"));
+        assert!(!board.in_check());
+        board.update_by_move(board.new_move_from_pure("h1h2"));
+        assert_eq!(board.checkers(), bitboard::from_square(Square::H2));
     }
 }
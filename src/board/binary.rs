@@ -0,0 +1,170 @@
+//! Compact binary encoding of a board, for storing millions of positions: It's fixed-size and much
+//! faster to read than FEN, as there is nothing to parse.
+//! The encoding, of `Board::BINARY_SIZE` bytes:
+//! - The version of the format, so that files written by older versions are detected.
+//! - The piece on each square, 4 bits per square starting from a1 in the low bits, 0 for empty
+//!   squares and the piece index + 1 otherwise.
+//! - The side to move in bit 0 and the castling rights in bits 1 to 4.
+//! - The en passant square, 0xFF if there is none.
+//! - The half move clock and the full move counter, as 16 bits little endian numbers. Larger
+//!   values are saturated, they can't happen in real games.
+
+use crate::{
+    board::bitboard::BitBoard,
+    common::{Color, Piece, Square},
+};
+
+use super::{Board, CastlingAbility};
+
+const NO_EN_PASSANT: u8 = 0xFF;
+
+const SQUARES_OFFSET: usize = 1;
+const FLAGS_OFFSET: usize = SQUARES_OFFSET + 32;
+const EN_PASSANT_OFFSET: usize = FLAGS_OFFSET + 1;
+const HALF_MOVE_OFFSET: usize = EN_PASSANT_OFFSET + 1;
+const FULL_MOVE_OFFSET: usize = HALF_MOVE_OFFSET + 2;
+
+fn saturated_u16(value: usize) -> [u8; 2] {
+    u16::try_from(value).unwrap_or(u16::MAX).to_le_bytes()
+}
+
+impl Board {
+    pub const BINARY_VERSION: u8 = 1;
+    pub const BINARY_SIZE: usize = FULL_MOVE_OFFSET + 2;
+
+    pub fn to_bytes(self) -> [u8; Self::BINARY_SIZE] {
+        let mut bytes = [0; Self::BINARY_SIZE];
+        bytes[0] = Self::BINARY_VERSION;
+        for (square, piece) in self.mailbox.iter().enumerate() {
+            if let Some(piece) = piece {
+                let code = *piece as u8 + 1;
+                bytes[SQUARES_OFFSET + square / 2] |= code << (4 * (square % 2));
+            }
+        }
+        bytes[FLAGS_OFFSET] = self.side_to_move as u8 | self.castling_ability.0 << 1;
+        bytes[EN_PASSANT_OFFSET] = self
+            .en_passant_target_square
+            .map_or(NO_EN_PASSANT, |square| square as u8);
+        bytes[HALF_MOVE_OFFSET..FULL_MOVE_OFFSET]
+            .copy_from_slice(&saturated_u16(self.half_move_clock));
+        bytes[FULL_MOVE_OFFSET..].copy_from_slice(&saturated_u16(self.full_move_counter));
+        bytes
+    }
+
+    // The position is checked like a FEN one, so invalid bytes give an error, not a broken board.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() != Self::BINARY_SIZE {
+            return Err(format!(
+                "{} bytes instead of {}",
+                bytes.len(),
+                Self::BINARY_SIZE
+            ));
+        }
+        if bytes[0] != Self::BINARY_VERSION {
+            return Err(format!("Unsupported binary board version {}", bytes[0]));
+        }
+
+        let mut pieces: [BitBoard; 12] = [0; 12];
+        for square in 0..64 {
+            let code = (bytes[SQUARES_OFFSET + square / 2] >> (4 * (square % 2))) & 0b1111;
+            if code == 0 {
+                continue;
+            }
+            let piece = Piece::ALL_PIECES
+                .get(usize::from(code) - 1)
+                .ok_or_else(|| format!("Invalid piece code {code}"))?;
+            pieces[*piece as usize] |= 1 << square;
+        }
+
+        let flags = bytes[FLAGS_OFFSET];
+        if flags >> 5 != 0 {
+            return Err(format!("Invalid flags {flags:#x}"));
+        }
+        let side_to_move = if flags & 1 == 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let en_passant_target_square = match bytes[EN_PASSANT_OFFSET] {
+            NO_EN_PASSANT => None,
+            square if square < 64 => Some(Square::from(square)),
+            square => return Err(format!("Invalid en passant square {square}")),
+        };
+        let number =
+            |offset: usize| usize::from(u16::from_le_bytes([bytes[offset], bytes[offset + 1]]));
+
+        Self::from_parts(
+            pieces,
+            side_to_move,
+            CastlingAbility(flags >> 1),
+            en_passant_target_square,
+            number(HALF_MOVE_OFFSET),
+            number(FULL_MOVE_OFFSET),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::utils::{fen, random_game::random_game};
+
+    #[test]
+    fn test_initial_board() {
+        let bytes = Board::initial_board().to_bytes();
+        assert_eq!(bytes.len(), 39);
+        assert_eq!(bytes[0], Board::BINARY_VERSION);
+        // White rook and knight on a1 and b1.
+        assert_eq!(bytes[1], 0x37);
+        assert_eq!(bytes[FLAGS_OFFSET], 0b1_1110);
+        assert_eq!(bytes[EN_PASSANT_OFFSET], NO_EN_PASSANT);
+        assert_eq!(Board::from_bytes(&bytes), Ok(Board::initial_board()));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for fen in [
+            fen::START_POSITION,
+            fen::KIWIPETE,
+            fen::POSITION_3,
+            fen::POSITION_4,
+            fen::POSITION_5,
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            for board in random_game(Board::from_fen(fen), 100, &mut rng) {
+                assert_eq!(
+                    Board::from_bytes(&board.to_bytes()),
+                    Ok(board),
+                    "{}",
+                    board.as_fen()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_bytes() {
+        let bytes = Board::initial_board().to_bytes();
+        assert!(Board::from_bytes(&bytes[1..]).is_err());
+
+        let mut invalid = bytes;
+        invalid[0] = 2;
+        assert!(Board::from_bytes(&invalid).is_err());
+        invalid = bytes;
+        invalid[1] = 0x0F;
+        assert!(Board::from_bytes(&invalid).is_err());
+        invalid = bytes;
+        invalid[FLAGS_OFFSET] = 0xFF;
+        assert!(Board::from_bytes(&invalid).is_err());
+        invalid = bytes;
+        invalid[EN_PASSANT_OFFSET] = 64;
+        assert!(Board::from_bytes(&invalid).is_err());
+        // A white pawn on a1.
+        invalid = bytes;
+        invalid[1] = 0x31;
+        assert!(Board::from_bytes(&invalid).is_err());
+    }
+}
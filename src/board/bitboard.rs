@@ -67,6 +67,19 @@ pub fn into_iter(bitboard: BitBoard) -> BitBoardIterator {
     BitBoardIterator(bitboard)
 }
 
+// Creates an iterator that yields the square of each set bit.
+pub fn squares(bitboard: BitBoard) -> SquareIterator {
+    SquareIterator(bitboard)
+}
+
+// The bitboard with the bits of these squares set.
+// BitBoard being u64, it can't implement FromIterator itself.
+pub fn from_squares(squares: impl IntoIterator<Item = Square>) -> BitBoard {
+    squares
+        .into_iter()
+        .fold(0, |bitboard, square| bitboard | from_square(square))
+}
+
 pub struct BitBoardIterator(u64);
 
 impl Iterator for BitBoardIterator {
@@ -84,7 +97,35 @@ impl Iterator for BitBoardIterator {
     }
 }
 
-pub use constants::INITIAL_BOARD;
+pub struct SquareIterator(u64);
+
+impl Iterator for SquareIterator {
+    type Item = Square;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0 == 0 {
+            return None;
+        }
+
+        let square = get_index(self.0).into();
+        self.0 &= self.0 - 1; // Reset least significant bit
+
+        Some(square)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.0.count_ones() as usize;
+        (count, Some(count))
+    }
+}
+
+impl ExactSizeIterator for SquareIterator {}
+
+pub use constants::{
+    ADJACENT_FILES_MASKS, DARK_SQUARES, DISTANCE, FILE_MASKS, FRONT_SPANS, INITIAL_BOARD,
+    KING_ZONES, LIGHT_SQUARES, MASK_RANK_1, MASK_RANK_2, MASK_RANK_7, MASK_RANK_8,
+    PASSED_PAWN_MASKS,
+};
 pub use debug::from_str;
 pub use debug::print;
 
@@ -116,6 +157,29 @@ mod tests {
         . . . . . . . .
         . . . . . . . .";
 
+    #[test]
+    fn test_squares() {
+        let bb: BitBoard = bitboard::from_str(SAMPLE_BB);
+        let squares: Vec<Square> = bitboard::squares(bb).collect();
+        assert_eq!(
+            squares,
+            [
+                Square::C3,
+                Square::E3,
+                Square::B4,
+                Square::F4,
+                Square::B6,
+                Square::F6,
+                Square::C7,
+                Square::E7
+            ]
+        );
+        assert_eq!(bitboard::squares(bb).len(), 8);
+        assert_eq!(bitboard::from_squares(squares), bb);
+        assert_eq!(bitboard::squares(0).next(), None);
+        assert_eq!(bitboard::from_squares([]), 0);
+    }
+
     #[test]
     fn test_get_index() {
         let bb: BitBoard = bitboard::from_str(SAMPLE_BB);
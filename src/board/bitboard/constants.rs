@@ -38,6 +38,121 @@ pub const NOT_HG_FILE: BitBoard = 4557430888798830399;
 pub const NOT_AB_FILE: BitBoard = 18229723555195321596;
 pub const MASK_RANK_3: BitBoard = 16711680;
 pub const MASK_RANK_6: BitBoard = 280375465082880;
+pub const MASK_RANK_1: BitBoard = 0xFF;
+pub const MASK_RANK_8: BitBoard = 0xFF << 56;
+// Ranks of the pawns about to promote.
+pub const MASK_RANK_2: BitBoard = 0xFF << 8;
+pub const MASK_RANK_7: BitBoard = 0xFF << 48;
+
+// Squares colors, A1 being dark.
+pub const LIGHT_SQUARES: BitBoard = 0x55AA_55AA_55AA_55AA;
+pub const DARK_SQUARES: BitBoard = !LIGHT_SQUARES;
+
+// Masks of each file, from A to H.
+pub const FILE_MASKS: [BitBoard; 8] = file_masks();
+
+// Masks of the files next to each file.
+pub const ADJACENT_FILES_MASKS: [BitBoard; 8] = adjacent_files_masks();
+
+// Squares in front of each square on the same file, from the point of view of each color.
+// <https://www.chessprogramming.org/Pawn_Spans>
+pub const FRONT_SPANS: [[BitBoard; 64]; 2] = [front_spans(true), front_spans(false)];
+
+// Front spans extended to the adjacent files: A pawn is passed if there are no enemy pawns there.
+// <https://www.chessprogramming.org/Passed_Pawns_(Bitboards)>
+pub const PASSED_PAWN_MASKS: [[BitBoard; 64]; 2] = [
+    passed_pawn_masks(&FRONT_SPANS[0]),
+    passed_pawn_masks(&FRONT_SPANS[1]),
+];
+
+// The square of the king and the squares around it.
+// <https://www.chessprogramming.org/King_Safety#Attacking_King_Zone>
+pub const KING_ZONES: [BitBoard; 64] = king_zones();
+
+// Number of king moves between two squares.
+// <https://www.chessprogramming.org/Distance>
+pub const DISTANCE: [[u8; 64]; 64] = distances();
+
+const fn file_masks() -> [BitBoard; 8] {
+    let mut masks = [0; 8];
+    let mut file = 0;
+    while file < 8 {
+        masks[file] = 0x0101_0101_0101_0101 << file;
+        file += 1;
+    }
+    masks
+}
+
+const fn adjacent_files_masks() -> [BitBoard; 8] {
+    let mut masks = [0; 8];
+    let mut file = 0;
+    while file < 8 {
+        let mask = file_masks()[file];
+        masks[file] = ((mask << 1) & NOT_A_FILE) | ((mask >> 1) & NOT_H_FILE);
+        file += 1;
+    }
+    masks
+}
+
+const fn front_spans(white: bool) -> [BitBoard; 64] {
+    let mut spans = [0; 64];
+    let mut sq = 0;
+    while sq < 64 {
+        let file = FILE_MASKS[sq % 8];
+        let rank = sq / 8;
+        spans[sq] = if white {
+            (file << 8) << (rank * 8)
+        } else {
+            (file >> 8) >> ((7 - rank) * 8)
+        };
+        sq += 1;
+    }
+    spans
+}
+
+const fn passed_pawn_masks(front_spans: &[BitBoard; 64]) -> [BitBoard; 64] {
+    let mut masks = [0; 64];
+    let mut sq = 0;
+    while sq < 64 {
+        let span = front_spans[sq];
+        masks[sq] = span | ((span << 1) & NOT_A_FILE) | ((span >> 1) & NOT_H_FILE);
+        sq += 1;
+    }
+    masks
+}
+
+const fn king_zones() -> [BitBoard; 64] {
+    let mut zones = [0; 64];
+    let mut sq = 0;
+    while sq < 64 {
+        let king: BitBoard = 1 << sq;
+        let row = king | ((king << 1) & NOT_A_FILE) | ((king >> 1) & NOT_H_FILE);
+        zones[sq] = row | (row << 8) | (row >> 8);
+        sq += 1;
+    }
+    zones
+}
+
+#[allow(clippy::cast_possible_truncation)]
+const fn distances() -> [[u8; 64]; 64] {
+    let mut distances = [[0; 64]; 64];
+    let mut a: usize = 0;
+    while a < 64 {
+        let mut b: usize = 0;
+        while b < 64 {
+            let rank_distance = (a / 8).abs_diff(b / 8);
+            let file_distance = (a % 8).abs_diff(b % 8);
+            distances[a][b] = if rank_distance > file_distance {
+                rank_distance as u8
+            } else {
+                file_distance as u8
+            };
+            b += 1;
+        }
+        a += 1;
+    }
+    distances
+}
 
 pub const CASTLING_KING_SIDE_MASKS: [BitBoard; 2] = [
     0b0000000000000000000000000000000000000000000000000000000001100000,
@@ -51,7 +166,10 @@ pub const CASTLING_QUEEN_SIDE_MASKS: [BitBoard; 2] = [
 
 #[cfg(test)]
 mod tests {
-    use crate::board::bitboard::{self, constants::*};
+    use crate::{
+        board::bitboard::{self, constants::*},
+        common::Square,
+    };
 
     #[test]
     fn test_clipping_bitboards() {
@@ -85,6 +203,134 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_square_colors() {
+        assert_eq!(
+            LIGHT_SQUARES,
+            bitboard::from_str(
+                r"
+              1 0 1 0 1 0 1 0
+              0 1 0 1 0 1 0 1
+              1 0 1 0 1 0 1 0
+              0 1 0 1 0 1 0 1
+              1 0 1 0 1 0 1 0
+              0 1 0 1 0 1 0 1
+              1 0 1 0 1 0 1 0
+              0 1 0 1 0 1 0 1"
+            )
+        );
+        assert_eq!(LIGHT_SQUARES | DARK_SQUARES, UNIVERSAL);
+    }
+
+    #[test]
+    fn test_spans() {
+        assert_eq!(
+            FRONT_SPANS[0][Square::C3 as usize],
+            bitboard::from_str(
+                r"
+              0 0 1 0 0 0 0 0
+              0 0 1 0 0 0 0 0
+              0 0 1 0 0 0 0 0
+              0 0 1 0 0 0 0 0
+              0 0 1 0 0 0 0 0
+              0 0 0 0 0 0 0 0
+              0 0 0 0 0 0 0 0
+              0 0 0 0 0 0 0 0"
+            )
+        );
+        assert_eq!(FRONT_SPANS[0][Square::C8 as usize], EMPTY);
+        assert_eq!(FRONT_SPANS[1][Square::C1 as usize], EMPTY);
+        assert_eq!(
+            PASSED_PAWN_MASKS[1][Square::A6 as usize],
+            bitboard::from_str(
+                r"
+              0 0 0 0 0 0 0 0
+              0 0 0 0 0 0 0 0
+              0 0 0 0 0 0 0 0
+              1 1 0 0 0 0 0 0
+              1 1 0 0 0 0 0 0
+              1 1 0 0 0 0 0 0
+              1 1 0 0 0 0 0 0
+              1 1 0 0 0 0 0 0"
+            )
+        );
+    }
+
+    #[test]
+    fn test_file_masks() {
+        assert_eq!(FILE_MASKS[0], !NOT_A_FILE);
+        assert_eq!(FILE_MASKS[7], !NOT_H_FILE);
+        assert_eq!(
+            FILE_MASKS[2],
+            bitboard::from_str(
+                r"
+              0 0 1 0 0 0 0 0
+              0 0 1 0 0 0 0 0
+              0 0 1 0 0 0 0 0
+              0 0 1 0 0 0 0 0
+              0 0 1 0 0 0 0 0
+              0 0 1 0 0 0 0 0
+              0 0 1 0 0 0 0 0
+              0 0 1 0 0 0 0 0"
+            )
+        );
+        assert_eq!(
+            ADJACENT_FILES_MASKS[2],
+            bitboard::from_str(
+                r"
+              0 1 0 1 0 0 0 0
+              0 1 0 1 0 0 0 0
+              0 1 0 1 0 0 0 0
+              0 1 0 1 0 0 0 0
+              0 1 0 1 0 0 0 0
+              0 1 0 1 0 0 0 0
+              0 1 0 1 0 0 0 0
+              0 1 0 1 0 0 0 0"
+            )
+        );
+        assert_eq!(ADJACENT_FILES_MASKS[7], FILE_MASKS[6]);
+    }
+
+    #[test]
+    fn test_king_zones() {
+        assert_eq!(
+            KING_ZONES[Square::E4 as usize],
+            bitboard::from_str(
+                r"
+              0 0 0 0 0 0 0 0
+              0 0 0 0 0 0 0 0
+              0 0 0 0 0 0 0 0
+              0 0 0 1 1 1 0 0
+              0 0 0 1 1 1 0 0
+              0 0 0 1 1 1 0 0
+              0 0 0 0 0 0 0 0
+              0 0 0 0 0 0 0 0"
+            )
+        );
+        assert_eq!(
+            KING_ZONES[Square::H1 as usize],
+            bitboard::from_str(
+                r"
+              0 0 0 0 0 0 0 0
+              0 0 0 0 0 0 0 0
+              0 0 0 0 0 0 0 0
+              0 0 0 0 0 0 0 0
+              0 0 0 0 0 0 0 0
+              0 0 0 0 0 0 0 0
+              0 0 0 0 0 0 1 1
+              0 0 0 0 0 0 1 1"
+            )
+        );
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(DISTANCE[Square::A1 as usize][Square::A1 as usize], 0);
+        assert_eq!(DISTANCE[Square::A1 as usize][Square::H8 as usize], 7);
+        assert_eq!(DISTANCE[Square::E4 as usize][Square::C7 as usize], 3);
+        assert_eq!(DISTANCE[Square::C7 as usize][Square::E4 as usize], 3);
+    }
+
     #[test]
     fn test_masks() {
         assert_eq!(
@@ -3,15 +3,11 @@
 #![allow(clippy::cast_possible_truncation)]
 #![allow(clippy::unreadable_literal)]
 
-use std::sync::OnceLock;
-
-use itertools::Itertools;
-
 // Masks for lines, ranks, diagonals.
 // <https://www.chessprogramming.org/On_an_empty_Board#By_Calculation_3>
 
 const fn rank_mask(sq: u64) -> u64 {
-    0xFF << (sq & 56)
+    0xFF << (sq & 0x38)
 }
 
 const fn file_mask(sq: u64) -> u64 {
@@ -54,6 +50,7 @@ const fn anti_diagonal_mask_ex(sq: u64) -> u64 {
 }
 
 // The masks for all 64 squares.
+#[derive(Clone, Copy)]
 struct MaskForSquare {
     bit_mask: u64, // 1 << sq for convenience
     diagonal_mask_ex: u64,
@@ -62,7 +59,7 @@ struct MaskForSquare {
 }
 
 impl MaskForSquare {
-    fn new(sq: u64) -> Self {
+    const fn new(sq: u64) -> Self {
         Self {
             bit_mask: 1 << sq,
             diagonal_mask_ex: diagonal_mask_ex(sq),
@@ -72,14 +69,22 @@ impl MaskForSquare {
     }
 }
 
-fn init_mask_for_square() -> [MaskForSquare; 64] {
-    (0..64).map(MaskForSquare::new).collect_array().unwrap()
+// Iterators aren't allowed in const functions, hence the while loops.
+const fn init_mask_for_square() -> [MaskForSquare; 64] {
+    let mut masks = [MaskForSquare::new(0); 64];
+    let mut sq = 1;
+    while sq < 64 {
+        masks[sq as usize] = MaskForSquare::new(sq);
+        sq += 1;
+    }
+    masks
 }
 
-// Line masks for all squares, statically initialized.
+// Line masks for all squares, generated at compile time.
+static MASKS: [MaskForSquare; 64] = init_mask_for_square();
+
 fn get_masks(sq: u8) -> &'static MaskForSquare {
-    static MASKS: OnceLock<[MaskForSquare; 64]> = OnceLock::new();
-    &MASKS.get_or_init(init_mask_for_square)[sq as usize]
+    &MASKS[sq as usize]
 }
 
 // For diagonals and file attacks, we just use hyperbola quintessence.
@@ -114,41 +119,51 @@ fn file_attacks(occ: u64, sq: u8) -> u64 {
 // <https://www.talkchess.com/forum3/viewtopic.php?t=71312&start=10>
 
 // Generate an attack mask containing empty squares and the first occupied square.
-fn generate_rank_attack_mask(occ: u64, file: u64) -> u64 {
+const fn generate_rank_attack_mask(occ: u64, file: u64) -> u64 {
     let mut mask = 0;
-    for x in (0..file).rev() {
+    let mut x = file;
+    while x > 0 {
+        x -= 1;
         let b = 1 << x;
         mask |= b;
         if (occ & b) == b {
             break;
         }
     }
-    for x in (file + 1)..8 {
+    let mut x = file + 1;
+    while x < 8 {
         let b = 1 << x;
         mask |= b;
         if (occ & b) == b {
             break;
         }
+        x += 1;
     }
     mask
 }
 
-fn init_rank_attack_mask_array() -> [u64; 512] {
-    (0..64)
-        .flat_map(|x| (0..8).map(move |file| generate_rank_attack_mask(x * 2, file)))
-        .collect_array()
-        .unwrap()
+const fn init_rank_attack_mask_array() -> [u64; 512] {
+    let mut masks = [0; 512];
+    let mut i = 0;
+    while i < 512 {
+        // 8 * inner six bits rank occupancy + file
+        let (x, file) = (i / 8, i % 8);
+        masks[i as usize] = generate_rank_attack_mask(x * 2, file);
+        i += 1;
+    }
+    masks
 }
 
-// Line masks for all squares, statically initialized.
+// Rank attacks for all occupancies and files, generated at compile time.
+static RANK_ATTACK_MASKS: [u64; 512] = init_rank_attack_mask_array();
+
 fn get_rank_attack_mask(i: u64) -> u64 {
-    static MASKS: OnceLock<[u64; 512]> = OnceLock::new();
-    MASKS.get_or_init(init_rank_attack_mask_array)[i as usize]
+    RANK_ATTACK_MASKS[i as usize]
 }
 
 fn rank_attacks(occ: u64, sq: u8) -> u64 {
     let file = u64::from(sq & 7);
-    let rkx8 = sq & 56; // rank * 8
+    let rkx8 = sq & 0x38; // rank * 8
     let rank_occ_x2 = (occ >> rkx8) & (2 * 63); // 2 times the inner six bit rank occupancy used as index
     let attacks = get_rank_attack_mask(4 * rank_occ_x2 + file); // 8 * rank occupancy + file
     attacks << rkx8
@@ -195,7 +210,7 @@ mod tests {
             1 1 1 1 1 . 1 1
             . . . . . . 1 .",
         );
-        let attacks = get_bishop_attacks(occupancy.into(), C5);
+        let attacks = get_bishop_attacks(occupancy, C5);
         assert_eq!(
             attacks,
             bitboard::from_str(
@@ -226,7 +241,7 @@ mod tests {
             1 1 1 1 1 . 1 1
             . . . . . . 1 .",
         );
-        let attacks = get_rook_attacks(occupancy.into(), C5);
+        let attacks = get_rook_attacks(occupancy, C5);
         assert_eq!(
             attacks,
             bitboard::from_str(
@@ -1,8 +1,10 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
 use itertools::Itertools;
 
 use crate::{
     board::bitboard::{self, from_array, BitBoard},
-    common::{Color, Move, Piece, Square},
+    common::{Color, Move, Piece, Square, NULL_MOVE_PURE},
     utils::fen,
 };
 
@@ -25,6 +27,9 @@ impl Board {
             pieces: [0; 12],
             all: [0; 2],
             occupied: 0,
+            mailbox: [None; 64],
+            kings: [None; 2],
+            checkers: 0,
             side_to_move: Color::White,
             en_passant_target_square: None,
             castling_ability: CastlingAbility::NONE,
@@ -44,6 +49,9 @@ impl Board {
             pieces,
             all,
             occupied,
+            mailbox: Self::gen_mailbox(&pieces),
+            kings: Self::gen_kings(&pieces),
+            checkers: 0,
             side_to_move: Color::White,
             en_passant_target_square: None,
             castling_ability: CastlingAbility::ALL,
@@ -55,7 +63,45 @@ impl Board {
         b
     }
 
+    // The same position with the colors swapped and the board flipped vertically, so its
+    // evaluation for the side to move must be the same.
+    pub fn mirror(&self) -> Self {
+        let mut pieces = [0; 12];
+        for piece in Piece::ALL_PIECES {
+            // The white and black pieces alternate in the enum.
+            pieces[piece as usize ^ 1] = self.pieces[piece as usize].swap_bytes();
+        }
+        let all = get_all_bitboards(&pieces);
+        let mut b = Self {
+            pieces,
+            all,
+            occupied: get_occupied_bitboard(&all),
+            mailbox: Self::gen_mailbox(&pieces),
+            kings: Self::gen_kings(&pieces),
+            checkers: self.checkers.swap_bytes(),
+            side_to_move: self.side_to_move.opposite(),
+            en_passant_target_square: self
+                .en_passant_target_square
+                .map(|square| (square as u8 ^ 0b11_1000).into()),
+            castling_ability: self.castling_ability.mirror(),
+            half_move_clock: self.half_move_clock,
+            full_move_counter: self.full_move_counter,
+            zobrist_key: 0,
+        };
+        b.zobrist_key = Self::gen_zobrist_key(&b);
+        b
+    }
+
+    // Only for FEN strings known to be valid, like the ones of the code.
     pub fn from_fen(fen: &str) -> Self {
+        Self::try_from_fen(fen).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    // Parses a FEN string, checking that the position can be played from: At most one king of
+    // each color (none is accepted for tests), no pawns on the first or last rank, no capture of
+    // the king possible, and an en passant square right after a double push.
+    // Castling rights without the king and rook on their initial squares are dropped.
+    pub fn try_from_fen(fen: &str) -> Result<Self, String> {
         let (
             piece_placement,
             side_to_move,
@@ -63,7 +109,7 @@ impl Board {
             en_passant_target_square,
             half_move_clock,
             full_move_counter,
-        ) = fen::parse(fen);
+        ) = fen::parse(fen)?;
 
         let pieces = Piece::ALL_PIECES
             .iter()
@@ -82,13 +128,35 @@ impl Board {
             .collect_array()
             .unwrap();
 
+        Self::from_parts(
+            pieces,
+            side_to_move,
+            CastlingAbility::new(&castling_ability),
+            en_passant_target_square,
+            half_move_clock,
+            full_move_counter,
+        )
+        .map_err(|err| format!("Invalid FEN {fen}: {err}"))
+    }
+
+    // Board with these pieces and state, checked like the FEN ones.
+    pub(super) fn from_parts(
+        pieces: [BitBoard; 12],
+        side_to_move: Color,
+        castling_ability: CastlingAbility,
+        en_passant_target_square: Option<Square>,
+        half_move_clock: usize,
+        full_move_counter: usize,
+    ) -> Result<Self, String> {
         let all = get_all_bitboards(&pieces);
         let occupied = get_occupied_bitboard(&all);
-        let castling_ability = CastlingAbility::new(&castling_ability);
         let mut b = Self {
             pieces,
             all,
             occupied,
+            mailbox: Self::gen_mailbox(&pieces),
+            kings: Self::gen_kings(&pieces),
+            checkers: 0,
             side_to_move,
             en_passant_target_square,
             castling_ability,
@@ -96,26 +164,105 @@ impl Board {
             full_move_counter,
             zobrist_key: 0,
         };
+        b.check_position()?;
         b.zobrist_key = Self::gen_zobrist_key(&b);
-        b
+        b.checkers = b.attacks_king(side_to_move);
+        b.clear_impossible_castling();
+        b.clear_unusable_en_passant();
+        Ok(b)
+    }
+
+    fn check_position(&self) -> Result<(), String> {
+        if self.count_pieces(Piece::WhiteKing) > 1 || self.count_pieces(Piece::BlackKing) > 1 {
+            return Err("more than one king of a color".to_string());
+        }
+        let pawns = self.pieces[Piece::WhitePawn as usize] | self.pieces[Piece::BlackPawn as usize];
+        if pawns & (bitboard::MASK_RANK_1 | bitboard::MASK_RANK_8) != 0 {
+            return Err("pawns on the first or last rank".to_string());
+        }
+        if self.attacks_king(self.side_to_move.opposite()) != 0 {
+            return Err("the king of the side not to move is attacked".to_string());
+        }
+        if let Some(square) = self.en_passant_target_square {
+            let invalid = || format!("no double push for the en passant square {square}");
+            let rank = match self.side_to_move {
+                Color::White => 5,
+                Color::Black => 2,
+            };
+            if square.get_rank() != rank {
+                return Err(invalid());
+            }
+            // The pawn passed over the square, from the square behind it.
+            let (pushed, behind) = match self.side_to_move {
+                Color::White => (square as u8 - 8, square as u8 + 8),
+                Color::Black => (square as u8 + 8, square as u8 - 8),
+            };
+            let pawn = Piece::get_pawn_of(self.side_to_move.opposite());
+            if self.mailbox[pushed as usize] != Some(pawn)
+                || self.mailbox[square as usize].is_some()
+                || self.mailbox[behind as usize].is_some()
+            {
+                return Err(invalid());
+            }
+        }
+        Ok(())
+    }
+
+    // Checks that the redundant parts of the board agree with the pieces, for tests and fuzzing.
+    pub fn check_invariants(&self) -> Result<(), String> {
+        let mut union = 0;
+        for bb in self.pieces {
+            if union & bb != 0 {
+                return Err("Pieces on the same square".to_string());
+            }
+            union |= bb;
+        }
+        if self.all != get_all_bitboards(&self.pieces) || self.occupied != union {
+            return Err("Wrong color or occupied bitboards".to_string());
+        }
+        if self.mailbox != Self::gen_mailbox(&self.pieces) {
+            return Err("Wrong mailbox".to_string());
+        }
+        if self.kings != Self::gen_kings(&self.pieces) {
+            return Err("Wrong king squares".to_string());
+        }
+        if self.checkers != self.attacks_king(self.side_to_move) {
+            return Err("Wrong checkers".to_string());
+        }
+        if self.zobrist_key != Self::gen_zobrist_key(self) {
+            return Err("Wrong Zobrist key".to_string());
+        }
+        let mut castling = *self;
+        castling.clear_impossible_castling();
+        if castling.castling_ability != self.castling_ability {
+            return Err("Castling rights without the king and rook".to_string());
+        }
+        self.check_position()
+    }
+
+    // The piece on each square. Use this only for a new board, moves keep it updated.
+    pub(super) fn gen_mailbox(pieces: &[BitBoard; 12]) -> [Option<Piece>; 64] {
+        let mut mailbox = [None; 64];
+        for piece in Piece::ALL_PIECES {
+            for square in bitboard::squares(pieces[piece as usize]) {
+                mailbox[square as usize] = Some(piece);
+            }
+        }
+        mailbox
+    }
+
+    // The squares of the kings. Like the mailbox, moves keep them updated.
+    pub(super) fn gen_kings(pieces: &[BitBoard; 12]) -> [Option<Square>; 2] {
+        [Color::White, Color::Black].map(|color| {
+            let kings = pieces[Piece::get_king_of(color) as usize];
+            (kings != 0).then(|| bitboard::get_index(kings).into())
+        })
     }
 
     pub fn as_fen(&self) -> String {
         let piece_placement = (0..8)
             .rev()
-            .flat_map(|rank| {
-                (0..8).map(move |file| {
-                    let index = rank * 8 + file;
-                    let mut piece = None;
-                    for (piece_index, bitboard) in self.pieces.iter().enumerate() {
-                        if bitboard::is_set(*bitboard, index) {
-                            piece = Some(Piece::ALL_PIECES[piece_index]);
-                            break;
-                        }
-                    }
-                    piece
-                })
-            })
+            .flat_map(|rank| (0..8).map(move |file| self.mailbox[rank * 8 + file]))
             .collect_vec();
         fen::create(
             &piece_placement,
@@ -135,41 +282,130 @@ impl Board {
         self.side_to_move.opposite()
     }
 
+    pub fn get_half_move_clock(&self) -> usize {
+        self.half_move_clock
+    }
+
+    pub fn get_full_move_counter(&self) -> usize {
+        self.full_move_counter
+    }
+
+    // Identifies the position, for building position tables, books or repetition maps.
+    // Different positions can have the same key, even if it's very unlikely.
     pub fn get_zobrist_key(&self) -> u64 {
         self.zobrist_key
     }
 
+    // Identifies the position independently of the Zobrist key, to detect Zobrist key collisions.
+    // Much slower, only for debugging.
+    pub fn get_verification_key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.pieces.hash(&mut hasher);
+        self.side_to_move.hash(&mut hasher);
+        self.en_passant_target_square.hash(&mut hasher);
+        self.castling_ability.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // The square must not be empty.
     pub fn find_piece_on(&self, sq: Square) -> Piece {
-        let index = sq as u8;
-        *Piece::ALL_PIECES
-            .iter()
-            .find(|&&p| bitboard::is_set(self.pieces[p as usize], index))
-            .unwrap()
+        self.mailbox[sq as usize].expect("No piece on the square")
+    }
+
+    pub fn piece_on(&self, sq: Square) -> Option<Piece> {
+        self.mailbox[sq as usize]
+    }
+
+    // None only for an edited board without a king of that color.
+    pub fn king_square(&self, color: Color) -> Option<Square> {
+        self.kings[color as usize]
     }
 
-    // Creates a valid move based on this board.
-    // If there are no pieces on the from position, the code will crash.
+    // For tests, with moves known to be valid: Panics on invalid ones, see try_new_move_from_pure().
+    // The null move "0000" must be handled by the caller with make_null_move().
+    #[cfg(test)]
     pub fn new_move_from_pure(&self, s: &str) -> Move {
-        debug_assert!(s.len() >= 4 && s.len() <= 5);
-        let from: Square = s[0..2].try_into().unwrap();
-        let to: Square = s[2..4].try_into().unwrap();
+        self.try_new_move_from_pure(s)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    // Creates a move based on this board, failing if the notation is invalid or if there is no
+    // piece on the from square. The move isn't checked to be legal, see why_illegal().
+    pub fn try_new_move_from_pure(&self, s: &str) -> Result<Move, String> {
+        if s == NULL_MOVE_PURE {
+            return Err("The null move isn't a Move".to_string());
+        }
+        let square = |range| {
+            s.get(range)
+                .and_then(|sq| Square::try_from(sq).ok())
+                .ok_or_else(|| format!("Invalid move {s}"))
+        };
+        let (from, to) = (square(0..2)?, square(2..4)?);
+        if s.len() > 5 {
+            return Err(format!("Invalid move {s}"));
+        }
+
+        let piece = self
+            .piece_on(from)
+            .ok_or_else(|| format!("No piece on the starting square of {s}"))?;
+        let captured = self.piece_on(to);
+
+        // Pure notation doesn't say which moves are special, so we deduce it from the position.
+        let color = piece.get_color() as usize;
+        for castling in [
+            Move::KING_TO_KING_SIDE_CASTLING[color],
+            Move::KING_TO_QUEEN_SIDE_CASTLING[color],
+        ] {
+            if piece.is_king() && from == castling.get_from() && to == castling.get_to() {
+                return Ok(castling);
+            }
+        }
+        if piece.is_pawn() && captured.is_none() {
+            if from.get_rank().abs_diff(to.get_rank()) == 2 {
+                return Ok(Move::double_push(from, to, piece));
+            }
+            if from.get_file() != to.get_file() {
+                return Ok(Move::en_passant(from, to, piece));
+            }
+        }
 
-        let piece = self.find_piece_on(from);
-        let to_bb: BitBoard = bitboard::from_square(to);
-        let is_capture = self.occupied & to_bb != 0;
         let promotion = if piece.is_pawn() && to.is_promotion_rank_for(piece.get_color()) {
-            let promotion_piece = match &s[4..5] {
-                "q" => Piece::get_queen_of(piece.get_color()),
-                "r" => Piece::get_rook_of(piece.get_color()),
-                "b" => Piece::get_bishop_of(piece.get_color()),
-                "n" => Piece::get_knight_of(piece.get_color()),
-                _ => panic!("Invalid promotion flag"),
+            // Some tools send the promotion piece in uppercase.
+            let promotion_piece = match s.get(4..5).map(str::to_ascii_lowercase).as_deref() {
+                Some("q") => Piece::get_queen_of(piece.get_color()),
+                Some("r") => Piece::get_rook_of(piece.get_color()),
+                Some("b") => Piece::get_bishop_of(piece.get_color()),
+                Some("n") => Piece::get_knight_of(piece.get_color()),
+                Some(_) => return Err(format!("Invalid promotion flag in {s}")),
+                None => return Err(format!("Missing promotion piece in {s}")),
             };
             Some(promotion_piece)
+        } else if s.len() == 5 {
+            return Err(format!(
+                "Promotion flag on a move that isn't a promotion in {s}"
+            ));
         } else {
             None
         };
-        Move::new(from, to, promotion, piece, is_capture)
+        Ok(Move::new(from, to, promotion, piece, captured))
+    }
+
+    pub fn get_pieces(&self, piece: Piece) -> BitBoard {
+        self.pieces[piece as usize]
+    }
+
+    pub fn count_pieces(&self, piece: Piece) -> u32 {
+        self.pieces[piece as usize].count_ones()
+    }
+
+    // The pieces of that color with their squares, by piece type then by square.
+    pub fn pieces_of(&self, color: Color) -> impl Iterator<Item = (Square, Piece)> + '_ {
+        Piece::ALL_PIECES
+            .into_iter()
+            .filter(move |piece| piece.get_color() == color)
+            .flat_map(|piece| {
+                bitboard::squares(self.pieces[piece as usize]).map(move |square| (square, piece))
+            })
     }
 
     // Computes a material score with the given piece values.
@@ -193,10 +429,43 @@ impl From<&str> for Board {
     }
 }
 
+// Hashing just writes the Zobrist key, which is much faster than hashing all the fields.
+// Caveat: Equality still compares all the fields, including the move counters. So a HashMap<Board, _>
+// considers the same position reached at different moves as different entries. To find
+// repetitions or transpositions, use the Zobrist key itself as the map key.
+impl Hash for Board {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.zobrist_key);
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+
     use super::*;
 
+    #[test]
+    fn test_pieces_of() {
+        let board: Board = "4k3/3p4/8/8/8/2N5/1P6/R3K3 w - - 0 1".into();
+        assert_eq!(
+            board.pieces_of(Color::White).collect::<Vec<_>>(),
+            [
+                (Square::B2, Piece::WhitePawn),
+                (Square::C3, Piece::WhiteKnight),
+                (Square::A1, Piece::WhiteRook),
+                (Square::E1, Piece::WhiteKing)
+            ]
+        );
+        assert_eq!(
+            board.pieces_of(Color::Black).collect::<Vec<_>>(),
+            [
+                (Square::D7, Piece::BlackPawn),
+                (Square::E8, Piece::BlackKing)
+            ]
+        );
+    }
+
     #[test]
     fn test_initial_board() {
         let board = Board::initial_board();
@@ -226,4 +495,128 @@ mod tests {
         assert_eq!(board, Board::initial_board());
         assert_eq!(board.en_passant_target_square, None);
     }
+
+    #[test]
+    fn test_try_from_fen_invalid_position() {
+        for invalid in [
+            "4k3/8/8/8/8/8/8/K3K3 w - - 0 1",
+            "4k2P/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K2r b - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - a1 0 1",
+            "4k3/8/8/3p4/8/8/8/4K3 w - e6 0 1",
+            "4k3/8/3P4/3p4/8/8/8/4K3 w - d6 0 1",
+        ] {
+            assert!(Board::try_from_fen(invalid).is_err(), "{invalid}");
+        }
+        let board = Board::try_from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        assert_eq!(board.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn test_try_new_move_from_pure() {
+        let board: Board = "r3k2r/8/8/8/4K3/8/8/R7 w kq - 0 1".into();
+        assert!(board.try_new_move_from_pure("e3e4").is_err());
+        assert!(board.try_new_move_from_pure("e4").is_err());
+        assert!(board.try_new_move_from_pure("e4e5qq").is_err());
+        assert!(board.try_new_move_from_pure("a1a2q").is_err());
+        assert!(board.try_new_move_from_pure("é4e5").is_err());
+        assert!(board.try_new_move_from_pure(NULL_MOVE_PURE).is_err());
+        // Only a king on its initial square can castle.
+        assert_eq!(
+            board.try_new_move_from_pure("e4g4"),
+            Ok(Move::quiet(Square::E4, Square::G4, Piece::WhiteKing))
+        );
+    }
+
+    #[test]
+    fn test_mirror() {
+        let board = Board::from_fen("r3k2r/8/8/8/3pP3/8/8/R3K3 b Qkq e3 0 1");
+        let mirrored = board.mirror();
+        assert_eq!(mirrored.as_fen(), "r3k3/8/8/3Pp3/8/8/8/R3K2R w KQq e6 0 1");
+        assert_eq!(mirrored.check_invariants(), Ok(()));
+        assert_eq!(mirrored.mirror(), board);
+        assert_eq!(
+            Board::initial_board().mirror().as_fen(),
+            fen::START_POSITION.replace(" w ", " b ")
+        );
+    }
+
+    #[test]
+    fn test_hash() {
+        let hash = |board: &Board| {
+            let mut hasher = DefaultHasher::new();
+            board.hash(&mut hasher);
+            hasher.finish()
+        };
+        let board1: Board = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1".into();
+        let board2: Board = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1".into();
+        let board3: Board = "4k3/8/8/8/8/4P3/8/4K3 b - - 0 1".into();
+        assert_eq!(hash(&board1), hash(&board2));
+        assert_ne!(hash(&board1), hash(&board3));
+
+        let positions: HashSet<Board> = [board1, board2, board3].into_iter().collect();
+        assert_eq!(positions.len(), 2);
+        assert!(positions.contains(&board3));
+    }
+
+    #[test]
+    fn test_new_move_from_pure() {
+        let board: Board = "r3k2r/8/8/3pP3/8/8/4P3/R3K2R w KQkq d6 0 1".into();
+        assert_eq!(
+            board.new_move_from_pure("e1g1"),
+            Move::KING_TO_KING_SIDE_CASTLING[0]
+        );
+        assert_eq!(
+            board.new_move_from_pure("e1c1"),
+            Move::KING_TO_QUEEN_SIDE_CASTLING[0]
+        );
+        assert_eq!(
+            board.new_move_from_pure("e1f1"),
+            Move::quiet(Square::E1, Square::F1, Piece::WhiteKing)
+        );
+        assert_eq!(
+            board.new_move_from_pure("e5d6"),
+            Move::en_passant(Square::E5, Square::D6, Piece::WhitePawn)
+        );
+        assert_eq!(
+            board.new_move_from_pure("e2e4"),
+            Move::double_push(Square::E2, Square::E4, Piece::WhitePawn)
+        );
+    }
+
+    #[test]
+    fn test_new_move_from_pure_promotion() {
+        let board: Board = "1n5k/P7/8/8/8/8/8/7K w - - 0 1".into();
+        assert_eq!(
+            board.new_move_from_pure("a7a8q"),
+            Move::new(
+                Square::A7,
+                Square::A8,
+                Some(Piece::WhiteQueen),
+                Piece::WhitePawn,
+                None
+            )
+        );
+        assert_eq!(
+            board.new_move_from_pure("a7b8n"),
+            Move::new(
+                Square::A7,
+                Square::B8,
+                Some(Piece::WhiteKnight),
+                Piece::WhitePawn,
+                Some(Piece::BlackKnight)
+            )
+        );
+        assert_eq!(
+            board.new_move_from_pure("a7a8R").get_promotion(),
+            Some(Piece::WhiteRook)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Missing promotion piece")]
+    fn test_new_move_from_pure_missing_promotion() {
+        let board: Board = "1n5k/P7/8/8/8/8/8/7K w - - 0 1".into();
+        board.new_move_from_pure("a7a8");
+    }
 }
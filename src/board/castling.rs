@@ -54,6 +54,11 @@ impl CastlingAbility {
         self.0 & (0b0010 << ((color as u8) * 2)) != 0
     }
 
+    // The same rights with the colors swapped.
+    pub fn mirror(self) -> Self {
+        Self(((self.0 & 0b0011) << 2) | (self.0 >> 2))
+    }
+
     pub fn as_pieces_iter(self) -> impl Iterator<Item = Piece> {
         [
             (self.white_can_castle_king_side(), Piece::WhiteKing),
@@ -1,6 +1,13 @@
 //! Visualization of a Board
 
-use std::{fmt::Display, io::Write};
+use std::{
+    fmt::{Display, Write as _},
+    io::Write,
+    str::FromStr,
+    sync::OnceLock,
+};
+
+use itertools::Itertools;
 
 use crate::{
     board::bitboard::{self, BitBoard},
@@ -10,6 +17,97 @@ use crate::{
 
 use super::Board;
 
+const RED_BACKGROUND: &str = "\x1b[41m";
+const GREEN_BACKGROUND: &str = "\x1b[42m";
+const LIGHT_BACKGROUND: &str = "\x1b[47m";
+const DARK_BACKGROUND: &str = "\x1b[100m";
+const BLACK_FOREGROUND: &str = "\x1b[30m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PieceSet {
+    Ascii,
+    Unicode,
+}
+
+// How boards are rendered, for terminals or logs without ANSI or Unicode support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub pieces: PieceSet,
+    // ANSI colors: Shaded squares, and the squares of the move highlighted.
+    pub colors: bool,
+    pub coordinates: bool,
+    // Shows the board from Black's side.
+    pub flip: bool,
+}
+
+impl Theme {
+    // Default for print(), going to a terminal.
+    pub const TERMINAL: Theme = Theme {
+        pieces: PieceSet::Unicode,
+        colors: true,
+        coordinates: true,
+        flip: false,
+    };
+
+    // Default for write(), whose output goes to UCI GUIs and logs.
+    pub const PLAIN: Theme = Theme {
+        pieces: PieceSet::Ascii,
+        colors: false,
+        coordinates: true,
+        flip: false,
+    };
+
+    // The theme set from the command line, or the default for print().
+    pub fn for_print() -> Theme {
+        THEME.get().copied().unwrap_or(Theme::TERMINAL)
+    }
+
+    // The theme set from the command line, or the default for write().
+    pub fn for_write() -> Theme {
+        THEME.get().copied().unwrap_or(Theme::PLAIN)
+    }
+
+    // Ranks and files in the order they are displayed, from the top left corner.
+    fn ranks_and_files(flip: bool) -> (Vec<u8>, Vec<u8>) {
+        if flip {
+            ((0..8).collect(), (0..8).rev().collect())
+        } else {
+            ((0..8).rev().collect(), (0..8).collect())
+        }
+    }
+}
+
+// Parses a comma separated list of changes to the terminal theme, like "ascii,plain,flip".
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut theme = Theme::TERMINAL;
+        for option in s.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+            match option {
+                "ascii" => theme.pieces = PieceSet::Ascii,
+                "unicode" => theme.pieces = PieceSet::Unicode,
+                "color" => theme.colors = true,
+                "plain" => theme.colors = false,
+                "coords" => theme.coordinates = true,
+                "nocoords" => theme.coordinates = false,
+                "flip" => theme.flip = true,
+                _ => return Err(format!("Invalid theme option {option}")),
+            }
+        }
+        Ok(theme)
+    }
+}
+
+// Theme set from the command line, used instead of the defaults by print() and write().
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+// Can only be set once, at startup.
+pub fn set_theme(theme: Theme) {
+    let _ = THEME.set(theme);
+}
+
 impl Board {
     const ASCII_PIECES: [char; 12] = ['P', 'p', 'N', 'n', 'B', 'b', 'R', 'r', 'Q', 'q', 'K', 'k'];
     const UNICODE_PIECES: [char; 12] = ['♙', '♟', '♘', '♞', '♗', '♝', '♖', '♜', '♕', '♛', '♔', '♚'];
@@ -21,78 +119,142 @@ impl Board {
     pub fn print_with_move(&self, mv: Option<Move>) {
         // We don't use write() here because we want the print functions to be captured
         // in tests, and stdout doesn't capture in tests <https://github.com/rust-lang/rust/issues/90785>
-        const RED: &str = "\x1b[31m";
-        const GREEN: &str = "\x1b[32m";
-        const RESET: &str = "\x1b[0m";
-        const INVERSE: &str = "\x1b[7m";
-        for rank in (0..8).rev() {
-            print!("  {} ", rank + 1);
-            for file in 0..8 {
+        print!("{}", self.render(Theme::for_print(), mv));
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_with_theme(writer, Theme::for_write())
+    }
+
+    pub fn write_with_theme<W: Write>(
+        &self,
+        writer: &mut W,
+        theme: Theme,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        write!(writer, "{}", self.render(theme, None))?;
+        writeln!(writer)?;
+        writeln!(writer, "FEN: {}", self.as_fen())?;
+        Ok(())
+    }
+
+    // The state of the position that the board doesn't show, as in the d command of Stockfish.
+    pub fn write_state<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn std::error::Error>> {
+        writeln!(writer, "Key: {:016X}", self.zobrist_key)?;
+        writeln!(writer, "Side to move: {}", self.side_to_move)?;
+        writeln!(writer, "Castling: {}", self.castling_ability)?;
+        writeln!(
+            writer,
+            "En passant: {}",
+            self.en_passant_target_square
+                .map_or("-".to_string(), |square| square.to_string())
+        )?;
+        writeln!(writer, "Halfmove clock: {}", self.half_move_clock)?;
+        writeln!(writer, "Fullmove number: {}", self.full_move_counter)?;
+        writeln!(writer, "Checkers: {}", format_squares(self.checkers))?;
+        Ok(())
+    }
+
+    // The board as text, one line per rank, with the squares of the move highlighted if the
+    // theme has colors.
+    fn render(&self, theme: Theme, mv: Option<Move>) -> String {
+        let pieces = match theme.pieces {
+            PieceSet::Ascii => &Self::ASCII_PIECES,
+            PieceSet::Unicode => &Self::UNICODE_PIECES,
+        };
+        let (ranks, files) = Theme::ranks_and_files(theme.flip);
+
+        let mut out = String::new();
+        for &rank in &ranks {
+            if theme.coordinates {
+                let _ = write!(out, "  {} ", rank + 1);
+            } else {
+                out.push_str("    ");
+            }
+            for &file in &files {
                 let index = rank * 8 + file;
-                let square: Square = ((b'a' + file) as char, rank as usize + 1).into();
-
-                let mut piece_char = '.';
-                for (piece, bitboard) in self.pieces.iter().enumerate() {
-                    if bitboard::is_set(*bitboard, index) {
-                        piece_char = Self::UNICODE_PIECES[piece];
-                        break;
-                    }
-                }
-                if let Some(m) = mv {
-                    if m.get_from() == square {
-                        print!(" {INVERSE}{RED}{piece_char}{RESET}");
-                    } else if m.get_to() == square {
-                        print!(" {INVERSE}{GREEN}{piece_char}{RESET}");
-                    } else {
-                        print!(" {piece_char}");
-                    }
+                let square = Square::from(index);
+                let piece_char = self
+                    .piece_on(square)
+                    .map_or('.', |piece| pieces[piece as usize]);
+                if theme.colors {
+                    let background = match mv {
+                        Some(m) if m.get_from() == square => RED_BACKGROUND,
+                        Some(m) if m.get_to() == square => GREEN_BACKGROUND,
+                        _ if (rank + file) % 2 == 0 => DARK_BACKGROUND,
+                        _ => LIGHT_BACKGROUND,
+                    };
+                    let _ = write!(out, "{background}{BLACK_FOREGROUND} {piece_char}{RESET}");
                 } else {
-                    print!(" {piece_char}");
+                    let _ = write!(out, " {piece_char}");
                 }
             }
-            println!();
+            out.push('\n');
         }
-        println!(
-            " {}  a b c d e f g h",
-            if self.get_side_to_move() == Color::White {
-                "=>"
-            } else {
-                "  "
-            }
-        );
-        // println!();
-        // println!("FEN: {}", self.as_fen());
+
+        let arrow = self.side_to_move_arrow(theme.flip);
+        let files = if theme.coordinates {
+            files.iter().map(|&file| char::from(b'a' + file)).join(" ")
+        } else {
+            String::new()
+        };
+        let _ = writeln!(out, "{}", format!(" {arrow}  {files}").trim_end());
+        out
     }
 
-    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn std::error::Error>> {
-        for rank in (0..8).rev() {
-            write!(writer, "  {} ", rank + 1)?;
-            for file in 0..8 {
+    // The arrow shows the side to move when it's at the bottom.
+    fn side_to_move_arrow(&self, flip: bool) -> &'static str {
+        let bottom_color = if flip { Color::Black } else { Color::White };
+        if self.get_side_to_move() == bottom_color {
+            "=>"
+        } else {
+            "  "
+        }
+    }
+
+    // Writes the board with the squares attacked by each side, followed by the checkers and the
+    // pinned pieces. A debugging aid for the move generation and king safety.
+    pub fn write_attacks<W: Write>(
+        &self,
+        writer: &mut W,
+        flip: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let white_attacks = self.attack_map(Color::White);
+        let black_attacks = self.attack_map(Color::Black);
+        let (ranks, files) = Theme::ranks_and_files(flip);
+        for &rank in &ranks {
+            let mut line = format!("  {} ", rank + 1);
+            for &file in &files {
                 let index = rank * 8 + file;
-                let mut piece_char = '.';
-                for (piece, bitboard) in self.pieces.iter().enumerate() {
-                    if bitboard::is_set(*bitboard, index) {
-                        piece_char = Self::ASCII_PIECES[piece];
-                        break;
-                    }
-                }
-                write!(writer, " {piece_char}")?;
+                let piece_char = self
+                    .piece_on(Square::from(index))
+                    .map_or('.', |piece| Self::ASCII_PIECES[piece as usize]);
+                let marker = match (
+                    bitboard::is_set(white_attacks, index),
+                    bitboard::is_set(black_attacks, index),
+                ) {
+                    (true, true) => '*',
+                    (true, false) => '+',
+                    (false, true) => '-',
+                    (false, false) => ' ',
+                };
+                line.extend([' ', piece_char, marker]);
             }
-            writeln!(writer)?;
+            writeln!(writer, "{}", line.trim_end())?;
         }
         writeln!(
             writer,
-            " {}  a b c d e f g h",
-            if self.get_side_to_move() == Color::White {
-                "=>"
-            } else {
-                "  "
-            }
+            " {}  {}",
+            self.side_to_move_arrow(flip),
+            files.iter().map(|&file| char::from(b'a' + file)).join("  ")
         )?;
         writeln!(writer)?;
-        writeln!(writer, "FEN: {}", self.as_fen())?;
-        // writeln!(writer, "Zobrist: {}", self.zobrist_key)?;
-        // writeln!(writer, "Zobrist gen: {}", Self::gen_zobrist_key(self))?;
+        writeln!(writer, "Attacked by White: +, by Black: -, by both: *")?;
+        writeln!(writer, "Checkers: {}", format_squares(self.checkers))?;
+        writeln!(
+            writer,
+            "Pinned: {}",
+            format_squares(self.pinned(Color::White) | self.pinned(Color::Black))
+        )?;
         Ok(())
     }
 
@@ -109,19 +271,26 @@ impl Board {
         bitboard::print(self.occupied);
     }
 
-    // Creates a valid move based on this board.
-    // In case of promotion, we promote to a queen.
-    // If there are no pieces on the from position, the code will crash.
-    pub fn new_move(&self, from: Square, to: Square) -> Move {
-        let piece = self.find_piece_on(from);
-        let to_bb: BitBoard = bitboard::from_square(to);
-        let is_capture = self.occupied & to_bb != 0;
-        let promotion = if piece.is_pawn() && to.is_promotion_rank_for(piece.get_color()) {
-            Some(Piece::get_queen_of(piece.get_color()))
-        } else {
-            None
-        };
-        Move::new(from, to, promotion, piece, is_capture)
+    // Creates a move based on this board, with the promotion piece for promotions, which can be of
+    // either color. It goes through the pure notation, failing like try_new_move_from_pure().
+    pub fn new_move(
+        &self,
+        from: Square,
+        to: Square,
+        promotion: Option<Piece>,
+    ) -> Result<Move, String> {
+        let promotion = promotion.map_or(String::new(), |piece| {
+            char::from(piece).to_ascii_lowercase().to_string()
+        });
+        self.try_new_move_from_pure(&format!("{from}{to}{promotion}"))
+    }
+}
+
+fn format_squares(bb: BitBoard) -> String {
+    if bb == 0 {
+        "-".to_string()
+    } else {
+        bitboard::squares(bb).join(" ")
     }
 }
 
@@ -135,12 +304,113 @@ impl Display for Board {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_render() {
+        let board: Board = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1".into();
+        assert_eq!(
+            board.render(Theme::PLAIN, None),
+            "  8  . . . . k . . .
+  7  . . . . . . . .
+  6  . . . . . . . .
+  5  . . . . . . . .
+  4  . . . . . . . .
+  3  . . . . . . . .
+  2  . . . . P . . .
+  1  . . . . K . . .
+     a b c d e f g h
+"
+        );
+
+        let theme: Theme = "ascii,plain,nocoords,flip".parse().unwrap();
+        assert_eq!(
+            board.render(theme, None),
+            "     . . . K . . . .
+     . . . P . . . .
+     . . . . . . . .
+     . . . . . . . .
+     . . . . . . . .
+     . . . . . . . .
+     . . . . . . . .
+     . . . k . . . .
+ =>
+"
+        );
+
+        // Colors shade the squares, and highlight the move.
+        let mv = board.new_move(Square::E8, Square::D8, None).unwrap();
+        let rendered = board.render(Theme::TERMINAL, Some(mv));
+        let first_line = rendered.lines().next().unwrap();
+        assert!(first_line.starts_with(&format!(
+            "  8 {LIGHT_BACKGROUND}{BLACK_FOREGROUND} .{RESET}"
+        )));
+        assert!(first_line.contains(&format!("{GREEN_BACKGROUND}{BLACK_FOREGROUND} .{RESET}")));
+        assert!(first_line.contains(&format!("{RED_BACKGROUND}{BLACK_FOREGROUND} ♚{RESET}")));
+    }
+
+    #[test]
+    fn test_parse_theme() {
+        assert_eq!("".parse(), Ok(Theme::TERMINAL));
+        assert_eq!("ascii, plain".parse(), Ok(Theme::PLAIN));
+        assert!("ascii,bold".parse::<Theme>().is_err());
+    }
+
+    #[test]
+    fn test_write_attacks() {
+        let board: Board = "4k3/4q3/8/8/8/8/4R3/4K3 b - - 0 1".into();
+        let mut out = Vec::new();
+        board.write_attacks(&mut out, false).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out,
+            "  8  .  .  .  .- k- .- .  .
+  7  .- .- .- .- q* .- .- .-
+  6  .  .  .  .- .* .- .  .
+  5  .  .  .- .  .* .  .- .
+  4  .  .- .  .  .* .  .  .-
+  3  .- .  .  .  .* .  .  .
+  2  .+ .+ .+ .+ R* .+ .+ .+
+  1  .  .  .  .+ K+ .+ .  .
+     a  b  c  d  e  f  g  h
+
+Attacked by White: +, by Black: -, by both: *
+Checkers: -
+Pinned: e2 e7
+"
+        );
+    }
+
+    #[test]
+    fn test_write_state() {
+        let state = |board: &Board| {
+            let mut out = Vec::new();
+            board.write_state(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let board: Board = "4k3/8/8/3pP3/8/8/8/R3K3 w Q d6 0 32".into();
+        assert_eq!(
+            state(&board),
+            format!(
+                "Key: {:016X}
+Side to move: White
+Castling: Q
+En passant: d6
+Halfmove clock: 0
+Fullmove number: 32
+Checkers: -
+",
+                board.get_zobrist_key()
+            )
+        );
+        let board: Board = "4k3/8/8/8/8/8/8/R3K2r w Q - 3 32".into();
+        assert!(state(&board).contains("Checkers: h1\n"));
+    }
+
     #[test]
     fn test_new_move() {
         let board = Board::initial_board();
         let from = Square::E2;
         let to = Square::E4;
-        let mv = board.new_move(from, to);
+        let mv = board.new_move(from, to, None).unwrap();
         assert_eq!(mv.get_from(), from);
         assert_eq!(mv.get_to(), to);
         assert_eq!(mv.get_piece(), Piece::WhitePawn);
@@ -153,11 +423,11 @@ mod tests {
         let board: Board = "rnbqkbnr/pppp1ppp/8/8/4p3/2N2P2/PPPPP1PP/R1BQKBNR w KQkq - 0 3".into();
         let from = Square::E2;
         let to = Square::E4;
-        let mv = board.new_move(from, to);
+        let mv = board.new_move(from, to, None).unwrap();
         assert_eq!(mv.get_from(), from);
         assert_eq!(mv.get_to(), to);
         assert_eq!(mv.get_piece(), Piece::WhitePawn);
-        assert!(mv.is_capture());
+        assert_eq!(mv.get_captured(), Some(Piece::BlackPawn));
         assert!(mv.get_promotion().is_none());
     }
 
@@ -166,11 +436,31 @@ mod tests {
         let board: Board = "6k1/4P3/8/8/8/8/8/4K3 w - - 0 1".into();
         let from = Square::E7;
         let to = Square::E8;
-        let mv = board.new_move(from, to);
+        let mv = board.new_move(from, to, Some(Piece::WhiteQueen)).unwrap();
         assert_eq!(mv.get_from(), from);
         assert_eq!(mv.get_to(), to);
         assert_eq!(mv.get_piece(), Piece::WhitePawn);
         assert!(!mv.is_capture());
         assert_eq!(mv.get_promotion(), Some(Piece::WhiteQueen));
+        // No default promotion piece.
+        assert!(board.new_move(from, to, None).is_err());
+    }
+
+    #[test]
+    fn test_new_move_under_promotion() {
+        let board: Board = "5n1k/4P3/8/8/8/8/8/4K3 w - - 0 1".into();
+        let mv = board
+            .new_move(Square::E7, Square::F8, Some(Piece::WhiteKnight))
+            .unwrap();
+        assert_eq!(mv.get_captured(), Some(Piece::BlackKnight));
+        assert_eq!(mv.get_promotion(), Some(Piece::WhiteKnight));
+        // The color of the promotion piece is the one of the pawn.
+        let mv = board
+            .new_move(Square::E7, Square::E8, Some(Piece::BlackRook))
+            .unwrap();
+        assert_eq!(mv.get_promotion(), Some(Piece::WhiteRook));
+        assert!(board
+            .new_move(Square::E1, Square::E2, Some(Piece::WhiteQueen))
+            .is_err());
     }
 }
@@ -0,0 +1,224 @@
+//! Board editing, to set up positions without going through FEN strings.
+//! The edited position may be illegal in between edits, like when setting up a physical board,
+//! but it must have a king of each color when searching it.
+
+use std::str::FromStr;
+
+use crate::{
+    board::bitboard,
+    common::{Color, Piece, Square},
+};
+
+use super::{zobrist::ZOBRIST_KEYS, Board, CastlingAbility};
+
+// Squares the pieces must be on for each castling right.
+const CASTLING_SQUARES: [(Piece, Square, Square); 4] = [
+    (Piece::WhiteKing, Square::E1, Square::H1),
+    (Piece::WhiteQueen, Square::E1, Square::A1),
+    (Piece::BlackKing, Square::E8, Square::H8),
+    (Piece::BlackQueen, Square::E8, Square::A8),
+];
+
+// An edit of the board, as given on the command line or in the non-standard UCI "edit" command:
+//   put e4 N, remove e4, side b, castling Kq (or castling -)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoardEdit {
+    Put(Square, Piece),
+    Remove(Square),
+    SideToMove(Color),
+    // Castling rights as in FEN: White king for K, black queen for q, etc.
+    Castling(Vec<Piece>),
+}
+
+impl FromStr for BoardEdit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let square = |s: &str| Square::try_from(s).map_err(|_| format!("Invalid square {s}"));
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        match tokens[..] {
+            ["put", sq, piece] => {
+                let piece = piece
+                    .parse::<char>()
+                    .ok()
+                    .and_then(|c| Piece::try_from(c).ok())
+                    .ok_or_else(|| format!("Invalid piece {piece}"))?;
+                Ok(BoardEdit::Put(square(sq)?, piece))
+            }
+            ["remove", sq] => Ok(BoardEdit::Remove(square(sq)?)),
+            ["side", "w"] => Ok(BoardEdit::SideToMove(Color::White)),
+            ["side", "b"] => Ok(BoardEdit::SideToMove(Color::Black)),
+            ["castling", "-"] => Ok(BoardEdit::Castling(Vec::new())),
+            ["castling", rights] => rights
+                .chars()
+                .map(|c| match c {
+                    'K' | 'Q' | 'k' | 'q' => Ok(Piece::try_from(c).unwrap()),
+                    _ => Err(format!("Invalid castling rights {rights}")),
+                })
+                .collect::<Result<_, _>>()
+                .map(BoardEdit::Castling),
+            _ => Err(format!("Invalid board edit {s}")),
+        }
+    }
+}
+
+impl Board {
+    // Puts the piece on the square, replacing the one that was there.
+    pub fn put_piece(&mut self, sq: Square, piece: Piece) {
+        self.remove_piece(sq);
+        let bb = bitboard::from_square(sq);
+        self.pieces[piece as usize] |= bb;
+        self.all[piece.get_color() as usize] |= bb;
+        self.occupied |= bb;
+        self.mailbox[sq as usize] = Some(piece);
+        self.zobrist_key ^= ZOBRIST_KEYS.piece_key(sq, piece);
+        self.after_edit();
+    }
+
+    // Returns the removed piece, if any.
+    pub fn remove_piece(&mut self, sq: Square) -> Option<Piece> {
+        let piece = self.piece_on(sq)?;
+        let bb = bitboard::from_square(sq);
+        self.pieces[piece as usize] &= !bb;
+        self.all[piece.get_color() as usize] &= !bb;
+        self.occupied &= !bb;
+        self.mailbox[sq as usize] = None;
+        self.zobrist_key ^= ZOBRIST_KEYS.piece_key(sq, piece);
+        self.after_edit();
+        Some(piece)
+    }
+
+    pub fn set_side_to_move(&mut self, color: Color) {
+        self.zobrist_key ^= ZOBRIST_KEYS.color_key(self.side_to_move);
+        self.side_to_move = color;
+        self.zobrist_key ^= ZOBRIST_KEYS.color_key(self.side_to_move);
+        self.after_edit();
+    }
+
+    // Sets the castling rights, given as in FEN. Rights without the king and rook on their
+    // initial squares are dropped.
+    pub fn set_castling(&mut self, rights: &[Piece]) {
+        self.zobrist_key ^= ZOBRIST_KEYS.castling_key(self.castling_ability);
+        self.castling_ability = CastlingAbility::new(rights);
+        self.zobrist_key ^= ZOBRIST_KEYS.castling_key(self.castling_ability);
+        self.after_edit();
+    }
+
+    // Searching and checking the outcome of the game need exactly one king of each color.
+    pub fn has_kings(&self) -> bool {
+        self.count_pieces(Piece::WhiteKing) == 1 && self.count_pieces(Piece::BlackKing) == 1
+    }
+
+    pub fn apply_edit(&mut self, edit: &BoardEdit) {
+        match edit {
+            BoardEdit::Put(sq, piece) => self.put_piece(*sq, *piece),
+            BoardEdit::Remove(sq) => {
+                self.remove_piece(*sq);
+            }
+            BoardEdit::SideToMove(color) => self.set_side_to_move(*color),
+            BoardEdit::Castling(rights) => self.set_castling(rights),
+        }
+    }
+
+    // Drops the castling rights without the king and the rook on their initial squares.
+    pub(super) fn clear_impossible_castling(&mut self) {
+        self.zobrist_key ^= ZOBRIST_KEYS.castling_key(self.castling_ability);
+        for (right, king_sq, rook_sq) in CASTLING_SQUARES {
+            let color = right.get_color();
+            if self.piece_on(king_sq) != Some(Piece::get_king_of(color))
+                || self.piece_on(rook_sq) != Some(Piece::get_rook_of(color))
+            {
+                self.castling_ability.clear(rook_sq);
+            }
+        }
+        self.zobrist_key ^= ZOBRIST_KEYS.castling_key(self.castling_ability);
+    }
+
+    // The castling rights and en passant square must stay consistent with the new position.
+    fn after_edit(&mut self) {
+        self.kings = Self::gen_kings(&self.pieces);
+        self.checkers = self.attacks_king(self.side_to_move);
+        self.clear_impossible_castling();
+
+        // An en passant capture is only possible right after the double push.
+        self.zobrist_key ^= ZOBRIST_KEYS.en_passant_key(self.en_passant_target_square);
+        self.en_passant_target_square = None;
+        self.zobrist_key ^= ZOBRIST_KEYS.en_passant_key(self.en_passant_target_square);
+
+        debug_assert_eq!(self.zobrist_key, Self::gen_zobrist_key(self));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_put_remove_piece() {
+        let mut board = Board::empty();
+        board.put_piece(Square::E1, Piece::WhiteKing);
+        board.put_piece(Square::E8, Piece::BlackKing);
+        board.put_piece(Square::D4, Piece::WhiteKnight);
+        assert_eq!(board, Board::from_fen("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1"));
+
+        // Replacing a piece.
+        board.put_piece(Square::D4, Piece::BlackQueen);
+        assert_eq!(board, Board::from_fen("4k3/8/8/8/3q4/8/8/4K3 w - - 0 1"));
+        assert_eq!(board.remove_piece(Square::D4), Some(Piece::BlackQueen));
+        assert_eq!(board.remove_piece(Square::D4), None);
+        assert_eq!(board, Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert!(board.has_kings());
+        board.remove_piece(Square::E8);
+        assert!(!board.has_kings());
+    }
+
+    #[test]
+    fn test_side_and_castling() {
+        let mut board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1");
+        board.set_side_to_move(Color::Black);
+        board.set_castling(&[Piece::WhiteKing, Piece::BlackQueen]);
+        assert_eq!(board, Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b Kq - 0 1"));
+
+        // Removing a rook drops its castling right.
+        board.remove_piece(Square::A8);
+        assert_eq!(board, Board::from_fen("4k2r/8/8/8/8/8/8/R3K2R b K - 0 1"));
+        // Rights can't be given without the king and rook.
+        board.set_castling(&[Piece::WhiteQueen, Piece::BlackQueen]);
+        assert_eq!(board, Board::from_fen("4k2r/8/8/8/8/8/8/R3K2R b Q - 0 1"));
+    }
+
+    #[test]
+    fn test_edit_clears_en_passant() {
+        let mut board = Board::from_fen("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1");
+        board.put_piece(Square::A1, Piece::WhiteRook);
+        assert_eq!(board, Board::from_fen("4k3/8/8/8/3pP3/8/8/R3K3 b - - 0 1"));
+    }
+
+    #[test]
+    fn test_parse_edit() {
+        assert_eq!(
+            "put e4 N".parse(),
+            Ok(BoardEdit::Put(Square::E4, Piece::WhiteKnight))
+        );
+        assert_eq!("remove h8".parse(), Ok(BoardEdit::Remove(Square::H8)));
+        assert_eq!("side b".parse(), Ok(BoardEdit::SideToMove(Color::Black)));
+        assert_eq!(
+            "castling Kq".parse(),
+            Ok(BoardEdit::Castling(vec![
+                Piece::WhiteKing,
+                Piece::BlackQueen
+            ]))
+        );
+        assert_eq!("castling -".parse(), Ok(BoardEdit::Castling(vec![])));
+        for invalid in [
+            "put e4",
+            "put e9 N",
+            "put e4 X",
+            "side x",
+            "castling KX",
+            "swap",
+        ] {
+            assert!(invalid.parse::<BoardEdit>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Explanations of why a move is illegal, so that the console and UCI modes can tell what's
+//! wrong with a move instead of just rejecting it.
+
+use std::fmt;
+
+use crate::{
+    board::bitboard::{self, movements},
+    common::{Move, Piece, Square},
+};
+
+use super::Board;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IllegalReason {
+    // Not a move in pure notation, like "e2e4" or "e7e8q".
+    InvalidNotation,
+    NoPiece,
+    // The piece belongs to the side not to move.
+    WrongSide,
+    CapturesOwnPiece,
+    // The piece can't move to that square, even on an empty board.
+    InvalidMovement,
+    // Another piece is in the way.
+    BlockedPath,
+    MissingPromotion,
+    InvalidPromotion,
+    NoCastlingRight,
+    CastlingOutOfCheck,
+    CastlingThroughCheck,
+    LeavesKingInCheck,
+}
+
+impl fmt::Display for IllegalReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            IllegalReason::InvalidNotation => "not a move in pure notation, like e2e4 or e7e8q",
+            IllegalReason::NoPiece => "no piece on the starting square",
+            IllegalReason::WrongSide => "the piece belongs to the side not to move",
+            IllegalReason::CapturesOwnPiece => "the target square has a piece of the same side",
+            IllegalReason::InvalidMovement => "the piece doesn't move that way",
+            IllegalReason::BlockedPath => "the path is blocked",
+            IllegalReason::MissingPromotion => "the promotion piece is missing",
+            IllegalReason::InvalidPromotion => "invalid promotion",
+            IllegalReason::NoCastlingRight => "castling right lost",
+            IllegalReason::CastlingOutOfCheck => "castling out of check",
+            IllegalReason::CastlingThroughCheck => "castling through an attacked square",
+            IllegalReason::LeavesKingInCheck => "the king would be in check",
+        };
+        write!(f, "{reason}")
+    }
+}
+
+// The reason of a move of the piece of the side to move that isn't pseudo-legal.
+fn why_not_generated(mv: Move) -> IllegalReason {
+    let (from, to, piece) = (mv.get_from(), mv.get_to(), mv.get_piece());
+    let promotion_move = piece.is_pawn() && to.is_promotion_rank_for(piece.get_color());
+    match mv.get_promotion() {
+        None if promotion_move => return IllegalReason::MissingPromotion,
+        Some(promotion)
+            if !promotion_move
+                || promotion.get_color() != piece.get_color()
+                || promotion.is_pawn()
+                || promotion.is_king() =>
+        {
+            return IllegalReason::InvalidPromotion
+        }
+        _ => {}
+    }
+
+    // Would the move be possible on an empty board?
+    let from_bb = bitboard::from_square(from);
+    let reachable = match piece {
+        Piece::WhiteBishop | Piece::BlackBishop => movements::get_bishop_attacks(from_bb, 0),
+        Piece::WhiteRook | Piece::BlackRook => movements::get_rook_attacks(from_bb, 0),
+        Piece::WhiteQueen | Piece::BlackQueen => {
+            movements::get_bishop_attacks(from_bb, 0) | movements::get_rook_attacks(from_bb, 0)
+        }
+        // Pushes onto a piece, or jumping over one.
+        Piece::WhitePawn => movements::get_white_pawn_moves(from_bb, 0, 0),
+        Piece::BlackPawn => movements::get_black_pawn_moves(from_bb, 0, 0),
+        _ => 0,
+    };
+    if reachable & bitboard::from_square(to) != 0 {
+        IllegalReason::BlockedPath
+    } else {
+        IllegalReason::InvalidMovement
+    }
+}
+
+impl Board {
+    // Returns why the move is illegal, or None if it's legal.
+    pub fn why_illegal(&self, mv: Move) -> Option<IllegalReason> {
+        let (from, to) = (mv.get_from(), mv.get_to());
+        let Some(piece) = self.piece_on(from) else {
+            return Some(IllegalReason::NoPiece);
+        };
+        if piece.get_color() != self.side_to_move {
+            return Some(IllegalReason::WrongSide);
+        }
+        if piece != mv.get_piece() {
+            return Some(IllegalReason::NoPiece);
+        }
+
+        if mv.is_castling() {
+            if let Some(reason) = self.why_illegal_castling(mv) {
+                return Some(reason);
+            }
+        } else if self.all[self.side_to_move as usize] & bitboard::from_square(to) != 0 {
+            return Some(IllegalReason::CapturesOwnPiece);
+        } else if !self.generate_moves_for(&[piece]).contains(&mv) {
+            return Some(why_not_generated(mv));
+        }
+        self.copy_with_move(mv)
+            .is_none()
+            .then_some(IllegalReason::LeavesKingInCheck)
+    }
+
+    fn why_illegal_castling(&self, mv: Move) -> Option<IllegalReason> {
+        let color = self.side_to_move;
+        let king_side = mv.get_to().get_file() > mv.get_from().get_file();
+        let (has_right, path_empty) = if king_side {
+            (
+                self.castling_ability.can_castle_king_side(color),
+                movements::can_castle_king_side(self.occupied, color),
+            )
+        } else {
+            (
+                self.castling_ability.can_castle_queen_side(color),
+                movements::can_castle_queen_side(self.occupied, color),
+            )
+        };
+        if !has_right {
+            return Some(IllegalReason::NoCastlingRight);
+        }
+        if !path_empty {
+            return Some(IllegalReason::BlockedPath);
+        }
+        if self.in_check() {
+            return Some(IllegalReason::CastlingOutOfCheck);
+        }
+        // The king passes over the square the rook moves to.
+        let passed = mv.get_castling_rook_move()?.get_to();
+        (self.attacks_to(passed) & self.all[color.opposite() as usize] != 0)
+            .then_some(IllegalReason::CastlingThroughCheck)
+    }
+
+    // Parses a move in pure notation, returning why it's illegal if it is.
+    pub fn parse_pure_move(&self, s: &str) -> Result<Move, IllegalReason> {
+        let square = |range| {
+            s.get(range)
+                .and_then(|sq| Square::try_from(sq).ok())
+                .ok_or(IllegalReason::InvalidNotation)
+        };
+        let (from, to) = (square(0..2)?, square(2..4)?);
+        let promotion = match s.get(4..).map(str::to_ascii_lowercase).as_deref() {
+            Some("") => None,
+            Some(p @ ("q" | "r" | "b" | "n")) => Some(p.to_string()),
+            _ => return Err(IllegalReason::InvalidNotation),
+        };
+
+        let piece = self.piece_on(from).ok_or(IllegalReason::NoPiece)?;
+        if piece.get_color() != self.side_to_move {
+            return Err(IllegalReason::WrongSide);
+        }
+        // Checked before creating the move, which requires the promotion piece to be valid.
+        let promotion_move = piece.is_pawn() && to.is_promotion_rank_for(piece.get_color());
+        match (promotion_move, &promotion) {
+            (true, None) => return Err(IllegalReason::MissingPromotion),
+            (false, Some(_)) => return Err(IllegalReason::InvalidPromotion),
+            _ => {}
+        }
+
+        let mv = self
+            .try_new_move_from_pure(s)
+            .map_err(|_| IllegalReason::InvalidNotation)?;
+        self.why_illegal(mv).map_or(Ok(mv), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Square::*;
+    use crate::utils::fen;
+
+    fn reason(fen: &str, mv: &str) -> Result<String, IllegalReason> {
+        let board = Board::from_fen(fen);
+        board.parse_pure_move(mv).map(|mv| mv.pure().to_string())
+    }
+
+    #[test]
+    fn test_parse_pure_move() {
+        let start = fen::START_POSITION;
+        assert_eq!(reason(start, "e2e4"), Ok("e2e4".to_string()));
+        assert_eq!(reason(start, "g1f3"), Ok("g1f3".to_string()));
+        assert_eq!(reason(start, "e2"), Err(IllegalReason::InvalidNotation));
+        assert_eq!(reason(start, "e2e9"), Err(IllegalReason::InvalidNotation));
+        assert_eq!(reason(start, "e2e4x"), Err(IllegalReason::InvalidNotation));
+        assert_eq!(reason(start, "e3e4"), Err(IllegalReason::NoPiece));
+        assert_eq!(reason(start, "e7e5"), Err(IllegalReason::WrongSide));
+        assert_eq!(reason(start, "d1d2"), Err(IllegalReason::CapturesOwnPiece));
+        assert_eq!(reason(start, "e2e5"), Err(IllegalReason::InvalidMovement));
+        assert_eq!(reason(start, "g1g3"), Err(IllegalReason::InvalidMovement));
+        assert_eq!(reason(start, "e2d3"), Err(IllegalReason::InvalidMovement));
+        assert_eq!(reason(start, "f1c4"), Err(IllegalReason::BlockedPath));
+        assert_eq!(reason(start, "e1g1"), Err(IllegalReason::BlockedPath));
+        assert_eq!(reason(start, "e2e4q"), Err(IllegalReason::InvalidPromotion));
+    }
+
+    #[test]
+    fn test_pawns() {
+        let fen = "4k3/P7/8/8/8/4n3/4P3/4K3 w - - 0 1";
+        assert_eq!(reason(fen, "e2e3"), Err(IllegalReason::BlockedPath));
+        assert_eq!(reason(fen, "e2e4"), Err(IllegalReason::BlockedPath));
+        assert_eq!(reason(fen, "a7a8"), Err(IllegalReason::MissingPromotion));
+        assert_eq!(reason(fen, "a7a8N"), Ok("a7a8n".to_string()));
+    }
+
+    #[test]
+    fn test_checks() {
+        // The e2 pawn is pinned.
+        let fen = "4k3/4r3/8/8/8/8/4P3/R3K2R w KQ - 0 1";
+        assert_eq!(reason(fen, "e2e3"), Ok("e2e3".to_string()));
+        let pinned = "4k3/8/8/8/1b6/8/3P4/R3K2R w KQ - 0 1";
+        assert_eq!(
+            reason(pinned, "d2d3"),
+            Err(IllegalReason::LeavesKingInCheck)
+        );
+        assert_eq!(reason(pinned, "e1g1"), Ok("e1g1".to_string()));
+
+        let attacked = "4k3/4r3/8/8/8/8/8/R3K2R w KQ - 0 1";
+        assert_eq!(
+            reason(attacked, "e1e2"),
+            Err(IllegalReason::LeavesKingInCheck)
+        );
+        assert_eq!(
+            reason(attacked, "e1c1"),
+            Err(IllegalReason::CastlingOutOfCheck)
+        );
+        let through = "4k3/8/8/8/8/8/8/R3K2R w Q - 0 1";
+        assert_eq!(reason(through, "e1g1"), Err(IllegalReason::NoCastlingRight));
+        let through = "3rk3/8/8/8/8/8/8/R3K2R w KQ - 0 1";
+        assert_eq!(
+            reason(through, "e1c1"),
+            Err(IllegalReason::CastlingThroughCheck)
+        );
+        let into = "2r1k3/8/8/8/8/8/8/R3K2R w KQ - 0 1";
+        assert_eq!(reason(into, "e1c1"), Err(IllegalReason::LeavesKingInCheck));
+    }
+
+    #[test]
+    fn test_why_illegal() {
+        let board = Board::from_fen(fen::START_POSITION);
+        for mv in board.generate_legal_moves() {
+            assert_eq!(board.why_illegal(mv), None);
+        }
+        assert_eq!(
+            board.why_illegal(Move::quiet(E2, E4, Piece::WhiteKnight)),
+            Some(IllegalReason::NoPiece)
+        );
+        assert_eq!(
+            board.why_illegal(Move::quiet(B1, B3, Piece::WhiteKnight)),
+            Some(IllegalReason::InvalidMovement)
+        );
+    }
+}
@@ -3,9 +3,9 @@
 use super::Board;
 
 use crate::{
-    board::bitboard::{self, movements},
+    board::bitboard::{self, movements, BitBoard},
     common::Move,
-    common::{Piece, Square},
+    common::{Color, Piece, Square},
 };
 
 impl Board {
@@ -21,10 +21,32 @@ impl Board {
             && movements::can_castle_queen_side(self.occupied, side_to_move)
     }
 
+    // Finds the piece of the side not to move on that square, which must not be empty.
+    fn find_captured_piece(&self, bb: BitBoard) -> Piece {
+        let piece = self.mailbox[bitboard::get_index(bb) as usize].expect("No piece to capture");
+        debug_assert_eq!(piece.get_color(), self.opposite_side());
+        piece
+    }
+
     // Generate all possible moves from this board.
     pub fn generate_moves_for(&self, pieces: &[Piece]) -> Vec<Move> {
         // Pseudo-legal or legal ones?
 
+        let mut moves_list = self.generate_moves_to(pieces, !0);
+
+        // Castling
+        if self.can_castle_king_side() {
+            moves_list.push(Move::KING_TO_KING_SIDE_CASTLING[self.get_side_to_move() as usize]);
+        }
+        if self.can_castle_queen_side() {
+            moves_list.push(Move::KING_TO_QUEEN_SIDE_CASTLING[self.get_side_to_move() as usize]);
+        }
+
+        moves_list
+    }
+
+    // Generates the moves of these pieces ending on the target squares, and the en passant captures.
+    fn generate_moves_to(&self, pieces: &[Piece], targets: BitBoard) -> Vec<Move> {
         let mut moves_list = Vec::new();
 
         for &piece in pieces
@@ -60,12 +82,16 @@ impl Board {
                     Piece::WhiteQueen | Piece::BlackQueen => {
                         movements::get_queen_moves(from_bb, self.occupied, own_bb)
                     }
-                };
+                } & targets;
 
                 // Generate moves.
                 for to_bb in bitboard::into_iter(moves_bb) {
                     let to_square: Square = bitboard::get_index(to_bb).into();
-                    let is_capture = opposite_bb & to_bb != 0;
+                    let captured = if opposite_bb & to_bb != 0 {
+                        Some(self.find_captured_piece(to_bb))
+                    } else {
+                        None
+                    };
 
                     // Promotions
                     if piece.is_pawn() && to_square.is_promotion_rank_for(self.get_side_to_move()) {
@@ -78,12 +104,16 @@ impl Board {
                                         to_square,
                                         Some(promotion_piece),
                                         piece,
-                                        is_capture,
+                                        captured,
                                     )
                                 }),
                         );
+                    } else if piece.is_pawn()
+                        && from_square.get_rank().abs_diff(to_square.get_rank()) == 2
+                    {
+                        moves_list.push(Move::double_push(from_square, to_square, piece));
                     } else {
-                        moves_list.push(Move::new(from_square, to_square, None, piece, is_capture));
+                        moves_list.push(Move::new(from_square, to_square, None, piece, captured));
                     }
                 }
 
@@ -101,31 +131,252 @@ impl Board {
                     };
 
                     moves_list.extend(bitboard::into_iter(ep_attacks_bb).map(|to_bb| {
-                        Move::capture(from_square, bitboard::get_index(to_bb).into(), piece)
+                        Move::en_passant(from_square, bitboard::get_index(to_bb).into(), piece)
                     }));
                 }
             }
         }
 
-        // Castling
-        if self.can_castle_king_side() {
-            moves_list.push(Move::KING_TO_KING_SIDE_CASTLING[self.get_side_to_move() as usize]);
+        moves_list
+    }
+
+    // Generates the pseudo-legal moves. When in check, only the ones that may get out of it.
+    pub fn generate_moves(&self) -> Vec<Move> {
+        if self.in_check() {
+            self.generate_evasions()
+        } else {
+            self.generate_moves_for(&Piece::ALL_PIECES)
         }
-        if self.can_castle_queen_side() {
-            moves_list.push(Move::KING_TO_QUEEN_SIDE_CASTLING[self.get_side_to_move() as usize]);
+    }
+
+    // Generates the king moves, and with a single checker the moves capturing it or blocking
+    // its attack. The en passant captures are always included, as they may take the checker.
+    fn generate_evasions(&self) -> Vec<Move> {
+        let king = Piece::get_king_of(self.get_side_to_move());
+        let mut moves_list = self.generate_moves_to(&[king], !0);
+        if bitboard::reset_ls1b(self.checkers) == 0 {
+            let king_square = self.kings[self.get_side_to_move() as usize].unwrap();
+            let targets = self.checkers | self.squares_between(king_square, self.checkers);
+            // The kings are the two last pieces.
+            moves_list.extend(self.generate_moves_to(&Piece::ALL_PIECES[..10], targets));
         }
+        moves_list
+    }
 
+    // The empty squares between the king and the piece attacking it, if it's a slider.
+    fn squares_between(&self, king_square: Square, attacker: BitBoard) -> BitBoard {
+        let attacker_square: Square = bitboard::get_index(attacker).into();
+        let piece = self.find_piece_on(attacker_square);
+        if piece.is_pawn() || piece.is_knight() {
+            return 0;
+        }
+        let attacks = if king_square.get_rank() == attacker_square.get_rank()
+            || king_square.get_file() == attacker_square.get_file()
+        {
+            movements::get_rook_attacks
+        } else {
+            movements::get_bishop_attacks
+        };
+        // The lines from two squares on the same line only cross between them.
+        attacks(bitboard::from_square(king_square), self.occupied)
+            & attacks(attacker, self.occupied)
+    }
+
+    // Generate only the captures, including en passant and capturing promotions.
+    pub fn generate_captures(&self) -> Vec<Move> {
+        self.generate_moves_to(&Piece::ALL_PIECES, self.all[self.opposite_side() as usize])
+    }
+
+    // Generates the moves changing the material, for a quiescence search: The captures, and the
+    // pushes promoting to a queen. The quiet under-promotions are rarely better, so they are only
+    // generated on request. Capturing promotions are captures, generated with all the pieces.
+    pub fn generate_noisy_moves(&self, under_promotions: bool) -> Vec<Move> {
+        let mut moves_list = self.generate_captures();
+
+        let color = self.get_side_to_move();
+        let pawn = Piece::get_pawn_of(color);
+        // The pawns on the rank before the promotion one, pushed to the empty squares.
+        let pushes = match color {
+            Color::White => (self.pieces[pawn as usize] & bitboard::MASK_RANK_7) << 8,
+            Color::Black => (self.pieces[pawn as usize] & bitboard::MASK_RANK_2) >> 8,
+        } & !self.occupied;
+        let promotion_pieces = &Piece::PROMOTION_PIECES[color as usize];
+        // The queen is the first one.
+        let promotion_pieces = if under_promotions {
+            &promotion_pieces[..]
+        } else {
+            &promotion_pieces[..1]
+        };
+        for to_bb in bitboard::into_iter(pushes) {
+            let from_bb = match color {
+                Color::White => to_bb >> 8,
+                Color::Black => to_bb << 8,
+            };
+            let from_square = bitboard::get_index(from_bb).into();
+            let to_square = bitboard::get_index(to_bb).into();
+            moves_list.extend(promotion_pieces.iter().map(|&promotion_piece| {
+                Move::new(from_square, to_square, Some(promotion_piece), pawn, None)
+            }));
+        }
         moves_list
     }
 
-    pub fn generate_moves(&self) -> Vec<Move> {
+    // Generates the quiet moves giving check, which a quiescence search may try on its first ply.
+    // Captures and promotions are noisy moves, so they aren't included.
+    pub fn generate_quiet_checks(&self) -> Vec<Move> {
         self.generate_moves_for(&Piece::ALL_PIECES)
+            .into_iter()
+            .filter(|mv| !mv.is_capture() && mv.get_promotion().is_none() && self.gives_check(*mv))
+            .collect()
+    }
+
+    // Generates the moves of a quiescence search: The noisy moves, with the quiet checks if asked
+    // to, usually on its first ply only.
+    pub fn generate_quiescence_moves(&self, quiet_checks: bool) -> Vec<Move> {
+        let mut moves_list = self.generate_noisy_moves(false);
+        if quiet_checks {
+            moves_list.extend(self.generate_quiet_checks());
+        }
+        moves_list
+    }
+
+    // Counts the legal moves without generating them, for the last ply of perft.
+    // The destinations of each piece are limited to the ones keeping the king safe: Along the
+    // line of the pin for a pinned piece, and capturing or blocking the checker when in check.
+    // Castling and en passant, rarer and harder to check, are made on a copy.
+    pub fn count_legal_moves(&self) -> usize {
+        let color = self.get_side_to_move();
+        let Some(king_square) = self.kings[color as usize] else {
+            return self.generate_legal_moves().len();
+        };
+        let own_bb = self.all[color as usize];
+        let opposite_bb = self.all[self.opposite_side() as usize];
+        let king_bb = bitboard::from_square(king_square);
+
+        // Without the king, the squares behind it on the line of a checking slider are attacked.
+        let without_king = self.occupied ^ king_bb;
+        let mut count = bitboard::into_iter(movements::get_king_moves(king_bb, own_bb))
+            .filter(|&to_bb| {
+                let to_square = bitboard::get_index(to_bb).into();
+                self.attacks_to_with(to_square, without_king) & opposite_bb == 0
+            })
+            .count();
+        if self.checkers.count_ones() > 1 {
+            // Only the king can get out of a double check.
+            return count;
+        }
+
+        let targets = if self.in_check() {
+            self.checkers | self.squares_between(king_square, self.checkers)
+        } else {
+            !own_bb
+        };
+        let pinned = self.pinned(color);
+        // The kings are the two last pieces.
+        for &piece in Piece::ALL_PIECES[..10]
+            .iter()
+            .filter(|p| p.get_color() == color)
+        {
+            for from_bb in bitboard::into_iter(self.pieces[piece as usize]) {
+                let moves_bb = match piece {
+                    Piece::WhiteKnight | Piece::BlackKnight => {
+                        movements::get_knight_moves(from_bb, own_bb)
+                    }
+                    Piece::WhitePawn => {
+                        movements::get_white_pawn_moves(from_bb, self.occupied, opposite_bb)
+                    }
+                    Piece::BlackPawn => {
+                        movements::get_black_pawn_moves(from_bb, self.occupied, opposite_bb)
+                    }
+                    Piece::WhiteBishop | Piece::BlackBishop => {
+                        movements::get_bishop_moves(from_bb, self.occupied, own_bb)
+                    }
+                    Piece::WhiteRook | Piece::BlackRook => {
+                        movements::get_rook_moves(from_bb, self.occupied, own_bb)
+                    }
+                    _ => movements::get_queen_moves(from_bb, self.occupied, own_bb),
+                } & targets;
+                let moves_bb = if pinned & from_bb == 0 {
+                    moves_bb
+                } else {
+                    moves_bb & Self::pin_line(king_square, from_bb)
+                };
+                count += moves_bb.count_ones() as usize;
+                if piece.is_pawn() {
+                    // Each promotion is 4 moves.
+                    let promotions = moves_bb & (bitboard::MASK_RANK_1 | bitboard::MASK_RANK_8);
+                    count += 3 * promotions.count_ones() as usize;
+                }
+            }
+        }
+
+        // With no targets, only the en passant captures are generated.
+        let pawn = Piece::get_pawn_of(color);
+        let mut special_moves = self.generate_moves_to(&[pawn], 0);
+        if !self.in_check() {
+            if self.can_castle_king_side() {
+                special_moves.push(Move::KING_TO_KING_SIDE_CASTLING[color as usize]);
+            }
+            if self.can_castle_queen_side() {
+                special_moves.push(Move::KING_TO_QUEEN_SIDE_CASTLING[color as usize]);
+            }
+        }
+        count
+            + special_moves
+                .into_iter()
+                .filter(|mv| self.copy_with_move(*mv).is_some())
+                .count()
+    }
+
+    // The squares a pinned piece can move to: The line going through the king and the piece.
+    pub(super) fn pin_line(king_square: Square, pinned: BitBoard) -> BitBoard {
+        let pinned_square: Square = bitboard::get_index(pinned).into();
+        let attacks = if king_square.get_rank() == pinned_square.get_rank()
+            || king_square.get_file() == pinned_square.get_file()
+        {
+            movements::get_rook_attacks
+        } else {
+            movements::get_bishop_attacks
+        };
+        // On an empty board, the lines from two squares on the same line only cross on it.
+        attacks(bitboard::from_square(king_square), 0) & attacks(pinned, 0)
+    }
+
+    // Generate only the legal moves, i.e. the ones not leaving our king in check.
+    // Slower than generate_moves(), as each move needs to be applied.
+    pub fn generate_legal_moves(&self) -> Vec<Move> {
+        self.generate_moves()
+            .into_iter()
+            .filter(|mv| self.copy_with_move(*mv).is_some())
+            .collect()
+    }
+
+    // Cross-checks the legal moves counted with the pins and checks against the ones generated
+    // by playing each pseudo-legal move, for the move generation verification of the search.
+    pub fn verify_legal_moves(&self) -> Result<(), String> {
+        let counted = self.count_legal_moves();
+        let generated = self.generate_legal_moves().len();
+        if counted == generated {
+            Ok(())
+        } else {
+            Err(format!(
+                "{}: {counted} legal moves counted, {generated} generated",
+                self.as_fen()
+            ))
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{common::Piece::*, common::Square::*};
+    use itertools::Itertools;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{
+        common::Piece::*,
+        common::Square::*,
+        utils::{fen, random_game::random_game},
+    };
 
     use super::*;
     #[test]
@@ -138,7 +389,7 @@ mod tests {
                 Move::quiet(C1, B1, WhiteKing),
                 Move::quiet(C1, D1, WhiteKing),
                 Move::quiet(C1, B2, WhiteKing),
-                Move::capture(C1, D2, WhiteKing),
+                Move::capture(C1, D2, WhiteKing, BlackPawn),
             ]
         );
     }
@@ -151,7 +402,7 @@ mod tests {
             moves,
             &[
                 Move::quiet(C8, B7, BlackKing),
-                Move::capture(C8, C7, BlackKing),
+                Move::capture(C8, C7, BlackKing, WhitePawn),
                 Move::quiet(C8, B8, BlackKing),
                 Move::quiet(C8, D8, BlackKing),
             ]
@@ -190,16 +441,16 @@ mod tests {
         assert_eq!(
             moves,
             &[
-                Move::capture(B2, A3, WhitePawn),
+                Move::capture(B2, A3, WhitePawn, BlackKnight),
                 Move::quiet(B2, B3, WhitePawn),
-                Move::capture(B2, C3, WhitePawn),
-                Move::quiet(B2, B4, WhitePawn),
-                Move::capture(D2, C3, WhitePawn),
+                Move::capture(B2, C3, WhitePawn, BlackPawn),
+                Move::double_push(B2, B4, WhitePawn),
+                Move::capture(D2, C3, WhitePawn, BlackPawn),
                 Move::quiet(E2, E3, WhitePawn),
                 Move::quiet(F2, F3, WhitePawn),
-                Move::quiet(F2, F4, WhitePawn),
+                Move::double_push(F2, F4, WhitePawn),
                 Move::quiet(H2, H3, WhitePawn),
-                Move::quiet(H2, H4, WhitePawn),
+                Move::double_push(H2, H4, WhitePawn),
                 Move::quiet(G3, G4, WhitePawn),
             ]
         );
@@ -213,16 +464,16 @@ mod tests {
             moves,
             &[
                 Move::quiet(C6, C5, BlackPawn),
-                Move::quiet(B7, B5, BlackPawn),
+                Move::double_push(B7, B5, BlackPawn),
                 Move::quiet(B7, B6, BlackPawn),
-                Move::capture(C7, D6, BlackPawn),
-                Move::capture(E7, D6, BlackPawn),
+                Move::capture(C7, D6, BlackPawn, WhiteBishop),
+                Move::capture(E7, D6, BlackPawn, WhiteBishop),
                 Move::quiet(E7, E6, BlackPawn),
-                Move::quiet(F7, F5, BlackPawn),
+                Move::double_push(F7, F5, BlackPawn),
                 Move::quiet(F7, F6, BlackPawn),
-                Move::capture(F7, G6, BlackPawn),
-                Move::quiet(H7, H5, BlackPawn),
-                Move::capture(H7, G6, BlackPawn),
+                Move::capture(F7, G6, BlackPawn, WhitePawn),
+                Move::double_push(H7, H5, BlackPawn),
+                Move::capture(H7, G6, BlackPawn, WhitePawn),
                 Move::quiet(H7, H6, BlackPawn),
             ]
         );
@@ -237,13 +488,13 @@ mod tests {
         assert_eq!(
             moves,
             &[
-                Move::capture(A4, B3, BlackPawn),
-                Move::capture(C4, B3, BlackPawn),
-                Move::quiet(F7, F5, BlackPawn),
+                Move::en_passant(A4, B3, BlackPawn),
+                Move::en_passant(C4, B3, BlackPawn),
+                Move::double_push(F7, F5, BlackPawn),
                 Move::quiet(F7, F6, BlackPawn),
-                Move::quiet(G7, G5, BlackPawn),
+                Move::double_push(G7, G5, BlackPawn),
                 Move::quiet(G7, G6, BlackPawn),
-                Move::quiet(H7, H5, BlackPawn),
+                Move::double_push(H7, H5, BlackPawn),
                 Move::quiet(H7, H6, BlackPawn),
             ]
         );
@@ -251,14 +502,30 @@ mod tests {
 
     #[test]
     fn test_en_passant_attacks_2() {
+        // En passant would leave the king in check, so the square is dropped when loading the FEN.
         let board: Board = "8/8/8/3k4/2pP4/1B6/6K1/8 b - d3 0 2".into();
         let moves = board.generate_moves_for(&[BlackPawn]);
         assert_eq!(
             moves,
             &[
-                Move::capture(C4, B3, BlackPawn),
+                Move::capture(C4, B3, BlackPawn, WhiteBishop),
                 Move::quiet(C4, C3, BlackPawn), // Push, leaves the king in check.
-                Move::capture(C4, D3, BlackPawn), // En passant, leaves the king in check.
+            ]
+        );
+    }
+
+    #[test]
+    fn test_generate_legal_moves() {
+        // Pinned knight, only king moves are legal.
+        let board: Board = "4k3/8/8/8/4r3/8/4N3/4K3 w - - 0 1".into();
+        assert_eq!(board.generate_moves().len(), 10);
+        assert_eq!(
+            board.generate_legal_moves(),
+            &[
+                Move::quiet(E1, D1, WhiteKing),
+                Move::quiet(E1, F1, WhiteKing),
+                Move::quiet(E1, D2, WhiteKing),
+                Move::quiet(E1, F2, WhiteKing),
             ]
         );
     }
@@ -272,9 +539,185 @@ mod tests {
             &[
                 Move::quiet(E1, F1, WhiteKing),
                 Move::quiet(E1, D2, WhiteKing),
-                Move::capture(E1, F2, WhiteKing),
-                Move::quiet(E1, G1, WhiteKing),
+                Move::capture(E1, F2, WhiteKing, BlackKnight),
+                Move::KING_TO_KING_SIDE_CASTLING[0],
             ]
         );
     }
+
+    #[test]
+    fn test_generate_captures() {
+        for fen in [
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            let board: Board = fen.into();
+            let captures: Vec<Move> = board
+                .generate_moves()
+                .into_iter()
+                .filter(|mv| mv.is_capture())
+                .collect();
+            assert_eq!(board.generate_captures(), captures, "{fen}");
+        }
+
+        // Capturing promotions and en passant.
+        let board: Board = "1n5k/P7/8/3pP3/8/8/8/7K w - d6 0 1".into();
+        assert_eq!(board.generate_captures().len(), 5);
+    }
+
+    #[test]
+    fn test_generate_noisy_moves() {
+        let pure = |moves: Vec<Move>| -> Vec<String> {
+            moves
+                .iter()
+                .map(|mv| mv.pure().to_string())
+                .sorted()
+                .collect()
+        };
+        // Pushes to the last rank.
+        let board: Board = "7k/P7/8/8/8/8/8/K7 w - - 0 1".into();
+        assert_eq!(pure(board.generate_noisy_moves(false)), ["a7a8q"]);
+        assert_eq!(
+            pure(board.generate_noisy_moves(true)),
+            ["a7a8b", "a7a8n", "a7a8q", "a7a8r"]
+        );
+        let board: Board = "7k/8/8/8/8/8/5p2/K7 b - - 0 1".into();
+        assert_eq!(pure(board.generate_noisy_moves(false)), ["f2f1q"]);
+
+        // The blocked pawn can only promote by capturing, to any piece.
+        let board: Board = "nn5k/P7/8/8/8/8/8/K7 w - - 0 1".into();
+        assert_eq!(
+            pure(board.generate_noisy_moves(false)),
+            ["a7b8b", "a7b8n", "a7b8q", "a7b8r"]
+        );
+        // Pawns on other ranks don't promote.
+        let board: Board = "7k/8/P7/8/8/8/6p1/K7 w - - 0 1".into();
+        assert!(board.generate_noisy_moves(true).is_empty());
+    }
+
+    #[test]
+    fn test_generate_quiet_checks() {
+        let board: Board = "7k/8/8/8/8/8/1NR5/B3K3 w - - 0 1".into();
+        let checks = board
+            .generate_quiet_checks()
+            .iter()
+            .map(|mv| mv.pure().to_string())
+            .sorted()
+            .collect_vec();
+        // Every knight move discovers a check, the rook checks along the rank and the file.
+        assert_eq!(checks, ["b2a4", "b2c4", "b2d1", "b2d3", "c2c8", "c2h2"]);
+
+        let board: Board = "4k3/8/8/8/8/8/7p/R3K2R w - - 0 1".into();
+        // Taking the pawn, and the rook check.
+        assert_eq!(board.generate_quiescence_moves(false).len(), 1);
+        assert_eq!(board.generate_quiescence_moves(true).len(), 2);
+    }
+
+    #[test]
+    fn test_noisy_moves_random_positions() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for fen in [
+            fen::POSITION_4,
+            fen::KIWIPETE,
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ] {
+            for board in random_game(Board::from_fen(fen), 60, &mut rng) {
+                for under_promotions in [false, true] {
+                    // Captures aren't limited to the evasions when in check.
+                    let noisy: Vec<Move> = board
+                        .generate_moves_for(&Piece::ALL_PIECES)
+                        .into_iter()
+                        .filter(|mv| {
+                            mv.is_capture()
+                                || mv
+                                    .get_promotion()
+                                    .is_some_and(|piece| under_promotions || piece.is_queen())
+                        })
+                        .collect();
+                    assert_eq!(
+                        board
+                            .generate_noisy_moves(under_promotions)
+                            .iter()
+                            .map(|mv| mv.pure().to_string())
+                            .sorted()
+                            .collect_vec(),
+                        noisy
+                            .iter()
+                            .map(|mv| mv.pure().to_string())
+                            .sorted()
+                            .collect_vec(),
+                        "{}",
+                        board.as_fen()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_evasions() {
+        for (fen, moves_count) in [
+            // Single check by a rook: King moves, capture or block.
+            ("6k1/8/8/1B6/8/8/8/R3K2r w Q - 0 1", 4),
+            // Double check: Only the king can move.
+            ("6k1/8/8/1B6/8/5n2/8/R3K2r w Q - 0 1", 2),
+            // The checking pawn can be taken en passant.
+            ("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1", 9),
+        ] {
+            let board: Board = fen.into();
+            assert!(board.in_check());
+            let moves = board.generate_legal_moves();
+            assert_eq!(moves.len(), moves_count, "{fen}");
+            // No legal move is missed.
+            let all_moves = board.generate_moves_for(&Piece::ALL_PIECES);
+            let legal_count = all_moves
+                .iter()
+                .filter(|&&mv| board.copy_with_move(mv).is_some())
+                .count();
+            assert_eq!(legal_count, moves_count, "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_count_legal_moves() {
+        for fen in [
+            fen::START_POSITION,
+            fen::KIWIPETE,
+            fen::POSITION_3,
+            fen::POSITION_4,
+            fen::POSITION_5,
+            fen::POSITION_6,
+            // Pinned pieces, on a line and on a diagonal.
+            "4k3/4r3/8/8/1b6/8/3PR3/4K3 w - - 0 1",
+            // The en passant capture would expose the king on the rank.
+            "8/8/8/K2Pp2r/8/8/8/4k3 w - e6 0 1",
+            // Checks, the double one and the one of a pawn taken en passant.
+            "6k1/8/8/1B6/8/5n2/8/R3K2r w Q - 0 1",
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+        ] {
+            let board = Board::from_fen(fen);
+            assert_eq!(
+                board.count_legal_moves(),
+                board.generate_legal_moves().len(),
+                "{fen}"
+            );
+        }
+
+        assert_eq!(Board::from_fen(fen::KIWIPETE).verify_legal_moves(), Ok(()));
+
+        let mut rng = StdRng::seed_from_u64(5);
+        for fen in [fen::START_POSITION, fen::KIWIPETE, fen::POSITION_4] {
+            for _ in 0..20 {
+                for board in random_game(Board::from_fen(fen), 100, &mut rng) {
+                    assert_eq!(
+                        board.count_legal_moves(),
+                        board.generate_legal_moves().len(),
+                        "{}",
+                        board.as_fen()
+                    );
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,125 @@
+//! End of the game according to the rules: Mate, stalemate, repetition, fifty moves
+//! and insufficient material.
+
+use crate::common::{Color, Piece};
+
+use super::Board;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl Outcome {
+    pub fn as_pgn_result(self) -> &'static str {
+        match self {
+            Outcome::WhiteWins => "1-0",
+            Outcome::BlackWins => "0-1",
+            Outcome::Draw => "1/2-1/2",
+        }
+    }
+
+    pub fn win_for(color: Color) -> Self {
+        match color {
+            Color::White => Outcome::WhiteWins,
+            Color::Black => Outcome::BlackWins,
+        }
+    }
+}
+
+impl Board {
+    // Only kings and at most one minor piece: Nobody can mate.
+    fn has_insufficient_material(&self) -> bool {
+        let count =
+            |pieces: &[Piece]| -> u32 { pieces.iter().map(|p| self.count_pieces(*p)).sum() };
+        count(&[
+            Piece::WhitePawn,
+            Piece::BlackPawn,
+            Piece::WhiteRook,
+            Piece::BlackRook,
+            Piece::WhiteQueen,
+            Piece::BlackQueen,
+        ]) == 0
+            && count(&[
+                Piece::WhiteKnight,
+                Piece::BlackKnight,
+                Piece::WhiteBishop,
+                Piece::BlackBishop,
+            ]) <= 1
+    }
+
+    // Checks if the game is over, returning the outcome and the reason.
+    // The history contains the Zobrist keys of the previous positions.
+    pub fn game_over(&self, history: &[u64]) -> Option<(Outcome, &'static str)> {
+        if self.generate_legal_moves().is_empty() {
+            return Some(if self.in_check() {
+                (Outcome::win_for(self.opposite_side()), "checkmate")
+            } else {
+                (Outcome::Draw, "stalemate")
+            });
+        }
+        if self.get_half_move_clock() >= 100 {
+            return Some((Outcome::Draw, "fifty moves rule"));
+        }
+        let key = self.get_zobrist_key();
+        if history.iter().filter(|k| **k == key).count() >= 2 {
+            return Some((Outcome::Draw, "threefold repetition"));
+        }
+        if self.has_insufficient_material() {
+            return Some((Outcome::Draw, "insufficient material"));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_game_over() {
+        assert_eq!(Board::initial_board().game_over(&[]), None);
+
+        let board: Board = "6k1/5ppp/8/8/8/8/8/R5K1 b - - 0 1".into();
+        assert_eq!(board.game_over(&[]), None);
+        let board: Board = "R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1".into();
+        assert_eq!(
+            board.game_over(&[]),
+            Some((Outcome::WhiteWins, "checkmate"))
+        );
+
+        let board: Board = "4k3/4P3/4Q3/8/8/8/8/5K2 b - - 0 1".into();
+        assert_eq!(board.game_over(&[]), Some((Outcome::Draw, "stalemate")));
+
+        let board: Board = "4k3/8/8/8/8/8/8/R3K3 w - - 100 80".into();
+        assert_eq!(
+            board.game_over(&[]),
+            Some((Outcome::Draw, "fifty moves rule"))
+        );
+
+        let board: Board = "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1".into();
+        assert_eq!(
+            board.game_over(&[]),
+            Some((Outcome::Draw, "insufficient material"))
+        );
+    }
+
+    #[test]
+    fn test_repetition() {
+        let mut board = Board::initial_board();
+        let mut history = Vec::new();
+        for _ in 0..2 {
+            for mv in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                assert_eq!(board.game_over(&history), None);
+                history.push(board.get_zobrist_key());
+                board.update_by_move(board.new_move_from_pure(mv));
+            }
+        }
+        assert_eq!(
+            board.game_over(&history),
+            Some((Outcome::Draw, "threefold repetition"))
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! Standard Algebraic Notation (SAN).
+//! <https://www.chessprogramming.org/Algebraic_Chess_Notation#Standard_Algebraic_Notation_.28SAN.29>
+//! Unlike the pure or LAN formats, SAN requires the board to be created or parsed:
+//! Disambiguation and check indications depend on the position.
+//! LAN can get the check indications too, with `Move::annotated()`.
+
+use itertools::Itertools;
+
+use crate::common::{Color, Flag, Move, Piece};
+
+use super::Board;
+
+impl Board {
+    // Formats the move in SAN. The move must be legal on this board.
+    pub fn move_to_san(&self, mv: Move) -> String {
+        assert!(
+            self.copy_with_move(mv).is_some(),
+            "SAN can only be created for legal moves"
+        );
+        self.move_to_san_without_check(mv) + self.check_marker(mv)
+    }
+
+    // "+" if the move gives check, "#" if it mates, empty otherwise or if the move is illegal.
+    pub fn check_marker(&self, mv: Move) -> &'static str {
+        // Most moves don't give check, no need to make them.
+        if !self.gives_check(mv) {
+            return "";
+        }
+        match self.copy_with_move(mv) {
+            Some(board_after) => {
+                if board_after.generate_legal_moves().is_empty() {
+                    "#"
+                } else {
+                    "+"
+                }
+            }
+            _ => "",
+        }
+    }
+
+    fn move_to_san_without_check(&self, mv: Move) -> String {
+        match mv.get_flag() {
+            Flag::KingSideCastling => return "O-O".to_string(),
+            Flag::QueenSideCastling => return "O-O-O".to_string(),
+            _ => {}
+        }
+
+        let piece = mv.get_piece();
+        let mut san = String::new();
+        if piece.is_pawn() {
+            if mv.is_capture() {
+                san.push(file_char(mv.get_from().get_file()));
+            }
+        } else {
+            san.push(piece_char(piece));
+
+            // Other pieces of the same type that can legally go to the same square.
+            let ambiguous = self
+                .generate_legal_moves()
+                .into_iter()
+                .filter(|m| {
+                    m.get_piece() == piece
+                        && m.get_to() == mv.get_to()
+                        && m.get_from() != mv.get_from()
+                })
+                .collect_vec();
+            if !ambiguous.is_empty() {
+                let from = mv.get_from();
+                if ambiguous
+                    .iter()
+                    .all(|m| m.get_from().get_file() != from.get_file())
+                {
+                    san.push(file_char(from.get_file()));
+                } else if ambiguous
+                    .iter()
+                    .all(|m| m.get_from().get_rank() != from.get_rank())
+                {
+                    san.push(rank_char(from.get_rank()));
+                } else {
+                    san.push_str(&from.to_string());
+                }
+            }
+        }
+
+        if mv.is_capture() {
+            san.push('x');
+        }
+        san.push_str(&mv.get_to().to_string());
+
+        if let Some(promotion) = mv.get_promotion() {
+            san.push('=');
+            san.push(piece_char(promotion));
+        }
+        san
+    }
+
+    // Parses a move in SAN. Returns None if the move is invalid or illegal on this board.
+    // Check indications and annotations (!, ?) are ignored.
+    pub fn new_move_from_san(&self, s: &str) -> Option<Move> {
+        let wanted = normalize_san(s);
+        self.generate_legal_moves()
+            .into_iter()
+            .find(|mv| normalize_san(&self.move_to_san_without_check(*mv)) == wanted)
+    }
+
+    // Formats a sequence of moves starting from this board as SAN with move numbers,
+    // for example "1. e4 e5 2. Nf3" or "3... Nc6 4. Bb5".
+    pub fn format_moves_as_san_string(&self, moves: &[Move]) -> String {
+        let mut board = *self;
+        let mut result = Vec::new();
+        for (i, mv) in moves.iter().enumerate() {
+            let move_number = board.full_move_counter;
+            if board.get_side_to_move() == Color::White {
+                result.push(format!("{move_number}. {}", board.move_to_san(*mv)));
+            } else if i == 0 {
+                result.push(format!("{move_number}... {}", board.move_to_san(*mv)));
+            } else {
+                result.push(board.move_to_san(*mv));
+            }
+            board.update_by_move(*mv);
+        }
+        result.join(" ")
+    }
+}
+
+impl Move {
+    // The move in LAN with its check marker, like "QD1xF7#".
+    pub fn annotated(self, board: &Board) -> String {
+        format!("{self}{}", board.check_marker(self))
+    }
+}
+
+fn piece_char(piece: Piece) -> char {
+    char::from(piece).to_ascii_uppercase()
+}
+
+fn file_char(file: u8) -> char {
+    (b'a' + file) as char
+}
+
+fn rank_char(rank: u8) -> char {
+    (b'1' + rank) as char
+}
+
+// Removes the parts of a SAN string that are not needed to identify the move.
+fn normalize_san(s: &str) -> String {
+    s.trim()
+        .replace('0', "O")
+        .chars()
+        .filter(|c| !matches!(c, '+' | '#' | '!' | '?' | '='))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{Piece::*, Square::*};
+
+    use super::*;
+
+    #[test]
+    fn test_move_to_san() {
+        let board = Board::initial_board();
+        assert_eq!(
+            board.move_to_san(Move::double_push(E2, E4, WhitePawn)),
+            "e4"
+        );
+        assert_eq!(board.move_to_san(Move::quiet(G1, F3, WhiteKnight)), "Nf3");
+
+        let board: Board = "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2".into();
+        assert_eq!(
+            board.move_to_san(Move::capture(E4, D5, WhitePawn, BlackPawn)),
+            "exd5"
+        );
+    }
+
+    #[test]
+    fn test_move_to_san_castling() {
+        let board: Board = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1".into();
+        assert_eq!(
+            board.move_to_san(Move::KING_TO_KING_SIDE_CASTLING[0]),
+            "O-O"
+        );
+        assert_eq!(
+            board.move_to_san(Move::KING_TO_QUEEN_SIDE_CASTLING[0]),
+            "O-O-O"
+        );
+    }
+
+    #[test]
+    fn test_move_to_san_disambiguation() {
+        let board: Board = "4k3/8/8/8/8/8/4K3/R6R w - - 0 1".into();
+        assert_eq!(board.move_to_san(Move::quiet(A1, D1, WhiteRook)), "Rad1");
+
+        let board: Board = "4k3/8/R7/8/8/8/8/R3K3 w - - 0 1".into();
+        assert_eq!(board.move_to_san(Move::quiet(A1, A3, WhiteRook)), "R1a3");
+
+        let board: Board = "k7/8/8/8/8/2Q1Q3/8/2Q1K3 w - - 0 1".into();
+        assert_eq!(board.move_to_san(Move::quiet(C3, D2, WhiteQueen)), "Qc3d2");
+    }
+
+    #[test]
+    fn test_move_to_san_promotion_and_checks() {
+        let board: Board = "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1".into();
+        assert_eq!(
+            board.move_to_san(Move::new(B7, B8, Some(WhiteQueen), WhitePawn, None)),
+            "b8=Q+"
+        );
+        assert_eq!(
+            board.move_to_san(Move::new(B7, B8, Some(WhiteKnight), WhitePawn, None)),
+            "b8=N"
+        );
+
+        let board: Board = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1".into();
+        assert_eq!(board.move_to_san(Move::quiet(A1, A8, WhiteRook)), "Ra8#");
+    }
+
+    #[test]
+    fn test_annotated() {
+        let board: Board = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1".into();
+        assert_eq!(Move::quiet(A1, A8, WhiteRook).annotated(&board), "RA1-A8#");
+        assert_eq!(Move::quiet(A1, A7, WhiteRook).annotated(&board), "RA1-A7");
+
+        let board: Board = "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1".into();
+        let promotion = Move::new(B7, B8, Some(WhiteQueen), WhitePawn, None);
+        assert_eq!(promotion.annotated(&board), "B7-B8Q+");
+
+        // Illegal moves have no marker.
+        let board: Board = "4k3/8/8/8/8/8/3r4/4K3 w - - 0 1".into();
+        assert_eq!(Move::quiet(E1, E2, WhiteKing).annotated(&board), "KE1-E2");
+    }
+
+    #[test]
+    fn test_new_move_from_san() {
+        let board = Board::initial_board();
+        assert_eq!(
+            board.new_move_from_san("e4"),
+            Some(Move::double_push(E2, E4, WhitePawn))
+        );
+        assert_eq!(
+            board.new_move_from_san("Nf3!"),
+            Some(Move::quiet(G1, F3, WhiteKnight))
+        );
+        assert_eq!(board.new_move_from_san("e5"), None);
+        assert_eq!(board.new_move_from_san("Ke2"), None);
+
+        let board: Board = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1".into();
+        assert_eq!(
+            board.new_move_from_san("0-0-0"),
+            Some(Move::KING_TO_QUEEN_SIDE_CASTLING[1])
+        );
+    }
+
+    #[test]
+    fn test_format_moves_as_san_string() {
+        let board = Board::initial_board();
+        let moves = [
+            Move::double_push(E2, E4, WhitePawn),
+            Move::double_push(E7, E5, BlackPawn),
+            Move::quiet(G1, F3, WhiteKnight),
+        ];
+        assert_eq!(board.format_moves_as_san_string(&moves), "1. e4 e5 2. Nf3");
+
+        let board: Board = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1".into();
+        let moves = [
+            Move::double_push(E7, E5, BlackPawn),
+            Move::quiet(G1, F3, WhiteKnight),
+        ];
+        assert_eq!(board.format_moves_as_san_string(&moves), "1... e5 2. Nf3");
+    }
+}
@@ -0,0 +1,142 @@
+//! Static Exchange Evaluation (SEE): The material won or lost by a capture once all the
+//! recaptures on that square are played, without searching.
+//! <https://www.chessprogramming.org/Static_Exchange_Evaluation>
+//! <https://www.chessprogramming.org/SEE_-_The_Swap_Algorithm>
+
+use crate::{
+    board::bitboard::{self, BitBoard},
+    common::{Color, Move, Piece, Score, Square},
+};
+
+use super::Board;
+
+impl Board {
+    // Returns the material balance of the exchange started by this move, from the point of
+    // view of the side to move. Each side recaptures with its least valuable attacker, and
+    // can stop the exchange when continuing would lose material.
+    // Pins and promotions on recaptures are ignored.
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn see(&self, mv: Move, piece_values: &[u32; 6]) -> Score {
+        let value = |piece: Piece| piece_values[piece as usize / 2] as Score;
+        let to = mv.get_to();
+
+        // gain[d] is the balance for the side making the d-th capture, if the exchange stopped there.
+        let mut gain = [0; 32];
+        let mut depth = 0;
+        gain[0] = mv.get_captured().map_or(0, value);
+        let mut attacker = mv.get_piece();
+        if let Some(promotion) = mv.get_promotion() {
+            gain[0] += value(promotion) - value(attacker);
+            attacker = promotion;
+        }
+
+        let mut occupied = self.occupied & !bitboard::from_square(mv.get_from());
+        if mv.is_en_passant() {
+            let captured_square = Square::new(mv.get_from().get_rank(), to.get_file());
+            occupied &= !bitboard::from_square(captured_square);
+        }
+        let mut side = self.opposite_side();
+
+        // The swap list isn't pruned, as it would only keep the sign of the result exact.
+        while depth + 1 < gain.len() {
+            let attackers = self.attacks_to_with(to, occupied) & occupied;
+            let Some((piece, from_bb)) = self.least_valuable_attacker(attackers, side) else {
+                break;
+            };
+            depth += 1;
+            // Capturing the previous attacker.
+            gain[depth] = value(attacker) - gain[depth - 1];
+            occupied &= !from_bb;
+            attacker = piece;
+            side = side.opposite();
+        }
+
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+        gain[0]
+    }
+
+    fn least_valuable_attacker(
+        &self,
+        attackers: BitBoard,
+        side: Color,
+    ) -> Option<(Piece, BitBoard)> {
+        Piece::ALL_PIECES
+            .into_iter()
+            .skip(side as usize)
+            .step_by(2)
+            .find_map(|piece| {
+                let bb = self.pieces[piece as usize] & attackers;
+                (bb != 0).then(|| (piece, bitboard::get_ls1b(bb)))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        common::{Piece::*, Square::*},
+        engine::eval::PIECE_VALUES,
+    };
+
+    use super::*;
+
+    fn see(fen: &str, mv: Move) -> Score {
+        Board::from_fen(fen).see(mv, &PIECE_VALUES)
+    }
+
+    #[test]
+    fn test_see_undefended() {
+        // Examples from <https://www.chessprogramming.org/SEE_-_The_Swap_Algorithm>
+        let mv = Move::capture(E1, E5, WhiteRook, BlackPawn);
+        assert_eq!(
+            see("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", mv),
+            100
+        );
+    }
+
+    #[test]
+    fn test_see_defended() {
+        // The knight takes a pawn defended by a pawn.
+        let mv = Move::capture(D3, E5, WhiteKnight, BlackPawn);
+        assert_eq!(
+            see(
+                "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+                mv
+            ),
+            100 - 320
+        );
+    }
+
+    #[test]
+    fn test_see_x_ray() {
+        // The rook behind the queen supports it, but the queen is worth more than the pawn.
+        let mv = Move::capture(D2, D5, WhiteQueen, BlackPawn);
+        assert_eq!(
+            see("3r2k1/8/8/3p4/8/8/3Q4/3R2K1 w - - 0 1", mv),
+            100 - 900 + 500
+        );
+        // Doubled rooks against a rook and a queen: The pawn is defended enough.
+        let mv = Move::capture(D2, D5, WhiteRook, BlackPawn);
+        assert_eq!(
+            see("3q2k1/3r4/8/3p4/8/8/3R4/3R2K1 w - - 0 1", mv),
+            100 - 500
+        );
+    }
+
+    #[test]
+    fn test_see_king_recapture() {
+        // The king can't recapture a defended piece.
+        let mv = Move::capture(B2, B7, WhiteRook, BlackPawn);
+        assert_eq!(see("8/1pk5/8/8/8/8/1R6/1R4K1 w - - 0 1", mv), 100);
+        assert_eq!(see("8/1pk5/8/8/8/8/1R6/6K1 w - - 0 1", mv), 100 - 500);
+    }
+
+    #[test]
+    fn test_see_en_passant() {
+        let mv = Move::en_passant(E5, D6, WhitePawn);
+        assert_eq!(see("4k3/2p5/8/3pP3/8/8/8/4K3 w - d6 0 1", mv), 0);
+    }
+}
@@ -1,7 +1,7 @@
 //! Board update by move.
 
 use crate::{
-    board::bitboard::{self, BitBoard},
+    board::bitboard::{self, movements, BitBoard},
     common::{Color, Move, Piece},
 };
 
@@ -19,15 +19,18 @@ impl Board {
         self.pieces[mv.get_piece() as usize] ^= from_to_bb;
         self.all[color as usize] ^= from_to_bb;
         self.occupied ^= from_to_bb;
+        self.mailbox[mv.get_from() as usize] = None;
+        self.mailbox[mv.get_to() as usize] = Some(mv.get_piece());
+        if mv.get_piece().is_king() {
+            self.kings[mv.get_piece().get_color() as usize] = Some(mv.get_to());
+        }
 
         self.zobrist_key ^= ZOBRIST_KEYS.piece_key(mv.get_from(), mv.get_piece());
         self.zobrist_key ^= ZOBRIST_KEYS.piece_key(mv.get_to(), mv.get_piece());
 
-        if mv.is_capture() {
+        if let Some(piece_captured) = mv.get_captured() {
             // If we are trying to move into the en-passant square, we need to correct the square we will clear.
-            let to_bb_capture = if mv.get_piece().is_pawn()
-                && matches!(self.en_passant_target_square, Some(sq) if sq == mv.get_to())
-            {
+            let to_bb_capture = if mv.is_en_passant() {
                 if color == Color::White {
                     to_bb >> 8
                 } else {
@@ -36,28 +39,18 @@ impl Board {
             } else {
                 to_bb
             };
+            debug_assert_ne!(self.pieces[piece_captured as usize] & to_bb_capture, 0);
 
-            // Loop over bitboards opposite color.
-            for (piece_idx, bb) in self
-                .pieces
-                .iter_mut()
-                .enumerate()
-                .skip(color.opposite() as usize)
-                .step_by(2)
-            {
-                if *bb & to_bb_capture != 0 {
-                    // Remove the captured piece.
-                    *bb ^= to_bb_capture;
-                    self.all[color.opposite() as usize] ^= to_bb_capture;
-                    self.occupied ^= to_bb_capture;
-
-                    let captured_square = bitboard::get_index(to_bb_capture).into();
-                    let piece_captured = Piece::ALL_PIECES[piece_idx];
-                    self.zobrist_key ^= ZOBRIST_KEYS.piece_key(captured_square, piece_captured);
-
-                    break;
-                }
+            // Remove the captured piece.
+            self.pieces[piece_captured as usize] ^= to_bb_capture;
+            self.all[color.opposite() as usize] ^= to_bb_capture;
+            self.occupied ^= to_bb_capture;
+
+            let captured_square = bitboard::get_index(to_bb_capture).into();
+            if mv.is_en_passant() {
+                self.mailbox[captured_square as usize] = None;
             }
+            self.zobrist_key ^= ZOBRIST_KEYS.piece_key(captured_square, piece_captured);
         }
 
         self.zobrist_key ^= ZOBRIST_KEYS.castling_key(self.castling_ability);
@@ -75,15 +68,12 @@ impl Board {
             let to_bb: BitBoard = bitboard::from_square(mv.get_to());
             self.pieces[mv.get_piece() as usize] &= !to_bb;
             self.pieces[promote_to as usize] |= to_bb;
+            self.mailbox[mv.get_to() as usize] = Some(promote_to);
 
             self.zobrist_key ^= ZOBRIST_KEYS.piece_key(mv.get_to(), mv.get_piece());
             self.zobrist_key ^= ZOBRIST_KEYS.piece_key(mv.get_to(), promote_to);
         }
 
-        self.zobrist_key ^= ZOBRIST_KEYS.en_passant_key(self.en_passant_target_square);
-        self.en_passant_target_square = mv.get_en_passant_target_square();
-        self.zobrist_key ^= ZOBRIST_KEYS.en_passant_key(self.en_passant_target_square);
-
         if let Some(castling_rook_move) = mv.get_castling_rook_move() {
             self.update_bitboards_by_move(castling_rook_move);
         }
@@ -102,9 +92,71 @@ impl Board {
         self.zobrist_key ^= ZOBRIST_KEYS.color_key(self.get_side_to_move());
         self.side_to_move = self.side_to_move.opposite();
         self.zobrist_key ^= ZOBRIST_KEYS.color_key(self.get_side_to_move());
+        self.checkers = self.attacks_king(self.side_to_move);
 
-        // Checking that the Zobrist key was correctly updated (debug builds only).
+        // Set after toggling the side, as we need to check if the new side to move can capture.
+        self.zobrist_key ^= ZOBRIST_KEYS.en_passant_key(self.en_passant_target_square);
+        self.en_passant_target_square = mv.get_en_passant_target_square();
+        self.zobrist_key ^= ZOBRIST_KEYS.en_passant_key(self.en_passant_target_square);
+        self.clear_unusable_en_passant();
+
+        // Checking that the Zobrist key, the mailbox and the kings were correctly updated
+        // (debug builds only).
         debug_assert_eq!(self.zobrist_key, Self::gen_zobrist_key(self));
+        debug_assert_eq!(self.mailbox, Self::gen_mailbox(&self.pieces));
+        debug_assert_eq!(self.kings, Self::gen_kings(&self.pieces));
+    }
+
+    // Passes the turn to the other side, as the "0000" move of UCI.
+    // It's not legal when in check, as the king could then be captured.
+    pub fn make_null_move(&mut self) {
+        debug_assert!(!self.in_check());
+        if self.side_to_move == Color::Black {
+            self.full_move_counter += 1;
+        }
+        self.half_move_clock += 1;
+
+        self.zobrist_key ^= ZOBRIST_KEYS.color_key(self.get_side_to_move());
+        self.side_to_move = self.side_to_move.opposite();
+        self.zobrist_key ^= ZOBRIST_KEYS.color_key(self.get_side_to_move());
+        // We weren't in check, and the other side can't be in check on our turn.
+        self.checkers = 0;
+
+        // The pawn that could have been taken en passant is safe now.
+        self.zobrist_key ^= ZOBRIST_KEYS.en_passant_key(self.en_passant_target_square);
+        self.en_passant_target_square = None;
+        self.zobrist_key ^= ZOBRIST_KEYS.en_passant_key(self.en_passant_target_square);
+
+        debug_assert_eq!(self.zobrist_key, Self::gen_zobrist_key(self));
+    }
+
+    // Removes the en passant square if no pawn can legally capture on it.
+    // Otherwise positions that differ only by an unusable en passant square would have different
+    // keys, breaking transpositions and repetition detection.
+    // The Zobrist key must be up to date when calling this.
+    pub fn clear_unusable_en_passant(&mut self) {
+        let Some(square) = self.en_passant_target_square else {
+            return;
+        };
+        let side = self.get_side_to_move();
+        let pawn = Piece::get_pawn_of(side);
+        let square_bb = bitboard::from_square(square);
+        // Our pawns attacking the square are on the squares an enemy pawn there would attack.
+        let attackers = if side == Color::White {
+            movements::get_black_pawn_attacks(square_bb)
+        } else {
+            movements::get_white_pawn_attacks(square_bb)
+        } & self.pieces[pawn as usize];
+        let can_capture = bitboard::into_iter(attackers).any(|from_bb| {
+            let from = bitboard::get_index(from_bb).into();
+            self.copy_with_move(Move::en_passant(from, square, pawn))
+                .is_some()
+        });
+        if !can_capture {
+            self.zobrist_key ^= ZOBRIST_KEYS.en_passant_key(self.en_passant_target_square);
+            self.en_passant_target_square = None;
+            self.zobrist_key ^= ZOBRIST_KEYS.en_passant_key(self.en_passant_target_square);
+        }
     }
 
     // Applies the move to self and returns a new board.
@@ -123,7 +175,7 @@ impl Board {
 
         if let Some(rook_mv) = mv.get_castling_rook_move() {
             // We are not allowed to be in check before the castling.
-            if self.attacks_king(king_color) != 0 {
+            if self.in_check() {
                 return None;
             }
 
@@ -169,7 +221,7 @@ mod tests {
             board.to_string(),
             "r1bqkb1r/pppppppp/5n2/8/1n6/1P3N2/PBPPPPPP/RN1QKB1R w KQkq - 5 4"
         );
-        board.update_by_move(Move::capture(B2, F6, WhiteBishop));
+        board.update_by_move(Move::capture(B2, F6, WhiteBishop, BlackKnight));
         assert_eq!(
             board.to_string(),
             "r1bqkb1r/pppppppp/5B2/8/1n6/1P3N2/P1PPPPPP/RN1QKB1R b KQkq - 0 4"
@@ -179,13 +231,13 @@ mod tests {
     #[test]
     fn test_update_by_move_capture() {
         let mut board: Board = "2k5/8/8/8/8/8/2Pp4/2K5 w - - 3 1".into();
-        let mv = Move::capture(C1, D2, WhiteKing);
+        let mv = Move::capture(C1, D2, WhiteKing, BlackPawn);
         board.update_by_move(mv);
         assert_eq!(board.to_string(), "2k5/8/8/8/8/8/2PK4/8 b - - 0 1");
 
         let mut board: Board =
             "rnbqkbnr/ppp1pppp/8/3p4/8/2N5/PPPPPPPP/R1BQKBNR w KQkq - 4 1".into();
-        let mv = Move::capture(C3, D5, WhiteKnight);
+        let mv = Move::capture(C3, D5, WhiteKnight, BlackPawn);
         board.update_by_move(mv);
         assert_eq!(
             board.to_string(),
@@ -196,7 +248,7 @@ mod tests {
     #[test]
     fn test_update_by_move_capture_2() {
         let mut board: Board = "8/8/8/3k4/2pP4/1B6/6K1/8 b - - 4 1".into();
-        let mv = Move::capture(C4, B3, BlackPawn);
+        let mv = Move::capture(C4, B3, BlackPawn, WhiteBishop);
         board.update_by_move(mv);
         assert_eq!(board.to_string(), "8/8/8/3k4/3P4/1p6/6K1/8 w - - 0 2");
         assert_eq!(board.pieces[Piece::WhiteBishop as usize], 0);
@@ -205,7 +257,7 @@ mod tests {
     #[test]
     fn test_update_by_move_double_push() {
         let mut board = Board::initial_board();
-        let mv = Move::quiet(B2, B4, WhitePawn);
+        let mv = Move::double_push(B2, B4, WhitePawn);
         board.update_by_move(mv);
         assert_eq!(
             board,
@@ -216,7 +268,7 @@ mod tests {
     #[test]
     fn test_update_by_move_castling() {
         let mut board: Board = "4k3/8/8/8/8/8/PPPPPPPP/R3K1NR w Q - 0 1".into();
-        let mv = Move::quiet(E1, C1, WhiteKing); // White queen side castle
+        let mv = Move::KING_TO_QUEEN_SIDE_CASTLING[0]; // White queen side castle
         board.update_by_move(mv);
         assert_eq!(board, "4k3/8/8/8/8/8/PPPPPPPP/2KR2NR b - - 1 1".into());
     }
@@ -236,7 +288,7 @@ mod tests {
     #[test]
     fn test_update_by_move_promotion() {
         let mut board: Board = "4k3/1P6/8/8/8/8/8/4K3 w - - 2 1".into();
-        let mv = Move::new(B7, B8, Some(WhiteQueen), WhitePawn, false);
+        let mv = Move::new(B7, B8, Some(WhiteQueen), WhitePawn, None);
         board.update_by_move(mv);
         assert_eq!(board, "1Q2k3/8/8/8/8/8/8/4K3 b - - 0 1".into());
     }
@@ -244,7 +296,7 @@ mod tests {
     #[test]
     fn test_update_by_move_en_passant_capture() {
         let mut board: Board = "rnbqkbnr/2pppppp/p7/Pp6/8/8/1PPPPPPP/RNBQKBNR w KQkq b6 0 3".into();
-        let mv = Move::capture(A5, B6, WhitePawn);
+        let mv = Move::en_passant(A5, B6, WhitePawn);
         board.update_by_move(mv);
         assert_eq!(
             board,
@@ -252,11 +304,59 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_update_by_move_en_passant_square() {
+        // No black pawn next to the pushed pawn: No en passant square.
+        let mut board = Board::initial_board();
+        board.update_by_move(Move::double_push(E2, E4, WhitePawn));
+        assert_eq!(
+            board.to_string(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+
+        let mut board: Board = "4k3/8/8/8/3p4/8/4P3/4K3 w - - 0 1".into();
+        board.update_by_move(Move::double_push(E2, E4, WhitePawn));
+        assert_eq!(board.to_string(), "4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1");
+
+        // The black pawn is pinned, so it can't take en passant.
+        let mut board: Board = "3R4/8/8/8/3p4/8/4P3/K2k4 w - - 0 1".into();
+        board.update_by_move(Move::double_push(E2, E4, WhitePawn));
+        assert_eq!(board.to_string(), "3R4/8/8/8/3pP3/8/8/K2k4 b - - 0 1");
+    }
+
+    #[test]
+    fn test_en_passant_square_transposition() {
+        // Same position reached with and without a double push.
+        let mut board1: Board = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1".into();
+        board1.update_by_move(Move::double_push(E2, E4, WhitePawn));
+        let board2: Board = "4k3/8/8/8/4P3/8/8/4K3 b - - 0 1".into();
+        assert_eq!(board1.get_zobrist_key(), board2.get_zobrist_key());
+
+        let board3: Board = "4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1".into();
+        assert_eq!(board3.to_string(), "4k3/8/8/8/4P3/8/8/4K3 b - - 0 1");
+        assert_eq!(board3.get_zobrist_key(), board2.get_zobrist_key());
+    }
+
+    #[test]
+    fn test_make_null_move() {
+        let mut board: Board = "rnbqkbnr/pppp1ppp/8/8/3pP3/8/PPP2PPP/RNBQKBNR b KQkq e3 0 3".into();
+        board.make_null_move();
+        assert_eq!(
+            board,
+            Board::from_fen("rnbqkbnr/pppp1ppp/8/8/3pP3/8/PPP2PPP/RNBQKBNR w KQkq - 1 4")
+        );
+        board.make_null_move();
+        assert_eq!(
+            board,
+            Board::from_fen("rnbqkbnr/pppp1ppp/8/8/3pP3/8/PPP2PPP/RNBQKBNR b KQkq - 2 4")
+        );
+    }
+
     #[test]
     fn test_copy_with_move_in_check_castling() {
         let board: Board =
             "r3k2r/p1pp1pb1/bn2Qnp1/2qPN3/1p2P3/2N5/PPPBBPPP/R3K2R b KQkq - 3 2".into();
-        let castling_mv = Move::quiet(E8, G8, BlackKing);
+        let castling_mv = Move::KING_TO_KING_SIDE_CASTLING[1];
         // Not allowed to castle if in check.
         assert_eq!(board.copy_with_move(castling_mv), None);
     }
@@ -264,7 +364,7 @@ mod tests {
     #[test]
     fn test_copy_with_move_castling_over_attacked_square() {
         let board: Board = "r3k2r/1b4bq/8/8/8/8/7B/3RK2R b Kkq - 1 1".into();
-        let castling_mv = Move::quiet(E8, C8, BlackKing);
+        let castling_mv = Move::KING_TO_QUEEN_SIDE_CASTLING[1];
         // Not allowed to castle over attacked square
         assert_eq!(board.copy_with_move(castling_mv), None);
     }
@@ -273,7 +373,7 @@ mod tests {
     fn test_copy_with_move_castling_rook_attacked() {
         let board: Board = "rnb2k1r/pp1Pbppp/2p5/q7/2B5/8/PPPQNnPP/RNB1K2R w KQ - 3 9".into();
         board.print();
-        let castling_mv = Move::quiet(E1, G1, WhiteKing);
+        let castling_mv = Move::KING_TO_KING_SIDE_CASTLING[0];
         // Rook is attacked, but castling is still allowed then.
         assert!(board.copy_with_move(castling_mv).is_some());
     }
@@ -292,11 +392,43 @@ mod tests {
         // Push or en passant taking is not allowed, as it leaves the king in check.
         let mv = Move::quiet(C4, C3, BlackPawn);
         assert_eq!(board.copy_with_move(mv), None);
-        let mv = Move::capture(C4, D3, BlackPawn);
+        let mv = Move::en_passant(C4, D3, BlackPawn);
         assert_eq!(board.copy_with_move(mv), None);
 
         // But taking the attacker is.
-        let mv = Move::capture(C4, B3, BlackPawn);
+        let mv = Move::capture(C4, B3, BlackPawn, WhiteBishop);
         assert!(board.copy_with_move(mv).is_some());
     }
+
+    #[test]
+    fn test_mailbox() {
+        // Castling, en passant capture and promotion keep the mailbox in sync with the bitboards.
+        let mut board: Board = "r3k3/1P6/8/8/5pP1/8/8/4K2R b Kq g3 0 1".into();
+        for mv in ["f4g3", "e1g1", "e8c8", "b7b8n"] {
+            board.update_by_move(board.new_move_from_pure(mv));
+            assert_eq!(board.mailbox, Board::gen_mailbox(&board.pieces));
+        }
+        assert_eq!(board.piece_on(B8), Some(WhiteKnight));
+        assert_eq!(board.piece_on(D8), Some(BlackRook));
+        assert_eq!(board.piece_on(F1), Some(WhiteRook));
+        assert_eq!(board.piece_on(G4), None);
+        assert_eq!(board.piece_on(G3), Some(BlackPawn));
+    }
+
+    #[test]
+    fn test_king_squares() {
+        let mut board: Board = "r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1".into();
+        assert_eq!(board.king_square(Color::White), Some(E1));
+        board.update_by_move(board.new_move_from_pure("e1g1"));
+        board.update_by_move(board.new_move_from_pure("e8d7"));
+        assert_eq!(board.king_square(Color::White), Some(G1));
+        assert_eq!(board.king_square(Color::Black), Some(D7));
+
+        board.remove_piece(D7);
+        assert_eq!(board.king_square(Color::Black), None);
+        assert_eq!(board.attacks_king(Color::Black), 0);
+        board.put_piece(F8, BlackKing);
+        assert_eq!(board.king_square(Color::Black), Some(F8));
+        assert_ne!(board.attacks_king(Color::Black), 0);
+    }
 }
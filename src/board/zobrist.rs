@@ -64,9 +64,9 @@ impl Keys {
     }
 }
 
-use once_cell::sync::Lazy;
+use std::sync::LazyLock;
 
-pub static ZOBRIST_KEYS: Lazy<Keys> = Lazy::new(Keys::init);
+pub static ZOBRIST_KEYS: LazyLock<Keys> = LazyLock::new(Keys::init);
 
 impl Board {
     // Generates a Zobrist key for the board.
@@ -76,9 +76,7 @@ impl Board {
         let mut key: u64 = 0;
 
         for piece in Piece::ALL_PIECES {
-            let pieces_bb = board.pieces[piece as usize];
-            for from_bb in bitboard::into_iter(pieces_bb) {
-                let square = bitboard::get_index(from_bb).into();
+            for square in bitboard::squares(board.pieces[piece as usize]) {
                 key ^= ZOBRIST_KEYS.piece_key(square, piece);
             }
         }
@@ -6,13 +6,17 @@ mod colors;
 mod moves;
 mod pieces;
 mod squares;
+mod value;
 
 pub use colors::Color;
 pub use moves::format_moves_as_pure_string;
+pub use moves::Flag;
 pub use moves::Move;
+pub use moves::NULL_MOVE_PURE;
 pub use pieces::Piece;
 pub use pieces::PieceListBoard;
 pub use squares::Square;
+pub use value::Value;
 
 // Centipawns
 pub type Score = i32;
@@ -22,14 +26,33 @@ pub const MAX_SCORE: Score = -MIN_SCORE;
 
 pub const ENGINE_NAME: &str = "Kaik";
 pub const ENGINE_AUTHOR: &str = "Vincent Oberle";
+// The git hash and the build info are set by build.rs.
+pub const ENGINE_VERSION: &str =
+    concat!(env!("CARGO_PKG_VERSION"), " (", env!("KAIK_GIT_HASH"), ")");
+// Detailed version, to include in bug reports.
+pub const ENGINE_BUILD_INFO: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("KAIK_GIT_HASH"),
+    ")\n",
+    env!("KAIK_BUILD_INFO")
+);
 
 #[cfg(test)]
 mod tests {
-    use crate::common::{MAX_SCORE, MIN_SCORE};
+    use crate::common::{ENGINE_BUILD_INFO, ENGINE_VERSION, MAX_SCORE, MIN_SCORE};
 
     #[test]
     fn test_min_max_score() {
         assert_eq!(-MIN_SCORE, MAX_SCORE);
         assert_eq!(-MAX_SCORE, MIN_SCORE);
     }
+
+    #[test]
+    fn test_engine_version() {
+        assert!(ENGINE_VERSION.starts_with(env!("CARGO_PKG_VERSION")));
+        assert!(ENGINE_VERSION.ends_with(')'));
+        assert!(ENGINE_BUILD_INFO.starts_with(ENGINE_VERSION));
+        assert!(ENGINE_BUILD_INFO.contains("profile"));
+    }
 }
@@ -7,6 +7,18 @@ use itertools::Itertools;
 
 use crate::{common::Piece, common::Square};
 
+// Moves that need more than moving the piece when applied.
+// Set by move generation, so that they don't need to be guessed from the squares, which is
+// ambiguous for castling (a king can go from E1 to G1 without castling in some positions).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    Normal,
+    DoublePush, // Pawn moving two squares, creating an en passant target square.
+    EnPassant,
+    KingSideCastling,
+    QueenSideCastling,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Move {
     // The minimum infortmation we need to encode a move.
@@ -15,9 +27,9 @@ pub struct Move {
     to: Square,
     promotion: Option<Piece>,
     // Following information helps to avoid board lookups when applying moves.
-    piece: Piece, // Piece performing the move
-    is_capture: bool,
-    // We can add more flags: Castling, double push pawn, en passant.
+    piece: Piece,            // Piece performing the move
+    captured: Option<Piece>, // Set at generation time, for move ordering and cheap updates.
+    flag: Flag,
 }
 
 impl Move {
@@ -26,7 +38,7 @@ impl Move {
         to: Square,
         promotion: Option<Piece>,
         piece: Piece,
-        is_capture: bool,
+        captured: Option<Piece>,
     ) -> Self {
         debug_assert!(match promotion {
             None => true,
@@ -37,16 +49,30 @@ impl Move {
             to,
             promotion,
             piece,
-            is_capture,
+            captured,
+            flag: Flag::Normal,
         }
     }
 
+    const fn with_flag(self, flag: Flag) -> Self {
+        Self { flag, ..self }
+    }
+
     pub const fn quiet(from: Square, to: Square, piece: Piece) -> Self {
-        Self::new(from, to, None, piece, false)
+        Self::new(from, to, None, piece, None)
     }
 
-    pub const fn capture(from: Square, to: Square, piece: Piece) -> Self {
-        Self::new(from, to, None, piece, true)
+    pub const fn capture(from: Square, to: Square, piece: Piece, captured: Piece) -> Self {
+        Self::new(from, to, None, piece, Some(captured))
+    }
+
+    pub const fn double_push(from: Square, to: Square, piece: Piece) -> Self {
+        Self::quiet(from, to, piece).with_flag(Flag::DoublePush)
+    }
+
+    pub const fn en_passant(from: Square, to: Square, piece: Piece) -> Self {
+        let captured = Piece::get_pawn_of(piece.get_color().opposite());
+        Self::capture(from, to, piece, captured).with_flag(Flag::EnPassant)
     }
 
     pub fn get_from(self) -> Square {
@@ -66,17 +92,33 @@ impl Move {
     }
 
     pub fn is_capture(self) -> bool {
-        self.is_capture
+        self.captured.is_some()
+    }
+
+    pub fn get_captured(self) -> Option<Piece> {
+        self.captured
+    }
+
+    pub fn get_flag(self) -> Flag {
+        self.flag
     }
 
     pub fn is_pawn_double_push(self) -> bool {
-        self.piece.is_pawn() && self.from.get_rank().abs_diff(self.to.get_rank()) == 2
+        self.flag == Flag::DoublePush
+    }
+
+    pub fn is_en_passant(self) -> bool {
+        self.flag == Flag::EnPassant
+    }
+
+    pub fn is_castling(self) -> bool {
+        matches!(self.flag, Flag::KingSideCastling | Flag::QueenSideCastling)
     }
 
     pub fn get_en_passant_target_square(self) -> Option<Square> {
         if self.is_pawn_double_push() {
             debug_assert_eq!(self.from.get_file(), self.to.get_file());
-            let rank = (self.from.get_rank() + self.to.get_rank()) / 2;
+            let rank = u8::midpoint(self.from.get_rank(), self.to.get_rank());
             Some(Square::new(rank, self.from.get_file()))
         } else {
             None
@@ -84,44 +126,32 @@ impl Move {
     }
 
     pub const KING_TO_KING_SIDE_CASTLING: [Move; 2] = [
-        Move::quiet(Square::E1, Square::G1, Piece::WhiteKing),
-        Move::quiet(Square::E8, Square::G8, Piece::BlackKing),
+        Move::quiet(Square::E1, Square::G1, Piece::WhiteKing).with_flag(Flag::KingSideCastling),
+        Move::quiet(Square::E8, Square::G8, Piece::BlackKing).with_flag(Flag::KingSideCastling),
     ];
 
     pub const KING_TO_QUEEN_SIDE_CASTLING: [Move; 2] = [
-        Move::quiet(Square::E1, Square::C1, Piece::WhiteKing),
-        Move::quiet(Square::E8, Square::C8, Piece::BlackKing),
+        Move::quiet(Square::E1, Square::C1, Piece::WhiteKing).with_flag(Flag::QueenSideCastling),
+        Move::quiet(Square::E8, Square::C8, Piece::BlackKing).with_flag(Flag::QueenSideCastling),
     ];
 
     // If this is a castling move, the move itself indicates the king move.
     // This function returns the extra rook move that needs to be done.
     pub fn get_castling_rook_move(self) -> Option<Move> {
-        const WHITE_KING_SIDE: Option<Move> =
-            Some(Move::quiet(Square::H1, Square::F1, Piece::WhiteRook));
-        const WHITE_QUEEN_SIDE: Option<Move> =
-            Some(Move::quiet(Square::A1, Square::D1, Piece::WhiteRook));
-        const BLACK_KING_SIDE: Option<Move> =
-            Some(Move::quiet(Square::H8, Square::F8, Piece::BlackRook));
-        const BLACK_QUEEN_SIDE: Option<Move> =
-            Some(Move::quiet(Square::A8, Square::D8, Piece::BlackRook));
-        if self.piece.is_king() {
-            if self.from == Square::E1 {
-                // White
-                if self.to == Square::G1 {
-                    return WHITE_KING_SIDE;
-                } else if self.to == Square::C1 {
-                    return WHITE_QUEEN_SIDE;
-                }
-            } else if self.from == Square::E8 {
-                // Black
-                if self.to == Square::G8 {
-                    return BLACK_KING_SIDE;
-                } else if self.to == Square::C8 {
-                    return BLACK_QUEEN_SIDE;
-                }
-            }
+        const KING_SIDE: [Move; 2] = [
+            Move::quiet(Square::H1, Square::F1, Piece::WhiteRook),
+            Move::quiet(Square::H8, Square::F8, Piece::BlackRook),
+        ];
+        const QUEEN_SIDE: [Move; 2] = [
+            Move::quiet(Square::A1, Square::D1, Piece::WhiteRook),
+            Move::quiet(Square::A8, Square::D8, Piece::BlackRook),
+        ];
+        let color = self.piece.get_color() as usize;
+        match self.flag {
+            Flag::KingSideCastling => Some(KING_SIDE[color]),
+            Flag::QueenSideCastling => Some(QUEEN_SIDE[color]),
+            _ => None,
         }
-        None
     }
 
     fn fmt_as_pure(self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -143,7 +173,7 @@ impl Move {
         // <https://www.chessprogramming.org/Algebraic_Chess_Notation#Long_Algebraic_Notation_.28LAN.29>
         let from = self.get_from().to_string().to_uppercase();
         let to = self.get_to().to_string().to_uppercase();
-        let separator = if self.is_capture { 'x' } else { '-' };
+        let separator = if self.is_capture() { 'x' } else { '-' };
         if self.piece.is_pawn() {
             let promotion = match self.get_promotion() {
                 Some(Piece::WhiteQueen | Piece::BlackQueen) => "Q",
@@ -159,9 +189,55 @@ impl Move {
         }
     }
 
+    // Packs the move into 27 bits, for the transposition table, which stores it in an atomic word.
+    // Missing pieces are encoded as 12, after the last piece.
+    pub fn to_bits(self) -> u32 {
+        let piece_bits = |piece: Option<Piece>| piece.map_or(12, |p| p as u32);
+        u32::from(self.from as u8)
+            | u32::from(self.to as u8) << 6
+            | piece_bits(self.promotion) << 12
+            | (self.piece as u32) << 16
+            | piece_bits(self.captured) << 20
+            | (self.flag as u32) << 24
+    }
+
+    // Unpacks a move packed by to_bits(). None if the bits don't encode a move.
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        let field = |shift: u32, mask: u32| (bits >> shift) & mask;
+        let piece = |shift: u32| match field(shift, 0xF) {
+            12 => Ok(None),
+            i => Piece::ALL_PIECES
+                .get(i as usize)
+                .copied()
+                .map(Some)
+                .ok_or(()),
+        };
+        let flag = match field(24, 0x7) {
+            0 => Flag::Normal,
+            1 => Flag::DoublePush,
+            2 => Flag::EnPassant,
+            3 => Flag::KingSideCastling,
+            4 => Flag::QueenSideCastling,
+            _ => return None,
+        };
+        if bits >> 27 != 0 {
+            return None;
+        }
+        Some(
+            Self::new(
+                Square::from(field(0, 0x3F)),
+                Square::from(field(6, 0x3F)),
+                piece(12).ok()?,
+                piece(16).ok()??,
+                piece(20).ok()?,
+            )
+            .with_flag(flag),
+        )
+    }
+
     pub fn pure(&self) -> impl std::fmt::Display + '_ {
         struct Pure<'a>(&'a Move);
-        impl<'a> std::fmt::Display for Pure<'a> {
+        impl std::fmt::Display for Pure<'_> {
             fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
                 self.0.fmt_as_pure(f)
             }
@@ -176,6 +252,10 @@ impl Display for Move {
     }
 }
 
+// UCI notation of the null move, passing the turn. It isn't represented as a Move,
+// see Board::make_null_move().
+pub const NULL_MOVE_PURE: &str = "0000";
+
 pub fn format_moves_as_pure_string(moves: &[Move]) -> String {
     moves.iter().map(Move::pure).join(" ")
 }
@@ -187,12 +267,40 @@ mod tests {
 
     #[test]
     fn test_move_new() {
-        let mv = Move::new(Square::E2, Square::E4, None, Piece::WhitePawn, false);
+        let mv = Move::new(Square::E2, Square::E4, None, Piece::WhitePawn, None);
         assert_eq!(mv.get_from(), Square::E2);
         assert_eq!(mv.get_to(), Square::E4);
         assert_eq!(mv.get_promotion(), None);
         assert_eq!(mv.get_piece(), Piece::WhitePawn);
-        assert_eq!(mv.is_capture(), false);
+        assert!(!mv.is_capture());
+    }
+
+    #[test]
+    fn test_bits() {
+        let moves = [
+            Move::quiet(Square::A1, Square::H8, Piece::WhiteQueen),
+            Move::capture(Square::E4, Square::D5, Piece::WhitePawn, Piece::BlackKnight),
+            Move::double_push(Square::E7, Square::E5, Piece::BlackPawn),
+            Move::en_passant(Square::E5, Square::D6, Piece::WhitePawn),
+            Move::new(
+                Square::B7,
+                Square::A8,
+                Some(Piece::WhiteKnight),
+                Piece::WhitePawn,
+                Some(Piece::BlackRook),
+            ),
+            Move::KING_TO_KING_SIDE_CASTLING[1],
+            Move::KING_TO_QUEEN_SIDE_CASTLING[0],
+        ];
+        for mv in moves {
+            assert!(mv.to_bits() < 1 << 27);
+            assert_eq!(Move::from_bits(mv.to_bits()), Some(mv));
+        }
+        // No piece moving, invalid flag, bits beyond the move.
+        let bits = moves[0].to_bits();
+        assert_eq!(Move::from_bits(bits | 12 << 16), None);
+        assert_eq!(Move::from_bits(bits | 7 << 24), None);
+        assert_eq!(Move::from_bits(bits | 1 << 27), None);
     }
 
     #[test]
@@ -202,22 +310,23 @@ mod tests {
         assert_eq!(mv.get_to(), Square::E4);
         assert_eq!(mv.get_promotion(), None);
         assert_eq!(mv.get_piece(), Piece::WhitePawn);
-        assert_eq!(mv.is_capture(), false);
+        assert!(!mv.is_capture());
     }
 
     #[test]
     fn test_move_capture() {
-        let mv = Move::capture(Square::E2, Square::E4, Piece::WhitePawn);
+        let mv = Move::capture(Square::E2, Square::E4, Piece::WhitePawn, Piece::BlackKnight);
         assert_eq!(mv.get_from(), Square::E2);
         assert_eq!(mv.get_to(), Square::E4);
         assert_eq!(mv.get_promotion(), None);
         assert_eq!(mv.get_piece(), Piece::WhitePawn);
-        assert_eq!(mv.is_capture(), true);
+        assert!(mv.is_capture());
+        assert_eq!(mv.get_captured(), Some(Piece::BlackKnight));
     }
 
     #[test]
     fn test_is_pawn_double_push() {
-        let mv = Move::quiet(Square::E2, Square::E4, Piece::WhitePawn);
+        let mv = Move::double_push(Square::E2, Square::E4, Piece::WhitePawn);
         assert!(mv.is_pawn_double_push());
         let mv = Move::quiet(Square::E2, Square::E3, Piece::WhitePawn);
         assert!(!mv.is_pawn_double_push());
@@ -225,7 +334,7 @@ mod tests {
 
     #[test]
     fn test_get_en_passant_target_square() {
-        let mv = Move::quiet(Square::E2, Square::E4, Piece::WhitePawn);
+        let mv = Move::double_push(Square::E2, Square::E4, Piece::WhitePawn);
         assert_eq!(mv.get_en_passant_target_square(), Some(Square::E3));
         let mv = Move::quiet(Square::E2, Square::E3, Piece::WhitePawn);
         assert_eq!(mv.get_en_passant_target_square(), None);
@@ -233,63 +342,68 @@ mod tests {
 
     #[test]
     fn test_get_castling_rook_move() {
-        let mv = Move::quiet(Square::E1, Square::G1, Piece::WhiteKing);
+        let mv = Move::KING_TO_KING_SIDE_CASTLING[0];
         assert_eq!(
             mv.get_castling_rook_move(),
             Some(Move::quiet(Square::H1, Square::F1, Piece::WhiteRook))
         );
-        let mv = Move::quiet(Square::E1, Square::C1, Piece::WhiteKing);
+        let mv = Move::KING_TO_QUEEN_SIDE_CASTLING[0];
         assert_eq!(
             mv.get_castling_rook_move(),
             Some(Move::quiet(Square::A1, Square::D1, Piece::WhiteRook))
         );
-        let mv = Move::quiet(Square::E8, Square::G8, Piece::BlackKing);
+        let mv = Move::KING_TO_KING_SIDE_CASTLING[1];
         assert_eq!(
             mv.get_castling_rook_move(),
             Some(Move::quiet(Square::H8, Square::F8, Piece::BlackRook))
         );
-        let mv = Move::quiet(Square::E8, Square::C8, Piece::BlackKing);
+        let mv = Move::KING_TO_QUEEN_SIDE_CASTLING[1];
         assert_eq!(
             mv.get_castling_rook_move(),
             Some(Move::quiet(Square::A8, Square::D8, Piece::BlackRook))
         );
+
+        // Same squares, but not a castling.
+        let mv = Move::quiet(Square::E1, Square::G1, Piece::WhiteKing);
+        assert!(!mv.is_castling());
+        assert_eq!(mv.get_castling_rook_move(), None);
     }
 
     #[test]
     fn test_fmt_as_pure() {
-        let mv = Move::quiet(Square::E2, Square::E4, Piece::WhitePawn);
+        let mv = Move::double_push(Square::E2, Square::E4, Piece::WhitePawn);
         assert_eq!(format!("{}", mv.pure()), "e2e4");
         let mv = Move::new(
             Square::E7,
             Square::E8,
             Some(Piece::WhiteQueen),
             Piece::WhitePawn,
-            false,
+            None,
         );
         assert_eq!(format!("{}", mv.pure()), "e7e8q");
     }
 
     #[test]
     fn test_fmt_as_lan() {
-        let mv = Move::quiet(Square::E2, Square::E4, Piece::WhitePawn);
-        assert_eq!(format!("{}", mv), "E2-E4");
-        let mv = Move::capture(Square::E2, Square::E4, Piece::WhitePawn);
-        assert_eq!(format!("{}", mv), "E2xE4");
+        let mv = Move::double_push(Square::E2, Square::E4, Piece::WhitePawn);
+        assert_eq!(format!("{mv}"), "E2-E4");
+        let mv = Move::capture(Square::E2, Square::E4, Piece::WhitePawn, Piece::BlackKnight);
+        assert_eq!(format!("{mv}"), "E2xE4");
         let mv = Move::new(
             Square::E7,
             Square::E8,
             Some(Piece::WhiteQueen),
             Piece::WhitePawn,
-            false,
+            None,
         );
-        assert_eq!(format!("{}", mv), "E7-E8Q");
+        assert_eq!(format!("{mv}"), "E7-E8Q");
     }
 
     #[test]
     fn test_format_moves_as_string() {
         let moves = [
-            Move::quiet(Square::E2, Square::E4, Piece::WhitePawn),
-            Move::capture(Square::D7, Square::D8, Piece::BlackPawn),
+            Move::double_push(Square::E2, Square::E4, Piece::WhitePawn),
+            Move::capture(Square::D7, Square::D8, Piece::BlackPawn, Piece::WhiteRook),
         ];
         assert_eq!(format_moves_as_pure_string(&moves), "e2e4 d7d8");
     }
@@ -4,7 +4,7 @@ use crate::common::Color;
 
 // The order of the enum is important because it is used to index arrays.
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Piece {
     WhitePawn,
     BlackPawn,
@@ -131,7 +131,7 @@ impl Square {
     }
 
     pub fn get_rank(self) -> u8 {
-        (self as u8 & 56) >> 3
+        (self as u8 & 0x38) >> 3
     }
 
     pub fn get_file(self) -> u8 {
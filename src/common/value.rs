@@ -0,0 +1,149 @@
+use std::ops::Neg;
+
+use super::Score;
+
+// Mates are encoded as the mate score minus the distance to the mate in plies, so that shorter
+// mates get higher scores.
+const MATE: Score = 40_000;
+// Longest mate that can be encoded, in plies. Scores beyond this distance from the mate score are
+// normal centipawn scores. Much more than the maximum depth of the search.
+const MAX_MATE_PLY: Score = 1000;
+
+// A score as given by the search: Centipawns, or a mate with its distance.
+// The search itself works on the raw scores, as its windows need the arithmetic on them,
+// and converts them at its boundaries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Value(Score);
+
+impl Value {
+    pub const DRAW: Value = Value(0);
+
+    // From a raw search score.
+    pub const fn new(score: Score) -> Self {
+        Self(score)
+    }
+
+    // The side to move mates in that many plies.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    pub const fn mate_in(ply: usize) -> Self {
+        Self(MATE - ply as Score)
+    }
+
+    // The side to move gets mated in that many plies.
+    pub const fn mated_in(ply: usize) -> Self {
+        Self(-Self::mate_in(ply).0)
+    }
+
+    pub const fn score(self) -> Score {
+        self.0
+    }
+
+    pub const fn is_mate(self) -> bool {
+        self.0.abs() >= MATE - MAX_MATE_PLY
+    }
+
+    // Distance to the mate in plies, if it's a mate.
+    #[allow(clippy::cast_sign_loss)]
+    pub const fn mate_plies(self) -> Option<usize> {
+        if self.is_mate() {
+            Some((MATE - self.0.abs()) as usize)
+        } else {
+            None
+        }
+    }
+
+    // Mate in y moves as UCI counts them, negative if getting mated.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    pub const fn mate_moves(self) -> Option<i32> {
+        match self.mate_plies() {
+            // A mate on an odd ply is delivered by the side to move.
+            Some(plies) if self.0 > 0 => Some((plies as i32 + 1) / 2),
+            Some(plies) => Some(-(plies as i32) / 2),
+            None => None,
+        }
+    }
+
+    // Mate scores are stored in the transposition table relative to the node, as the same
+    // position can be reached at different distances from the root.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    pub const fn to_tt(self, ply: usize) -> Score {
+        let ply = ply as Score;
+        if !self.is_mate() {
+            self.0
+        } else if self.0 > 0 {
+            self.0 + ply
+        } else {
+            self.0 - ply
+        }
+    }
+
+    // Reverse of to_tt, for the node at that ply.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    pub const fn from_tt(score: Score, ply: usize) -> Self {
+        let ply = ply as Score;
+        if !Self(score).is_mate() {
+            Self(score)
+        } else if score > 0 {
+            Self(score - ply)
+        } else {
+            Self(score + ply)
+        }
+    }
+
+    // "cp 25" or "mate -3", as in the UCI info command.
+    pub fn to_uci_string(self) -> String {
+        match self.mate_moves() {
+            Some(moves) => format!("mate {moves}"),
+            None => format!("cp {}", self.0),
+        }
+    }
+}
+
+impl Neg for Value {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self(-self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_centipawns() {
+        let value = Value::new(-35);
+        assert!(!value.is_mate());
+        assert_eq!(value.mate_moves(), None);
+        assert_eq!(value.to_uci_string(), "cp -35");
+        assert!(Value::new(3000) < Value::mate_in(20));
+    }
+
+    #[test]
+    fn test_mates() {
+        assert_eq!(Value::mate_in(1).mate_moves(), Some(1));
+        assert_eq!(Value::mate_in(3).mate_moves(), Some(2));
+        assert_eq!(Value::mated_in(0).mate_moves(), Some(0));
+        assert_eq!(Value::mated_in(2).mate_moves(), Some(-1));
+        assert_eq!(Value::mated_in(4).mate_plies(), Some(4));
+        assert_eq!(-Value::mate_in(3), Value::mated_in(3));
+        assert!(Value::mate_in(1) > Value::mate_in(3));
+        assert_eq!(Value::mate_in(3).to_uci_string(), "mate 2");
+        assert_eq!(Value::mated_in(2).to_uci_string(), "mate -1");
+    }
+
+    #[test]
+    fn test_tt() {
+        // Mate in 5 from the root, found at ply 2: Mate in 3 from the node.
+        let stored = Value::mate_in(5).to_tt(2);
+        assert_eq!(Value::new(stored), Value::mate_in(3));
+        // Reached again at ply 4, it's a mate in 7 from the root.
+        assert_eq!(Value::from_tt(stored, 4), Value::mate_in(7));
+        assert_eq!(
+            Value::from_tt(Value::mated_in(6).to_tt(3), 1),
+            Value::mated_in(4)
+        );
+        assert_eq!(Value::from_tt(Value::new(120).to_tt(3), 1), Value::new(120));
+    }
+}
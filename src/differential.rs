@@ -0,0 +1,221 @@
+//! Differential testing of the move generation against shakmaty, a reference chess library:
+//! On each position, the legal moves and the positions they lead to must be the same, and so must
+//! the perft counts. A mismatch is shrunk to a minimal position: Down the perft tree to the
+//! position whose moves differ, then without the pieces that don't matter for the difference.
+//! Only built with the differential feature, as shakmaty is only needed for these tests:
+//! cargo t --release --features differential differential
+
+use itertools::Itertools;
+use shakmaty::{fen::Fen, CastlingMode, Chess, EnPassantMode, Position};
+
+use crate::{
+    board::{Board, BoardEdit},
+    common::Square,
+    perft,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub fen: String,
+    pub reason: String,
+}
+
+fn reference_position(board: &Board) -> Result<Chess, String> {
+    let fen = board.as_fen();
+    Fen::from_ascii(fen.as_bytes())
+        .map_err(|err| err.to_string())?
+        .into_position(CastlingMode::Standard)
+        .map_err(|err| format!("shakmaty rejects {fen}: {err}"))
+}
+
+// The FEN without the en passant square and the clocks: The libraries write the en passant
+// square in different cases, and the clocks don't change the moves.
+fn position_fields(fen: &str) -> String {
+    fen.split(' ').take(3).join(" ")
+}
+
+// Legal moves in pure notation, with the FEN of the position they lead to, sorted.
+type Moves = Vec<(String, String)>;
+
+// The legal moves of both libraries.
+fn moves(board: &Board, reference: &Chess) -> (Moves, Moves) {
+    let kaik = board
+        .generate_legal_moves()
+        .into_iter()
+        .filter_map(|mv| {
+            let child = board.copy_with_move(mv)?;
+            Some((mv.pure().to_string(), position_fields(&child.as_fen())))
+        })
+        .sorted()
+        .collect();
+    let reference = reference
+        .legal_moves()
+        .into_iter()
+        .map(|mv| {
+            let child = reference.clone().play(mv).expect("Legal move");
+            let fen = Fen::from_position(&child, EnPassantMode::Legal).to_string();
+            (
+                mv.to_uci(CastlingMode::Standard).to_string(),
+                position_fields(&fen),
+            )
+        })
+        .sorted()
+        .collect();
+    (kaik, reference)
+}
+
+// Compares the moves of the position, not the ones further down the tree.
+fn local_mismatch(board: &Board) -> Option<String> {
+    let reference = reference_position(board).ok()?;
+    let (kaik, reference) = moves(board, &reference);
+    if kaik == reference {
+        return None;
+    }
+    let only_in = |a: &[(String, String)], b: &[(String, String)]| {
+        a.iter()
+            .filter(|m| !b.contains(m))
+            .map(|(mv, fen)| format!("{mv} ({fen})"))
+            .join(", ")
+    };
+    Some(format!(
+        "Kaik only: [{}], shakmaty only: [{}]",
+        only_in(&kaik, &reference),
+        only_in(&reference, &kaik)
+    ))
+}
+
+fn perft_mismatch(board: &Board, depth: usize) -> Option<String> {
+    let reference = reference_position(board).ok()?;
+    let expected = shakmaty::perft(&reference, u32::try_from(depth).unwrap());
+    let nodes = perft::perft(board, depth) as u64;
+    (nodes != expected).then(|| format!("perft {depth}: {nodes} instead of {expected}"))
+}
+
+// Compares the moves of the position, then the perft counts up to the depth.
+// Positions rejected by shakmaty, which are stricter about impossible checks, are skipped.
+pub fn compare(board: &Board, depth: usize) -> Option<Mismatch> {
+    let reason = local_mismatch(board)
+        .or_else(|| (2..=depth).find_map(|depth| perft_mismatch(board, depth)))?;
+    Some(Mismatch {
+        fen: board.as_fen(),
+        reason,
+    })
+}
+
+// Minimal position showing the mismatch found in the board at that depth.
+pub fn shrink(board: &Board, depth: usize) -> Mismatch {
+    let board = shrink_with(
+        board,
+        depth,
+        &|board| local_mismatch(board).is_some(),
+        &|board, depth| perft_mismatch(board, depth).is_some(),
+    );
+    compare(&board, depth).unwrap_or_else(|| Mismatch {
+        fen: board.as_fen(),
+        reason: "No mismatch".to_string(),
+    })
+}
+
+fn shrink_with(
+    board: &Board,
+    depth: usize,
+    local_mismatch: &dyn Fn(&Board) -> bool,
+    perft_mismatch: &dyn Fn(&Board, usize) -> bool,
+) -> Board {
+    // The perft counts differ, so they differ for at least one move, down to a position whose
+    // moves differ.
+    let mut board = *board;
+    let mut depth = depth;
+    while !local_mismatch(&board) && depth > 1 {
+        let child = board
+            .generate_legal_moves()
+            .into_iter()
+            .filter_map(|mv| board.copy_with_move(mv))
+            .find(|child| perft_mismatch(child, depth - 1));
+        let Some(child) = child else {
+            break;
+        };
+        board = child;
+        depth -= 1;
+    }
+
+    // Removes the pieces one by one, as long as the moves still differ. The kings must stay.
+    loop {
+        let smaller = (0..64u8)
+            .map(Square::from)
+            .filter(|&square| board.piece_on(square).is_some_and(|p| !p.is_king()))
+            .filter_map(|square| {
+                let mut edited = board;
+                edited.apply_edit(&BoardEdit::Remove(square));
+                // Through FEN, so that the board is checked and consistent.
+                Board::try_from_fen(&edited.as_fen()).ok()
+            })
+            .find(|edited| local_mismatch(edited));
+        match smaller {
+            Some(smaller) => board = smaller,
+            None => return board,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        common::Piece,
+        utils::{fen, random_game::random_game},
+    };
+
+    const POSITIONS: [&str; 6] = [
+        fen::START_POSITION,
+        fen::KIWIPETE,
+        fen::POSITION_3,
+        fen::POSITION_4,
+        fen::POSITION_5,
+        fen::POSITION_6,
+    ];
+
+    #[test]
+    fn test_perft_positions() {
+        for fen in POSITIONS {
+            let board = Board::from_fen(fen);
+            // Not skipped.
+            assert!(reference_position(&board).is_ok());
+            assert_eq!(compare(&board, 3), None);
+        }
+    }
+
+    #[test]
+    fn test_random_positions() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for fen in POSITIONS {
+            for _ in 0..50 {
+                for (ply, board) in random_game(Board::from_fen(fen), 150, &mut rng).enumerate() {
+                    // Perft on some of them only, it's much slower.
+                    let depth = if ply % 10 == 0 { 2 } else { 1 };
+                    assert!(
+                        compare(&board, depth).is_none(),
+                        "{:?}",
+                        shrink(&board, depth)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_shrink() {
+        // A fake bug with the white queen on d1, found with a perft 2 from the start position.
+        let bug = |board: &Board| board.piece_on(Square::D1) == Some(Piece::WhiteQueen);
+        let after_e4: Board = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1".into();
+        let shrunk = shrink_with(&after_e4, 2, &bug, &|_, _| true);
+        assert_eq!(shrunk.as_fen(), "4k3/8/8/8/8/8/8/3QK3 b - - 0 1");
+
+        // Down the tree first when the moves are the same.
+        let bug = |board: &Board| board.count_pieces(Piece::WhitePawn) < 8;
+        let shrunk = shrink_with(&Board::initial_board(), 3, &bug, &|board, _| bug(board));
+        assert_eq!(shrunk.as_fen(), "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+    }
+}
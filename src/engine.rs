@@ -1,6 +1,12 @@
 //! The engine module contains the "brain" of the engine,
 //! i.e. what makes the decision which moves to play: Evaluation, search, etc.
 
+pub mod config;
+pub mod difficulty;
 pub mod eval;
+pub mod experience;
 pub mod game;
+pub mod resign;
 pub mod search;
+pub mod transposition;
+pub mod tunables;
@@ -0,0 +1,237 @@
+//! Engine configuration profiles, loaded from TOML files.
+//! They allow to compare parameter sets without recompiling. Missing values keep their defaults,
+//! so a profile only needs to list what it changes:
+//!
+//!     difficulty = "club"
+//!
+//!     [search]
+//!     hash = 64
+//!     razor_margin = 300
+//!     algorithm = "negamax"
+//!
+//!     [eval]
+//!     backend = "pst"
+//!     knight = 300
+//!     passed_pawn_bonus = [0, 10, 15, 25, 40, 70, 120, 0]
+//!
+//!     [resign]
+//!     resign_score = 800
+//!     draw_moves = 0
+//!
+//! Kaik has no opening book yet, so there are no book settings.
+
+use std::fs;
+
+use serde::Deserialize;
+
+use crate::{
+    common::Score,
+    engine::{
+        difficulty::Difficulty,
+        eval::{self, pawns, EvalBackend},
+        search::SearchAlgorithm,
+        transposition,
+    },
+};
+
+pub const DEFAULT_RAZOR_MARGIN: Score = 250;
+pub const DEFAULT_RAZOR_MAX_DEPTH: usize = 3;
+pub const DEFAULT_PROBCUT_MARGIN: Score = 200;
+pub const DEFAULT_PROBCUT_MIN_DEPTH: usize = 5;
+pub const DEFAULT_PROBCUT_REDUCTION: usize = 4;
+pub const DEFAULT_EASY_MOVE_MARGIN: Score = 150;
+// Small enough to only change the choice between moves of about the same value.
+pub const DEFAULT_JITTER_MAX: Score = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    // Size of the transposition table in MB.
+    pub hash: usize,
+    // Razoring margin per ply of depth, in centipawns. 0 disables razoring.
+    pub razor_margin: Score,
+    // Razoring is only done on the last plies.
+    pub razor_max_depth: usize,
+    // How far above beta a shallow search must get for ProbCut to prune. 0 disables ProbCut.
+    pub probcut_margin: Score,
+    // ProbCut is tried from that depth, with shallow searches reduced by probcut_reduction.
+    pub probcut_min_depth: usize,
+    pub probcut_reduction: usize,
+    // A move is easy if no other move scores within this margin of it.
+    pub easy_move_margin: Score,
+    // Maximum random bonus added to the root moves scores, for opening variety.
+    pub jitter_max: Score,
+    // Quiet checks on the first ply of the quiescence search, see
+    // Board::generate_quiescence_moves(). It finds more mates, for a bigger search.
+    pub quiescence_checks: bool,
+    // Skips the knight, bishop and rook promotions losing the promoted piece. Can be disabled to
+    // check that the pruning doesn't change the results.
+    pub prune_under_promotions: bool,
+    pub algorithm: SearchAlgorithm,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            hash: transposition::DEFAULT_SIZE_MB,
+            razor_margin: DEFAULT_RAZOR_MARGIN,
+            razor_max_depth: DEFAULT_RAZOR_MAX_DEPTH,
+            probcut_margin: DEFAULT_PROBCUT_MARGIN,
+            probcut_min_depth: DEFAULT_PROBCUT_MIN_DEPTH,
+            probcut_reduction: DEFAULT_PROBCUT_REDUCTION,
+            easy_move_margin: DEFAULT_EASY_MOVE_MARGIN,
+            jitter_max: DEFAULT_JITTER_MAX,
+            quiescence_checks: false,
+            prune_under_promotions: true,
+            algorithm: SearchAlgorithm::default(),
+        }
+    }
+}
+
+// Piece values are in centipawns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvalConfig {
+    pub backend: EvalBackend,
+    pub pawn: u32,
+    pub knight: u32,
+    pub bishop: u32,
+    pub rook: u32,
+    pub queen: u32,
+    // Indexed by the rank of the pawn from its owner's point of view.
+    pub passed_pawn_bonus: [Score; 8],
+}
+
+impl EvalConfig {
+    // Values of the pieces in the order of eval::PIECE_VALUES, the king being fixed.
+    pub fn piece_values(&self) -> [u32; 6] {
+        [
+            self.pawn,
+            self.knight,
+            self.bishop,
+            self.rook,
+            self.queen,
+            eval::PIECE_VALUES[5],
+        ]
+    }
+}
+
+impl Default for EvalConfig {
+    fn default() -> Self {
+        let [pawn, knight, bishop, rook, queen, _king] = eval::PIECE_VALUES;
+        Self {
+            backend: EvalBackend::default(),
+            pawn,
+            knight,
+            bishop,
+            rook,
+            queen,
+            passed_pawn_bonus: pawns::PASSED_PAWN_BONUS,
+        }
+    }
+}
+
+// When the engine resigns or agrees to a draw, in the console play mode and in tournaments.
+// Scores are in centipawns, from the engine's point of view. A number of moves of 0 disables
+// resigning or draw offers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResignConfig {
+    // Resigns when the score stayed below -resign_score for resign_moves moves in a row.
+    pub resign_score: Score,
+    pub resign_moves: usize,
+    // Offers a draw when the score stayed within draw_score of 0 for draw_moves moves in a row,
+    // from move draw_min_moves on.
+    pub draw_score: Score,
+    pub draw_moves: usize,
+    pub draw_min_moves: usize,
+}
+
+impl Default for ResignConfig {
+    fn default() -> Self {
+        Self {
+            resign_score: 1000,
+            resign_moves: 5,
+            draw_score: 10,
+            draw_moves: 10,
+            draw_min_moves: 40,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub difficulty: Difficulty,
+    pub search: SearchConfig,
+    pub eval: EvalConfig,
+    pub resign: ResignConfig,
+}
+
+impl Config {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        toml::from_str(content).map_err(|err| err.to_string())
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+        Self::parse(&content).map_err(|err| format!("{path}: {err}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_empty() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            "difficulty = \"club\"
+
+            [search]
+            hash = 64
+            razor_margin = 300
+            quiescence_checks = true
+            prune_under_promotions = false
+            algorithm = \"negamax\"
+
+            [eval]
+            backend = \"pst\"
+            knight = 300
+            passed_pawn_bonus = [0, 10, 15, 25, 40, 70, 120, 0]
+
+            [resign]
+            resign_score = 800
+            draw_moves = 0",
+        )
+        .unwrap();
+        assert_eq!(config.difficulty, Difficulty::Club);
+        assert_eq!(config.search.hash, 64);
+        assert_eq!(config.search.razor_margin, 300);
+        assert!(config.search.quiescence_checks);
+        assert!(!config.search.prune_under_promotions);
+        assert_eq!(config.search.algorithm, SearchAlgorithm::Negamax);
+        assert_eq!(config.eval.backend, EvalBackend::Pst);
+        assert_eq!(config.eval.knight, 300);
+        assert_eq!(config.eval.bishop, EvalConfig::default().bishop);
+        assert_eq!(config.eval.passed_pawn_bonus[6], 120);
+        assert_eq!(config.resign.resign_score, 800);
+        assert_eq!(config.resign.draw_moves, 0);
+        assert_eq!(
+            config.resign.resign_moves,
+            ResignConfig::default().resign_moves
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Config::parse("[eval]\nknigth = 300").is_err());
+        assert!(Config::parse("[search]\nhash = \"big\"").is_err());
+        assert!(Config::parse("[eval]\npassed_pawn_bonus = [1, 2]").is_err());
+    }
+}
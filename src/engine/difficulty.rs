@@ -0,0 +1,152 @@
+//! Difficulty presets, for players who don't want to tune the options one by one.
+//! A preset caps the search depth, uses only part of the thinking time, and randomizes the
+//! choice of the moves more, for more of the game. The presets only apply when playing:
+//! Analysis and infinite searches keep the full strength.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::Deserialize;
+
+use crate::{common::Score, engine::game::SearchParams};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Beginner,
+    Club,
+    #[default]
+    Max,
+}
+
+// What a preset changes. The random bonus is added to the root moves scores, see
+// SearchConfig::jitter_max, in the first random_moves moves of the game.
+struct Preset {
+    max_depth: Option<usize>,
+    // Percentage of the clock and move times the search gets.
+    time_percent: u32,
+    jitter_max: Score,
+    random_moves: usize,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Beginner, Difficulty::Club, Difficulty::Max];
+
+    fn preset(self) -> Option<Preset> {
+        match self {
+            Difficulty::Beginner => Some(Preset {
+                max_depth: Some(2),
+                time_percent: 10,
+                jitter_max: 150,
+                random_moves: usize::MAX,
+            }),
+            Difficulty::Club => Some(Preset {
+                max_depth: Some(6),
+                time_percent: 40,
+                jitter_max: 30,
+                random_moves: 20,
+            }),
+            Difficulty::Max => None,
+        }
+    }
+
+    // Limits the search to the preset. Infinite searches are left alone, as they only end on
+    // the stop command.
+    pub fn limit(self, search_params: SearchParams) -> SearchParams {
+        let Some(preset) = self.preset() else {
+            return search_params;
+        };
+        if search_params.is_infinite() {
+            return search_params;
+        }
+        let scale = |time: Option<_>| time.map(|time| time * preset.time_percent / 100);
+        let mut search = search_params.search;
+        search.jitter_max = search.jitter_max.max(preset.jitter_max);
+        SearchParams {
+            depth: match (search_params.depth, preset.max_depth) {
+                (Some(depth), Some(max_depth)) => Some(depth.min(max_depth)),
+                (depth, max_depth) => depth.or(max_depth),
+            },
+            white_time: scale(search_params.white_time),
+            black_time: scale(search_params.black_time),
+            white_increment: scale(search_params.white_increment),
+            black_increment: scale(search_params.black_increment),
+            move_time: scale(search_params.move_time),
+            search,
+            ..search_params
+        }
+    }
+
+    // Number of moves at the start of the game whose choice is randomized, at least.
+    pub fn random_moves(self) -> usize {
+        self.preset().map_or(0, |preset| preset.random_moves)
+    }
+}
+
+impl Display for Difficulty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difficulty::Beginner => write!(f, "beginner"),
+            Difficulty::Club => write!(f, "club"),
+            Difficulty::Max => write!(f, "max"),
+        }
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "beginner" => Ok(Difficulty::Beginner),
+            "club" => Ok(Difficulty::Club),
+            "max" => Ok(Difficulty::Max),
+            _ => Err(format!("Unknown difficulty {s}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_limit() {
+        let clock = SearchParams {
+            white_time: Some(Duration::from_secs(50)),
+            black_time: Some(Duration::from_secs(50)),
+            white_increment: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let limited = Difficulty::Club.limit(clock);
+        assert_eq!(limited.depth, Some(6));
+        assert_eq!(limited.white_time, Some(Duration::from_secs(20)));
+        assert_eq!(limited.white_increment, Some(Duration::from_millis(400)));
+        assert_eq!(limited.black_increment, None);
+        assert_eq!(limited.search.jitter_max, 30);
+
+        let depth = SearchParams {
+            depth: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(Difficulty::Beginner.limit(depth).depth, Some(2));
+        assert_eq!(Difficulty::Club.limit(depth).depth, Some(4));
+        assert_eq!(Difficulty::Max.limit(depth).depth, Some(4));
+
+        // Until the stop command.
+        assert_eq!(
+            Difficulty::Beginner.limit(SearchParams::default()).depth,
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        for difficulty in Difficulty::ALL {
+            assert_eq!(difficulty.to_string().parse(), Ok(difficulty));
+        }
+        assert_eq!("Club".parse(), Ok(Difficulty::Club));
+        assert!("expert".parse::<Difficulty>().is_err());
+    }
+}
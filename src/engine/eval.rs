@@ -1,30 +1,201 @@
 //! Evaluation of the position.
+//! The evaluation is behind the Evaluator trait, so that the backends can be compared.
+//! There is no NNUE backend yet, it needs a trained network.
+
+mod endgame;
+mod imbalance;
+mod patterns;
+pub mod pawns;
+mod pst;
+pub mod wdl;
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::Deserialize;
 
 use crate::{
     board::Board,
-    common::{Color, Score},
+    common::{Color, Piece, Score},
+    engine::config::EvalConfig,
 };
 
-#[allow(clippy::cast_possible_wrap)]
-pub fn eval(board: &Board) -> Score {
-    let (white_score, black_score) = material_scores(board);
+// From <https://www.chessprogramming.org/Simplified_Evaluation_Function>
+// Pawn, knight, bishop, rook, queen and king, in centipawns.
+pub const PIECE_VALUES: [u32; 6] = [100, 320, 330, 500, 900, 20000];
+
+pub trait Evaluator: Send + Sync {
     // The score is relative to who is moving
     // <https://www.chessprogramming.org/Evaluation#Side_to_move_relative>
+    fn eval(&self, board: &Board) -> Score;
+
+    // Values used by the search for exchanges, in the order of PIECE_VALUES.
+    fn piece_values(&self) -> [u32; 6] {
+        PIECE_VALUES
+    }
+}
+
+// Evaluation backends, selected at runtime.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EvalBackend {
+    // Material only.
+    Material,
+    // Material with its imbalances, pawn structure and trapped pieces, scaled down in drawish
+    // endgames.
+    #[default]
+    Classical,
+    // Classical with piece-square tables.
+    Pst,
+}
+
+impl Display for EvalBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalBackend::Material => write!(f, "Material"),
+            EvalBackend::Classical => write!(f, "Classical"),
+            EvalBackend::Pst => write!(f, "PST"),
+        }
+    }
+}
+
+impl FromStr for EvalBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "material" => Ok(EvalBackend::Material),
+            "classical" => Ok(EvalBackend::Classical),
+            "pst" => Ok(EvalBackend::Pst),
+            _ => Err(format!("Unknown evaluation {s}")),
+        }
+    }
+}
+
+// Creates the evaluator of the backend selected in the configuration.
+pub fn new_evaluator(config: &EvalConfig) -> Box<dyn Evaluator> {
+    match config.backend {
+        EvalBackend::Material => Box::new(Material(*config)),
+        EvalBackend::Classical => Box::new(Classical(*config)),
+        EvalBackend::Pst => Box::new(Pst(*config)),
+    }
+}
+
+#[derive(Default)]
+pub struct Material(EvalConfig);
+
+impl Evaluator for Material {
+    #[allow(clippy::cast_possible_wrap)]
+    fn eval(&self, board: &Board) -> Score {
+        let (white_score, black_score) = material_scores(board, &self.0);
+        side_relative(board, white_score as i32 - black_score as i32)
+    }
+
+    fn piece_values(&self) -> [u32; 6] {
+        self.0.piece_values()
+    }
+}
+
+#[derive(Default)]
+pub struct Classical(EvalConfig);
+
+impl Evaluator for Classical {
+    fn eval(&self, board: &Board) -> Score {
+        eval(board, &self.0)
+    }
+
+    fn piece_values(&self) -> [u32; 6] {
+        self.0.piece_values()
+    }
+}
+
+#[derive(Default)]
+pub struct Pst(EvalConfig);
+
+impl Evaluator for Pst {
+    fn eval(&self, board: &Board) -> Score {
+        side_relative(board, classical_score(board, &self.0, true))
+    }
+
+    fn piece_values(&self) -> [u32; 6] {
+        self.0.piece_values()
+    }
+}
+
+// The classical evaluation.
+pub fn eval(board: &Board, config: &EvalConfig) -> Score {
+    side_relative(board, classical_score(board, config, false))
+}
+
+// From White's point of view.
+#[allow(clippy::cast_possible_wrap)]
+fn classical_score(board: &Board, config: &EvalConfig, with_pst: bool) -> Score {
+    let endgame = is_endgame(board);
+    let (white_score, black_score) = material_scores(board, config);
+    let mut score = white_score as i32 - black_score as i32
+        + imbalance::eval(endgame::MaterialKey::new(board))
+        + pawns::eval(board, endgame, &config.passed_pawn_bonus)
+        + patterns::eval(board);
+    if with_pst {
+        score += pst::eval(board, endgame);
+    }
+    endgame::scale(board, score)
+}
+
+fn side_relative(board: &Board, score: Score) -> Score {
     if board.get_side_to_move() == Color::White {
-        white_score as i32 - black_score as i32
+        score
     } else {
-        black_score as i32 - white_score as i32
+        -score
     }
 }
 
-fn material_scores(board: &Board) -> (u32, u32) {
-    // From <https://www.chessprogramming.org/Simplified_Evaluation_Function>
-    const P_VALUE: u32 = 100;
-    const N_VALUE: u32 = 320;
-    const B_VALUE: u32 = 330;
-    const R_VALUE: u32 = 500;
-    const Q_VALUE: u32 = 900;
-    const K_VALUE: u32 = 20000;
+// From <https://www.chessprogramming.org/Simplified_Evaluation_Function>:
+// Both sides have no queens, or each side which has a queen has at most one minor piece.
+fn is_endgame(board: &Board) -> bool {
+    [Color::White, Color::Black].iter().all(|&color| {
+        board.count_pieces(Piece::get_queen_of(color)) == 0
+            || (board.count_pieces(Piece::get_rook_of(color)) == 0
+                && board.count_pieces(Piece::get_knight_of(color))
+                    + board.count_pieces(Piece::get_bishop_of(color))
+                    <= 1)
+    })
+}
+
+fn material_scores(board: &Board, config: &EvalConfig) -> (u32, u32) {
+    board.material_scores(&config.piece_values())
+}
 
-    board.material_scores(&[P_VALUE, N_VALUE, B_VALUE, R_VALUE, Q_VALUE, K_VALUE])
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluator(backend: EvalBackend) -> Box<dyn Evaluator> {
+        new_evaluator(&EvalConfig {
+            backend,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_backends() {
+        let board: Board = "4k3/8/8/8/3N4/3P4/8/4K3 b - - 0 1".into();
+        assert_eq!(evaluator(EvalBackend::Material).eval(&board), -420);
+        let classical = evaluator(EvalBackend::Classical).eval(&board);
+        assert_eq!(classical, eval(&board, &EvalConfig::default()));
+        // The centralized knight gets a bonus.
+        let pst = evaluator(EvalBackend::Pst).eval(&board);
+        assert!(pst < classical);
+    }
+
+    #[test]
+    fn test_backend_from_str() {
+        for backend in [
+            EvalBackend::Material,
+            EvalBackend::Classical,
+            EvalBackend::Pst,
+        ] {
+            assert_eq!(backend.to_string().parse(), Ok(backend));
+        }
+        assert!("nnue".parse::<EvalBackend>().is_err());
+    }
 }
@@ -0,0 +1,270 @@
+//! Endgame knowledge.
+//! Endgames are recognized by their material key. Some are known draws, others have a high
+//! draw tendency and have their score scaled toward zero.
+//! In the elementary mates, all the safe moves of the winning piece have the same material
+//! score, so the lone king is driven to the edge and the winning king brought closer to it.
+//! <https://www.chessprogramming.org/Material_Hash_Table>
+//! <https://www.chessprogramming.org/Draw_Evaluation>
+//! <https://www.chessprogramming.org/Mop-up_Evaluation>
+
+use std::{collections::HashMap, sync::LazyLock};
+
+use crate::{
+    board::{bitboard, Board},
+    common::{Color, Piece, Score, Square},
+};
+
+use super::pawns::distance;
+
+// Identifies the material on the board: The count of each piece, on 4 bits each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialKey(u64);
+
+impl MaterialKey {
+    pub fn new(board: &Board) -> Self {
+        Self(Piece::ALL_PIECES.iter().fold(0, |key, &piece| {
+            key | (u64::from(board.count_pieces(piece)) << (4 * piece as u64))
+        }))
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn count(self, piece: Piece) -> u32 {
+        ((self.0 >> (4 * piece as u64)) & 0xF) as u32
+    }
+
+    // Number of knights, bishops, rooks and queens of that color.
+    pub fn count_non_pawn_pieces(self, color: Color) -> u32 {
+        [
+            Piece::get_knight_of(color),
+            Piece::get_bishop_of(color),
+            Piece::get_rook_of(color),
+            Piece::get_queen_of(color),
+        ]
+        .iter()
+        .map(|&piece| self.count(piece))
+        .sum()
+    }
+
+    // Same material with the colors swapped.
+    fn mirror(self) -> Self {
+        const WHITE_PIECES: u64 = 0x0F0F_0F0F_0F0F;
+        Self(((self.0 & WHITE_PIECES) << 4) | ((self.0 >> 4) & WHITE_PIECES))
+    }
+}
+
+// Creates the key from a signature listing the white pieces, then the black ones, like "KRPvKR".
+impl From<&str> for MaterialKey {
+    fn from(value: &str) -> Self {
+        let (white, black) = value.split_once('v').expect("Invalid material signature");
+        let pieces = white
+            .chars()
+            .map(|c| c.to_ascii_uppercase())
+            .chain(black.chars().map(|c| c.to_ascii_lowercase()))
+            .map(|c| Piece::try_from(c).expect("Invalid material signature"));
+        Self(pieces.fold(0, |key, piece| key + (1 << (4 * piece as u64))))
+    }
+}
+
+// Scale factors are in 1/64th.
+pub const SCALE_NORMAL: Score = 64;
+const SCALE_DRAWISH: Score = 16;
+const SCALE_ROOK_ENDGAME_PAWN_UP: Score = 32;
+const SCALE_OPPOSITE_BISHOPS: Score = 16;
+
+// Bonuses of the winning side in the elementary mates, per square.
+const LONE_KING_CENTER_DISTANCE_BONUS: Score = 20;
+const KINGS_PROXIMITY_BONUS: Score = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Endgame {
+    Draw,          // Nobody can win, whatever the position of the pieces.
+    Scaled(Score), // Hard to win, the score is scaled with this factor.
+    Mate(Color),   // The color mates the lone king.
+}
+
+impl Endgame {
+    // Same endgame with the colors swapped.
+    fn mirror(self) -> Self {
+        match self {
+            Endgame::Mate(winner) => Endgame::Mate(winner.opposite()),
+            _ => self,
+        }
+    }
+}
+
+// The endgames recognized by their exact material.
+static ENDGAMES: LazyLock<HashMap<MaterialKey, Endgame>> = LazyLock::new(|| {
+    [
+        ("KvK", Endgame::Draw),
+        ("KNvK", Endgame::Draw),
+        ("KBvK", Endgame::Draw),
+        ("KNNvK", Endgame::Draw),
+        ("KNvKN", Endgame::Scaled(SCALE_DRAWISH)),
+        ("KBvKN", Endgame::Scaled(SCALE_DRAWISH)),
+        ("KBvKB", Endgame::Scaled(SCALE_DRAWISH)),
+        ("KRvKN", Endgame::Scaled(SCALE_DRAWISH)),
+        ("KRvKB", Endgame::Scaled(SCALE_DRAWISH)),
+        ("KRvKR", Endgame::Scaled(SCALE_DRAWISH)),
+        ("KQvKQ", Endgame::Scaled(SCALE_DRAWISH)),
+        ("KQvK", Endgame::Mate(Color::White)),
+        ("KRvK", Endgame::Mate(Color::White)),
+    ]
+    .into_iter()
+    .flat_map(|(signature, endgame)| {
+        let key = MaterialKey::from(signature);
+        [(key, endgame), (key.mirror(), endgame.mirror())]
+    })
+    .collect()
+});
+
+// Endgames that are identified by more than just the material.
+fn scale_factor(board: &Board, key: MaterialKey) -> Score {
+    let only_pieces =
+        |color: Color, piece: Piece| key.count_non_pawn_pieces(color) == 1 && key.count(piece) == 1;
+    let pawns_difference = key
+        .count(Piece::WhitePawn)
+        .abs_diff(key.count(Piece::BlackPawn));
+
+    // Opposite colored bishops, with pawns.
+    if only_pieces(Color::White, Piece::WhiteBishop)
+        && only_pieces(Color::Black, Piece::BlackBishop)
+    {
+        let white_bishop_on_light =
+            board.get_pieces(Piece::WhiteBishop) & bitboard::LIGHT_SQUARES != 0;
+        let black_bishop_on_light =
+            board.get_pieces(Piece::BlackBishop) & bitboard::LIGHT_SQUARES != 0;
+        if white_bishop_on_light != black_bishop_on_light {
+            return SCALE_OPPOSITE_BISHOPS;
+        }
+    }
+
+    // Rook endings with a pawn more.
+    if only_pieces(Color::White, Piece::WhiteRook)
+        && only_pieces(Color::Black, Piece::BlackRook)
+        && pawns_difference == 1
+    {
+        return SCALE_ROOK_ENDGAME_PAWN_UP;
+    }
+
+    SCALE_NORMAL
+}
+
+// Number of files and ranks between the square and the 4 center squares, from 0 to 6.
+fn center_distance(square: Square) -> Score {
+    let from_center = |coord: u8| Score::from(coord.max(7 - coord) - 4);
+    from_center(square.get_file()) + from_center(square.get_rank())
+}
+
+// Drives the lone king to the edge, where it can be mated, and brings the kings together, as the
+// winning king is needed for the mate.
+fn mate_score(board: &Board, winner: Color, score: Score) -> Score {
+    let loser = winner.opposite();
+    let (Some(winning_king), Some(lone_king)) =
+        (board.king_square(winner), board.king_square(loser))
+    else {
+        return score;
+    };
+    // Leaves are evaluated without checking for the end of the game, and cornering the lone king
+    // is how the stalemate happens.
+    if board.get_side_to_move() == loser && !board.in_check() && board.count_legal_moves() == 0 {
+        return 0;
+    }
+    let bonus = LONE_KING_CENTER_DISTANCE_BONUS * center_distance(lone_king)
+        + KINGS_PROXIMITY_BONUS * (7 - distance(winning_king, lone_king));
+    match winner {
+        Color::White => score + bonus,
+        Color::Black => score - bonus,
+    }
+}
+
+// Adjusts the score with endgame knowledge.
+pub fn scale(board: &Board, score: Score) -> Score {
+    let key = MaterialKey::new(board);
+
+    // None of the endgames we know have more than two pieces per side.
+    if key.count_non_pawn_pieces(Color::White) > 2 || key.count_non_pawn_pieces(Color::Black) > 2 {
+        return score;
+    }
+
+    match ENDGAMES.get(&key) {
+        Some(Endgame::Draw) => 0,
+        Some(Endgame::Scaled(factor)) => score * factor / SCALE_NORMAL,
+        Some(Endgame::Mate(winner)) => mate_score(board, *winner, score),
+        None => score * scale_factor(board, key) / SCALE_NORMAL,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_material_key() {
+        let board: Board = "8/5k2/8/3p4/8/2B5/1P6/3RK3 w - - 0 1".into();
+        let key = MaterialKey::new(&board);
+        assert_eq!(key, MaterialKey::from("KRBPvKP"));
+        assert_eq!(key.count(Piece::WhiteRook), 1);
+        assert_eq!(key.count(Piece::BlackRook), 0);
+        assert_eq!(key.count(Piece::BlackPawn), 1);
+        assert_eq!(key.count_non_pawn_pieces(Color::White), 2);
+        assert_eq!(key.count_non_pawn_pieces(Color::Black), 0);
+        assert_eq!(key.mirror(), MaterialKey::from("KPvKRBP"));
+
+        let key = MaterialKey::new(&Board::initial_board());
+        assert_eq!(key.count(Piece::WhitePawn), 8);
+        assert_eq!(key.count(Piece::BlackQueen), 1);
+        assert_eq!(key.mirror(), key);
+    }
+
+    #[test]
+    fn test_draws() {
+        let board: Board = "8/8/8/4k3/8/8/8/1NN1K3 w - - 0 1".into();
+        assert_eq!(scale(&board, 640), 0);
+        let board: Board = "8/8/8/4k3/8/8/8/2b1K3 b - - 0 1".into();
+        assert_eq!(scale(&board, -330), 0);
+    }
+
+    #[test]
+    fn test_rook_endgames() {
+        let board: Board = "8/5k2/8/3r4/3P4/8/4K3/7R w - - 0 1".into();
+        assert_eq!(scale(&board, 100), 50);
+        // Two pawns up is winning.
+        let board: Board = "8/5k2/8/3r4/3P4/5P2/4K3/7R w - - 0 1".into();
+        assert_eq!(scale(&board, 200), 200);
+    }
+
+    #[test]
+    fn test_opposite_colored_bishops() {
+        let board: Board = "2b5/5k2/8/8/3P4/5P2/1P2K3/2B5 w - - 0 1".into();
+        assert_eq!(scale(&board, 300), 75);
+        // Same colored bishops.
+        let board: Board = "1b6/5k2/8/8/3P4/5P2/1P2K3/2B5 w - - 0 1".into();
+        assert_eq!(scale(&board, 300), 300);
+    }
+
+    #[test]
+    fn test_mates() {
+        // The lone king is better in the center, and far from the other king.
+        let center: Board = "8/8/8/3k4/8/8/8/Q3K3 w - - 0 1".into();
+        let edge: Board = "3k4/8/8/8/8/8/8/Q3K3 w - - 0 1".into();
+        let close: Board = "3k4/8/3K4/8/8/8/8/Q7 w - - 0 1".into();
+        assert!(scale(&center, 900) > 900);
+        assert!(scale(&edge, 900) > scale(&center, 900));
+        assert!(scale(&close, 900) > scale(&edge, 900));
+        // Same for Black.
+        let edge: Board = "3K4/8/8/8/8/8/8/r3k3 w - - 0 1".into();
+        assert!(scale(&edge, -500) < -500);
+        // Stalemate.
+        let board: Board = "k7/2Q5/1K6/8/8/8/8/8 b - - 0 1".into();
+        assert_eq!(scale(&board, 900), 0);
+        // Mate.
+        let board: Board = "k7/1Q6/1K6/8/8/8/8/8 b - - 0 1".into();
+        assert!(scale(&board, 900) > 900);
+    }
+
+    #[test]
+    fn test_no_endgame() {
+        let board = Board::initial_board();
+        assert_eq!(scale(&board, 25), 25);
+    }
+}
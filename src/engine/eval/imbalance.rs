@@ -0,0 +1,89 @@
+//! Material imbalance: Adjusts the piece values depending on the other pieces of their side, so
+//! it only needs the piece counts of the material key.
+//! Two bishops cover both square colors, while two knights or two rooks do the same job twice.
+//! Knights lose value as pawns come off the board, rooks gain some as the files open.
+//! <https://www.chessprogramming.org/Material#Imbalance>
+//! <https://www.chessprogramming.org/Bishop_Pair>
+
+use crate::common::{Color, Piece, Score};
+
+use super::endgame::MaterialKey;
+
+const BISHOP_PAIR_BONUS: Score = 30;
+const KNIGHT_PAIR_PENALTY: Score = 8;
+const ROOK_PAIR_PENALTY: Score = 16;
+// Per knight or rook, for each own pawn more or less than 5, from Larry Kaufman's
+// "The Evaluation of Material Imbalances".
+const KNIGHT_PAWN_ADJUSTMENT: Score = 6;
+const ROOK_PAWN_ADJUSTMENT: Score = 12;
+const BASE_PAWNS: Score = 5;
+
+fn side_imbalance(key: MaterialKey, color: Color) -> Score {
+    let count = |piece: Piece| Score::try_from(key.count(piece)).unwrap();
+    let knights = count(Piece::get_knight_of(color));
+    let bishops = count(Piece::get_bishop_of(color));
+    let rooks = count(Piece::get_rook_of(color));
+    let pawns = count(Piece::get_pawn_of(color));
+
+    let mut score = 0;
+    if bishops >= 2 {
+        score += BISHOP_PAIR_BONUS;
+    }
+    if knights >= 2 {
+        score -= KNIGHT_PAIR_PENALTY;
+    }
+    if rooks >= 2 {
+        score -= ROOK_PAIR_PENALTY;
+    }
+    score += knights * KNIGHT_PAWN_ADJUSTMENT * (pawns - BASE_PAWNS);
+    score -= rooks * ROOK_PAWN_ADJUSTMENT * (pawns - BASE_PAWNS);
+    score
+}
+
+// From White's point of view.
+pub fn eval(key: MaterialKey) -> Score {
+    side_imbalance(key, Color::White) - side_imbalance(key, Color::Black)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bishop_pair() {
+        assert_eq!(
+            eval(MaterialKey::from("KBBPPPPPvKBNPPPPP")),
+            BISHOP_PAIR_BONUS
+        );
+        assert_eq!(
+            eval(MaterialKey::from("KBNPPPPPvKBBPPPPP")),
+            -BISHOP_PAIR_BONUS
+        );
+        // Same material for both sides.
+        assert_eq!(eval(MaterialKey::from("KRBBPPvKRBBPP")), 0);
+    }
+
+    #[test]
+    fn test_redundancy() {
+        assert_eq!(
+            eval(MaterialKey::from("KNNPPPPPvKBNPPPPP")),
+            -KNIGHT_PAIR_PENALTY
+        );
+        assert_eq!(
+            eval(MaterialKey::from("KRRPPPPPvKQPPPPP")),
+            -ROOK_PAIR_PENALTY
+        );
+    }
+
+    #[test]
+    fn test_pawns_adjustment() {
+        // With few pawns, the rook is better than the knight.
+        let few_pawns = eval(MaterialKey::from("KRPPvKNPP"));
+        assert_eq!(
+            few_pawns,
+            3 * ROOK_PAWN_ADJUSTMENT + 3 * KNIGHT_PAWN_ADJUSTMENT
+        );
+        let many_pawns = eval(MaterialKey::from("KRPPPPPPPPvKNPPPPPPPP"));
+        assert!(many_pawns < 0);
+    }
+}
@@ -0,0 +1,108 @@
+//! Trapped pieces, recognized by patterns of squares.
+//! A bishop taking a pawn on a7 gets trapped by the pawn coming to b6, and a rook next to its
+//! king in the corner can only get out with a rook lift once the king can't castle anymore.
+//! The patterns are for White, flipped vertically for Black.
+//! <https://www.chessprogramming.org/Trapped_Pieces>
+
+use crate::{
+    board::{bitboard::BitBoard, Board},
+    common::{Color, Piece, Score, Square},
+};
+
+const TRAPPED_BISHOP_PENALTY: Score = 100;
+const BOXED_ROOK_PENALTY: Score = 50;
+
+const fn bb(square: Square) -> BitBoard {
+    1 << square as u64
+}
+
+// Bishop square, and the enemy pawn square trapping it.
+const TRAPPED_BISHOPS: [(BitBoard, BitBoard); 4] = [
+    (bb(Square::A7), bb(Square::B6)),
+    (bb(Square::H7), bb(Square::G6)),
+    (bb(Square::B8), bb(Square::C7)),
+    (bb(Square::G8), bb(Square::F7)),
+];
+
+// King squares, and the rook squares between the king and the corner. A king on these squares
+// has moved, so it can't castle to free the rook anymore.
+const BOXED_ROOKS: [(BitBoard, BitBoard); 2] = [
+    (
+        bb(Square::F1) | bb(Square::G1),
+        bb(Square::G1) | bb(Square::H1) | bb(Square::H2),
+    ),
+    (
+        bb(Square::B1) | bb(Square::C1),
+        bb(Square::A1) | bb(Square::B1) | bb(Square::A2),
+    ),
+];
+
+// The pattern for the color: Flipped vertically for Black.
+fn relative(mask: BitBoard, color: Color) -> BitBoard {
+    match color {
+        Color::White => mask,
+        Color::Black => mask.swap_bytes(),
+    }
+}
+
+fn side_penalty(board: &Board, color: Color) -> Score {
+    let bishops = board.get_pieces(Piece::get_bishop_of(color));
+    let enemy_pawns = board.get_pieces(Piece::get_pawn_of(color.opposite()));
+    let trapped_bishops = TRAPPED_BISHOPS
+        .iter()
+        .filter(|&&(bishop, pawn)| {
+            bishops & relative(bishop, color) != 0 && enemy_pawns & relative(pawn, color) != 0
+        })
+        .count();
+
+    let king = board.get_pieces(Piece::get_king_of(color));
+    let rooks = board.get_pieces(Piece::get_rook_of(color));
+    let boxed_rooks = BOXED_ROOKS
+        .iter()
+        .filter(|&&(king_squares, rook_squares)| {
+            king & relative(king_squares, color) != 0 && rooks & relative(rook_squares, color) != 0
+        })
+        .count();
+
+    TRAPPED_BISHOP_PENALTY * Score::try_from(trapped_bishops).unwrap()
+        + BOXED_ROOK_PENALTY * Score::try_from(boxed_rooks).unwrap()
+}
+
+// From White's point of view.
+pub fn eval(board: &Board) -> Score {
+    side_penalty(board, Color::Black) - side_penalty(board, Color::White)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trapped_bishop() {
+        let board: Board = "4k3/B7/1p6/8/8/8/8/4K3 w - - 0 1".into();
+        assert_eq!(eval(&board), -TRAPPED_BISHOP_PENALTY);
+        // Not trapped without the pawn.
+        let board: Board = "4k3/B7/8/8/8/8/8/4K3 w - - 0 1".into();
+        assert_eq!(eval(&board), 0);
+        // Same for Black.
+        let board: Board = "4k3/8/8/8/8/6P1/7b/4K3 w - - 0 1".into();
+        assert_eq!(eval(&board), TRAPPED_BISHOP_PENALTY);
+    }
+
+    #[test]
+    fn test_boxed_rook() {
+        let board: Board = "4k3/8/8/8/8/8/5PPP/5K1R w - - 0 1".into();
+        assert_eq!(eval(&board), -BOXED_ROOK_PENALTY);
+        // Not after castling.
+        let board: Board = "4k3/8/8/8/8/8/5PPP/5RK1 w - - 0 1".into();
+        assert_eq!(eval(&board), 0);
+        let board: Board = "1rk5/8/8/8/8/8/8/4K3 b - - 0 1".into();
+        assert_eq!(eval(&board), BOXED_ROOK_PENALTY);
+    }
+
+    #[test]
+    fn test_symmetry() {
+        let board: Board = "1rk5/B7/1p6/8/8/8/5PPP/5K1R w - - 0 1".into();
+        assert_eq!(eval(&board), -eval(&board.mirror()));
+    }
+}
@@ -0,0 +1,148 @@
+//! Pawn structure evaluation.
+//! <https://www.chessprogramming.org/Passed_Pawn>
+
+use crate::{
+    board::{
+        bitboard::{self, movements},
+        Board,
+    },
+    common::{Color, Piece, Score, Square},
+};
+
+// Bonus for a passed pawn, indexed by its rank from the pawn owner's point of view.
+pub const PASSED_PAWN_BONUS: [Score; 8] = [0, 5, 10, 20, 35, 60, 100, 0];
+
+// In the endgame, the kings should be close to the square in front of a passed pawn,
+// to escort it or to stop it. Counted per square of distance.
+const ENEMY_KING_DISTANCE_BONUS: Score = 5;
+const OWN_KING_DISTANCE_PENALTY: Score = 2;
+
+pub(super) fn distance(a: Square, b: Square) -> Score {
+    Score::from(bitboard::DISTANCE[a as usize][b as usize])
+}
+
+fn relative_rank(square: Square, color: Color) -> u8 {
+    match color {
+        Color::White => square.get_rank(),
+        Color::Black => 7 - square.get_rank(),
+    }
+}
+
+fn king_square(board: &Board, color: Color) -> Square {
+    board
+        .king_square(color)
+        .expect("Evaluated board without a king")
+}
+
+fn passed_pawns(board: &Board, color: Color, endgame: bool, passed_bonus: &[Score; 8]) -> Score {
+    let own_pawns = board.get_pieces(Piece::get_pawn_of(color));
+    let enemy_pawns = board.get_pieces(Piece::get_pawn_of(color.opposite()));
+    let passers: Vec<Square> = bitboard::squares(own_pawns)
+        .filter(|&sq| bitboard::PASSED_PAWN_MASKS[color as usize][sq as usize] & enemy_pawns == 0)
+        .collect();
+    if passers.is_empty() {
+        return 0;
+    }
+
+    let protected_squares = match color {
+        Color::White => movements::get_white_pawn_attacks(own_pawns),
+        Color::Black => movements::get_black_pawn_attacks(own_pawns),
+    };
+    let own_king = king_square(board, color);
+    let enemy_king = king_square(board, color.opposite());
+
+    passers
+        .iter()
+        .map(|&sq| {
+            let rank = relative_rank(sq, color);
+            let mut bonus = passed_bonus[rank as usize];
+
+            let protected = protected_squares & bitboard::from_square(sq) != 0;
+            let connected = passers.iter().any(|&other| {
+                bitboard::ADJACENT_FILES_MASKS[sq.get_file() as usize]
+                    & bitboard::from_square(other)
+                    != 0
+                    && other.get_rank().abs_diff(sq.get_rank()) <= 1
+            });
+            if protected {
+                bonus += bonus / 4;
+            }
+            if connected {
+                bonus += bonus / 4;
+            }
+
+            // Only matters once the pawn is advanced enough.
+            if endgame && rank >= 3 {
+                let front = match color {
+                    Color::White => Square::from(sq as u8 + 8),
+                    Color::Black => Square::from(sq as u8 - 8),
+                };
+                bonus += (Score::from(rank) - 2)
+                    * (ENEMY_KING_DISTANCE_BONUS * distance(enemy_king, front)
+                        - OWN_KING_DISTANCE_PENALTY * distance(own_king, front));
+            }
+            bonus
+        })
+        .sum()
+}
+
+// Score of the pawn structure, from White's point of view.
+pub fn eval(board: &Board, endgame: bool, passed_bonus: &[Score; 8]) -> Score {
+    passed_pawns(board, Color::White, endgame, passed_bonus)
+        - passed_pawns(board, Color::Black, endgame, passed_bonus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passed_pawns() {
+        // No passed pawns at the start.
+        assert_eq!(eval(&Board::initial_board(), false, &PASSED_PAWN_BONUS), 0);
+
+        // The d5 and h7 pawns are passed, the b4 and a7 ones aren't.
+        let board: Board = "4k3/p6p/8/3P4/1P6/8/8/4K3 w - - 0 1".into();
+        assert_eq!(
+            passed_pawns(&board, Color::White, false, &PASSED_PAWN_BONUS),
+            35
+        );
+        assert_eq!(
+            passed_pawns(&board, Color::Black, false, &PASSED_PAWN_BONUS),
+            5
+        );
+        assert_eq!(eval(&board, false, &PASSED_PAWN_BONUS), 30);
+    }
+
+    #[test]
+    fn test_protected_and_connected_passed_pawns() {
+        // The d5 pawn is protected and connected, the c4 one only connected.
+        let board: Board = "4k3/8/8/3P4/2P5/8/8/4K3 w - - 0 1".into();
+        assert_eq!(
+            passed_pawns(&board, Color::White, false, &PASSED_PAWN_BONUS),
+            53 + 25
+        );
+        // Connected on the same rank.
+        let board: Board = "4k3/8/8/2PP4/8/8/8/4K3 w - - 0 1".into();
+        assert_eq!(
+            passed_pawns(&board, Color::White, false, &PASSED_PAWN_BONUS),
+            2 * 43
+        );
+    }
+
+    #[test]
+    fn test_king_proximity() {
+        // The black king is in front of the pawn, then far away from it.
+        let close: Board = "8/3k4/8/3P4/8/8/8/4K3 w - - 0 1".into();
+        let far: Board = "8/7k/8/3P4/8/8/8/4K3 w - - 0 1".into();
+        assert_eq!(
+            eval(&close, false, &PASSED_PAWN_BONUS),
+            eval(&far, false, &PASSED_PAWN_BONUS)
+        );
+        assert!(eval(&close, true, &PASSED_PAWN_BONUS) < eval(&far, true, &PASSED_PAWN_BONUS));
+
+        // Same from Black's side, with the white king far.
+        let board: Board = "4k3/8/8/8/3p4/8/8/K7 b - - 0 1".into();
+        assert!(eval(&board, true, &PASSED_PAWN_BONUS) < eval(&board, false, &PASSED_PAWN_BONUS));
+    }
+}
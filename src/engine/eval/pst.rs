@@ -0,0 +1,154 @@
+//! Piece-square tables: Bonus or penalty for a piece depending on its square.
+//! Tables from <https://www.chessprogramming.org/Simplified_Evaluation_Function>
+
+use crate::{
+    board::{bitboard, Board},
+    common::{Color, Piece, Score},
+};
+
+// The tables are from White's point of view, the first line being the 8th rank.
+// They are indexed by the square with its rank flipped for White.
+type Table = [Score; 64];
+
+#[rustfmt::skip]
+const PAWN: Table = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT: Table = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP: Table = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK: Table = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN: Table = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+// The king should stay sheltered in the middle game, and get active in the endgame.
+#[rustfmt::skip]
+const KING_MIDDLE_GAME: Table = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+#[rustfmt::skip]
+const KING_ENDGAME: Table = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+fn side_score(board: &Board, color: Color, endgame: bool) -> Score {
+    let king = if endgame {
+        &KING_ENDGAME
+    } else {
+        &KING_MIDDLE_GAME
+    };
+    let tables = [&PAWN, &KNIGHT, &BISHOP, &ROOK, &QUEEN, king];
+    // Flipping the rank makes White's squares match the table orientation.
+    let flip = match color {
+        Color::White => 56,
+        Color::Black => 0,
+    };
+    tables
+        .iter()
+        .enumerate()
+        .map(|(i, table)| {
+            let pieces = board.get_pieces(Piece::ALL_PIECES[2 * i + color as usize]);
+            bitboard::squares(pieces)
+                .map(|square| table[square as usize ^ flip])
+                .sum::<Score>()
+        })
+        .sum()
+}
+
+// Score of the piece placement, from White's point of view.
+pub fn eval(board: &Board, endgame: bool) -> Score {
+    side_score(board, Color::White, endgame) - side_score(board, Color::Black, endgame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symmetry() {
+        assert_eq!(eval(&Board::initial_board(), false), 0);
+        let board: Board =
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 4 4".into();
+        assert_eq!(eval(&board, false), 0);
+    }
+
+    #[test]
+    fn test_placement() {
+        // A centralized knight is better than one on the rim.
+        let center: Board = "4k3/8/8/8/3N4/8/8/4K3 w - - 0 1".into();
+        let rim: Board = "4k3/8/8/8/N7/8/8/4K3 w - - 0 1".into();
+        assert_eq!(eval(&center, false) - eval(&rim, false), 20 + 30);
+        // Same for Black, with the opposite sign.
+        let center: Board = "4k3/8/8/3n4/8/8/8/4K3 w - - 0 1".into();
+        assert_eq!(eval(&center, false), -20);
+
+        // The king moves to the center in the endgame.
+        let board: Board = "8/8/8/3k4/8/8/8/6K1 w - - 0 1".into();
+        assert!(eval(&board, false) > 0);
+        assert!(eval(&board, true) < 0);
+    }
+}
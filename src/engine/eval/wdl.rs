@@ -0,0 +1,89 @@
+//! Converts scores into win / draw / loss probabilities, reported to GUIs with `UCI_ShowWDL`.
+//! The model is a logistic curve, as in Stockfish: The win probability for a score x is
+//! 1 / (1 + exp((a - x) / b)) and the loss probability is the win probability of -x.
+//! <https://github.com/official-stockfish/WDL_model>
+
+use crate::common::{Score, Value};
+
+// Score in centipawns at which half of the games are won.
+const WIN_MIDPOINT: f64 = 100.0;
+// How quickly the win probability increases around the midpoint.
+// Both constants are not fitted on Kaik's games yet. They give the usual shape where a
+// one pawn advantage wins half of the games, and an even position is mostly drawn.
+const WIN_SPREAD: f64 = 40.0;
+
+// Probabilities in per mille, as UCI expects them. They always sum up to 1000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wdl {
+    pub win: u32,
+    pub draw: u32,
+    pub loss: u32,
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn win_rate(score: Score) -> u32 {
+    (1000.0 / (1.0 + ((WIN_MIDPOINT - f64::from(score)) / WIN_SPREAD).exp())).round() as u32
+}
+
+impl Wdl {
+    // Score from the side to move point of view.
+    pub fn from_score(score: Score) -> Self {
+        let win = win_rate(score);
+        let loss = win_rate(-score);
+        Self {
+            win,
+            draw: 1000 - win - loss,
+            loss,
+        }
+    }
+
+    // A search score: Mates are won or lost for sure.
+    pub fn from_value(value: Value) -> Self {
+        if !value.is_mate() {
+            Self::from_score(value.score())
+        } else if value > Value::DRAW {
+            Self {
+                win: 1000,
+                draw: 0,
+                loss: 0,
+            }
+        } else {
+            Self {
+                win: 0,
+                draw: 0,
+                loss: 1000,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_score() {
+        let even = Wdl::from_score(0);
+        assert_eq!(even.win, even.loss);
+        assert!(even.draw > 800);
+
+        assert_eq!(Wdl::from_score(100).win, 500);
+        assert_eq!(Wdl::from_score(-100).loss, 500);
+        assert!(Wdl::from_score(1000).win > 990);
+
+        for score in [-3000, -250, -10, 0, 35, 150, 900] {
+            let wdl = Wdl::from_score(score);
+            assert_eq!(wdl.win + wdl.draw + wdl.loss, 1000);
+            let mirrored = Wdl::from_score(-score);
+            assert_eq!(wdl.win, mirrored.loss);
+            assert_eq!(wdl.loss, mirrored.win);
+        }
+    }
+
+    #[test]
+    fn test_from_value() {
+        assert_eq!(Wdl::from_value(Value::mate_in(5)).win, 1000);
+        assert_eq!(Wdl::from_value(Value::mated_in(4)).loss, 1000);
+        assert_eq!(Wdl::from_value(Value::new(150)), Wdl::from_score(150));
+    }
+}
@@ -0,0 +1,272 @@
+//! Experience file: The engine remembers the results of its searches from one game to the next.
+//! After each search, the best move of the root position is recorded with its score and depth,
+//! and the records of the game are appended to the file when it's over.
+//! Before a search, the move of the record of the root position, if any, is put in the
+//! transposition table, so that it's searched first. Only the move: The score doesn't account for
+//! the history of the game, like repetitions, and would end the search at the root with a cutoff.
+//! The file has one record per line: Zobrist key in hexadecimal, move, score and depth.
+//!
+//!     463b96181691fc9c e2e4 35 12
+
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+};
+
+use crate::{
+    board::Board,
+    common::Score,
+    engine::transposition::{Bound, TranspositionTable},
+};
+
+// Shallower searches aren't worth remembering.
+pub const MIN_DEPTH: usize = 4;
+
+pub const DEFAULT_FILE: &str = "kaik_experience.txt";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub key: u64,
+    // In pure notation, as moves can only be decoded with the board.
+    pub best_move: String,
+    pub score: Score,
+    pub depth: usize,
+}
+
+impl Record {
+    fn parse(line: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid experience record: {line}");
+        let fields: Vec<&str> = line.split_ascii_whitespace().collect();
+        let [key, best_move, score, depth] = fields[..] else {
+            return Err(invalid());
+        };
+        Ok(Self {
+            key: u64::from_str_radix(key, 16).map_err(|_| invalid())?,
+            best_move: best_move.to_string(),
+            score: score.parse().map_err(|_| invalid())?,
+            depth: depth.parse().map_err(|_| invalid())?,
+        })
+    }
+
+    fn line(&self) -> String {
+        format!(
+            "{:016x} {} {} {}",
+            self.key, self.best_move, self.score, self.depth
+        )
+    }
+}
+
+pub struct Experience {
+    path: String,
+    // Deepest record of each position.
+    records: HashMap<u64, Record>,
+    // Records of the current game, not written yet.
+    pending: Vec<Record>,
+}
+
+impl Experience {
+    // Empty experience, saved to that file.
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            records: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    // A missing file is an empty experience, created on the first save.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let mut experience = Self::new(path);
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(format!("{path}: {err}")),
+        };
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            experience.insert(Record::parse(line).map_err(|err| format!("{path}: {err}"))?);
+        }
+        Ok(experience)
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn get(&self, key: u64) -> Option<&Record> {
+        self.records.get(&key)
+    }
+
+    // Keeps the record if it's at least as deep as the known one. Returns true if it was kept.
+    fn insert(&mut self, record: Record) -> bool {
+        let known = self.records.get(&record.key);
+        if known.is_some_and(|known| known.depth > record.depth || *known == record) {
+            return false;
+        }
+        self.records.insert(record.key, record);
+        true
+    }
+
+    // Records the result of the search of the board, as found in the transposition table.
+    // Results of interrupted iterations are never stored there, so it's the last complete one.
+    pub fn learn(&mut self, board: &Board, tt: &TranspositionTable) {
+        let Some(entry) = tt.probe(board) else {
+            return;
+        };
+        let Some(best_move) = entry.best_move else {
+            return;
+        };
+        if entry.bound != Bound::Exact || entry.depth < MIN_DEPTH {
+            return;
+        }
+        let record = Record {
+            key: entry.key,
+            best_move: best_move.pure().to_string(),
+            score: entry.score,
+            depth: entry.depth,
+        };
+        if self.insert(record.clone()) {
+            self.pending.push(record);
+        }
+    }
+
+    // Puts the move of the record of the board in the transposition table, for the next search.
+    pub fn seed(&self, board: &Board, tt: &TranspositionTable) {
+        let Some(record) = self.get(board.get_zobrist_key()) else {
+            return;
+        };
+        // A legal move protects against a different position with the same key.
+        let Ok(best_move) = board.parse_pure_move(&record.best_move) else {
+            warn!("Illegal experience move {} ignored", record.best_move);
+            return;
+        };
+        info!(
+            "Experience: {} at depth {} scored {}",
+            record.best_move, record.depth, record.score
+        );
+        tt.store_line(board, &[best_move]);
+    }
+
+    // Appends the records of the game to the file.
+    pub fn save(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        for record in &self.pending {
+            writeln!(file, "{}", record.line())?;
+        }
+        self.pending.clear();
+        Ok(())
+    }
+
+    // Forgets everything, deleting the file.
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.records.clear();
+        self.pending.clear();
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::transposition::Entry, utils::fen};
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(name);
+        let path = path.to_str().unwrap().to_string();
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_record() {
+        let line = "463b96181691fc9c e2e4 -35 12";
+        let record = Record::parse(line).unwrap();
+        assert_eq!(record.key, 0x463b_9618_1691_fc9c);
+        assert_eq!(record.score, -35);
+        assert_eq!(record.line(), line);
+        assert!(Record::parse("463b96181691fc9c e2e4 35").is_err());
+        assert!(Record::parse("xyz e2e4 35 12").is_err());
+    }
+
+    #[test]
+    fn test_learn_seed() {
+        let board = Board::initial_board();
+        let mut experience = Experience::load(&temp_path("kaik_test_learn.txt")).unwrap();
+        let tt = TranspositionTable::new(1);
+        let mv = board.new_move_from_pure("d2d4");
+        let entry = |depth| Entry {
+            key: board.get_zobrist_key(),
+            depth,
+            score: 20,
+            bound: Bound::Exact,
+            best_move: Some(mv),
+        };
+
+        // Too shallow.
+        tt.store(&board, entry(MIN_DEPTH - 1));
+        experience.learn(&board, &tt);
+        assert_eq!(experience.len(), 0);
+
+        tt.store(&board, entry(6));
+        experience.learn(&board, &tt);
+        experience.learn(&board, &tt);
+        assert_eq!(experience.len(), 1);
+        assert_eq!(experience.pending.len(), 1);
+        // Shallower results don't replace deeper ones.
+        tt.store(&board, entry(5));
+        experience.learn(&board, &tt);
+        assert_eq!(experience.get(board.get_zobrist_key()).unwrap().depth, 6);
+
+        // Only the move is seeded, with an entry that can't cause a cutoff.
+        tt.clear();
+        experience.seed(&board, &tt);
+        let seeded = tt.probe(&board).unwrap();
+        assert_eq!((seeded.best_move, seeded.depth), (Some(mv), 0));
+        // Nothing known about other positions.
+        let other = Board::from_fen(fen::KIWIPETE);
+        experience.seed(&other, &tt);
+        assert_eq!(tt.probe(&other), None);
+    }
+
+    #[test]
+    fn test_save_load_clear() {
+        let path = temp_path("kaik_test_experience.txt");
+        let board = Board::from_fen(fen::KIWIPETE);
+        let mut experience = Experience::load(&path).unwrap();
+        let tt = TranspositionTable::new(1);
+        tt.store(
+            &board,
+            Entry {
+                key: board.get_zobrist_key(),
+                depth: 5,
+                score: -40,
+                bound: Bound::Exact,
+                best_move: Some(board.new_move_from_pure("e1g1")),
+            },
+        );
+        experience.learn(&board, &tt);
+        experience.save().unwrap();
+        // Saved once only.
+        experience.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+
+        let mut loaded = Experience::load(&path).unwrap();
+        assert_eq!(loaded.records, experience.records);
+        loaded.clear().unwrap();
+        assert_eq!(loaded.len(), 0);
+        assert!(!std::path::Path::new(&path).exists());
+
+        fs::write(&path, "invalid\n").unwrap();
+        assert!(Experience::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
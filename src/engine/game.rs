@@ -6,25 +6,88 @@
 //! are executed in a separate thread.
 
 use std::{
+    cell::OnceCell,
+    fs,
     io::Write,
     sync::{
         atomic::{AtomicBool, Ordering},
         mpsc::Sender,
-        Arc,
+        Arc, Mutex,
     },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 use crate::{
-    board::Board,
-    common::Move,
-    common::Score,
-    search::{self, Result},
+    board::{outcome::Outcome, Board, BoardEdit, Theme},
+    common::NULL_MOVE_PURE,
+    common::{format_moves_as_pure_string, Move},
+    common::{Score, Value},
+    engine::{
+        config::{Config, SearchConfig},
+        difficulty::Difficulty,
+        eval::{self, wdl::Wdl, EvalBackend, Evaluator},
+        experience::{self, Experience},
+        transposition::{self, TranspositionTable},
+        tunables,
+    },
+    search::{
+        draw_rules::{self, VERIFICATION_DEPTH},
+        summary,
+        trace::{Trace, TraceFormat},
+        Result, SearchAlgorithm, Searcher, MAX_PLY,
+    },
 };
 
+// The search trace is written to this file, with the extension of the format.
+const TRACE_FILE_NAME: &str = "search_trace";
+
+// How often a finished infinite search checks for the stop command.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 // Parameters passed to the search.
 #[derive(Debug, Clone, Copy, Default)]
+#[allow(clippy::struct_excessive_bools)] // Independent UCI settings.
 pub struct SearchParams {
     pub depth: Option<usize>,
+    // Clock, as sent by the GUI. Searches with neither a clock nor a depth are infinite.
+    pub white_time: Option<Duration>,
+    pub black_time: Option<Duration>,
+    pub white_increment: Option<Duration>,
+    pub black_increment: Option<Duration>,
+    // Number of moves until the next time control.
+    pub moves_to_go: Option<u32>,
+    // Exact time to search.
+    pub move_time: Option<Duration>,
+    // Maximum number of nodes to search.
+    pub nodes: Option<usize>,
+    // Debug mode, see the UCI debug command: Reports transposition table statistics.
+    pub debug: bool,
+    // Seed of the small random bonus added to the root moves scores, for opening variety.
+    pub jitter_seed: Option<u64>,
+    // Searching on the opponent's time, until a ponder hit. The UCI handler doesn't support it yet.
+    pub ponder: bool,
+    // Analysis mode: The search output shouldn't be cut short.
+    pub analyse_mode: bool,
+    // Report win / draw / loss probabilities with the score, see UCI_ShowWDL.
+    pub show_wdl: bool,
+    // Debugging: Cross-checks the move generation at each node of the search.
+    pub verify_move_gen: bool,
+    // Number of plies of the search tree to dump for debugging, 0 to disable.
+    pub trace_plies: usize,
+    pub trace_format: TraceFormat,
+    pub search: SearchConfig,
+}
+
+impl SearchParams {
+    // Without any limit, the search only ends on the stop command.
+    pub fn is_infinite(&self) -> bool {
+        self.depth.is_none()
+            && self.white_time.is_none()
+            && self.black_time.is_none()
+            && self.move_time.is_none()
+            && self.nodes.is_none()
+    }
 }
 
 // Events the game can send back to the user / UI.
@@ -35,21 +98,72 @@ pub enum Event {
 }
 
 // Whatever the engine wants to send to the UI.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum InfoData {
-    Depth(usize),   // search depth in plies
-    Score(Score),   // score from the engine's point of view in centipawns
-    ScoreMate(i32), // mate in y moves. If the engine is getting mated use negative values.
-    Nodes(usize),   // number of nodes searched
-    Pv(Vec<Move>),  // the best line found
+    Depth(usize),    // search depth in plies
+    SelDepth(usize), // selective search depth in plies
+    Score(Value),    // score from the engine's point of view, in centipawns or mate
+    Wdl(Wdl),        // win / draw / loss probabilities of the score
+    Nodes(usize),    // number of nodes searched
+    HashFull(usize), // transposition table usage in per mille
+    Pv(Vec<Move>),   // the best line found
     String(String),
 }
 
+// Life cycle of the search thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchState {
+    Idle,
+    Searching,
+    // Stop requested, but the search hasn't returned its move yet.
+    Stopping,
+}
+
+// Root position of a search, with the best line found.
+type RootLine = (Board, Vec<Move>);
+
+#[allow(clippy::struct_excessive_bools)] // Independent UCI settings.
 pub struct Game {
     board: Board,
+    // Zobrist keys of the positions before the current one, for repetitions.
+    history: Vec<u64>,
+    // Set when the position ends the game, with the reason.
+    outcome: Option<(Outcome, &'static str)>,
+    // Legal moves of the position, generated on the first call to legal_moves().
+    legal_moves: OnceCell<Vec<Move>>,
     debug: bool,
+    // Set by GUIs analysing positions rather than playing games, see UCI_AnalyseMode.
+    // Any playing-only behavior (opening book, contempt, strength limit, adjudication)
+    // must be disabled in this mode.
+    analyse_mode: bool,
+    show_wdl: bool,
+    verify_move_gen: bool,
+    trace_plies: usize,
+    trace_format: TraceFormat,
+    // JSON Lines file the search summaries are appended to, see search::summary.
+    search_log: Option<String>,
+    config: Config,
+    // Number of moves at the start of the game whose choice is randomized a bit, 0 to disable.
+    opening_variety: usize,
+    // Fixed seed to reproduce games, 0 to pick a random one for each game.
+    random_seed: u64,
+    current_seed: u64,
     stop_flag: Arc<AtomicBool>,
-    // Should we store the state of the game? Running/Over? Checkmate/Stalemate/etc?
+    search_thread: Option<JoinHandle<()>>,
+    // Position received during a search, for the next one: FEN (None for the starting position) and moves.
+    queued_position: Option<(Option<String>, Vec<String>)>,
+    // Kept between searches, as results of the previous moves are still useful.
+    tt: Arc<TranspositionTable>,
+    // Root and best line of the last search, set by the search thread. When the game follows
+    // the line, the rest of it is the best line predicted for the next search.
+    last_line: Arc<Mutex<Option<RootLine>>>,
+    // Search provided by the user of Game. If None, the built-in one of the configured algorithm is used.
+    searcher: Option<Arc<dyn Searcher>>,
+    // Same for the evaluation, the configured backend being used if None.
+    evaluator: Option<Arc<dyn Evaluator>>,
+    // Learning from previous games, None when disabled.
+    experience: Option<Arc<Mutex<Experience>>>,
+    experience_file: String,
 }
 
 impl Game {
@@ -57,109 +171,1012 @@ impl Game {
     pub fn new() -> Self {
         Self {
             board: Board::initial_board(),
+            history: Vec::new(),
+            outcome: None,
+            legal_moves: OnceCell::new(),
             debug: false,
+            analyse_mode: false,
+            show_wdl: false,
+            verify_move_gen: false,
+            trace_plies: 0,
+            trace_format: TraceFormat::default(),
+            search_log: None,
+            config: Config::default(),
+            opening_variety: 0,
+            random_seed: 0,
+            current_seed: rand::random(),
             stop_flag: Arc::new(AtomicBool::new(false)),
+            search_thread: None,
+            queued_position: None,
+            tt: Arc::new(TranspositionTable::new(transposition::DEFAULT_SIZE_MB)),
+            last_line: Arc::new(Mutex::new(None)),
+            searcher: None,
+            evaluator: None,
+            experience: None,
+            experience_file: experience::DEFAULT_FILE.to_string(),
         }
     }
 
+    // Results from the previous game are unlikely to be useful, and it makes the engine
+    // behavior reproducible.
     pub fn new_game(&mut self) {
-        self.board = Board::initial_board();
+        self.end_search("New game");
+        self.save_experience();
+        self.queued_position = None;
+        self.set_to_startpos();
+        self.clear_hash();
+        *self.last_line.lock().unwrap() = None;
+        self.current_seed = self.new_seed();
+    }
+
+    fn new_seed(&self) -> u64 {
+        if self.random_seed == 0 {
+            rand::random()
+        } else {
+            self.random_seed
+        }
+    }
+
+    pub fn set_opening_variety(&mut self, moves: usize) {
+        self.opening_variety = moves;
+    }
+
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.random_seed = seed;
+        self.current_seed = self.new_seed();
+    }
+
+    // Only when playing, analysis must show the real scores.
+    fn get_jitter_seed(&self) -> Option<u64> {
+        let random_moves = self
+            .opening_variety
+            .max(self.config.difficulty.random_moves());
+        (!self.analyse_mode && self.board.get_full_move_counter() <= random_moves)
+            .then_some(self.current_seed)
+    }
+
+    // The rest of the best line of the last search, if the game followed it to the current board.
+    fn predicted_line(&self) -> Option<Vec<Move>> {
+        let last_line = self.last_line.lock().unwrap();
+        let (root, line) = last_line.as_ref()?;
+        let mut board = *root;
+        for (i, &mv) in line.iter().enumerate() {
+            if board == self.board {
+                return Some(line[i..].to_vec());
+            }
+            board.update_by_move(mv);
+        }
+        None
+    }
+
+    // A running search goes on with the cleared table.
+    pub fn clear_hash(&mut self) {
+        self.tt.clear();
     }
 
     pub fn set_to_startpos(&mut self) {
         self.board = Board::initial_board();
+        self.history.clear();
+        self.update_outcome();
     }
 
     pub fn set_to_fen(&mut self, fen: &str) {
         self.board = Board::from_fen(fen);
+        self.history.clear();
+        self.update_outcome();
     }
 
     pub fn get_board(&self) -> Board {
         self.board
     }
 
-    pub fn display_board<W: Write>(&self, writer: &mut W) {
-        let _ = self.board.write(writer);
+    pub fn display_board<W: Write>(&self, writer: &mut W, flip: bool) {
+        let mut theme = Theme::for_write();
+        theme.flip |= flip;
+        let _ = self.board.write_with_theme(writer, theme);
+        let _ = self.board.write_state(writer);
+        let key = self.board.get_zobrist_key();
+        let repetitions = self.history.iter().filter(|&&k| k == key).count();
+        let _ = writeln!(writer, "Repetitions: {repetitions}");
+    }
+
+    pub fn display_attacks<W: Write>(&self, writer: &mut W, flip: bool) {
+        let _ = self.board.write_attacks(writer, flip);
     }
 
     pub fn apply_moves(&mut self, moves: &[String]) {
         for mv in moves {
-            self.board.update_by_move(self.board.new_move_from_pure(mv));
+            // Passing when in check would let the opponent capture the king.
+            if mv == NULL_MOVE_PURE && self.board.in_check() {
+                warn!("Ignoring null move while in check");
+                continue;
+            }
+            let key = self.board.get_zobrist_key();
+            if mv == NULL_MOVE_PURE {
+                self.board.make_null_move();
+            } else {
+                match self.board.parse_pure_move(mv) {
+                    Ok(mv) => self.board.update_by_move(mv),
+                    Err(reason) => {
+                        warn!("Illegal move {mv}: {reason}, ignoring the next moves");
+                        break;
+                    }
+                }
+            }
+            self.history.push(key);
+        }
+        self.update_outcome();
+    }
+
+    // Edits the board, for setting up positions. The history is lost, as the position may not
+    // be reachable by moves anymore.
+    pub fn edit_board(&mut self, edit: &BoardEdit) {
+        if self.get_search_state() != SearchState::Idle {
+            warn!("Ignoring board edit during a search");
+            return;
+        }
+        self.apply_queued_position();
+        self.board.apply_edit(edit);
+        self.history.clear();
+        if self.board.has_kings() {
+            self.update_outcome();
+        } else {
+            self.outcome = None;
+            self.legal_moves.take();
+        }
+    }
+
+    // Called after each change of the board.
+    fn update_outcome(&mut self) {
+        self.legal_moves.take();
+        self.outcome = self.board.game_over(&self.history);
+        if let Some((outcome, reason)) = self.outcome {
+            info!("Game over: {} by {reason}", outcome.as_pgn_result());
+        }
+    }
+
+    // The outcome of the game and its reason, if the current position ends it.
+    pub fn get_outcome(&self) -> Option<(Outcome, &'static str)> {
+        self.outcome
+    }
+
+    // The legal moves of the current position, for GUIs asking for them repeatedly.
+    // Generated once per position, they are cached until the board changes.
+    pub fn legal_moves(&self) -> &[Move] {
+        self.legal_moves
+            .get_or_init(|| self.board.generate_legal_moves())
+    }
+
+    pub fn get_search_state(&self) -> SearchState {
+        match &self.search_thread {
+            Some(handle) if !handle.is_finished() => {
+                if self.stop_flag.load(Ordering::Relaxed) {
+                    SearchState::Stopping
+                } else {
+                    SearchState::Searching
+                }
+            }
+            _ => SearchState::Idle,
         }
     }
 
     // Starts a search and returns the best move found.
     // The search is executed in a separate thread started by this function.
+    // If a search is already running, it's stopped and the new one replaces it.
     pub fn start_search(&mut self, search_params: SearchParams, event_sender: &Sender<Event>) {
-        // The spec is not explicit about what to do if we receive a start search
-        // when a search is already running.
-        // Probably we should stop the current search and start a new one.
-        // For now, we ignore the command.
-        if self.stop_flag.load(Ordering::Relaxed) {
-            warn!("A search is already running, stop it first");
+        if self.get_search_state() != SearchState::Idle {
+            info!("A search is already running, restarting it with the new parameters");
+            self.stop_search();
+        }
+        self.wait_for_search();
+        // Only once the thread is over: A stop may come while it stores the results, after
+        // the best move was sent.
+        self.stop_flag.store(false, Ordering::Relaxed);
+        self.apply_queued_position();
+        // Not when debug mode is set, which may be during a search using the table.
+        self.tt.set_verification(self.debug);
+
+        if !self.board.has_kings() {
+            let msg = "invalid position: each side needs a king".to_string();
+            event_sender
+                .send(Event::Info(vec![InfoData::String(msg)]))
+                .unwrap();
+            event_sender.send(Event::BestMove(None, None)).unwrap();
             return;
         }
 
+        // GUIs may still ask for a move, for example when a draw isn't claimed.
+        if let Some((outcome, reason)) = self.outcome {
+            let msg = format!("game over: {} by {reason}", outcome.as_pgn_result());
+            event_sender
+                .send(Event::Info(vec![InfoData::String(msg)]))
+                .unwrap();
+        }
+
         let board_clone = self.board;
-        let search_params_clone = search_params;
+        let history = self.history.clone();
+        let search_params_clone = SearchParams {
+            debug: self.debug,
+            jitter_seed: self.get_jitter_seed(),
+            analyse_mode: self.is_analyse_mode(),
+            show_wdl: self.show_wdl,
+            verify_move_gen: self.verify_move_gen,
+            trace_plies: self.trace_plies,
+            trace_format: self.trace_format,
+            search: self.config.search,
+            ..search_params
+        };
+        // Only when playing, like the opening variety.
+        let search_params_clone = if self.analyse_mode {
+            search_params_clone
+        } else {
+            self.config.difficulty.limit(search_params_clone)
+        };
         let event_sender_clone = event_sender.clone();
         let search_thread_stop_flag = self.stop_flag.clone();
+        let tt_clone = self.tt.clone();
+        // Only when playing, like the opening variety.
+        // Seeded first, so that its move, found by a deeper search, is the one of the root.
+        let experience = self.experience.clone().filter(|_| !self.analyse_mode);
+        if let Some(experience) = &experience {
+            experience.lock().unwrap().seed(&board_clone, &tt_clone);
+        }
+        // The entries of the predicted line may have been replaced since the last search.
+        if let Some(line) = self.predicted_line() {
+            info!("Predicted line {}", format_moves_as_pure_string(&line));
+            tt_clone.store_line(&board_clone, &line);
+        }
+        let last_line = self.last_line.clone();
+        let search_log = self.search_log.clone();
+        let searcher = self
+            .searcher
+            .clone()
+            .unwrap_or_else(|| Arc::new(self.config.search.algorithm));
+        let evaluator = self
+            .evaluator
+            .clone()
+            .unwrap_or_else(|| eval::new_evaluator(&self.config.eval).into());
 
-        std::thread::spawn(move || {
+        self.search_thread = Some(std::thread::spawn(move || {
             run_search(
+                searcher.as_ref(),
+                evaluator.as_ref(),
                 board_clone,
+                &history,
                 search_params_clone,
                 event_sender_clone,
                 search_thread_stop_flag,
+                &tt_clone,
+                search_log.as_deref(),
             );
-        });
+            *last_line.lock().unwrap() =
+                Some((board_clone, tt_clone.best_line(&board_clone, MAX_PLY)));
+            if let Some(experience) = experience {
+                experience.lock().unwrap().learn(&board_clone, &tt_clone);
+            }
+        }));
     }
 
     pub fn stop_search(&mut self) {
-        self.stop_flag.store(true, Ordering::Relaxed);
+        match self.get_search_state() {
+            SearchState::Searching => self.stop_flag.store(true, Ordering::Relaxed),
+            SearchState::Stopping => info!("The search is already stopping"),
+            SearchState::Idle => warn!("No search to stop"),
+        }
+    }
+
+    // Blocks until the current search, if any, is finished.
+    pub fn wait_for_search(&mut self) {
+        if let Some(handle) = self.search_thread.take() {
+            handle.join().expect("Search thread panicked");
+        }
+    }
+
+    // For changes that can't be done during a search: Waiting for an infinite search would block
+    // forever, as the stop command can't be read meanwhile.
+    fn end_search(&mut self, change: &str) {
+        if self.get_search_state() != SearchState::Idle {
+            warn!("{change} during a search, stopping it");
+            self.stop_search();
+        }
+        self.wait_for_search();
+    }
+
+    // Sets the position from a FEN, or the starting one if None, and applies the moves.
+    // During a search, the position is queued and only used by the next search.
+    pub fn set_position(&mut self, fen: Option<&str>, moves: &[String]) {
+        self.queued_position = Some((fen.map(String::from), moves.to_vec()));
+        if self.get_search_state() == SearchState::Idle {
+            self.apply_queued_position();
+        } else {
+            info!("Search running, position queued");
+        }
+    }
+
+    fn apply_queued_position(&mut self) {
+        if let Some((fen, moves)) = self.queued_position.take() {
+            if let Some(fen) = fen {
+                self.set_to_fen(&fen);
+            } else {
+                self.set_to_startpos();
+            }
+            self.apply_moves(&moves);
+        }
+    }
+
+    // A new hash size takes effect on the next search, a running one keeps its table.
+    pub fn set_config(&mut self, config: Config) {
+        if config.search.hash != self.config.search.hash {
+            self.tt = Arc::new(TranspositionTable::new(config.search.hash));
+        }
+        self.config = config;
+    }
+
+    pub fn get_config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn set_razor_margin(&mut self, margin: Score) {
+        self.config.search.razor_margin = margin;
+    }
+
+    // Sets one of the tunable search parameters, by its name.
+    pub fn set_tunable(&mut self, name: &str, value: i32) -> std::result::Result<(), String> {
+        let tunable = tunables::find(name).ok_or_else(|| format!("Unknown tunable {name}"))?;
+        tunable.set_value(&mut self.config.search, value)
+    }
+
+    pub fn set_difficulty(&mut self, difficulty: Difficulty) {
+        self.config.difficulty = difficulty;
+    }
+
+    pub fn set_search_algorithm(&mut self, algorithm: SearchAlgorithm) {
+        self.config.search.algorithm = algorithm;
+    }
+
+    // Replaces the built-in search with a custom one, None restores it.
+    // It takes effect on the next search.
+    pub fn set_searcher(&mut self, searcher: Option<Arc<dyn Searcher>>) {
+        self.searcher = searcher;
+    }
+
+    pub fn set_eval_backend(&mut self, backend: EvalBackend) {
+        self.config.eval.backend = backend;
+    }
+
+    // Replaces the evaluation of the configured backend with a custom one, None restores it.
+    // It takes effect on the next search.
+    pub fn set_evaluator(&mut self, evaluator: Option<Arc<dyn Evaluator>>) {
+        self.evaluator = evaluator;
+    }
+
+    // Stops the current search, if any.
+    // Enabling loads the experience file, disabling saves what was learned.
+    pub fn set_experience(&mut self, enabled: bool) {
+        self.end_search("Experience change");
+        if !enabled {
+            self.save_experience();
+            self.experience = None;
+        } else if self.experience.is_none() {
+            match Experience::load(&self.experience_file) {
+                Ok(experience) => {
+                    info!("Experience of {} positions loaded", experience.len());
+                    self.experience = Some(Arc::new(Mutex::new(experience)));
+                }
+                Err(err) => warn!("Experience disabled, invalid file {err}"),
+            }
+        }
+    }
+
+    pub fn set_experience_file(&mut self, path: &str) {
+        let enabled = self.experience.is_some();
+        self.set_experience(false);
+        self.experience_file = path.to_string();
+        self.set_experience(enabled);
+    }
+
+    pub fn is_experience(&self) -> bool {
+        self.experience.is_some()
+    }
+
+    // Appends what was learned during the game to the experience file.
+    pub fn save_experience(&mut self) {
+        if let Some(experience) = &self.experience {
+            if let Err(err) = experience.lock().unwrap().save() {
+                warn!(
+                    "Failed to save the experience to {}: {err}",
+                    self.experience_file
+                );
+            }
+        }
+    }
+
+    // Stops the current search, if any.
+    pub fn clear_experience(&mut self) {
+        self.end_search("Experience clearing");
+        let result = match &self.experience {
+            Some(experience) => experience.lock().unwrap().clear(),
+            // Forgetting must work even when the experience isn't used.
+            None => Experience::new(&self.experience_file).clear(),
+        };
+        if let Err(err) = result {
+            warn!(
+                "Failed to clear the experience {}: {err}",
+                self.experience_file
+            );
+        }
     }
 
+    // Debug mode checks the transposition table for Zobrist key collisions, which clears it.
+    // The table is only switched at the next search.
     pub fn set_debug(&mut self, val: bool) {
         self.debug = val;
     }
+
+    pub fn set_analyse_mode(&mut self, val: bool) {
+        self.analyse_mode = val;
+    }
+
+    pub fn is_analyse_mode(&self) -> bool {
+        self.analyse_mode
+    }
+
+    pub fn set_show_wdl(&mut self, val: bool) {
+        self.show_wdl = val;
+    }
+
+    pub fn is_show_wdl(&self) -> bool {
+        self.show_wdl
+    }
+
+    pub fn set_verify_move_gen(&mut self, val: bool) {
+        self.verify_move_gen = val;
+    }
+
+    pub fn set_trace_plies(&mut self, plies: usize) {
+        self.trace_plies = plies;
+    }
+
+    pub fn set_trace_format(&mut self, format: TraceFormat) {
+        self.trace_format = format;
+    }
+
+    // None disables the search summaries.
+    pub fn set_search_log(&mut self, path: Option<&str>) {
+        self.search_log = path.map(String::from);
+    }
 }
 
-#[allow(clippy::needless_pass_by_value)]
+#[allow(clippy::needless_pass_by_value, clippy::too_many_arguments)]
 fn run_search(
+    searcher: &dyn Searcher,
+    evaluator: &dyn Evaluator,
     board: Board,
+    history: &[u64],
     search_params: SearchParams,
     event_sender: Sender<Event>,
     stop_flag: Arc<AtomicBool>,
+    tt: &TranspositionTable,
+    search_log: Option<&str>,
 ) {
-    if stop_flag.load(Ordering::Relaxed) {
-        return; // Stop immediately
+    let result = search(
+        searcher,
+        evaluator,
+        board,
+        history,
+        &search_params,
+        &event_sender,
+        &stop_flag,
+        tt,
+        search_log,
+    );
+    // The UCI protocol doesn't allow the best move of an infinite search before the stop
+    // command, even when the search is over, on a mate or at the maximum depth.
+    if search_params.is_infinite() {
+        while !stop_flag.load(Ordering::Relaxed) {
+            thread::sleep(STOP_POLL_INTERVAL);
+        }
     }
-
-    search(board, &search_params, &event_sender, &stop_flag);
-
-    // Search is over, clearing the stop flag.
-    stop_flag.store(false, Ordering::Relaxed);
+    send_best_move(&board, &result, &event_sender);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn search(
+    searcher: &dyn Searcher,
+    evaluator: &dyn Evaluator,
     board: Board,
+    history: &[u64],
     search_params: &SearchParams,
     event_sender: &Sender<Event>,
     stop_flag: &Arc<AtomicBool>,
-) {
-    let result = search::run(&board, search_params, event_sender, stop_flag);
-    match result {
+    tt: &TranspositionTable,
+    search_log: Option<&str>,
+) -> Result {
+    let mut trace = Trace::new(search_params.trace_plies);
+    let now = Instant::now();
+    let mut run_searcher = |event_sender: &Sender<Event>| {
+        searcher.search(
+            &board,
+            search_params,
+            evaluator,
+            event_sender,
+            stop_flag,
+            tt,
+            &mut trace,
+        )
+    };
+    // The info of the last depth is only needed for the summary.
+    let (result, last_info) = if search_log.is_some() {
+        let (result, info) = summary::record(event_sender, run_searcher);
+        (result, Some(info))
+    } else {
+        (run_searcher(event_sender), None)
+    };
+    let elapsed = now.elapsed();
+    let tt_stats = tt.get_stats();
+    if trace.is_enabled() {
+        write_trace(&trace, search_params.trace_format);
+    }
+    let result = match result {
+        Result::BestMove(mv, score) => {
+            let depth = search_params
+                .depth
+                .map_or(VERIFICATION_DEPTH, |depth| depth.min(VERIFICATION_DEPTH));
+            match draw_rules::replace_root_move(&board, history, mv, score, depth, evaluator, tt) {
+                Some((replacement, msg)) => {
+                    info!("{msg}");
+                    event_sender
+                        .send(Event::Info(vec![InfoData::String(msg)]))
+                        .unwrap();
+                    Result::BestMove(replacement, score)
+                }
+                None => result,
+            }
+        }
+        _ => result,
+    };
+    if let (Some(path), Some(info)) = (search_log, last_info) {
+        summary::append(
+            path,
+            &summary::create(&board, &result, &info, elapsed, &tt_stats),
+        );
+    }
+    result
+}
+
+fn send_best_move(board: &Board, result: &Result, event_sender: &Sender<Event>) {
+    match *result {
         Result::BestMove(mv, _score) => {
-            info!("Move {}", mv);
+            info!("Move {}", mv.annotated(board));
             event_sender.send(Event::BestMove(Some(mv), None)).unwrap();
         }
-        Result::CheckMate => {
-            info!("Checkmate");
+        Result::CheckMate | Result::StaleMate => {
+            info!("{result}");
+            // Some GUIs handle the missing best move poorly, so we tell why there is none.
+            let msg = format!("no legal move: {}", result.to_string().to_lowercase());
+            event_sender
+                .send(Event::Info(vec![InfoData::String(msg)]))
+                .unwrap();
             event_sender.send(Event::BestMove(None, None)).unwrap();
         }
-        Result::StaleMate => {
-            info!("Stalemate");
-            event_sender.send(Event::BestMove(None, None)).unwrap();
+    }
+}
+
+fn write_trace(trace: &Trace, format: TraceFormat) {
+    let path = format!("{TRACE_FILE_NAME}.{}", format.extension());
+    match fs::write(&path, trace.dump(format)) {
+        Ok(()) => info!("Search trace written to {path}"),
+        Err(err) => warn!("Failed to write search trace to {path}: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    fn best_moves_count(receiver: &mpsc::Receiver<Event>) -> usize {
+        receiver
+            .try_iter()
+            .filter(|event| matches!(event, Event::BestMove(_, _)))
+            .count()
+    }
+
+    #[test]
+    fn test_restart_search() {
+        let mut game = Game::new();
+        let (sender, receiver) = mpsc::channel();
+        assert_eq!(game.get_search_state(), SearchState::Idle);
+
+        game.start_search(SearchParams::default(), &sender); // Infinite search.
+        assert_eq!(game.get_search_state(), SearchState::Searching);
+
+        // The infinite search is replaced, both send their move.
+        let search_params = SearchParams {
+            depth: Some(2),
+            ..Default::default()
+        };
+        game.start_search(search_params, &sender);
+        game.wait_for_search();
+        assert_eq!(game.get_search_state(), SearchState::Idle);
+        assert_eq!(best_moves_count(&receiver), 2);
+
+        // Nothing to stop.
+        game.stop_search();
+        assert_eq!(game.get_search_state(), SearchState::Idle);
+        assert!(!game.stop_flag.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_late_stop() {
+        let mut game = Game::new();
+        let (sender, receiver) = mpsc::channel();
+        let search_params = SearchParams {
+            depth: Some(3),
+            ..Default::default()
+        };
+        game.start_search(search_params, &sender);
+        game.wait_for_search();
+        // A stop reaching the thread after the best move, when it stores the results.
+        game.stop_flag.store(true, Ordering::Relaxed);
+
+        // The next search isn't aborted.
+        let _ = receiver.try_iter().count();
+        game.start_search(search_params, &sender);
+        game.wait_for_search();
+        assert!(receiver.try_iter().any(|event| matches!(
+            &event,
+            Event::Info(infos) if infos.iter().any(|data| matches!(data, InfoData::Depth(3)))
+        )));
+    }
+
+    #[test]
+    fn test_no_legal_move() {
+        let mut game = Game::new();
+        let (sender, receiver) = mpsc::channel();
+        game.set_to_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        let search_params = SearchParams {
+            depth: Some(1),
+            ..Default::default()
+        };
+        game.start_search(search_params, &sender);
+        game.wait_for_search();
+
+        let events: Vec<Event> = receiver.try_iter().collect();
+        let Some([Event::Info(infos), Event::BestMove(None, None)]) = events.last_chunk() else {
+            panic!("Unexpected events {events:?}");
+        };
+        assert!(matches!(&infos[..], [InfoData::String(msg)] if msg == "no legal move: stalemate"));
+    }
+
+    #[test]
+    fn test_infinite_no_legal_move() {
+        let mut game = Game::new();
+        let (sender, receiver) = mpsc::channel();
+        game.set_to_fen("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1");
+        game.start_search(SearchParams::default(), &sender);
+
+        // Over right away, but the best move waits for the stop command.
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(game.get_search_state(), SearchState::Searching);
+        assert!(!receiver
+            .try_iter()
+            .any(|event| matches!(event, Event::BestMove(_, _))));
+        // Meanwhile, the hash table is available.
+        game.clear_hash();
+
+        game.stop_search();
+        game.wait_for_search();
+        let events: Vec<Event> = receiver.try_iter().collect();
+        assert!(matches!(events.last(), Some(Event::BestMove(None, None))));
+    }
+
+    #[test]
+    fn test_debug_during_search() {
+        let mut game = Game::new();
+        let (sender, _receiver) = mpsc::channel();
+        game.start_search(SearchParams::default(), &sender);
+        game.set_debug(true);
+        assert!(!game.tt.is_verification());
+        game.stop_search();
+        game.wait_for_search();
+
+        // Applied to the table by the next search.
+        let search_params = SearchParams {
+            depth: Some(1),
+            ..Default::default()
+        };
+        game.start_search(search_params, &sender);
+        game.wait_for_search();
+        assert!(game.tt.is_verification());
+    }
+
+    #[test]
+    fn test_legal_moves() {
+        let mut game = Game::new();
+        assert_eq!(game.legal_moves().len(), 20);
+        // Cached until the board changes.
+        assert!(std::ptr::eq(game.legal_moves(), game.legal_moves()));
+
+        game.apply_moves(&["e2e4".to_string()]);
+        assert_eq!(game.legal_moves(), game.get_board().generate_legal_moves());
+        game.set_to_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert!(game.legal_moves().is_empty());
+        game.edit_board(&"remove f7".parse().unwrap());
+        assert_eq!(game.legal_moves().len(), 1);
+    }
+
+    #[test]
+    fn test_apply_illegal_moves() {
+        let mut game = Game::new();
+        // The moves after the illegal one are ignored.
+        game.apply_moves(&["e2e4", "e2e4", "e7e5"].map(String::from));
+        assert_eq!(
+            game.get_board().as_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+        assert_eq!(game.history.len(), 1);
+    }
+
+    #[test]
+    fn test_display_board() {
+        let mut game = Game::new();
+        let moves = ["g1f3", "g8f6", "f3g1", "f6g8"].map(String::from);
+        game.apply_moves(&moves);
+        let mut out = Vec::new();
+        game.display_board(&mut out, false);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(&format!("FEN: {}", game.get_board())));
+        assert!(out.contains("Halfmove clock: 4\n"));
+        assert!(out.contains("Fullmove number: 3\n"));
+        assert!(out.contains("Repetitions: 1\n"));
+    }
+
+    #[test]
+    fn test_games_in_threads() {
+        // Each game owns its search thread and hash table, so games can run side by side.
+        let handles: Vec<_> = [None, Some("8/8/8/8/8/5k2/8/4K2R w K - 0 1")]
+            .into_iter()
+            .map(|fen| {
+                let mut game = Game::new();
+                std::thread::spawn(move || {
+                    let (sender, receiver) = mpsc::channel();
+                    game.set_position(fen, &[]);
+                    let search_params = SearchParams {
+                        depth: Some(2),
+                        ..Default::default()
+                    };
+                    game.start_search(search_params, &sender);
+                    game.wait_for_search();
+                    best_moves_count(&receiver)
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), 1);
+        }
+    }
+
+    // Plays the last legal move, without searching.
+    struct LastMoveSearcher;
+
+    impl Searcher for LastMoveSearcher {
+        fn search(
+            &self,
+            board: &Board,
+            _search_params: &SearchParams,
+            _evaluator: &dyn Evaluator,
+            _event_sender: &Sender<Event>,
+            _stop_flag: &Arc<AtomicBool>,
+            _tt: &TranspositionTable,
+            _trace: &mut Trace,
+        ) -> Result {
+            Result::BestMove(*board.generate_legal_moves().last().unwrap(), Value::DRAW)
         }
     }
+
+    #[test]
+    fn test_custom_searcher() {
+        let mut game = Game::new();
+        let (sender, receiver) = mpsc::channel();
+        let expected = *game.get_board().generate_legal_moves().last().unwrap();
+        game.set_searcher(Some(Arc::new(LastMoveSearcher)));
+        let search_params = SearchParams {
+            depth: Some(1),
+            ..Default::default()
+        };
+        game.start_search(search_params, &sender);
+        game.wait_for_search();
+        let events: Vec<Event> = receiver.try_iter().collect();
+        assert!(matches!(&events[..], [Event::BestMove(Some(mv), None)] if *mv == expected));
+
+        // Back to the built-in search.
+        game.set_searcher(None);
+        game.start_search(search_params, &sender);
+        game.wait_for_search();
+        assert!(receiver
+            .try_iter()
+            .any(|event| matches!(event, Event::Info(_))));
+    }
+
+    #[test]
+    fn test_position_queued() {
+        let mut game = Game::new();
+        let (sender, receiver) = mpsc::channel();
+        game.start_search(SearchParams::default(), &sender);
+
+        let moves = ["e2e4".to_string()];
+        game.set_position(None, &moves);
+        assert_eq!(game.get_board(), Board::initial_board());
+
+        game.stop_search();
+        game.wait_for_search();
+        assert_eq!(best_moves_count(&receiver), 1);
+        // Still queued, until the next search.
+        assert_eq!(game.get_board(), Board::initial_board());
+
+        let search_params = SearchParams {
+            depth: Some(1),
+            ..Default::default()
+        };
+        game.start_search(search_params, &sender);
+        let mut board = Board::initial_board();
+        board.update_by_move(board.new_move_from_pure("e2e4"));
+        assert_eq!(game.get_board(), board);
+        game.wait_for_search();
+
+        // Applied right away when idle.
+        game.set_position(None, &[]);
+        assert_eq!(game.get_board(), Board::initial_board());
+    }
+
+    #[test]
+    fn test_jitter_seed() {
+        let mut game = Game::new();
+        assert_eq!(game.get_jitter_seed(), None);
+
+        game.set_opening_variety(2);
+        game.set_random_seed(42);
+        assert_eq!(game.get_jitter_seed(), Some(42));
+        game.new_game();
+        assert_eq!(game.get_jitter_seed(), Some(42));
+
+        game.set_analyse_mode(true);
+        assert_eq!(game.get_jitter_seed(), None);
+        game.set_analyse_mode(false);
+
+        // Only the first moves.
+        game.set_position(None, &["e2e4", "e7e5", "g1f3", "b8c6"].map(String::from));
+        assert_eq!(game.get_jitter_seed(), None);
+
+        // Random seed for each game.
+        game.set_random_seed(0);
+        game.new_game();
+        let seed = game.get_jitter_seed();
+        game.new_game();
+        assert_ne!(game.get_jitter_seed(), seed);
+    }
+
+    #[test]
+    fn test_experience() {
+        let path = std::env::temp_dir().join("kaik_test_game_experience.txt");
+        let path = path.to_str().unwrap();
+        let mut game = Game::new();
+        game.set_experience_file(path);
+        game.clear_experience();
+        game.set_experience(true);
+        assert!(game.is_experience());
+
+        // Line found at the last depth, and the best move.
+        let search = |game: &mut Game| {
+            let (sender, receiver) = mpsc::channel();
+            let search_params = SearchParams {
+                depth: Some(5),
+                ..Default::default()
+            };
+            game.start_search(search_params, &sender);
+            game.wait_for_search();
+            let events: Vec<Event> = receiver.try_iter().collect();
+            let pv = events.iter().rev().find_map(|event| match event {
+                Event::Info(info) => info.iter().find_map(|data| match data {
+                    InfoData::Pv(pv) => Some(pv.clone()),
+                    _ => None,
+                }),
+                Event::BestMove(_, _) => None,
+            });
+            let best_move = events.iter().find_map(|event| match event {
+                Event::BestMove(mv, _) => *mv,
+                Event::Info(_) => None,
+            });
+            (pv.unwrap(), best_move.unwrap())
+        };
+        let (_, best_move) = search(&mut game);
+        game.new_game();
+        let content = std::fs::read_to_string(path).unwrap();
+        assert_eq!(content.lines().count(), 1);
+        assert!(content.contains(&format!(" {} ", best_move.pure())));
+
+        // The next game starts from what was learned, the move searched first.
+        let mut next_game = Game::new();
+        next_game.set_experience_file(path);
+        next_game.set_experience(true);
+        next_game
+            .experience
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .seed(&next_game.board, &next_game.tt);
+        let root = next_game.tt.probe(&next_game.board).unwrap();
+        assert_eq!(root.best_move, Some(best_move));
+        // Not a cutoff at the root, the search goes to the requested depth.
+        let (learned_pv, learned_move) = search(&mut next_game);
+        assert_eq!(learned_move, best_move);
+        assert!(learned_pv.len() > 1, "{learned_pv:?}");
+
+        next_game.set_experience(false);
+        next_game.clear_experience();
+        assert!(!std::path::Path::new(path).exists());
+    }
+
+    #[test]
+    fn test_experience_during_search() {
+        let path = std::env::temp_dir().join("kaik_test_experience_during_search.txt");
+        let mut game = Game::new();
+        game.set_experience_file(path.to_str().unwrap());
+        let (sender, receiver) = mpsc::channel();
+
+        // The infinite searches are stopped instead of waited for.
+        game.start_search(SearchParams::default(), &sender);
+        game.set_experience(true);
+        assert!(game.is_experience());
+        assert_eq!(best_moves_count(&receiver), 1);
+
+        game.start_search(SearchParams::default(), &sender);
+        game.clear_experience();
+        assert_eq!(best_moves_count(&receiver), 1);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_predicted_line() {
+        let mut game = Game::new();
+        let (sender, _receiver) = mpsc::channel();
+        let search_params = SearchParams {
+            depth: Some(5),
+            ..Default::default()
+        };
+        game.start_search(search_params, &sender);
+        game.wait_for_search();
+        let (root, line) = game.last_line.lock().unwrap().clone().unwrap();
+        assert_eq!(root, Board::initial_board());
+        assert!(line.len() >= 3, "{line:?}");
+        assert_eq!(game.predicted_line(), Some(line.clone()));
+
+        // The opponent played the expected reply.
+        let moves = line[..2]
+            .iter()
+            .map(|mv| mv.pure().to_string())
+            .collect::<Vec<_>>();
+        game.set_position(None, &moves);
+        assert_eq!(game.predicted_line(), Some(line[2..].to_vec()));
+
+        // Or another move.
+        let other = root
+            .copy_with_move(line[0])
+            .unwrap()
+            .generate_legal_moves()
+            .into_iter()
+            .find(|&mv| mv != line[1])
+            .unwrap();
+        game.set_position(
+            None,
+            &[line[0].pure().to_string(), other.pure().to_string()],
+        );
+        assert_eq!(game.predicted_line(), None);
+
+        game.new_game();
+        assert_eq!(game.predicted_line(), None);
+    }
 }
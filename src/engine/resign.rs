@@ -0,0 +1,163 @@
+//! Resignation and draw offers, so that games don't have to be played until mate.
+//! The decisions are based on the scores of the engine's last moves: It resigns when it has been
+//! clearly lost for several moves, and offers or accepts a draw when the score stayed close to 0
+//! for long enough, late enough in the game.
+
+use crate::{
+    common::Score,
+    engine::{config::ResignConfig, game::InfoData},
+    utils::pgn::Eval,
+};
+
+// Mate scores count as this many centipawns.
+const MATE_CENTIPAWNS: Score = 100_000;
+
+// Scores of the moves played by one side, from its point of view.
+#[derive(Debug, Default)]
+pub struct ScoreHistory {
+    scores: Vec<Score>,
+}
+
+impl ScoreHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, score: Score) {
+        self.scores.push(score);
+    }
+
+    // Forgets the score of the last move, when it's taken back.
+    pub fn pop(&mut self) {
+        self.scores.pop();
+    }
+
+    // True if the last `count` scores all match the condition. A count of 0 never matches.
+    pub fn sustained(&self, count: usize, condition: impl Fn(Score) -> bool) -> bool {
+        count > 0
+            && self.scores.len() >= count
+            && self.scores[self.scores.len() - count..]
+                .iter()
+                .all(|&score| condition(score))
+    }
+
+    pub fn should_resign(&self, config: &ResignConfig) -> bool {
+        self.sustained(config.resign_moves, |score| score <= -config.resign_score)
+    }
+
+    // The full move number is the one of the game, like in FEN.
+    pub fn offers_draw(&self, config: &ResignConfig, full_move: usize) -> bool {
+        full_move >= config.draw_min_moves
+            && self.sustained(config.draw_moves, |score| score.abs() <= config.draw_score)
+    }
+
+    // A draw offer is accepted when we would offer one, or when we are worse.
+    pub fn accepts_draw(&self, config: &ResignConfig, full_move: usize) -> bool {
+        self.offers_draw(config, full_move)
+            || self
+                .scores
+                .last()
+                .is_some_and(|&score| score < -config.draw_score)
+    }
+}
+
+// The evaluation of the last search info, if any.
+pub fn eval_of(info: &[InfoData]) -> Option<Eval> {
+    info.iter().rev().find_map(|data| match data {
+        InfoData::Score(score) => Some(
+            score
+                .mate_moves()
+                .map_or(Eval::Centipawns(score.score()), Eval::Mate),
+        ),
+        _ => None,
+    })
+}
+
+pub fn score_of(eval: Eval) -> Score {
+    match eval {
+        Eval::Centipawns(score) => score,
+        Eval::Mate(moves) => mate_score(moves),
+    }
+}
+
+// Score of a mate in that number of moves, negative when getting mated.
+pub fn mate_score(moves: i32) -> Score {
+    if moves > 0 {
+        MATE_CENTIPAWNS
+    } else {
+        -MATE_CENTIPAWNS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Value;
+
+    fn history(scores: &[Score]) -> ScoreHistory {
+        let mut history = ScoreHistory::new();
+        for &score in scores {
+            history.push(score);
+        }
+        history
+    }
+
+    #[test]
+    fn test_resign() {
+        let config = ResignConfig {
+            resign_score: 500,
+            resign_moves: 3,
+            ..Default::default()
+        };
+        assert!(!history(&[-600, -700]).should_resign(&config));
+        assert!(history(&[0, -600, -700, -500]).should_resign(&config));
+        assert!(!history(&[-600, -700, -400]).should_resign(&config));
+        assert!(history(&[-600, -700, mate_score(-3)]).should_resign(&config));
+
+        let disabled = ResignConfig {
+            resign_moves: 0,
+            ..config
+        };
+        assert!(!history(&[-600, -700, -800]).should_resign(&disabled));
+    }
+
+    #[test]
+    fn test_draw() {
+        let config = ResignConfig {
+            draw_score: 10,
+            draw_moves: 2,
+            draw_min_moves: 30,
+            ..Default::default()
+        };
+        let level = history(&[40, 5, -10]);
+        assert!(level.offers_draw(&config, 30));
+        assert!(!level.offers_draw(&config, 29));
+        assert!(level.accepts_draw(&config, 30));
+
+        let winning = history(&[0, 0, 50]);
+        assert!(!winning.offers_draw(&config, 40));
+        assert!(!winning.accepts_draw(&config, 40));
+        let losing = history(&[0, 0, -50]);
+        assert!(!losing.offers_draw(&config, 40));
+        assert!(losing.accepts_draw(&config, 40));
+    }
+
+    #[test]
+    fn test_eval_of() {
+        assert_eq!(eval_of(&[]), None);
+        assert_eq!(
+            eval_of(&[
+                InfoData::Depth(3),
+                InfoData::Score(Value::new(-25)),
+                InfoData::Nodes(100)
+            ]),
+            Some(Eval::Centipawns(-25))
+        );
+        assert_eq!(
+            eval_of(&[InfoData::Score(Value::mate_in(3))]),
+            Some(Eval::Mate(2))
+        );
+        assert_eq!(score_of(Eval::Mate(2)), MATE_CENTIPAWNS);
+        assert_eq!(score_of(Eval::Centipawns(-25)), -25);
+    }
+}
@@ -1,16 +1,42 @@
 //! Search
 
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::{atomic::AtomicBool, mpsc::Sender, Arc},
+    time::Duration,
+};
 
-use crate::common::{Move, Score};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    board::Board,
+    common::{Move, Value},
+    engine::{
+        eval::Evaluator,
+        game::{Event, InfoData, SearchParams},
+        transposition::TranspositionTable,
+    },
+};
+
+// Maximum depth of the search, in plies from the root.
+pub const MAX_PLY: usize = 128;
 
 #[derive(Debug, PartialEq)]
 pub enum Result {
-    BestMove(Move, Score),
+    BestMove(Move, Value),
     CheckMate,
     StaleMate,
 }
 
+// A line of play found by the search, with its score from the side to move point of view.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub score: Value,
+    pub pv: Vec<Move>,
+}
+
 impl Display for Result {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -21,11 +47,124 @@ impl Display for Result {
     }
 }
 
+// The result as JSON for scripts, with the info sent for the last depth searched.
+pub fn json_report(
+    depth: usize,
+    result: &Result,
+    info: &[InfoData],
+    elapsed: Duration,
+) -> serde_json::Value {
+    let mut report = json!({
+        "depth": depth,
+        "time_ms": elapsed.as_millis(),
+    });
+    match result {
+        Result::BestMove(mv, _) => report["bestmove"] = json!(mv.pure().to_string()),
+        Result::CheckMate => report["result"] = json!("checkmate"),
+        Result::StaleMate => report["result"] = json!("stalemate"),
+    }
+    for data in info {
+        match data {
+            InfoData::SelDepth(seldepth) => report["seldepth"] = json!(seldepth),
+            InfoData::Score(score) => match score.mate_moves() {
+                Some(moves) => report["mate"] = json!(moves),
+                None => report["score"] = json!(score.score()),
+            },
+            InfoData::Nodes(nodes) => {
+                report["nodes"] = json!(nodes);
+                report["nps"] = json!(*nodes as u128 * 1_000_000 / elapsed.as_micros().max(1));
+            }
+            InfoData::Pv(pv) => {
+                report["pv"] = pv.iter().map(|mv| mv.pure().to_string()).collect();
+            }
+            _ => {}
+        }
+    }
+    report
+}
+
+// Search implementations, selected at runtime.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchAlgorithm {
+    #[default]
+    AlphaBeta,
+    // Searches the full tree without any pruning, much slower. As all the moves get an exact
+    // score, it's a reference to check that the pruning of alpha-beta doesn't change the result.
+    Negamax,
+}
+
+impl Display for SearchAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchAlgorithm::AlphaBeta => write!(f, "AlphaBeta"),
+            SearchAlgorithm::Negamax => write!(f, "Negamax"),
+        }
+    }
+}
+
+impl FromStr for SearchAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "alphabeta" => Ok(SearchAlgorithm::AlphaBeta),
+            "negamax" => Ok(SearchAlgorithm::Negamax),
+            _ => Err(format!("Unknown search algorithm {s}")),
+        }
+    }
+}
+
+// A search implementation, run by Game on its own thread.
+// It reports its progress on the event sender, and must return soon after the stop flag is set.
+// The best move is returned, not sent: Game sends it once the search is over.
+pub trait Searcher: Send + Sync {
+    #[allow(clippy::too_many_arguments)] // Everything a search uses is owned by Game.
+    fn search(
+        &self,
+        board: &Board,
+        search_params: &SearchParams,
+        evaluator: &dyn Evaluator,
+        event_sender: &Sender<Event>,
+        stop_flag: &Arc<AtomicBool>,
+        tt: &TranspositionTable,
+        trace: &mut Trace,
+    ) -> Result;
+}
+
+// The built-in searches, using the algorithm itself instead of the one in the parameters.
+impl Searcher for SearchAlgorithm {
+    fn search(
+        &self,
+        board: &Board,
+        search_params: &SearchParams,
+        evaluator: &dyn Evaluator,
+        event_sender: &Sender<Event>,
+        stop_flag: &Arc<AtomicBool>,
+        tt: &TranspositionTable,
+        trace: &mut Trace,
+    ) -> Result {
+        let mut search_params = *search_params;
+        search_params.search.algorithm = *self;
+        run(
+            board,
+            &search_params,
+            evaluator,
+            event_sender,
+            stop_flag,
+            tt,
+            trace,
+        )
+    }
+}
+
 mod alphabeta;
+pub mod draw_rules;
+pub mod stats;
+pub mod summary;
+mod time;
+pub mod trace;
 
-// If we have multiple search implementation they can be chosen via features.
-// The default search implementation is specified in Cargo.toml.
-// It can be changed at the command-line:
-//     cargo r --no-default-features --features negamax
-// #[cfg(feature = "alphabeta")]
-pub use alphabeta::run;
+// Both algorithms share the implementation: Negamax is alpha-beta with a full window everywhere.
+pub use alphabeta::{find_mate, multi_pv, run};
+use trace::Trace;
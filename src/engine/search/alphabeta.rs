@@ -1,180 +1,857 @@
 //! Alpha Beta search
 //! Good explanation <http://web.archive.org/web/20070704121716/http://www.brucemo.com/compchess/programming/alphabeta.htm>
+//! The search doesn't recurse: It uses an explicit stack with the state of each ply instead,
+//! so that deep searches can't overflow the thread stack.
 
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    mpsc::Sender,
-    Arc,
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread,
 };
 
 use crate::{
     board::Board,
-    common::{format_moves_as_pure_string, Move, Score, MAX_SCORE, MIN_SCORE},
+    common::{format_moves_as_pure_string, Move, Score, Value, MAX_SCORE, MIN_SCORE},
     engine::{
-        eval::eval,
+        config::SearchConfig,
+        eval::{wdl::Wdl, Evaluator},
         game::{Event, InfoData, SearchParams},
+        transposition::{Bound, Entry, TranspositionTable},
+    },
+    search::{
+        stats::SearchStats,
+        time::TimeManager,
+        trace::{Reason, Trace},
+        Line,
+        Result::{self, BestMove, CheckMate, StaleMate},
+        SearchAlgorithm, MAX_PLY,
     },
-    search::Result::{self, BestMove, CheckMate, StaleMate},
 };
 
-const MATE_SCORE: Score = 40_000;
+// Below this depth the search is too quick for the easy move detection to save anything.
+const EASY_MOVE_MIN_DEPTH: usize = 4;
 
-fn mate_in(score: Score) -> Option<i32> {
-    // Handle up to mate in 500 or so.
-    if score >= MATE_SCORE - 1000 {
-        let dist = (MATE_SCORE - score + 1) / 2;
-        info!("Mate in {dist}");
-        Some(dist)
-    } else {
-        None
-    }
+// Margins and random bonuses only make sense for centipawn scores.
+fn is_mate_score(score: Score) -> bool {
+    Value::new(score).is_mate()
 }
 
-fn mated_in(score: Score) -> Option<i32> {
-    if score <= -MATE_SCORE + 1000 {
-        let dist = (MATE_SCORE + score) / 2;
-        info!("Mated in {dist}");
-        Some(dist)
-    } else {
-        None
+// State of the search at one ply. The stack of these replaces recursion, so the depth of
+// the search is bounded by MAX_PLY and not by the size of the thread stack.
+struct Node {
+    board: Board,
+    depth: usize,
+    alpha: Score,
+    beta: Score,
+    original_alpha: Score,
+    key: u64,
+    moves: Vec<Move>,
+    next_move: usize,
+    // The move being searched, leading to the next ply.
+    current_move: Option<Move>,
+    traced: bool,
+    // Legal moves searched so far.
+    moves_searched: usize,
+    best_score: Score,
+    best_move: Option<Move>,
+    // Best line from this ply.
+    pv: Vec<Move>,
+    // Quiet moves that caused a beta cutoff at this ply. They are kept between nodes,
+    // as sibling positions are often refuted by the same move.
+    killers: [Option<Move>; 2],
+}
+
+impl Node {
+    fn new() -> Self {
+        Self {
+            board: Board::initial_board(),
+            depth: 0,
+            alpha: MIN_SCORE,
+            beta: MAX_SCORE,
+            original_alpha: MIN_SCORE,
+            key: 0,
+            moves: Vec::new(),
+            next_move: 0,
+            current_move: None,
+            traced: false,
+            moves_searched: 0,
+            best_score: MIN_SCORE,
+            best_move: None,
+            pv: Vec::new(),
+            killers: [None; 2],
+        }
     }
 }
 
-// The stop_flag should be checked regularly. When true, the search should be interrupted
-// and return the best move found so far.
-// Mate scoring logic from <http://web.archive.org/web/20070707035457/www.brucemo.com/compchess/programming/matescore.htm>
-#[allow(clippy::too_many_arguments)] // TODO Fix with a Search struct (stop_flag, nodes_count)
-fn alphabeta(
-    board: &Board,
-    depth: usize,
-    mut alpha: Score,
-    beta: Score,
-    mate: Score,
-    stop_flag: &Arc<AtomicBool>,
-    nodes_count: &mut usize,
-    pv_line: &mut Vec<Move>,
-) -> Score {
-    if depth == 0 || stop_flag.load(Ordering::Relaxed) {
-        // TODO here we should do a quiescence search, which makes the alpha-beta search much more stable.
-        // <https://www.chessprogramming.org/Quiescence_Search>
-        return eval(board);
+pub struct Search<'a> {
+    // Should be checked regularly. When true, the search should be interrupted
+    // and return the best move found so far.
+    stop_flag: &'a AtomicBool,
+    nodes_count: usize,
+    // The search stops once it has searched that many nodes.
+    node_limit: Option<usize>,
+    // Selective depth: The deepest ply reached.
+    seldepth: usize,
+    // Adds a small deterministic bonus to the root moves scores when set.
+    jitter_seed: Option<u64>,
+    // Panics when the legal moves of a node differ between the two move generations.
+    verify_move_gen: bool,
+    config: SearchConfig,
+    tt: &'a TranspositionTable,
+    evaluator: &'a dyn Evaluator,
+    // Indexed by ply.
+    stack: Vec<Node>,
+    stats: SearchStats,
+}
+
+impl<'a> Search<'a> {
+    pub fn new(
+        stop_flag: &'a AtomicBool,
+        tt: &'a TranspositionTable,
+        evaluator: &'a dyn Evaluator,
+    ) -> Self {
+        Self {
+            stop_flag,
+            nodes_count: 0,
+            node_limit: None,
+            seldepth: 0,
+            jitter_seed: None,
+            verify_move_gen: false,
+            config: SearchConfig::default(),
+            tt,
+            evaluator,
+            stack: (0..=MAX_PLY).map(|_| Node::new()).collect(),
+            stats: SearchStats::default(),
+        }
     }
 
-    let mut legal_moves = false;
-    let mut best_score = MIN_SCORE;
+    fn is_stopped(&self) -> bool {
+        self.stop_flag.load(Ordering::Relaxed)
+            || self
+                .node_limit
+                .is_some_and(|limit| self.nodes_count >= limit)
+    }
 
-    let move_list = board.generate_moves();
-    for mv in move_list {
-        if let Some(board_copy) = board.copy_with_move(mv) {
-            *nodes_count += 1;
-            let mut child_line = Vec::new();
-            let score = -alphabeta(
+    // Razoring, ProbCut and transposition table cutoffs are all forms of pruning.
+    fn is_pruning(&self) -> bool {
+        self.config.algorithm == SearchAlgorithm::AlphaBeta
+    }
+
+    // Counters of the search since it was created, for all the iterations.
+    pub fn stats(&self) -> &SearchStats {
+        &self.stats
+    }
+
+    // Best line found by the last search from that ply.
+    pub fn pv(&self, ply: usize) -> &[Move] {
+        &self.stack[ply].pv
+    }
+
+    // Searches the board, which is at the specified ply from the root.
+    // Mate scoring logic from <http://web.archive.org/web/20070707035457/www.brucemo.com/compchess/programming/matescore.htm>
+    pub fn alphabeta(
+        &mut self,
+        board: &Board,
+        depth: usize,
+        alpha: Score,
+        beta: Score,
+        root_ply: usize,
+        trace: &mut Trace,
+    ) -> Score {
+        let mut ply = root_ply;
+        let mut returned = self.enter(ply, board, depth, alpha, beta, trace);
+        loop {
+            let Some(score) = returned else {
+                // Node is being searched, continue with its next move.
+                returned = if let Some(board_copy) = self.next_child(ply, trace) {
+                    let node = &self.stack[ply];
+                    // With the jitter, root moves up to jitter_max below alpha can still beat it.
+                    let alpha = if ply == 0 && self.jitter_seed.is_some() {
+                        node.alpha.saturating_sub(self.config.jitter_max)
+                    } else {
+                        node.alpha
+                    };
+                    let (depth, alpha, beta) = match self.config.algorithm {
+                        SearchAlgorithm::AlphaBeta => (node.depth - 1, -node.beta, -alpha),
+                        // Without pruning, all moves get an exact score.
+                        SearchAlgorithm::Negamax => (node.depth - 1, MIN_SCORE, MAX_SCORE),
+                    };
+                    ply += 1;
+                    self.enter(ply, &board_copy, depth, alpha, beta, trace)
+                } else {
+                    Some(self.exit(ply))
+                };
+                continue;
+            };
+            if ply == root_ply {
+                return score;
+            }
+            ply -= 1;
+            let score = if ply == 0 {
+                self.add_jitter(-score)
+            } else {
+                -score
+            };
+            returned = if self.on_child_searched(ply, score, trace) {
+                Some(self.exit(ply))
+            } else {
+                None
+            };
+        }
+    }
+
+    // The bonus depends on the move, so that the same move gets the same bonus at each depth.
+    fn add_jitter(&self, score: Score) -> Score {
+        let Some(seed) = self.jitter_seed else {
+            return score;
+        };
+        if is_mate_score(score) {
+            return score;
+        }
+        let mv = self.stack[0].current_move.expect("No move searched");
+        let move_bits = u64::from(mv.get_from() as u8) << 8 | u64::from(mv.get_to() as u8);
+        let hash = splitmix64(seed ^ splitmix64(move_bits));
+        score
+            + Score::try_from(hash % (u64::from(self.config.jitter_max.unsigned_abs()) + 1))
+                .unwrap()
+    }
+
+    // Razoring: Near the leaves, a position whose static evaluation is far below alpha is unlikely
+    // to get back above it. A shallower null-window search verifies it, to catch tactics.
+    // <https://www.chessprogramming.org/Razoring>
+    // The verification runs as a nested search of the same ply, before the node is set up.
+    // As it's shallower, the nesting is bounded by the razoring maximum depth.
+    fn razor(&mut self, ply: usize, board: &Board, depth: usize, alpha: Score) -> Option<Score> {
+        if !self.is_pruning()
+            || ply == 0
+            || depth > self.config.razor_max_depth
+            || self.config.razor_margin <= 0
+            || is_mate_score(alpha)
+        {
+            return None;
+        }
+        let margin = self.config.razor_margin * Score::try_from(depth).unwrap();
+        if self.evaluator.eval(board) + margin >= alpha || board.in_check() {
+            return None;
+        }
+        self.stats.razor_attempts += 1;
+        let threshold = alpha - margin;
+        let score = self.alphabeta(
+            board,
+            depth - 1,
+            threshold - 1,
+            threshold,
+            ply,
+            &mut Trace::disabled(),
+        );
+        let razored = score < threshold;
+        if razored {
+            self.stats.razor_cutoffs += 1;
+        }
+        razored.then_some(score)
+    }
+
+    // ProbCut: If a good capture beats beta by a margin in a shallow search, the full depth search
+    // would very likely beat beta too. Only captures winning enough material are tried.
+    // <https://www.chessprogramming.org/ProbCut>
+    // The shallow searches run as nested searches from the next ply, before the node is set up.
+    fn probcut(&mut self, ply: usize, board: &Board, depth: usize, beta: Score) -> Option<Score> {
+        if !self.is_pruning()
+            || ply == 0
+            || depth < self.config.probcut_min_depth
+            || self.config.probcut_margin <= 0
+            || is_mate_score(beta)
+            || board.in_check()
+        {
+            return None;
+        }
+        let probcut_beta = beta + self.config.probcut_margin;
+        let piece_values = self.evaluator.piece_values();
+        // The static evaluation is a good enough estimate of the score before the capture.
+        let needed_gain = probcut_beta - self.evaluator.eval(board);
+        for mv in board.generate_captures() {
+            if board.see(mv, &piece_values) < needed_gain {
+                continue;
+            }
+            let Some(board_copy) = board.copy_with_move(mv) else {
+                continue;
+            };
+            self.tt.prefetch(board_copy.get_zobrist_key());
+            self.nodes_count += 1;
+            self.stats.probcut_attempts += 1;
+            let score = -self.alphabeta(
                 &board_copy,
-                depth - 1,
-                -beta,
-                -alpha,
-                mate - 1,
-                stop_flag,
-                nodes_count,
-                &mut child_line,
+                depth.saturating_sub(self.config.probcut_reduction),
+                -probcut_beta,
+                -probcut_beta + 1,
+                ply + 1,
+                &mut Trace::disabled(),
             );
-            legal_moves = true;
+            if self.is_stopped() {
+                return None;
+            }
+            if score >= probcut_beta {
+                self.stats.probcut_cutoffs += 1;
+                return Some(score);
+            }
+        }
+        None
+    }
+
+    // Quiescence search: At the leaves, the noisy moves are searched until the position is quiet,
+    // so that it isn't evaluated in the middle of an exchange.
+    // <https://www.chessprogramming.org/Quiescence_Search>
+    // The side to move can stand pat with the static evaluation, as it doesn't have to capture,
+    // except when in check where all the evasions are searched. The quiet checks, if asked for,
+    // are only searched on the first ply, as they don't run out like the captures.
+    // Unlike the main search, it recurses: The noisy moves run out quickly, and it stops at
+    // MAX_PLY too.
+    fn quiescence(
+        &mut self,
+        board: &Board,
+        mut alpha: Score,
+        beta: Score,
+        ply: usize,
+        quiet_checks: bool,
+    ) -> Score {
+        self.seldepth = self.seldepth.max(ply);
+        let in_check = board.in_check();
+        if ply >= MAX_PLY || self.is_stopped() {
+            return self.evaluator.eval(board);
+        }
+        let mut best_score = if in_check {
+            Value::mated_in(ply).score()
+        } else {
+            self.evaluator.eval(board)
+        };
+        if best_score >= beta {
+            return best_score;
+        }
+        alpha = alpha.max(best_score);
 
+        let piece_values = self.evaluator.piece_values();
+        let mut moves = if in_check {
+            board.generate_moves()
+        } else {
+            let mut moves = board.generate_quiescence_moves(quiet_checks);
+            // Losing material can't get above the stand pat.
+            moves.retain(|&mv| board.see(mv, &piece_values) >= 0);
+            moves
+        };
+        // The moves winning the most material first.
+        moves.sort_by_cached_key(|&mv| std::cmp::Reverse(board.see(mv, &piece_values)));
+        for mv in moves {
+            let Some(board_copy) = board.copy_with_move(mv) else {
+                continue;
+            };
+            self.nodes_count += 1;
+            self.stats.quiescence_nodes += 1;
+            let score = -self.quiescence(&board_copy, -beta, -alpha, ply + 1, false);
             if score > best_score {
                 best_score = score;
-                if score > alpha {
-                    alpha = score;
-                    // PV update.
-                    pv_line.clear();
-                    pv_line.push(mv);
-                    pv_line.extend_from_slice(&child_line);
+                if score >= beta {
+                    break;
                 }
+                alpha = alpha.max(score);
+            }
+        }
+        best_score
+    }
+
+    // Starts searching a node. Returns its score if it can be decided without searching its moves.
+    fn enter(
+        &mut self,
+        ply: usize,
+        board: &Board,
+        depth: usize,
+        alpha: Score,
+        beta: Score,
+        trace: &mut Trace,
+    ) -> Option<Score> {
+        self.stack[ply].pv.clear();
+        self.seldepth = self.seldepth.max(ply);
+        // Nothing must search beyond the stack, whatever the depth it was given.
+        if depth == 0 || ply >= MAX_PLY || self.is_stopped() {
+            if depth > 0 && self.is_stopped() {
+                trace.mark(ply, Reason::Stopped);
+            }
+            let quiet_checks = self.config.quiescence_checks;
+            return Some(self.quiescence(board, alpha, beta, ply, quiet_checks));
+        }
+
+        if let Some(score) = self.razor(ply, board, depth, alpha) {
+            trace.mark(ply, Reason::Razored);
+            return Some(score);
+        }
+        if let Some(score) = self.probcut(ply, board, depth, beta) {
+            trace.mark(ply, Reason::ProbCut);
+            return Some(score);
+        }
+
+        let key = board.get_zobrist_key();
+        let mut moves = board.generate_moves();
+        if self.verify_move_gen {
+            if let Err(err) = board.verify_legal_moves() {
+                panic!("Move generation mismatch in {err}");
             }
-            if score >= beta {
-                break; // fail soft beta-cutoff
+        }
+        if self.is_pruning() && self.config.prune_under_promotions {
+            let piece_values = self.evaluator.piece_values();
+            moves.retain(|&mv| !is_hopeless_under_promotion(board, mv, &piece_values));
+        }
+        let mut tt_move = None;
+        self.stats.tt_probes += 1;
+        if let Some(entry) = self.tt.probe(board) {
+            self.stats.tt_hits += 1;
+            // Mate scores are stored relative to the node, see Value::to_tt.
+            let score = Value::from_tt(entry.score, ply).score();
+            if self.is_pruning() && entry.depth >= depth {
+                let cutoff = match entry.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+                if cutoff {
+                    self.stats.tt_cutoffs += 1;
+                    trace.mark(ply, Reason::TranspositionCutoff);
+                    // The rest of the PV is lost.
+                    self.stack[ply].pv.extend(entry.best_move);
+                    return Some(score);
+                }
             }
+            tt_move = entry.best_move;
         }
+
+        let node = &mut self.stack[ply];
+        // Search the best move of the previous search first, it's likely to be the best again.
+        // Then the killers, which are likely to cause a cutoff, and the under-promotions last, as
+        // the queen promotion is almost always better. The sort is stable, so the order of the
+        // other moves is kept.
+        moves.sort_by_key(|&mv| {
+            if Some(mv) == tt_move {
+                0
+            } else if Some(mv) == node.killers[0] {
+                1
+            } else if Some(mv) == node.killers[1] {
+                2
+            } else if mv.get_promotion().is_some_and(|piece| !piece.is_queen()) {
+                4
+            } else {
+                3
+            }
+        });
+        node.board = *board;
+        node.depth = depth;
+        node.alpha = alpha;
+        node.beta = beta;
+        node.original_alpha = alpha;
+        node.key = key;
+        node.moves = moves;
+        node.next_move = 0;
+        node.current_move = None;
+        node.moves_searched = 0;
+        node.best_score = MIN_SCORE;
+        node.best_move = None;
+        None
     }
 
-    if legal_moves {
-        best_score
-    } else if board.in_check() {
-        -mate // Checkmate
-    } else {
-        0 // Stalemate
-          // Doesn't have to be 0, see <http://web.archive.org/web/20070707023203/http://www.brucemo.com/compchess/programming/contempt.htm>
+    // Plays the next legal move of the node, returning the resulting board.
+    fn next_child(&mut self, ply: usize, trace: &mut Trace) -> Option<Board> {
+        let node = &mut self.stack[ply];
+        while let Some(&mv) = node.moves.get(node.next_move) {
+            node.next_move += 1;
+            if let Some(board_copy) = node.board.copy_with_move(mv) {
+                self.tt.prefetch(board_copy.get_zobrist_key());
+                self.nodes_count += 1;
+                node.current_move = Some(mv);
+                node.traced = trace.enter(ply + 1, Some(mv), node.alpha, node.beta);
+                return Some(board_copy);
+            }
+        }
+        None
     }
+
+    // Updates the node with the score of its current move. Returns true on a beta cutoff.
+    fn on_child_searched(&mut self, ply: usize, score: Score, trace: &mut Trace) -> bool {
+        let (nodes, children) = self.stack.split_at_mut(ply + 1);
+        let node = &mut nodes[ply];
+        let mv = node.current_move.expect("No move searched");
+        node.moves_searched += 1;
+        if node.traced {
+            if score >= node.beta {
+                trace.mark(ply + 1, Reason::BetaCutoff);
+            }
+            trace.exit(score);
+        }
+
+        if score > node.best_score {
+            node.best_score = score;
+            node.best_move = Some(mv);
+            if score > node.alpha {
+                node.alpha = score;
+                // PV update.
+                node.pv.clear();
+                node.pv.push(mv);
+                node.pv.extend_from_slice(&children[0].pv);
+            }
+        }
+        if score < node.beta {
+            return false;
+        }
+        // Fail soft beta-cutoff.
+        self.stats.add_beta_cutoff(node.moves_searched - 1);
+        if !mv.is_capture() && mv.get_promotion().is_none() && node.killers[0] != Some(mv) {
+            node.killers[1] = node.killers[0];
+            node.killers[0] = Some(mv);
+        }
+        true
+    }
+
+    // Finishes searching a node, returning its score.
+    fn exit(&mut self, ply: usize) -> Score {
+        let node = &self.stack[ply];
+        if node.moves_searched == 0 {
+            return if node.board.in_check() {
+                Value::mated_in(ply).score() // Checkmate
+            } else {
+                0 // Stalemate
+                  // Doesn't have to be 0, see <http://web.archive.org/web/20070707023203/http://www.brucemo.com/compchess/programming/contempt.htm>
+            };
+        }
+
+        // Results of an interrupted search are not reliable.
+        if !self.is_stopped() {
+            let bound = if node.best_score <= node.original_alpha {
+                Bound::Upper
+            } else if node.best_score >= node.beta {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            self.tt.store(
+                &node.board,
+                Entry {
+                    key: node.key,
+                    depth: node.depth,
+                    score: Value::new(node.best_score).to_tt(ply),
+                    bound,
+                    best_move: node.best_move,
+                },
+            );
+        }
+        node.best_score
+    }
+}
+
+// An under-promotion is only worth it to avoid a stalemate or to give a check the queen can't.
+// Losing the promoted piece at once isn't, the queen promotion does at least as well then.
+fn is_hopeless_under_promotion(board: &Board, mv: Move, piece_values: &[u32; 6]) -> bool {
+    mv.get_promotion().is_some_and(|piece| !piece.is_queen())
+        && board.see(mv, piece_values) < 0
+        && !board.gives_check(mv)
+}
+
+// Mixes the bits of the value, see <https://prng.di.unimi.it/splitmix64.c>
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
 }
 
 // Executes an alpha-beta search with iterative deepening.
+// When playing with a clock, a watchdog thread stops the search once the hard time limit is reached.
 pub fn run(
     board: &Board,
     search_params: &SearchParams,
+    evaluator: &dyn Evaluator,
     event_sender: &Sender<Event>,
     stop_flag: &Arc<AtomicBool>,
+    tt: &TranspositionTable,
+    trace: &mut Trace,
 ) -> Result {
-    // usize::MAX is for infinite search
-    let max_depth = search_params.depth.unwrap_or(usize::MAX);
+    tt.new_search();
+    let mut search = Search::new(stop_flag, tt, evaluator);
+    search.jitter_seed = search_params.jitter_seed;
+    search.verify_move_gen = search_params.verify_move_gen;
+    search.config = search_params.search;
+    search.node_limit = search_params.nodes;
+
+    let time_manager = TimeManager::new(search_params, board.get_side_to_move());
+    // When pondering, there is no hard limit until the ponder hit.
+    let result = match time_manager.as_ref().and_then(TimeManager::hard_time_left) {
+        None => iterative_deepening(
+            &mut search,
+            board,
+            search_params,
+            event_sender,
+            trace,
+            time_manager,
+        ),
+        Some(hard_limit) => thread::scope(|s| {
+            let (done_sender, done_receiver) = mpsc::channel::<()>();
+            s.spawn(move || {
+                // Disconnected when the search is over.
+                if done_receiver.recv_timeout(hard_limit) == Err(RecvTimeoutError::Timeout) {
+                    info!("Hard time limit reached");
+                    stop_flag.store(true, Ordering::Relaxed);
+                }
+            });
+            let result = iterative_deepening(
+                &mut search,
+                board,
+                search_params,
+                event_sender,
+                trace,
+                time_manager,
+            );
+            drop(done_sender);
+            result
+        }),
+    };
+
+    if search_params.debug {
+        let msg = format!("stats {}", search.stats());
+        event_sender
+            .send(Event::Info(vec![InfoData::String(msg)]))
+            .unwrap();
+    }
+    result
+}
 
-    let mut nodes_count = 0;
-    let mut pv_line = Vec::new();
+fn iterative_deepening(
+    search: &mut Search,
+    board: &Board,
+    search_params: &SearchParams,
+    event_sender: &Sender<Event>,
+    trace: &mut Trace,
+    mut time_manager: Option<TimeManager>,
+) -> Result {
+    // Infinite searches are bounded by the stack of the search, and at least one depth is
+    // searched to have a move.
+    let max_depth = search_params.depth.unwrap_or(MAX_PLY).clamp(1, MAX_PLY);
 
     let mut result = StaleMate; // Dummy init val.
     let mut depth = 1;
     loop {
-        let score = alphabeta(
-            board,
-            depth,
-            MIN_SCORE,
-            MAX_SCORE,
-            MATE_SCORE,
-            stop_flag,
-            &mut nodes_count,
-            &mut pv_line,
-        );
-        if depth > 1 && stop_flag.load(Ordering::Relaxed) {
+        // Only the last iteration is traced.
+        trace.clear();
+        let traced = trace.enter(0, None, MIN_SCORE, MAX_SCORE);
+        search.seldepth = 0;
+        let score = search.alphabeta(board, depth, MIN_SCORE, MAX_SCORE, 0, trace);
+        if traced {
+            trace.exit(score);
+        }
+        if depth > 1 && search.is_stopped() {
             // If we got interrupted during a search at any depth beyond the first,
             // we ignore the incomplete results from that depth and use the previous one.
             break;
         }
+        if search.is_stopped() && search.pv(0).is_empty() {
+            // Stopped before a move of the first depth was searched, for example by a quit right
+            // after the go command: Any legal move is better than none, its score is unknown.
+            return match board.generate_legal_moves().first() {
+                Some(&mv) => BestMove(mv, Value::DRAW),
+                None if board.in_check() => CheckMate,
+                None => StaleMate,
+            };
+        }
+        let pv_line = search.pv(0).to_vec();
 
         info!("PV: {}", format_moves_as_pure_string(&pv_line));
 
         let mut info_data = vec![
             InfoData::Depth(depth),
-            InfoData::Nodes(nodes_count),
+            InfoData::SelDepth(search.seldepth),
+            InfoData::Nodes(search.nodes_count),
+            InfoData::HashFull(search.tt.hashfull()),
             InfoData::Pv(pv_line.clone()),
         ];
 
-        if let Some(mate_in) = mate_in(score) {
-            info_data.push(InfoData::ScoreMate(mate_in));
-        } else if let Some(mated_in) = mated_in(score) {
-            if mated_in == 0 {
-                debug_assert!(pv_line.is_empty());
-                return CheckMate;
-            }
-            // Use negative values if we are getting mated.
-            info_data.push(InfoData::ScoreMate(-mated_in));
-        } else {
-            info_data.push(InfoData::Score(score));
+        let value = Value::new(score);
+        if value == Value::mated_in(0) {
+            debug_assert!(pv_line.is_empty());
+            return CheckMate;
         }
+        info_data.push(InfoData::Score(value));
+        if search_params.show_wdl {
+            info_data.push(InfoData::Wdl(Wdl::from_value(value)));
+        }
+        if let Some(moves) = value.mate_moves() {
+            info!("Mate in {moves}");
+        }
+        let mate_found = value.is_mate();
 
         event_sender.send(Event::Info(info_data)).unwrap();
+        if search_params.debug {
+            let stats = search.tt.get_stats();
+            let msg = format!(
+                "tt probes {} hits {} collisions {}",
+                stats.probes, stats.hits, stats.collisions
+            );
+            event_sender
+                .send(Event::Info(vec![InfoData::String(msg)]))
+                .unwrap();
+        }
 
         if pv_line.is_empty() {
             return StaleMate;
         }
 
-        result = BestMove(pv_line[0], score);
+        result = BestMove(pv_line[0], value);
 
-        depth += 1;
-        if depth >= max_depth || stop_flag.load(Ordering::Relaxed) {
+        // Searching deeper won't find a shorter mate, so no need to continue when playing.
+        // In analysis mode, GUIs expect the output of each depth up to the requested one.
+        let limited = search_params.depth.is_some() || time_manager.is_some();
+        if mate_found && !search_params.analyse_mode && limited {
             break;
         }
+
+        if let Some(tm) = time_manager.as_mut() {
+            tm.on_iteration(pv_line[0]);
+            // The verification is only worth its cost once the best move has settled.
+            if depth >= EASY_MOVE_MIN_DEPTH
+                && !tm.is_easy_move()
+                && tm.is_best_move_stable()
+                && is_easy_move(search, board, pv_line[0], score, depth)
+            {
+                info!("Easy move {}", pv_line[0]);
+                tm.set_easy_move();
+            }
+            if tm.should_stop() {
+                break;
+            }
+        }
+
+        if depth >= max_depth || search.is_stopped() {
+            break;
+        }
+        depth += 1;
     }
     result
 }
 
+// An easy move is one that is much better than all the other moves, like recapturing a piece.
+// Confirming it doesn't need exact scores for the other moves: A reduced depth null-window search
+// around the best score minus a margin is enough to show that none of them comes close.
+fn is_easy_move(
+    search: &mut Search,
+    board: &Board,
+    best_move: Move,
+    best_score: Score,
+    depth: usize,
+) -> bool {
+    if is_mate_score(best_score) {
+        return false;
+    }
+    let threshold = best_score - search.config.easy_move_margin;
+    let verification_depth = (depth / 2).max(1);
+    let easy = board
+        .generate_moves()
+        .into_iter()
+        .filter(|&mv| mv != best_move)
+        .all(|mv| {
+            board.copy_with_move(mv).is_none_or(|board_copy| {
+                search.nodes_count += 1;
+                let score = -search.alphabeta(
+                    &board_copy,
+                    verification_depth - 1,
+                    -threshold,
+                    -threshold + 1,
+                    1,
+                    &mut Trace::disabled(),
+                );
+                score < threshold
+            })
+        });
+    // Results of an interrupted search are not reliable.
+    easy && !search.is_stopped()
+}
+
+// Searches a forced mate by the side to move in up to that many moves, for solving puzzles.
+// Razoring and ProbCut are disabled, so that no mate is missed. The window only accepts mates
+// within the depth of each iteration: Any other score fails low at once, so an iteration only
+// proves or refutes a mate, and the first one finding it gives the shortest.
+pub fn find_mate(
+    board: &Board,
+    max_moves: usize,
+    stop_flag: &Arc<AtomicBool>,
+    tt: &TranspositionTable,
+    evaluator: &dyn Evaluator,
+) -> Option<Line> {
+    let mut search = Search::new(stop_flag, tt, evaluator);
+    search.config.razor_margin = 0;
+    search.config.probcut_margin = 0;
+    for moves in 1..=max_moves {
+        let depth = 2 * moves - 1;
+        if depth > MAX_PLY {
+            break;
+        }
+        // Mate on the last ply of the depth, or sooner.
+        let alpha = Value::mate_in(depth + 1).score();
+        let score = search.alphabeta(board, depth, alpha, MAX_SCORE, 0, &mut Trace::disabled());
+        if search.is_stopped() {
+            return None;
+        }
+        if score > alpha {
+            return Some(Line {
+                score: Value::new(score),
+                pv: search.pv(0).to_vec(),
+            });
+        }
+    }
+    None
+}
+
+// Searches all root moves to the specified depth and returns the best lines, best first.
+// Used for analysis, where alternatives to the best move are interesting as well.
+pub fn multi_pv(
+    board: &Board,
+    depth: usize,
+    lines_count: usize,
+    stop_flag: &Arc<AtomicBool>,
+    tt: &TranspositionTable,
+    evaluator: &dyn Evaluator,
+) -> Vec<Line> {
+    assert!(depth > 0 && lines_count > 0);
+    let mut search = Search::new(stop_flag, tt, evaluator);
+    let mut lines: Vec<Line> = Vec::new();
+
+    for mv in board.generate_moves() {
+        if let Some(board_copy) = board.copy_with_move(mv) {
+            // A move needs an exact score only if it can make it to the list of the best lines.
+            let alpha = if lines.len() == lines_count {
+                lines[lines_count - 1].score.score()
+            } else {
+                MIN_SCORE
+            };
+
+            let score = -search.alphabeta(
+                &board_copy,
+                depth - 1,
+                -MAX_SCORE,
+                -alpha,
+                1,
+                &mut Trace::disabled(),
+            );
+            if score > alpha {
+                let mut pv = vec![mv];
+                pv.extend_from_slice(search.pv(1));
+                lines.push(Line {
+                    score: Value::new(score),
+                    pv,
+                });
+                lines.sort_by_key(|line| -line.score);
+                lines.truncate(lines_count);
+            }
+        }
+    }
+    lines
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -182,60 +859,242 @@ mod tests {
     use crate::common::Move;
     use crate::common::Piece::*;
     use crate::common::Square::*;
+    use crate::engine::config::DEFAULT_JITTER_MAX;
+    use crate::engine::eval::Classical;
+    use crate::utils::fen;
 
-    #[test]
-    fn test_startpos_depth_4() {
-        let board = Board::initial_board();
-        let mut nodes_count = 0;
-        let mut pv_line = Vec::new();
-        let score = alphabeta(
-            &board,
-            4,
+    // Returns the score, the number of nodes searched and the PV.
+    fn search(board: &Board, depth: usize) -> (Score, usize, Vec<Move>) {
+        let stop_flag = AtomicBool::new(false);
+        let tt = TranspositionTable::new(1);
+        let evaluator = Classical::default();
+        let mut search = Search::new(&stop_flag, &tt, &evaluator);
+        let score = search.alphabeta(
+            board,
+            depth,
             MIN_SCORE,
             MAX_SCORE,
-            MATE_SCORE,
-            &Arc::new(AtomicBool::new(false)),
-            &mut nodes_count,
-            &mut pv_line,
+            0,
+            &mut Trace::disabled(),
         );
+        (score, search.nodes_count, search.pv(0).to_vec())
+    }
+
+    #[test]
+    fn test_find_mate() {
+        let find = |fen: &str, max_moves| {
+            let stop_flag = Arc::new(AtomicBool::new(false));
+            let tt = TranspositionTable::new(1);
+            find_mate(
+                &Board::from_fen(fen),
+                max_moves,
+                &stop_flag,
+                &tt,
+                &Classical::default(),
+            )
+        };
+
+        let line = find("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3).unwrap();
+        assert_eq!(line.score.mate_moves(), Some(1));
+        assert_eq!(line.pv, [Move::quiet(A1, A8, WhiteRook)]);
+
+        // The king must come closer first.
+        let fen = "k7/8/2K5/8/8/8/8/7R w - - 0 1";
+        assert_eq!(find(fen, 1), None);
+        let line = find(fen, 3).unwrap();
+        assert_eq!(line.score.mate_moves(), Some(2));
+        let mut board = Board::from_fen(fen);
+        for mv in &line.pv {
+            board.update_by_move(*mv);
+        }
+        assert!(board.in_check() && board.generate_legal_moves().is_empty());
+
+        assert_eq!(find(fen::KIWIPETE, 2), None);
+    }
+
+    #[test]
+    fn test_startpos_depth_4() {
+        let board = Board::initial_board();
+        let (score, nodes_count, pv_line) = search(&board, 4);
 
         assert_eq!(pv_line[0], Move::quiet(A2, A3, WhitePawn));
         assert_eq!(score, 0);
-        assert_eq!(nodes_count, 2024);
+        assert_eq!(nodes_count, 1310);
         assert_eq!(
             pv_line,
             [
                 Move::quiet(A2, A3, WhitePawn),
-                Move::quiet(A7, A5, BlackPawn),
+                Move::double_push(A7, A5, BlackPawn),
                 Move::quiet(B2, B3, WhitePawn),
                 Move::quiet(A5, A4, BlackPawn),
             ]
         );
-        assert_eq!(mate_in(score), None);
-        assert_eq!(mated_in(score), None);
+        assert!(!Value::new(score).is_mate());
     }
 
     #[test]
     fn test_mated_minus_1() {
         // Mated on next move.
         let board: Board = "2kr1b2/Rp3pp1/8/8/2b1K2r/4P1pP/8/1NB1nBNR w - - 0 40".into();
-        let mut nodes_count = 0;
-        let mut pv_line = Vec::new();
-        let score = alphabeta(
-            &board,
-            4,
+        let (score, _, pv_line) = search(&board, 4);
+
+        assert_eq!(pv_line[0], Move::quiet(E4, E5, WhiteKing));
+        assert_eq!(Value::new(score), Value::mated_in(2));
+        assert_eq!(Value::new(score).mate_moves(), Some(-1));
+    }
+
+    // Returns the score, the number of nodes searched and the best move.
+    fn search_with_config(
+        board: &Board,
+        depth: usize,
+        config: SearchConfig,
+    ) -> (Score, usize, Move) {
+        let stop_flag = AtomicBool::new(false);
+        let tt = TranspositionTable::new(1);
+        let evaluator = Classical::default();
+        let mut search = Search::new(&stop_flag, &tt, &evaluator);
+        search.config = config;
+        let score = search.alphabeta(
+            board,
+            depth,
             MIN_SCORE,
             MAX_SCORE,
-            MATE_SCORE,
-            &Arc::new(AtomicBool::new(false)),
-            &mut nodes_count,
-            &mut pv_line,
+            0,
+            &mut Trace::disabled(),
         );
+        (score, search.nodes_count, search.pv(0)[0])
+    }
 
-        assert_eq!(pv_line[0], Move::quiet(E4, E5, WhiteKing));
-        assert_eq!(mated_in(score), Some(1));
-        assert_eq!(mate_in(score), None);
-        assert_eq!(score, -MATE_SCORE + 2);
+    #[test]
+    fn test_negamax() {
+        let board = Board::initial_board();
+        let config = SearchConfig {
+            algorithm: SearchAlgorithm::Negamax,
+            ..Default::default()
+        };
+        let stop_flag = AtomicBool::new(false);
+        let tt = TranspositionTable::new(1);
+        let evaluator = Classical::default();
+        let mut negamax = Search::new(&stop_flag, &tt, &evaluator);
+        negamax.config = config;
+        let score = negamax.alphabeta(&board, 4, MIN_SCORE, MAX_SCORE, 0, &mut Trace::disabled());
+        // All the nodes of the tree are searched: The sum of the perft results up to depth 4,
+        // then the quiescence search from the leaves.
+        assert_eq!(
+            negamax.nodes_count - negamax.stats().quiescence_nodes,
+            20 + 400 + 8902 + 197_281
+        );
+        // Same score as alpha-beta, which prunes most of them.
+        assert_eq!(score, search(&board, 4).0);
+    }
+
+    #[test]
+    fn test_razoring() {
+        // White is a rook up, so most of black's replies are far below alpha.
+        let board: Board = "r3k3/ppp2ppp/2n5/8/3P4/2N5/PPP2PPP/R3K2R w KQq - 0 1".into();
+        let config = SearchConfig {
+            probcut_margin: 0,
+            ..Default::default()
+        };
+        let (score, nodes_count, best_move) = search_with_config(
+            &board,
+            4,
+            SearchConfig {
+                razor_margin: 0,
+                ..config
+            },
+        );
+        let (razored_score, razored_nodes_count, razored_best_move) =
+            search_with_config(&board, 4, config);
+        assert!(razored_nodes_count < nodes_count);
+        assert_eq!(razored_best_move, best_move);
+        assert!((razored_score - score).abs() < 100);
+    }
+
+    #[test]
+    fn test_probcut() {
+        // The white queen is attacked, capturing it refutes all the other moves.
+        let board: Board = "4k3/8/1p6/2p5/3Q4/8/5PPP/4K3 w - - 0 1".into();
+        let config = SearchConfig {
+            razor_margin: 0,
+            ..Default::default()
+        };
+        let (score, nodes_count, best_move) = search_with_config(
+            &board,
+            6,
+            SearchConfig {
+                probcut_margin: 0,
+                ..config
+            },
+        );
+        let (probcut_score, probcut_nodes_count, probcut_best_move) =
+            search_with_config(&board, 6, config);
+        assert!(probcut_nodes_count < nodes_count);
+        assert_eq!(probcut_best_move, best_move);
+        assert!((probcut_score - score).abs() < 100);
+    }
+
+    #[test]
+    fn test_under_promotions() {
+        let board: Board = "r3k3/1P6/8/8/8/8/6p1/4K2R w - - 0 1".into();
+        let piece_values = Classical::default().piece_values();
+        let hopeless =
+            |mv| is_hopeless_under_promotion(&board, board.new_move_from_pure(mv), &piece_values);
+        assert!(hopeless("b7b8n"));
+        assert!(hopeless("b7b8b"));
+        assert!(!hopeless("b7b8q"));
+        // Check.
+        assert!(!hopeless("b7b8r"));
+        // Wins the rook.
+        assert!(!hopeless("b7a8n"));
+
+        let config = SearchConfig {
+            prune_under_promotions: false,
+            ..Default::default()
+        };
+        let (score, nodes_count, best_move) = search_with_config(&board, 4, config);
+        let (pruned_score, pruned_nodes_count, pruned_best_move) =
+            search_with_config(&board, 4, SearchConfig::default());
+        assert!(pruned_nodes_count < nodes_count);
+        assert_eq!(pruned_best_move, best_move);
+        assert_eq!(pruned_score, score);
+    }
+
+    #[test]
+    fn test_tt_mate_distance() {
+        let stop_flag = AtomicBool::new(false);
+        let tt = TranspositionTable::new(1);
+        let evaluator = Classical::default();
+        let board: Board = "k7/8/2K5/8/8/8/8/7R w - - 0 1".into();
+        let child = board
+            .copy_with_move(Move::quiet(C6, B6, WhiteKing))
+            .unwrap();
+
+        // Stored at the root of the first search.
+        let mut search = Search::new(&stop_flag, &tt, &evaluator);
+        let score = search.alphabeta(&child, 3, MIN_SCORE, MAX_SCORE, 0, &mut Trace::disabled());
+        assert_eq!(Value::new(score), Value::mated_in(2));
+
+        // Found one ply deeper in the second one, too shallow to see the mate by itself.
+        let mut search = Search::new(&stop_flag, &tt, &evaluator);
+        let score = search.alphabeta(&board, 2, MIN_SCORE, MAX_SCORE, 0, &mut Trace::disabled());
+        assert_eq!(Value::new(score), Value::mate_in(3));
+        assert_eq!(Value::new(score).mate_moves(), Some(2));
+        assert_eq!(search.pv(0)[0], Move::quiet(C6, B6, WhiteKing));
+
+        // The deeper iterations find the mate in the table at all plies.
+        for depth in 3..=6 {
+            let mut search = Search::new(&stop_flag, &tt, &evaluator);
+            let score = search.alphabeta(
+                &board,
+                depth,
+                MIN_SCORE,
+                MAX_SCORE,
+                0,
+                &mut Trace::disabled(),
+            );
+            assert_eq!(Value::new(score), Value::mate_in(3));
+        }
     }
 
     #[test]
@@ -243,45 +1102,457 @@ mod tests {
         // Has both a smothered mate via a queen sacrifice and simpler
         // one via a knight sacrifice, in 2 moves.
         let board: Board = "2r4k/6pp/8/4N3/8/1Q6/B5PP/7K w - - 0 1".into();
-        let mut nodes_count = 0;
-        let mut pv_line = Vec::new();
-        let score = alphabeta(
+        let (score, _, pv_line) = search(&board, 4);
+
+        assert_eq!(pv_line[0], Move::quiet(E5, G6, WhiteKnight));
+        assert_eq!(Value::new(score), Value::mate_in(3));
+        assert_eq!(Value::new(score).mate_moves(), Some(2));
+    }
+
+    #[test]
+    fn test_max_ply() {
+        // The search doesn't recurse, so even a small thread stack is enough to reach MAX_PLY.
+        let handle = std::thread::Builder::new()
+            .stack_size(128 * 1024)
+            .spawn(|| {
+                let board: Board = "8/8/4k3/8/8/4K3/8/8 w - - 0 1".into();
+                let (sender, receiver) = std::sync::mpsc::channel();
+                let result = run(
+                    &board,
+                    &SearchParams::default(), // Infinite search.
+                    &Classical::default(),
+                    &sender,
+                    &Arc::new(AtomicBool::new(false)),
+                    &TranspositionTable::new(1),
+                    &mut Trace::disabled(),
+                );
+                let depths = receiver.try_iter().count();
+                (result, depths)
+            })
+            .unwrap();
+        let (result, depths) = handle.join().unwrap();
+        assert!(matches!(result, BestMove(_, Value::DRAW)));
+        assert_eq!(depths, MAX_PLY);
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let board = Board::initial_board();
+        for depth in [1, 2, 5] {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let search_params = SearchParams {
+                depth: Some(depth),
+                ..Default::default()
+            };
+            run(
+                &board,
+                &search_params,
+                &Classical::default(),
+                &sender,
+                &Arc::new(AtomicBool::new(false)),
+                &TranspositionTable::new(1),
+                &mut Trace::disabled(),
+            );
+            // The requested depth is the last one searched.
+            let depths: Vec<usize> = receiver
+                .try_iter()
+                .filter_map(|event| match event {
+                    Event::Info(infos) => infos.iter().find_map(|info| match info {
+                        InfoData::Depth(depth) => Some(*depth),
+                        _ => None,
+                    }),
+                    Event::BestMove(..) => None,
+                })
+                .collect();
+            assert_eq!(depths, (1..=depth).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_quiescence() {
+        // Taking the pawn loses the queen to the recapture, found by the quiescence search.
+        let board: Board = "7k/8/4p3/3p4/8/8/8/K2Q4 w - - 0 1".into();
+        let (_, _, pv_line) = search(&board, 1);
+        assert_ne!(pv_line[0], Move::capture(D1, D5, WhiteQueen, BlackPawn));
+
+        // The promotion is a noisy move.
+        let board: Board = "7k/1P6/8/8/8/8/8/K7 w - - 0 1".into();
+        let stop_flag = AtomicBool::new(false);
+        let tt = TranspositionTable::new(1);
+        let evaluator = Classical::default();
+        let mut search = Search::new(&stop_flag, &tt, &evaluator);
+        let score = search.quiescence(&board, MIN_SCORE, MAX_SCORE, 0, false);
+        assert!(score > evaluator.eval(&board) + 500);
+        assert!(search.stats().quiescence_nodes > 0);
+    }
+
+    #[test]
+    fn test_quiescence_checks() {
+        // The mate is a quiet move, only found when the checks are searched.
+        let board: Board = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1".into();
+        let stop_flag = AtomicBool::new(false);
+        let tt = TranspositionTable::new(1);
+        let evaluator = Classical::default();
+        let mut search = Search::new(&stop_flag, &tt, &evaluator);
+        let score = search.quiescence(&board, MIN_SCORE, MAX_SCORE, 0, false);
+        assert!(!Value::new(score).is_mate());
+        let score = search.quiescence(&board, MIN_SCORE, MAX_SCORE, 0, true);
+        assert_eq!(Value::new(score), Value::mate_in(1));
+
+        // From the configuration, at the leaves of the search.
+        search.config.quiescence_checks = true;
+        let score = search.alphabeta(&board, 0, MIN_SCORE, MAX_SCORE, 0, &mut Trace::disabled());
+        assert_eq!(Value::new(score), Value::mate_in(1));
+    }
+
+    #[test]
+    fn test_seldepth() {
+        let board = Board::initial_board();
+        let stop_flag = AtomicBool::new(false);
+        let tt = TranspositionTable::new(1);
+        let evaluator = Classical::default();
+        let mut search = Search::new(&stop_flag, &tt, &evaluator);
+        search.alphabeta(&board, 3, MIN_SCORE, MAX_SCORE, 0, &mut Trace::disabled());
+        // The quiescence search goes beyond the depth, with the captures.
+        assert_eq!(search.seldepth, 4);
+
+        // Near the end of the stack, the search stops at MAX_PLY whatever the depth.
+        search.seldepth = 0;
+        search.alphabeta(
             &board,
-            4,
+            5,
             MIN_SCORE,
             MAX_SCORE,
-            MATE_SCORE,
-            &Arc::new(AtomicBool::new(false)),
-            &mut nodes_count,
-            &mut pv_line,
+            MAX_PLY - 1,
+            &mut Trace::disabled(),
         );
-
-        assert_eq!(pv_line[0], Move::quiet(E5, G6, WhiteKnight));
-        assert_eq!(mate_in(score), Some(2));
-        assert_eq!(mated_in(score), None);
-        assert_eq!(score, MATE_SCORE - 3);
+        assert_eq!(search.seldepth, MAX_PLY);
     }
 
     #[test]
     fn test_stalemate() {
         // Black to move, but it cannot, stalemate.
         let board: Board = "4k3/4P3/4Q3/8/8/8/8/5K2 b - - 0 1".into();
-        let mut nodes_count = 0;
-        let mut pv_line = Vec::new();
-        let score = alphabeta(
+        let (score, _, pv_line) = search(&board, 4);
+
+        assert!(pv_line.is_empty());
+        assert_eq!(score, 0);
+        assert!(!Value::new(score).is_mate());
+    }
+
+    #[test]
+    fn test_run_analyse_mode() {
+        let board: Board = "2r4k/6pp/8/4N3/8/1Q6/B5PP/7K w - - 0 1".into();
+        let count_infos = |analyse_mode| {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let search_params = SearchParams {
+                depth: Some(6),
+                analyse_mode,
+                ..Default::default()
+            };
+            let result = run(
+                &board,
+                &search_params,
+                &Classical::default(),
+                &sender,
+                &Arc::new(AtomicBool::new(false)),
+                &TranspositionTable::new(1),
+                &mut Trace::disabled(),
+            );
+            assert_eq!(
+                result,
+                BestMove(Move::quiet(E5, G6, WhiteKnight), Value::mate_in(3))
+            );
+            receiver.try_iter().count()
+        };
+        // The mate in 2 is found at depth 5, razoring hiding it before.
+        assert_eq!(count_infos(false), 5);
+        assert_eq!(count_infos(true), 6);
+    }
+
+    #[test]
+    fn test_run_show_wdl() {
+        let board = Board::initial_board();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let search_params = SearchParams {
+            depth: Some(3),
+            show_wdl: true,
+            ..Default::default()
+        };
+        run(
+            &board,
+            &search_params,
+            &Classical::default(),
+            &sender,
+            &Arc::new(AtomicBool::new(false)),
+            &TranspositionTable::new(1),
+            &mut Trace::disabled(),
+        );
+        for event in receiver.try_iter() {
+            let Event::Info(info_data) = event else {
+                continue;
+            };
+            assert!(info_data.iter().any(
+                |info| matches!(info, InfoData::Wdl(wdl) if wdl.win + wdl.draw + wdl.loss == 1000)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_run_trace() {
+        let board = Board::initial_board();
+        let (sender, _receiver) = std::sync::mpsc::channel();
+        let search_params = SearchParams {
+            depth: Some(3),
+            ..Default::default()
+        };
+        let mut trace = Trace::new(1);
+        run(
+            &board,
+            &search_params,
+            &Classical::default(),
+            &sender,
+            &Arc::new(AtomicBool::new(false)),
+            &TranspositionTable::new(1),
+            &mut trace,
+        );
+        // The root and its 20 moves, at the last depth.
+        let text = trace.dump(crate::engine::search::trace::TraceFormat::Text);
+        assert_eq!(text.lines().count(), 21);
+        assert!(text.starts_with("root [-inf, +inf] "));
+        assert!(text.contains("\n  a2a3 [-inf, +inf] "));
+    }
+
+    #[test]
+    fn test_stats() {
+        let board: Board = "r3k3/ppp2ppp/2n5/8/3P4/2N5/PPP2PPP/R3K2R w KQq - 0 1".into();
+        let stop_flag = AtomicBool::new(false);
+        let tt = TranspositionTable::new(1);
+        let evaluator = Classical::default();
+        let mut search = Search::new(&stop_flag, &tt, &evaluator);
+        for depth in 1..=5 {
+            search.alphabeta(
+                &board,
+                depth,
+                MIN_SCORE,
+                MAX_SCORE,
+                0,
+                &mut Trace::disabled(),
+            );
+        }
+        let stats = search.stats().clone();
+        // The TT and killer moves are searched first, so most cutoffs are on the first move.
+        assert!(stats.beta_cutoffs[0] * 2 > stats.total_beta_cutoffs());
+        assert!(stats.tt_cutoffs > 0);
+        assert!(stats.tt_cutoffs <= stats.tt_hits && stats.tt_hits <= stats.tt_probes);
+        assert!(stats.razor_cutoffs > 0);
+        assert!(stats.razor_cutoffs <= stats.razor_attempts);
+        assert!(stats.probcut_cutoffs <= stats.probcut_attempts);
+
+        // In debug mode, they are sent at the end of the search.
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let search_params = SearchParams {
+            depth: Some(3),
+            debug: true,
+            ..Default::default()
+        };
+        run(
+            &board,
+            &search_params,
+            &Classical::default(),
+            &sender,
+            &Arc::new(AtomicBool::new(false)),
+            &TranspositionTable::new(1),
+            &mut Trace::disabled(),
+        );
+        let last = receiver.try_iter().last();
+        assert!(
+            matches!(&last, Some(Event::Info(info)) if matches!(&info[..], [InfoData::String(s)] if s.starts_with("stats cutoffs "))),
+            "{last:?}"
+        );
+    }
+
+    #[test]
+    fn test_is_easy_move() {
+        let is_easy = |board: &Board, best_move, best_score| {
+            let stop_flag = AtomicBool::new(false);
+            let tt = TranspositionTable::new(1);
+            let evaluator = Classical::default();
+            let mut search = Search::new(&stop_flag, &tt, &evaluator);
+            is_easy_move(&mut search, board, best_move, best_score, 4)
+        };
+        // Taking the queen.
+        let board: Board = "4k3/8/8/3q4/4P3/8/8/4K3 w - - 0 1".into();
+        let exd5 = Move::capture(E4, D5, WhitePawn, BlackQueen);
+        assert!(is_easy(&board, exd5, 700));
+
+        let board = Board::initial_board();
+        assert!(!is_easy(&board, Move::quiet(A2, A3, WhitePawn), 0));
+    }
+
+    #[test]
+    fn test_run_move_time() {
+        let board = Board::initial_board();
+        let (sender, _receiver) = std::sync::mpsc::channel();
+        let search_params = SearchParams {
+            move_time: Some(std::time::Duration::from_millis(200)),
+            ..Default::default()
+        };
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let now = std::time::Instant::now();
+        let result = run(
+            &board,
+            &search_params,
+            &Classical::default(),
+            &sender,
+            &stop_flag,
+            &TranspositionTable::new(1),
+            &mut Trace::disabled(),
+        );
+        assert!(matches!(result, BestMove(_, _)));
+        assert!(now.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_run_stopped() {
+        let run_stopped = |fen: &str| {
+            let (sender, _receiver) = std::sync::mpsc::channel();
+            run(
+                &Board::from_fen(fen),
+                &SearchParams::default(),
+                &Classical::default(),
+                &sender,
+                &Arc::new(AtomicBool::new(true)),
+                &TranspositionTable::new(1),
+                &mut Trace::disabled(),
+            )
+        };
+        // Stopped before it started, there is still a move to play.
+        assert!(matches!(run_stopped(fen::START_POSITION), BestMove(_, _)));
+        assert!(matches!(
+            run_stopped("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"),
+            StaleMate
+        ));
+        assert!(matches!(
+            run_stopped("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1"),
+            CheckMate
+        ));
+    }
+
+    #[test]
+    fn test_run_nodes() {
+        let board = Board::initial_board();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        // Without a depth, only the nodes limit stops the search.
+        let search_params = SearchParams {
+            nodes: Some(5000),
+            ..Default::default()
+        };
+        let result = run(
+            &board,
+            &search_params,
+            &Classical::default(),
+            &sender,
+            &Arc::new(AtomicBool::new(false)),
+            &TranspositionTable::new(1),
+            &mut Trace::disabled(),
+        );
+        assert!(matches!(result, BestMove(_, _)));
+        let nodes = receiver
+            .try_iter()
+            .filter_map(|event| match event {
+                Event::Info(info) => info.into_iter().find_map(|data| match data {
+                    InfoData::Nodes(nodes) => Some(nodes),
+                    _ => None,
+                }),
+                Event::BestMove(..) => None,
+            })
+            .last()
+            .unwrap();
+        assert!(nodes <= 5000);
+    }
+
+    #[test]
+    fn test_run_verify_move_gen() {
+        let (sender, _receiver) = std::sync::mpsc::channel();
+        let search_params = SearchParams {
+            depth: Some(3),
+            verify_move_gen: true,
+            ..Default::default()
+        };
+        // Panics on a mismatch.
+        let result = run(
+            &Board::from_fen(fen::KIWIPETE),
+            &search_params,
+            &Classical::default(),
+            &sender,
+            &Arc::new(AtomicBool::new(false)),
+            &TranspositionTable::new(1),
+            &mut Trace::disabled(),
+        );
+        assert!(matches!(result, BestMove(_, _)));
+    }
+
+    #[test]
+    fn test_run_jitter() {
+        let board = Board::initial_board();
+        let best_move = |jitter_seed| {
+            let (sender, _receiver) = std::sync::mpsc::channel();
+            let search_params = SearchParams {
+                depth: Some(4),
+                jitter_seed,
+                ..Default::default()
+            };
+            let result = run(
+                &board,
+                &search_params,
+                &Classical::default(),
+                &sender,
+                &Arc::new(AtomicBool::new(false)),
+                &TranspositionTable::new(1),
+                &mut Trace::disabled(),
+            );
+            let BestMove(mv, score) = result else {
+                panic!("No best move");
+            };
+            assert!((0..=DEFAULT_JITTER_MAX).contains(&score.score()));
+            mv
+        };
+        assert_eq!(best_move(None), Move::quiet(A2, A3, WhitePawn));
+        // Deterministic for a seed, but varies between seeds.
+        assert_eq!(best_move(Some(1)), best_move(Some(1)));
+        let first = best_move(Some(1));
+        assert!((2..10).any(|seed| best_move(Some(seed)) != first));
+    }
+
+    #[test]
+    fn test_multi_pv() {
+        // Mates in 2 via a knight or a queen sacrifice (see test_smothered_mate).
+        let board: Board = "2r4k/6pp/8/4N3/8/1Q6/B5PP/7K w - - 0 1".into();
+        let lines = multi_pv(
             &board,
             4,
-            MIN_SCORE,
-            MAX_SCORE,
-            MATE_SCORE,
+            3,
             &Arc::new(AtomicBool::new(false)),
-            &mut nodes_count,
-            &mut pv_line,
+            &TranspositionTable::new(1),
+            &Classical::default(),
         );
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].pv[0], Move::quiet(E5, G6, WhiteKnight));
+        assert_eq!(lines[0].score.mate_moves(), Some(2));
+        assert!(lines[0].score >= lines[1].score);
+        assert!(lines[1].score >= lines[2].score);
 
-        assert!(pv_line.is_empty());
-        assert_eq!(score, 0);
-        assert_eq!(mate_in(score), None);
-        assert_eq!(mated_in(score), None);
+        // Same best move and score as the regular search.
+        let lines = multi_pv(
+            &board,
+            4,
+            1,
+            &Arc::new(AtomicBool::new(false)),
+            &TranspositionTable::new(1),
+            &Classical::default(),
+        );
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].score, Value::mate_in(3));
     }
 }
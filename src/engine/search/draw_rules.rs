@@ -0,0 +1,135 @@
+//! Root move and the draw rules: The search doesn't know the game history, so it can't see that a
+//! move repeats a position for the third time or reaches the fifty moves limit. Before the best
+//! move is played, such a draw is avoided when winning, and taken when losing.
+
+use std::sync::{atomic::AtomicBool, Arc};
+
+use crate::{
+    board::{outcome::Outcome, Board},
+    common::{Move, Score, Value},
+    engine::{eval::Evaluator, transposition::TranspositionTable},
+};
+
+use super::multi_pv;
+
+// Beyond this score, the game is won or lost: A draw is worth avoiding or taking.
+pub const DRAW_MARGIN: Score = 50;
+
+// Maximum depth of the search ranking the other moves, when the best one must be avoided.
+pub const VERIFICATION_DEPTH: usize = 4;
+
+// Why the move ends the game in a draw, if it does. The history has the keys of the positions
+// before the board, followed by the key of the board itself.
+fn draw_reason(board: &Board, history: &[u64], mv: Move) -> Option<&'static str> {
+    match board.copy_with_move(mv)?.game_over(history) {
+        Some((Outcome::Draw, reason)) => Some(reason),
+        _ => None,
+    }
+}
+
+// Checks the best move of the search and its score against the draw rules. Returns the move to
+// play instead with the reason, if the best move gives away a won game, or if a draw saves a lost
+// one. The history has the Zobrist keys of the positions before the board.
+pub fn replace_root_move(
+    board: &Board,
+    history: &[u64],
+    best_move: Move,
+    score: Value,
+    depth: usize,
+    evaluator: &dyn Evaluator,
+    tt: &TranspositionTable,
+) -> Option<(Move, String)> {
+    let mut history = history.to_vec();
+    history.push(board.get_zobrist_key());
+
+    if score.score() >= DRAW_MARGIN {
+        let reason = draw_reason(board, &history, best_move)?;
+        // The other moves have no exact score, a short search ranks them. Its own stop flag
+        // keeps it going if the main search was stopped.
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let moves_count = board.generate_legal_moves().len();
+        let line = multi_pv(board, depth.max(1), moves_count, &stop_flag, tt, evaluator)
+            .into_iter()
+            .find(|line| draw_reason(board, &history, line.pv[0]).is_none())
+            // Still better than the draw.
+            .filter(|line| line.score > Value::DRAW)?;
+        Some((
+            line.pv[0],
+            format!("{} avoids a draw by {reason}", line.pv[0].pure()),
+        ))
+    } else if score.score() <= -DRAW_MARGIN && draw_reason(board, &history, best_move).is_none() {
+        board
+            .generate_legal_moves()
+            .into_iter()
+            .find_map(|mv| draw_reason(board, &history, mv).map(|reason| (mv, reason)))
+            .map(|(mv, reason)| (mv, format!("{} takes a draw by {reason}", mv.pure())))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::eval::Classical;
+
+    fn replace(board: &Board, history: &[u64], best_move: &str, score: Score) -> Option<String> {
+        let tt = TranspositionTable::new(1);
+        let best_move = board.new_move_from_pure(best_move);
+        replace_root_move(
+            board,
+            history,
+            best_move,
+            Value::new(score),
+            2,
+            &Classical::default(),
+            &tt,
+        )
+        .map(|(mv, _)| mv.pure().to_string())
+    }
+
+    fn key_after(board: &Board, mv: &str) -> u64 {
+        let mut board = *board;
+        board.update_by_move(board.new_move_from_pure(mv));
+        board.get_zobrist_key()
+    }
+
+    #[test]
+    fn test_avoid_repetition() {
+        let board: Board = "4k3/8/8/8/8/8/8/Q3K3 w - - 10 40".into();
+        let repeated = key_after(&board, "a1a2");
+        let history = [repeated, 1, repeated, 2];
+
+        let mv = replace(&board, &history, "a1a2", 900).unwrap();
+        assert_ne!(mv, "a1a2");
+        // Only the third time is a draw.
+        assert_eq!(replace(&board, &history[..2], "a1a2", 900), None);
+        // Level positions may repeat.
+        assert_eq!(replace(&board, &history, "a1a2", 20), None);
+    }
+
+    #[test]
+    fn test_avoid_fifty_moves() {
+        let board: Board = "4k3/8/8/8/8/8/P7/1Q2K3 w - - 99 80".into();
+        // Only a pawn move resets the clock.
+        let mv = replace(&board, &[], "b1b2", 900).unwrap();
+        assert!(mv.starts_with("a2"));
+        assert_eq!(replace(&board, &[], "a2a4", 900), None);
+    }
+
+    #[test]
+    fn test_take_draw() {
+        let board: Board = "4k3/8/8/8/8/8/8/Q3K3 b - - 10 40".into();
+        let repeated = key_after(&board, "e8d7");
+        let history = [repeated, 1, repeated, 2];
+
+        assert_eq!(
+            replace(&board, &history, "e8f7", -900),
+            Some("e8d7".to_string())
+        );
+        // Already a draw.
+        assert_eq!(replace(&board, &history, "e8d7", -900), None);
+        // Nothing to save.
+        assert_eq!(replace(&board, &[], "e8f7", -900), None);
+    }
+}
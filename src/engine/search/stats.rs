@@ -0,0 +1,100 @@
+//! Search statistics, to see how well the move ordering and the pruning work when tuning them.
+//! A good move ordering makes most beta cutoffs happen on the first move searched.
+//! The search has no null move pruning, so there is no counter for it.
+
+use std::fmt::Display;
+
+// Beta cutoffs are counted by index of the move searched, the last one counting the later moves.
+pub const CUTOFF_INDEXES: usize = 5;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    pub beta_cutoffs: [usize; CUTOFF_INDEXES],
+    pub tt_probes: usize,
+    pub tt_hits: usize,
+    pub tt_cutoffs: usize,
+    pub razor_attempts: usize,
+    pub razor_cutoffs: usize,
+    pub probcut_attempts: usize,
+    pub probcut_cutoffs: usize,
+    // Included in the nodes count of the search.
+    pub quiescence_nodes: usize,
+}
+
+impl SearchStats {
+    // The index is the one of the move among the legal moves searched, from 0.
+    pub fn add_beta_cutoff(&mut self, move_index: usize) {
+        self.beta_cutoffs[move_index.min(CUTOFF_INDEXES - 1)] += 1;
+    }
+
+    pub fn total_beta_cutoffs(&self) -> usize {
+        self.beta_cutoffs.iter().sum()
+    }
+}
+
+// Percentage of the total, 0 when there is nothing.
+#[allow(clippy::cast_precision_loss)]
+fn rate(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        100.0 * count as f64 / total as f64
+    }
+}
+
+impl Display for SearchStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cutoffs = self.total_beta_cutoffs();
+        write!(f, "cutoffs {cutoffs} by move")?;
+        for (index, count) in self.beta_cutoffs.iter().enumerate() {
+            let plus = if index == CUTOFF_INDEXES - 1 { "+" } else { "" };
+            write!(f, " {}{plus} {:.1}%", index + 1, rate(*count, cutoffs))?;
+        }
+        write!(
+            f,
+            " tt probes {} hits {:.1}% cutoffs {:.1}%",
+            self.tt_probes,
+            rate(self.tt_hits, self.tt_probes),
+            rate(self.tt_cutoffs, self.tt_probes)
+        )?;
+        write!(
+            f,
+            " razor {}/{} probcut {}/{} quiescence nodes {}",
+            self.razor_cutoffs,
+            self.razor_attempts,
+            self.probcut_cutoffs,
+            self.probcut_attempts,
+            self.quiescence_nodes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let mut stats = SearchStats {
+            tt_probes: 200,
+            tt_hits: 50,
+            tt_cutoffs: 20,
+            razor_attempts: 4,
+            razor_cutoffs: 1,
+            quiescence_nodes: 30,
+            ..Default::default()
+        };
+        for index in [0, 0, 0, 1, 7] {
+            stats.add_beta_cutoff(index);
+        }
+        assert_eq!(stats.beta_cutoffs, [3, 1, 0, 0, 1]);
+        assert_eq!(
+            stats.to_string(),
+            "cutoffs 5 by move 1 60.0% 2 20.0% 3 0.0% 4 0.0% 5+ 20.0% \
+             tt probes 200 hits 25.0% cutoffs 10.0% razor 1/4 probcut 0/0 quiescence nodes 30"
+        );
+        assert!(SearchStats::default()
+            .to_string()
+            .starts_with("cutoffs 0 by move 1 0.0%"));
+    }
+}
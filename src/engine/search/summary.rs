@@ -0,0 +1,161 @@
+//! Search summaries, to analyse the engine behavior over many games with scripts instead of
+//! parsing the logs. Each completed search appends a single JSON record to a JSON Lines file:
+//!
+//!     {"fen":"...","bestmove":"e2e4","depth":8,"seldepth":8,"score":25,"pv":["e2e4",...],
+//!      "nodes":123456,"nps":456789,"time_ms":270,"tt":{"probes":...,"hits":...,...}}
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    board::Board,
+    engine::{
+        game::{Event, InfoData},
+        transposition,
+    },
+};
+
+use super::Result;
+
+// Runs the search with its own event sender, forwarding the events while keeping the info of the
+// last completed depth, which is returned with the result.
+pub fn record<F>(event_sender: &Sender<Event>, search: F) -> (Result, Vec<InfoData>)
+where
+    F: FnOnce(&Sender<Event>) -> Result,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|s| {
+        let forwarder = s.spawn(move || {
+            let mut last_info = Vec::new();
+            for event in receiver {
+                if let Event::Info(info) = &event {
+                    if info.iter().any(|data| matches!(data, InfoData::Depth(_))) {
+                        last_info.clone_from(info);
+                    }
+                }
+                event_sender.send(event).unwrap();
+            }
+            last_info
+        });
+        let result = search(&sender);
+        // Closes the channel, ending the forwarder.
+        drop(sender);
+        (result, forwarder.join().unwrap())
+    })
+}
+
+// The record of a search, like the JSON report of the search command with the position and the
+// transposition table statistics.
+pub fn create(
+    board: &Board,
+    result: &Result,
+    info: &[InfoData],
+    elapsed: Duration,
+    tt_stats: &transposition::Stats,
+) -> Value {
+    let depth = info
+        .iter()
+        .find_map(|data| match data {
+            InfoData::Depth(depth) => Some(*depth),
+            _ => None,
+        })
+        .unwrap_or_default();
+    let mut summary = super::json_report(depth, result, info, elapsed);
+    summary["fen"] = json!(board.as_fen());
+    summary["tt"] = json!({
+        "probes": tt_stats.probes,
+        "hits": tt_stats.hits,
+        "collisions": tt_stats.collisions,
+    });
+    if let Some(hashfull) = info.iter().find_map(|data| match data {
+        InfoData::HashFull(hashfull) => Some(*hashfull),
+        _ => None,
+    }) {
+        summary["tt"]["hashfull"] = json!(hashfull);
+    }
+    summary
+}
+
+// Appends the record as a line of the file, creating it if needed.
+pub fn append(path: &str, summary: &Value) {
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{summary}"));
+    if let Err(err) = result {
+        warn!("Failed to write search summary to {path}: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common;
+
+    #[test]
+    fn test_create() {
+        let board = Board::initial_board();
+        let mv = board.parse_pure_move("e2e4").unwrap();
+        let info = vec![
+            InfoData::Depth(3),
+            InfoData::Nodes(1000),
+            InfoData::HashFull(12),
+            InfoData::Pv(vec![mv]),
+            InfoData::Score(common::Value::new(25)),
+        ];
+        let stats = transposition::Stats {
+            probes: 10,
+            hits: 4,
+            collisions: 0,
+        };
+        let summary = create(
+            &board,
+            &Result::BestMove(mv, common::Value::new(25)),
+            &info,
+            Duration::from_millis(100),
+            &stats,
+        );
+        assert_eq!(summary["fen"], board.as_fen());
+        assert_eq!(summary["bestmove"], "e2e4");
+        assert_eq!(summary["depth"], 3);
+        assert_eq!(summary["score"], 25);
+        assert_eq!(summary["pv"], json!(["e2e4"]));
+        assert_eq!(summary["nodes"], 1000);
+        assert_eq!(summary["time_ms"], 100);
+        assert_eq!(
+            summary["tt"],
+            json!({"probes": 10, "hits": 4, "collisions": 0, "hashfull": 12})
+        );
+        // A single line.
+        assert!(!summary.to_string().contains('\n'));
+    }
+
+    #[test]
+    fn test_record() {
+        let (sender, receiver) = mpsc::channel();
+        let (result, info) = record(&sender, |sender| {
+            for depth in 1..=2 {
+                let info = vec![InfoData::Depth(depth), InfoData::Nodes(depth * 10)];
+                sender.send(Event::Info(info)).unwrap();
+            }
+            let msg = InfoData::String("done".to_string());
+            sender.send(Event::Info(vec![msg])).unwrap();
+            Result::StaleMate
+        });
+        assert_eq!(result, Result::StaleMate);
+        assert!(matches!(
+            &info[..],
+            [InfoData::Depth(2), InfoData::Nodes(20)]
+        ));
+        // All the events were forwarded.
+        assert_eq!(receiver.try_iter().count(), 3);
+    }
+}
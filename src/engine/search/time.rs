@@ -0,0 +1,323 @@
+//! Time management: decides how long to think on a move when playing with a clock.
+//! A soft limit is checked between iterations of the iterative deepening, and a hard limit
+//! interrupts the search if an iteration takes too long.
+//! The soft limit is adjusted during the search:
+//! - It's shortened for easy moves, where one move is much better than all others.
+//!   The time saved is banked for the harder moves.
+//! - It's extended when the best move keeps changing between iterations, as the search
+//!   hasn't settled yet.
+//!
+//! When pondering, the search starts on the opponent's time and our clock only starts on the
+//! ponder hit. The time spent pondering on the move the opponent played was still useful,
+//! so it's counted against the soft limit instead of restarting it.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    common::{Color, Move},
+    engine::game::SearchParams,
+};
+
+// When the GUI doesn't tell how many moves until the next time control, we assume this many.
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// Time lost communicating with the GUI, kept in reserve.
+const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+// Enough to always complete the first iteration, so that we have a move to play.
+const MIN_THINKING_TIME: Duration = Duration::from_millis(10);
+// How much of the soft limit an easy move gets.
+const EASY_MOVE_FACTOR: f64 = 0.3;
+// How much a best move change extends the soft limit. Older changes matter less, as
+// the instability decays by half at each iteration.
+const INSTABILITY_FACTOR: f64 = 1.0;
+const INSTABILITY_DECAY: f64 = 0.5;
+
+pub struct TimeManager {
+    start: Instant,
+    // When our clock started: The start of the search, or the ponder hit.
+    clock_start: Instant,
+    pondering: bool,
+    soft_limit: Duration,
+    hard_limit: Duration,
+    best_move: Option<Move>,
+    instability: f64,
+    easy_move: bool,
+}
+
+impl TimeManager {
+    // Returns None if the search isn't limited by time.
+    pub fn new(search_params: &SearchParams, side: Color) -> Option<Self> {
+        let (soft_limit, hard_limit) = if let Some(move_time) = search_params.move_time {
+            let limit = move_time.saturating_sub(MOVE_OVERHEAD);
+            (limit, limit)
+        } else {
+            let (time_left, increment) = match side {
+                Color::White => (search_params.white_time, search_params.white_increment),
+                Color::Black => (search_params.black_time, search_params.black_increment),
+            };
+            let time_left = time_left?.saturating_sub(MOVE_OVERHEAD);
+            let increment = increment.unwrap_or_default();
+            let moves_to_go = search_params
+                .moves_to_go
+                .filter(|&n| n > 0)
+                .unwrap_or(DEFAULT_MOVES_TO_GO);
+            let soft_limit = (time_left / moves_to_go + increment * 3 / 4).min(time_left);
+            // Never use more than a third of the remaining time on a single move.
+            let hard_limit = (soft_limit * 4).min(time_left / 3).max(soft_limit);
+            (soft_limit, hard_limit)
+        };
+        let start = Instant::now();
+        Some(Self {
+            start,
+            clock_start: start,
+            pondering: search_params.ponder,
+            soft_limit: soft_limit.max(MIN_THINKING_TIME),
+            hard_limit: hard_limit.max(MIN_THINKING_TIME),
+            best_move: None,
+            instability: 0.0,
+            easy_move: false,
+        })
+    }
+
+    pub fn hard_limit(&self) -> Duration {
+        self.hard_limit
+    }
+
+    // Time left until the hard limit. None while pondering, as our clock isn't running yet.
+    pub fn hard_time_left(&self) -> Option<Duration> {
+        self.hard_time_left_at(Instant::now())
+    }
+
+    fn hard_time_left_at(&self, now: Instant) -> Option<Duration> {
+        if self.pondering {
+            None
+        } else {
+            Some(self.hard_limit.saturating_sub(now - self.clock_start))
+        }
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.pondering
+    }
+
+    // The opponent played the move we were pondering on, the search continues on our time.
+    pub fn ponder_hit(&mut self) {
+        self.ponder_hit_at(Instant::now());
+    }
+
+    fn ponder_hit_at(&mut self, now: Instant) {
+        self.pondering = false;
+        self.clock_start = now;
+    }
+
+    // To be called after each completed iteration, with its best move.
+    pub fn on_iteration(&mut self, best_move: Move) {
+        self.instability *= INSTABILITY_DECAY;
+        if self.best_move.is_some_and(|mv| mv != best_move) {
+            self.instability += INSTABILITY_FACTOR;
+            // An easy move that got replaced wasn't that easy.
+            self.easy_move = false;
+        }
+        self.best_move = Some(best_move);
+    }
+
+    // Is the current best move stable enough to be worth checking if it's an easy move.
+    pub fn is_best_move_stable(&self) -> bool {
+        self.instability < INSTABILITY_FACTOR * INSTABILITY_DECAY * INSTABILITY_DECAY
+    }
+
+    pub fn set_easy_move(&mut self) {
+        self.easy_move = true;
+    }
+
+    pub fn is_easy_move(&self) -> bool {
+        self.easy_move
+    }
+
+    // The soft limit, adjusted for the easy move and the best move instability.
+    pub fn allocated_time(&self) -> Duration {
+        let factor = if self.easy_move {
+            EASY_MOVE_FACTOR
+        } else {
+            1.0 + self.instability
+        };
+        self.soft_limit.mul_f64(factor).min(self.hard_limit)
+    }
+
+    // Should we stop instead of starting a new iteration.
+    pub fn should_stop(&self) -> bool {
+        self.should_stop_at(Instant::now())
+    }
+
+    fn should_stop_at(&self, now: Instant) -> bool {
+        // The time spent pondering counts, but not beyond what our clock allows.
+        !self.pondering
+            && (now - self.start >= self.allocated_time()
+                || now - self.clock_start >= self.hard_limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{Piece::*, Square::*};
+
+    use super::*;
+
+    fn clock(time_left: u64, increment: u64) -> SearchParams {
+        SearchParams {
+            white_time: Some(Duration::from_millis(time_left)),
+            white_increment: Some(Duration::from_millis(increment)),
+            black_time: Some(Duration::from_millis(30_030)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_no_time_limit() {
+        assert!(TimeManager::new(&SearchParams::default(), Color::White).is_none());
+        // Only the opponent has a clock.
+        let search_params = SearchParams {
+            black_time: Some(Duration::from_mins(1)),
+            ..Default::default()
+        };
+        assert!(TimeManager::new(&search_params, Color::White).is_none());
+    }
+
+    #[test]
+    fn test_move_time() {
+        let search_params = SearchParams {
+            move_time: Some(Duration::from_millis(1030)),
+            ..Default::default()
+        };
+        let tm = TimeManager::new(&search_params, Color::Black).unwrap();
+        assert_eq!(tm.allocated_time(), Duration::from_secs(1));
+        assert_eq!(tm.hard_limit(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_clock() {
+        let tm = TimeManager::new(&clock(60_030, 1000), Color::White).unwrap();
+        assert_eq!(tm.allocated_time(), Duration::from_millis(2000 + 750));
+        assert_eq!(tm.hard_limit(), Duration::from_secs(11));
+
+        // Black has less time and no increment.
+        let tm = TimeManager::new(&clock(60_030, 1000), Color::Black).unwrap();
+        assert_eq!(tm.allocated_time(), Duration::from_secs(1));
+
+        // Not much time left, the hard limit is capped.
+        let search_params = SearchParams {
+            moves_to_go: Some(1),
+            ..clock(3030, 0)
+        };
+        let tm = TimeManager::new(&search_params, Color::White).unwrap();
+        assert_eq!(tm.allocated_time(), Duration::from_secs(3));
+        assert_eq!(tm.hard_limit(), Duration::from_secs(3));
+
+        // Flagging.
+        let tm = TimeManager::new(&clock(0, 0), Color::White).unwrap();
+        assert_eq!(tm.allocated_time(), MIN_THINKING_TIME);
+    }
+
+    #[test]
+    fn test_instability() {
+        let mut tm = TimeManager::new(&clock(60_030, 0), Color::White).unwrap();
+        let soft_limit = tm.allocated_time();
+        let e4 = Move::double_push(E2, E4, WhitePawn);
+        let d4 = Move::double_push(D2, D4, WhitePawn);
+
+        tm.on_iteration(e4);
+        tm.on_iteration(e4);
+        assert_eq!(tm.allocated_time(), soft_limit);
+        assert!(tm.is_best_move_stable());
+
+        tm.on_iteration(d4);
+        assert_eq!(tm.allocated_time(), soft_limit * 2);
+        tm.on_iteration(e4);
+        assert_eq!(tm.allocated_time(), soft_limit.mul_f64(2.5));
+        assert!(!tm.is_best_move_stable());
+
+        // Settles down.
+        tm.on_iteration(e4);
+        tm.on_iteration(e4);
+        tm.on_iteration(e4);
+        assert!(tm.allocated_time() < soft_limit.mul_f64(1.2));
+        assert!(tm.is_best_move_stable());
+    }
+
+    #[test]
+    fn test_easy_move() {
+        let mut tm = TimeManager::new(&clock(60_030, 0), Color::White).unwrap();
+        let soft_limit = tm.allocated_time();
+        let e4 = Move::double_push(E2, E4, WhitePawn);
+        tm.on_iteration(e4);
+        tm.set_easy_move();
+        assert!(tm.is_easy_move());
+        assert_eq!(tm.allocated_time(), soft_limit.mul_f64(EASY_MOVE_FACTOR));
+
+        tm.on_iteration(e4);
+        assert!(tm.is_easy_move());
+        tm.on_iteration(Move::double_push(D2, D4, WhitePawn));
+        assert!(!tm.is_easy_move());
+        assert!(tm.allocated_time() > soft_limit);
+    }
+
+    #[test]
+    fn test_ponder_hit() {
+        let search_params = SearchParams {
+            ponder: true,
+            ..clock(60_030, 0)
+        };
+        let mut tm = TimeManager::new(&search_params, Color::White).unwrap();
+        let soft_limit = tm.allocated_time();
+        assert!(tm.is_pondering());
+        assert_eq!(tm.hard_time_left(), None);
+        // Pondering can last as long as the opponent thinks.
+        assert!(!tm.should_stop_at(tm.start + tm.hard_limit * 10));
+
+        // The time spent pondering is deducted from the budget.
+        let hit = tm.start + soft_limit / 2;
+        tm.ponder_hit_at(hit);
+        assert!(!tm.is_pondering());
+        assert_eq!(tm.hard_time_left_at(hit), Some(tm.hard_limit));
+        assert!(!tm.should_stop_at(hit));
+        assert!(tm.should_stop_at(tm.start + soft_limit));
+    }
+
+    #[test]
+    fn test_ponder_hit_late() {
+        let search_params = SearchParams {
+            ponder: true,
+            ..clock(60_030, 0)
+        };
+        let mut tm = TimeManager::new(&search_params, Color::White).unwrap();
+        // Pondered longer than the budget: Move right away.
+        let hit = tm.start + tm.hard_limit * 2;
+        tm.ponder_hit_at(hit);
+        assert!(tm.should_stop_at(hit));
+        // But the hard limit is relative to the ponder hit, as our clock only started then.
+        assert_eq!(tm.hard_time_left_at(hit), Some(tm.hard_limit));
+        assert_eq!(
+            tm.hard_time_left_at(hit + tm.hard_limit / 2),
+            Some(tm.hard_limit / 2)
+        );
+    }
+
+    #[test]
+    fn test_ponder_miss() {
+        // On a ponder miss, the GUI stops the search and starts a new one on the right position,
+        // which gets the full budget.
+        let pondering = TimeManager::new(
+            &SearchParams {
+                ponder: true,
+                ..clock(60_030, 0)
+            },
+            Color::White,
+        )
+        .unwrap();
+        let tm = TimeManager::new(&clock(60_030, 0), Color::White).unwrap();
+        assert!(!tm.is_pondering());
+        assert_eq!(tm.allocated_time(), pondering.allocated_time());
+        assert!(!tm.should_stop_at(tm.start + tm.allocated_time() / 2));
+        assert!(tm.should_stop_at(tm.start + tm.allocated_time()));
+        assert_eq!(tm.hard_time_left_at(tm.start), Some(tm.hard_limit));
+    }
+}
@@ -0,0 +1,255 @@
+//! Search tree tracing, to help diagnose search and pruning bugs.
+//! Records the first plies of the tree, with the search window and the result of each node,
+//! and dumps it as indented text or as a Graphviz DOT graph.
+//! <https://graphviz.org/doc/info/lang.html>
+
+use std::fmt::{Display, Write};
+
+use crate::common::{Move, Score, MAX_SCORE, MIN_SCORE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    #[default]
+    Text,
+    Dot,
+}
+
+impl TraceFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            TraceFormat::Text => "txt",
+            TraceFormat::Dot => "dot",
+        }
+    }
+}
+
+// Why the search of a node or of its siblings was cut short.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    BetaCutoff,          // The move refuted the parent, siblings are pruned.
+    TranspositionCutoff, // The score came from the transposition table.
+    Razored,             // Far below alpha, verified with a shallower search.
+    ProbCut,             // A capture beat beta by a margin in a shallower search.
+    Stopped,             // The search was interrupted.
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reason::BetaCutoff => write!(f, "beta cutoff"),
+            Reason::TranspositionCutoff => write!(f, "tt cutoff"),
+            Reason::Razored => write!(f, "razored"),
+            Reason::ProbCut => write!(f, "probcut"),
+            Reason::Stopped => write!(f, "stopped"),
+        }
+    }
+}
+
+// The window and score are from the point of view of the side playing the move.
+#[derive(Debug)]
+struct Node {
+    ply: usize,
+    mv: Option<Move>, // None for the root.
+    alpha: Score,
+    beta: Score,
+    score: Option<Score>,
+    reasons: Vec<Reason>,
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(mv) = self.mv {
+            write!(f, "{}", mv.pure())?;
+        } else {
+            write!(f, "root")?;
+        }
+        write!(
+            f,
+            " [{}, {}] ",
+            format_bound(self.alpha),
+            format_bound(self.beta)
+        )?;
+        if let Some(score) = self.score {
+            write!(f, "{score}")?;
+        } else {
+            write!(f, "?")?;
+        }
+        for reason in &self.reasons {
+            write!(f, " ({reason})")?;
+        }
+        Ok(())
+    }
+}
+
+fn format_bound(score: Score) -> String {
+    match score {
+        MIN_SCORE => "-inf".to_string(),
+        MAX_SCORE => "+inf".to_string(),
+        _ => score.to_string(),
+    }
+}
+
+pub struct Trace {
+    max_ply: usize,
+    nodes: Vec<Node>,
+    open_nodes: Vec<usize>, // Nodes being searched.
+}
+
+impl Trace {
+    // Records nodes up to max_ply plies from the root. Zero disables tracing.
+    pub fn new(max_ply: usize) -> Self {
+        Self {
+            max_ply,
+            nodes: Vec::new(),
+            open_nodes: Vec::new(),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self::new(0)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_ply > 0
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.open_nodes.clear();
+    }
+
+    // Starts recording a node. Returns false if the node isn't recorded,
+    // otherwise exit() must be called once the node is searched.
+    pub fn enter(&mut self, ply: usize, mv: Option<Move>, alpha: Score, beta: Score) -> bool {
+        if !self.is_enabled() || ply > self.max_ply {
+            return false;
+        }
+        self.open_nodes.push(self.nodes.len());
+        self.nodes.push(Node {
+            ply,
+            mv,
+            alpha,
+            beta,
+            score: None,
+            reasons: Vec::new(),
+        });
+        true
+    }
+
+    pub fn exit(&mut self, score: Score) {
+        let index = self.open_nodes.pop().expect("No node being traced");
+        self.nodes[index].score = Some(score);
+    }
+
+    // Notes why the node being searched at this ply was cut short.
+    pub fn mark(&mut self, ply: usize, reason: Reason) {
+        if let Some(&index) = self.open_nodes.last() {
+            if self.nodes[index].ply == ply {
+                self.nodes[index].reasons.push(reason);
+            }
+        }
+    }
+
+    pub fn dump(&self, format: TraceFormat) -> String {
+        match format {
+            TraceFormat::Text => self.to_text(),
+            TraceFormat::Dot => self.to_dot(),
+        }
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        for node in &self.nodes {
+            writeln!(text, "{}{node}", "  ".repeat(node.ply)).unwrap();
+        }
+        text
+    }
+
+    fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph search {{").unwrap();
+        writeln!(dot, "  node [shape=box, fontname=monospace];").unwrap();
+        // Nodes are stored in pre-order, so the parent is the last node of the previous ply.
+        let mut parents: Vec<usize> = Vec::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            writeln!(dot, "  n{i} [label=\"{node}\"];").unwrap();
+            parents.truncate(node.ply);
+            if let Some(parent) = parents.last() {
+                writeln!(dot, "  n{parent} -> n{i};").unwrap();
+            }
+            parents.push(i);
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{Piece, Square};
+
+    use super::*;
+
+    fn sample_trace() -> Trace {
+        let mut trace = Trace::new(1);
+        assert!(trace.enter(0, None, MIN_SCORE, MAX_SCORE));
+        assert!(trace.enter(
+            1,
+            Some(Move::double_push(Square::E2, Square::E4, Piece::WhitePawn)),
+            MIN_SCORE,
+            MAX_SCORE
+        ));
+        // Too deep.
+        assert!(!trace.enter(
+            2,
+            Some(Move::double_push(Square::E7, Square::E5, Piece::BlackPawn)),
+            MIN_SCORE,
+            MAX_SCORE
+        ));
+        trace.exit(30);
+        assert!(trace.enter(
+            1,
+            Some(Move::double_push(Square::D2, Square::D4, Piece::WhitePawn)),
+            30,
+            MAX_SCORE
+        ));
+        trace.mark(1, Reason::TranspositionCutoff);
+        trace.exit(10);
+        trace.exit(30);
+        trace
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut trace = Trace::disabled();
+        assert!(!trace.enter(0, None, MIN_SCORE, MAX_SCORE));
+        assert_eq!(trace.dump(TraceFormat::Text), "");
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(
+            sample_trace().dump(TraceFormat::Text),
+            "root [-inf, +inf] 30
+  e2e4 [-inf, +inf] 30
+  d2d4 [30, +inf] 10 (tt cutoff)
+"
+        );
+    }
+
+    #[test]
+    fn test_dot() {
+        assert_eq!(
+            sample_trace().dump(TraceFormat::Dot),
+            r#"digraph search {
+  node [shape=box, fontname=monospace];
+  n0 [label="root [-inf, +inf] 30"];
+  n1 [label="e2e4 [-inf, +inf] 30"];
+  n0 -> n1;
+  n2 [label="d2d4 [30, +inf] 10 (tt cutoff)"];
+  n0 -> n2;
+}
+"#
+        );
+    }
+}
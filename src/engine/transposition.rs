@@ -0,0 +1,597 @@
+//! Transposition table.
+//! Caches the search results by Zobrist key, so that positions reached via different move orders,
+//! or already searched in a previous iteration or a previous move, don't need to be searched again.
+//! <https://www.chessprogramming.org/Transposition_Table>
+//! For debugging, a verification mode stores a second key computed independently of the Zobrist
+//! one, to detect entries of different positions with the same Zobrist key.
+//! The table can be shared by several search threads without locks: Entries are stored in atomic
+//! words, with the key mixed by XOR with the other words, so that an entry torn by concurrent writes
+//! doesn't match any key and is seen as missing.
+//! <https://www.chessprogramming.org/Shared_Hash_Table#Lockless>
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+use crate::{
+    board::Board,
+    common::{Move, Score},
+};
+
+pub const DEFAULT_SIZE_MB: usize = 16;
+
+// How the stored score relates to the real score of the position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    Lower, // The search failed high, the real score is at least this one.
+    Upper, // The search failed low, the real score is at most this one.
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub key: u64,
+    pub depth: usize,
+    pub score: Score,
+    pub bound: Bound,
+    pub best_move: Option<Move>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    entry: Entry,
+    // Search during which the entry was stored.
+    generation: u8,
+    // Only set in verification mode.
+    verification_key: u64,
+}
+
+// Bits of the data word, beyond the 32 bits of the score.
+const DEPTH_SHIFT: u32 = 32;
+const BOUND_SHIFT: u32 = 40;
+const GENERATION_SHIFT: u32 = 42;
+const USED_BIT: u64 = 1 << 50;
+// Bit of the move word set when there is a move, beyond the 27 bits of the move.
+const MOVE_BIT: u64 = 1 << 27;
+
+// A slot in atomic words. Each word is read and written atomically, but not the slot as a whole.
+#[derive(Debug, Default)]
+struct AtomicSlot {
+    // The key XORed with the other words, which must be read with it to get the key back.
+    checked_key: AtomicU64,
+    data: AtomicU64,
+    best_move: AtomicU64,
+    verification_key: AtomicU64,
+}
+
+impl AtomicSlot {
+    // Missing if empty, or torn by a concurrent write, in which case no key matches it.
+    fn load(&self) -> Option<Slot> {
+        let data = self.data.load(Ordering::Relaxed);
+        let best_move = self.best_move.load(Ordering::Relaxed);
+        let verification_key = self.verification_key.load(Ordering::Relaxed);
+        let key = self.checked_key.load(Ordering::Relaxed) ^ data ^ best_move ^ verification_key;
+        if data & USED_BIT == 0 {
+            return None;
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        let byte = |shift: u32| (data >> shift) as u8;
+        let bound = match byte(BOUND_SHIFT) & 3 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        };
+        let best_move = if best_move & MOVE_BIT == 0 {
+            None
+        } else {
+            #[allow(clippy::cast_possible_truncation)]
+            Some(Move::from_bits(best_move as u32 & !(MOVE_BIT as u32))?)
+        };
+        Some(Slot {
+            entry: Entry {
+                key,
+                depth: usize::from(byte(DEPTH_SHIFT)),
+                #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+                score: data as u32 as Score,
+                bound,
+                best_move,
+            },
+            generation: byte(GENERATION_SHIFT),
+            verification_key,
+        })
+    }
+
+    fn store(&self, slot: &Slot) {
+        let entry = slot.entry;
+        let depth = u8::try_from(entry.depth).unwrap_or(u8::MAX);
+        #[allow(clippy::cast_sign_loss)]
+        let data = u64::from(entry.score as u32)
+            | u64::from(depth) << DEPTH_SHIFT
+            | (entry.bound as u64) << BOUND_SHIFT
+            | u64::from(slot.generation) << GENERATION_SHIFT
+            | USED_BIT;
+        let best_move = entry
+            .best_move
+            .map_or(0, |mv| u64::from(mv.to_bits()) | MOVE_BIT);
+        let checked_key = entry.key ^ data ^ best_move ^ slot.verification_key;
+        self.store_words([checked_key, data, best_move, slot.verification_key]);
+    }
+
+    fn clear(&self) {
+        self.store_words([0; 4]);
+    }
+
+    fn store_words(&self, [checked_key, data, best_move, verification_key]: [u64; 4]) {
+        self.checked_key.store(checked_key, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+        self.best_move.store(best_move, Ordering::Relaxed);
+        self.verification_key
+            .store(verification_key, Ordering::Relaxed);
+    }
+}
+
+// Two-tier bucket: Deep entries are expensive to recompute so they are kept in the depth-preferred
+// slot, while the always-replace slot keeps the most recent results.
+#[derive(Debug, Default)]
+struct Bucket {
+    depth_preferred: AtomicSlot,
+    always_replace: AtomicSlot,
+}
+
+// Counted since the start of the search.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub probes: usize,
+    pub hits: usize,
+    // Entries matching the Zobrist key of a different position, only detected in verification mode.
+    pub collisions: usize,
+}
+
+// Stats shared by the threads using the table.
+#[derive(Debug, Default)]
+struct Counters {
+    probes: AtomicUsize,
+    hits: AtomicUsize,
+    collisions: AtomicUsize,
+}
+
+impl Counters {
+    fn increment(counter: &AtomicUsize) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn reset(&self) {
+        for counter in [&self.probes, &self.hits, &self.collisions] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+// All the methods only need a shared reference, so that the table can be shared by the search
+// threads, and cleared from another thread during a search.
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    generation: AtomicU8,
+    verification: AtomicBool,
+    stats: Counters,
+}
+
+impl TranspositionTable {
+    // Creates a table using at most the specified memory.
+    pub fn new(size_mb: usize) -> Self {
+        let max_buckets = size_mb * 1024 * 1024 / size_of::<Bucket>();
+        // Power of two, so that the index can be computed with a mask.
+        let count = if max_buckets.is_power_of_two() {
+            max_buckets
+        } else {
+            (max_buckets.next_power_of_two() / 2).max(1)
+        };
+        Self {
+            buckets: (0..count).map(|_| Bucket::default()).collect(),
+            generation: AtomicU8::new(0),
+            verification: AtomicBool::new(false),
+            stats: Counters::default(),
+        }
+    }
+
+    pub fn clear(&self) {
+        for bucket in &self.buckets {
+            bucket.depth_preferred.clear();
+            bucket.always_replace.clear();
+        }
+        self.generation.store(0, Ordering::Relaxed);
+        self.stats.reset();
+    }
+
+    // To be called at the start of each search, so that entries of the previous searches
+    // can be replaced even if they are deeper.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        self.stats.reset();
+    }
+
+    // Existing entries have no verification key, so changing the mode clears the table.
+    pub fn set_verification(&self, verification: bool) {
+        if self.verification.swap(verification, Ordering::Relaxed) != verification {
+            self.clear();
+        }
+    }
+
+    pub fn is_verification(&self) -> bool {
+        self.verification.load(Ordering::Relaxed)
+    }
+
+    pub fn get_stats(&self) -> Stats {
+        Stats {
+            probes: self.stats.probes.load(Ordering::Relaxed),
+            hits: self.stats.hits.load(Ordering::Relaxed),
+            collisions: self.stats.collisions.load(Ordering::Relaxed),
+        }
+    }
+
+    fn verification_key(&self, board: &Board) -> u64 {
+        if self.is_verification() {
+            board.get_verification_key()
+        } else {
+            0
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn index(&self, key: u64) -> usize {
+        (key as usize) & (self.buckets.len() - 1)
+    }
+
+    // Hints the CPU to load the bucket of the key into its cache. Called as soon as the key of a
+    // child position is known, the memory access then overlaps with the work before the probe.
+    // Only on x86-64, where SSE is always available. Elsewhere, it does nothing.
+    #[inline]
+    pub fn prefetch(&self, key: u64) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            let bucket: *const Bucket = &raw const self.buckets[self.index(key)];
+            // SAFETY: A prefetch doesn't access the memory, it can't fault.
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(bucket.cast::<i8>()) };
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = key;
+    }
+
+    pub fn probe(&self, board: &Board) -> Option<Entry> {
+        let verification_key = self.verification_key(board);
+        self.probe_key(board.get_zobrist_key(), verification_key)
+    }
+
+    fn probe_key(&self, key: u64, verification_key: u64) -> Option<Entry> {
+        Counters::increment(&self.stats.probes);
+        let bucket = &self.buckets[self.index(key)];
+        let slot = [&bucket.depth_preferred, &bucket.always_replace]
+            .into_iter()
+            .filter_map(AtomicSlot::load)
+            .find(|slot| slot.entry.key == key)?;
+        if slot.verification_key != verification_key {
+            Counters::increment(&self.stats.collisions);
+            warn!("Transposition table collision on key {key:#x}");
+            return None;
+        }
+        Counters::increment(&self.stats.hits);
+        Some(slot.entry)
+    }
+
+    pub fn store(&self, board: &Board, entry: Entry) {
+        debug_assert_eq!(entry.key, board.get_zobrist_key());
+        let verification_key = self.verification_key(board);
+        self.store_key(entry, verification_key);
+    }
+
+    // Another thread may store in the bucket at the same time: One of the entries is lost, or
+    // torn and so missing, as when it's replaced.
+    fn store_key(&self, entry: Entry, verification_key: u64) {
+        let bucket = &self.buckets[self.index(entry.key)];
+        let slot = Slot {
+            entry,
+            generation: self.generation.load(Ordering::Relaxed),
+            verification_key,
+        };
+        let replace_depth_preferred = bucket.depth_preferred.load().is_none_or(|existing| {
+            existing.entry.key == entry.key
+                || existing.generation != slot.generation
+                || existing.entry.depth <= entry.depth
+        });
+        if replace_depth_preferred {
+            bucket.depth_preferred.store(&slot);
+        } else {
+            bucket.always_replace.store(&slot);
+        }
+    }
+
+    // How full the table is, in per mille, counting only the entries of the current search.
+    // Estimated on a sample of the table, like most engines do, as it's sent regularly to the GUI.
+    pub fn hashfull(&self) -> usize {
+        const SAMPLE_SIZE: usize = 500;
+        let generation = self.generation.load(Ordering::Relaxed);
+        let sample = &self.buckets[..SAMPLE_SIZE.min(self.buckets.len())];
+        let used = sample
+            .iter()
+            .flat_map(|bucket| [&bucket.depth_preferred, &bucket.always_replace])
+            .filter_map(AtomicSlot::load)
+            .filter(|slot| slot.generation == generation)
+            .count();
+        used * 1000 / (sample.len() * 2)
+    }
+
+    // The best line from the board, following the best moves of the entries. It stops at the
+    // first position missing or repeated, or whose move isn't legal, as it could be from another
+    // position with the same key.
+    pub fn best_line(&self, board: &Board, max_len: usize) -> Vec<Move> {
+        let mut line = Vec::new();
+        let mut board = *board;
+        let mut keys = vec![board.get_zobrist_key()];
+        while line.len() < max_len {
+            let Some(mv) = self.probe(&board).and_then(|entry| entry.best_move) else {
+                break;
+            };
+            if board.why_illegal(mv).is_some() {
+                break;
+            }
+            board.update_by_move(mv);
+            if keys.contains(&board.get_zobrist_key()) {
+                break;
+            }
+            keys.push(board.get_zobrist_key());
+            line.push(mv);
+        }
+        line
+    }
+
+    // Makes the moves of the line the best moves of their positions, so that they are searched
+    // first. Only the positions without best move are changed, as the move must match the score
+    // of the entry. The ones without entry get one of depth 0, which can't cause a cutoff.
+    pub fn store_line(&self, board: &Board, line: &[Move]) {
+        let mut board = *board;
+        for &mv in line {
+            if board.why_illegal(mv).is_some() {
+                return;
+            }
+            let entry = self.probe(&board).unwrap_or(Entry {
+                key: board.get_zobrist_key(),
+                depth: 0,
+                score: 0,
+                bound: Bound::Upper,
+                best_move: None,
+            });
+            if entry.best_move.is_none() {
+                self.store(
+                    &board,
+                    Entry {
+                        best_move: Some(mv),
+                        ..entry
+                    },
+                );
+            }
+            board.update_by_move(mv);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{
+        common::{Piece, Square},
+        utils::random_game::random_game,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_size() {
+        let tt = TranspositionTable::new(1);
+        assert!(tt.buckets.len().is_power_of_two());
+        assert!(tt.buckets.len() * size_of::<Bucket>() <= 1024 * 1024);
+        assert_eq!(TranspositionTable::new(0).buckets.len(), 1);
+    }
+
+    fn entry(key: u64, depth: usize) -> Entry {
+        Entry {
+            key,
+            depth,
+            score: 25,
+            bound: Bound::Exact,
+            best_move: Some(Move::double_push(Square::E2, Square::E4, Piece::WhitePawn)),
+        }
+    }
+
+    #[test]
+    fn test_store_probe() {
+        let tt = TranspositionTable::new(1);
+        let entry = entry(0x1234_5678_9ABC_DEF0, 3);
+        assert_eq!(tt.probe_key(entry.key, 0), None);
+        tt.store_key(entry, 0);
+        assert_eq!(tt.probe_key(entry.key, 0), Some(entry));
+        // Same index, different key.
+        assert_eq!(tt.probe_key(entry.key ^ 0xFFFF_0000_0000_0000, 0), None);
+
+        tt.clear();
+        assert_eq!(tt.probe_key(entry.key, 0), None);
+    }
+
+    #[test]
+    fn test_replacement() {
+        let tt = TranspositionTable::new(1);
+        // All in the same bucket.
+        let deep = entry(0x1234_5678_9ABC_DEF0, 6);
+        let shallow = entry(deep.key ^ 0xFFFF_0000_0000_0000, 2);
+        let recent = entry(deep.key ^ 0x0000_FFFF_0000_0000, 1);
+
+        tt.store_key(deep, 0);
+        tt.store_key(shallow, 0);
+        assert_eq!(tt.probe_key(deep.key, 0), Some(deep));
+        assert_eq!(tt.probe_key(shallow.key, 0), Some(shallow));
+
+        // The shallow entry is replaced, the deep one is kept.
+        tt.store_key(recent, 0);
+        assert_eq!(tt.probe_key(deep.key, 0), Some(deep));
+        assert_eq!(tt.probe_key(shallow.key, 0), None);
+        assert_eq!(tt.probe_key(recent.key, 0), Some(recent));
+
+        // Same position searched again, less deep.
+        let updated = Entry { depth: 4, ..deep };
+        tt.store_key(updated, 0);
+        assert_eq!(tt.probe_key(deep.key, 0), Some(updated));
+
+        // Entries of previous searches are replaced.
+        tt.new_search();
+        tt.store_key(shallow, 0);
+        assert_eq!(tt.probe_key(deep.key, 0), None);
+        assert_eq!(tt.probe_key(shallow.key, 0), Some(shallow));
+        assert_eq!(tt.probe_key(recent.key, 0), Some(recent));
+    }
+
+    #[test]
+    fn test_best_line() {
+        let tt = TranspositionTable::new(1);
+        let mut board = Board::initial_board();
+        let line = ["e2e4", "e7e5", "g1f3", "b8c6"].map(|mv| {
+            let mv = board.new_move_from_pure(mv);
+            board.update_by_move(mv);
+            mv
+        });
+        let board = Board::initial_board();
+        assert!(tt.best_line(&board, 10).is_empty());
+
+        tt.store_line(&board, &line);
+        assert_eq!(tt.best_line(&board, 10), line);
+        assert_eq!(tt.best_line(&board, 2), line[..2]);
+        // The hints don't replace the scores of existing entries.
+        let after_e4 = board.copy_with_move(line[0]).unwrap();
+        let mut existing = entry(after_e4.get_zobrist_key(), 5);
+        existing.best_move = None;
+        tt.store(&after_e4, existing);
+        tt.store_line(&board, &line);
+        assert_eq!(
+            tt.probe(&after_e4),
+            Some(Entry {
+                best_move: Some(line[1]),
+                ..existing
+            })
+        );
+
+        // An illegal move ends the line.
+        let illegal = board.new_move_from_pure("e2e5");
+        tt.store_line(&after_e4, &[line[1], illegal]);
+        assert_eq!(tt.best_line(&board, 10), line);
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn test_concurrent_access() {
+        // The entry of each position is derived from its key, so that any entry read can be checked.
+        let expected = |board: &Board| {
+            let key = board.get_zobrist_key();
+            let moves = board.generate_legal_moves();
+            Entry {
+                key,
+                depth: (key % 100) as usize,
+                score: Score::from((key >> 40) as i16),
+                bound: [Bound::Exact, Bound::Lower, Bound::Upper][(key % 3) as usize],
+                best_move: (!moves.is_empty()).then(|| moves[(key as usize) % moves.len()]),
+            }
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let positions: Vec<(Board, Entry)> = (0..10)
+            .flat_map(|_| random_game(Board::initial_board(), 100, &mut rng).collect::<Vec<_>>())
+            .map(|board| (board, expected(&board)))
+            .collect();
+
+        // A single bucket, so that the writers keep replacing the entries the readers are reading.
+        let tt = TranspositionTable::new(0);
+        assert_eq!(tt.buckets.len(), 1);
+        let hits = thread::scope(|s| {
+            for writer in 0..2 {
+                let (tt, positions) = (&tt, &positions);
+                s.spawn(move || {
+                    for i in 0..500_000 {
+                        let (board, entry) = &positions[(i * 31 + writer) % positions.len()];
+                        tt.store(board, *entry);
+                    }
+                });
+            }
+            let readers: Vec<_> = (0..2)
+                .map(|reader| {
+                    let (tt, positions) = (&tt, &positions);
+                    s.spawn(move || {
+                        let mut hits = 0;
+                        for i in 0..500_000 {
+                            let (board, expected) = &positions[(i * 17 + reader) % positions.len()];
+                            if let Some(entry) = tt.probe(board) {
+                                // Never a mix of entries.
+                                assert_eq!(entry, *expected);
+                                if let Some(mv) = entry.best_move {
+                                    assert_eq!(board.why_illegal(mv), None);
+                                }
+                                hits += 1;
+                            }
+                        }
+                        hits
+                    })
+                })
+                .collect();
+            readers
+                .into_iter()
+                .map(|reader| reader.join().unwrap())
+                .sum::<usize>()
+        });
+        assert!(hits > 0);
+        assert_eq!(tt.get_stats().hits, hits);
+    }
+
+    #[test]
+    fn test_hashfull() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(), 0);
+        for key in 0..500 {
+            tt.store_key(entry(key, 1), 0);
+        }
+        assert_eq!(tt.hashfull(), 500);
+        for key in 0..500 {
+            tt.store_key(entry(key + (1 << 40), 0), 0);
+        }
+        assert_eq!(tt.hashfull(), 1000);
+
+        tt.new_search();
+        assert_eq!(tt.hashfull(), 0);
+        tt.store_key(entry(0, 1), 0);
+        assert_eq!(tt.hashfull(), 1);
+    }
+
+    #[test]
+    fn test_verification() {
+        let tt = TranspositionTable::new(1);
+        let board = Board::initial_board();
+        let entry = entry(board.get_zobrist_key(), 3);
+        tt.store(&board, entry);
+        tt.set_verification(true);
+        assert!(tt.is_verification());
+        assert_eq!(tt.probe(&board), None);
+
+        tt.store(&board, entry);
+        assert_eq!(tt.probe(&board), Some(entry));
+        // Same Zobrist key, but a different position.
+        let verification_key = board.get_verification_key();
+        assert_eq!(tt.probe_key(entry.key, verification_key ^ 1), None);
+        assert_eq!(
+            tt.get_stats(),
+            Stats {
+                probes: 3,
+                hits: 1,
+                collisions: 1
+            }
+        );
+
+        tt.new_search();
+        assert_eq!(tt.get_stats(), Stats::default());
+    }
+}
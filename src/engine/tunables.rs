@@ -0,0 +1,192 @@
+//! The search parameters that can be tuned, with their ranges, in one place.
+//! Built with the `tune` feature, they are UCI options, so that an external SPSA driver like
+//! `OpenBench` can play games with perturbed values and converge to better ones:
+//! <https://www.chessprogramming.org/SPSA>
+//! The values are the ones of the search configuration, so a tuned set can be kept as a profile.
+//! The search has no late move reductions, futility pruning nor aspiration windows yet, so the
+//! tunables are the razoring, `ProbCut` and easy move parameters.
+
+use crate::engine::config::SearchConfig;
+
+// Learning rate at the end of the tuning, the usual OpenBench value.
+const SPSA_R_END: f64 = 0.002;
+
+pub struct Tunable {
+    // UCI option name, without spaces as SPSA drivers expect.
+    pub name: &'static str,
+    pub min: i32,
+    pub max: i32,
+    // Size of the perturbations at the end of the tuning, the c_end of OpenBench.
+    pub step: f64,
+    get: fn(&SearchConfig) -> i32,
+    set: fn(&mut SearchConfig, i32),
+}
+
+// The ranges start at 1 for depths, so the conversions can't fail.
+fn from_depth(depth: usize) -> i32 {
+    i32::try_from(depth).unwrap()
+}
+
+fn to_depth(value: i32) -> usize {
+    usize::try_from(value).unwrap()
+}
+
+pub const TUNABLES: [Tunable; 6] = [
+    Tunable {
+        name: "RazorMargin",
+        min: 0,
+        max: 2000,
+        step: 20.0,
+        get: |config| config.razor_margin,
+        set: |config, value| config.razor_margin = value,
+    },
+    Tunable {
+        name: "RazorMaxDepth",
+        min: 1,
+        max: 6,
+        step: 0.5,
+        get: |config| from_depth(config.razor_max_depth),
+        set: |config, value| config.razor_max_depth = to_depth(value),
+    },
+    Tunable {
+        name: "ProbCutMargin",
+        min: 0,
+        max: 1000,
+        step: 15.0,
+        get: |config| config.probcut_margin,
+        set: |config, value| config.probcut_margin = value,
+    },
+    Tunable {
+        name: "ProbCutMinDepth",
+        min: 2,
+        max: 10,
+        step: 0.5,
+        get: |config| from_depth(config.probcut_min_depth),
+        set: |config, value| config.probcut_min_depth = to_depth(value),
+    },
+    Tunable {
+        name: "ProbCutReduction",
+        min: 1,
+        max: 6,
+        step: 0.5,
+        get: |config| from_depth(config.probcut_reduction),
+        set: |config, value| config.probcut_reduction = to_depth(value),
+    },
+    Tunable {
+        name: "EasyMoveMargin",
+        min: 0,
+        max: 1000,
+        step: 15.0,
+        get: |config| config.easy_move_margin,
+        set: |config, value| config.easy_move_margin = value,
+    },
+];
+
+// Option names are not case sensitive.
+pub fn find(name: &str) -> Option<&'static Tunable> {
+    TUNABLES
+        .iter()
+        .find(|tunable| tunable.name.eq_ignore_ascii_case(name))
+}
+
+impl Tunable {
+    pub fn value(&self, config: &SearchConfig) -> i32 {
+        (self.get)(config)
+    }
+
+    pub fn set_value(&self, config: &mut SearchConfig, value: i32) -> Result<(), String> {
+        if !(self.min..=self.max).contains(&value) {
+            return Err(format!(
+                "{} must be between {} and {}, not {value}",
+                self.name, self.min, self.max
+            ));
+        }
+        (self.set)(config, value);
+        Ok(())
+    }
+
+    // Declaration of the option in the answer to the uci command.
+    pub fn uci_option(&self) -> String {
+        format!(
+            "name {} type spin default {} min {} max {}",
+            self.name,
+            self.value(&SearchConfig::default()),
+            self.min,
+            self.max
+        )
+    }
+
+    // Line of the OpenBench SPSA inputs: name, type, default, min, max, c_end, r_end.
+    pub fn spsa_input(&self) -> String {
+        format!(
+            "{}, int, {}, {}, {}, {}, {}",
+            self.name,
+            self.value(&SearchConfig::default()),
+            self.min,
+            self.max,
+            self.step,
+            SPSA_R_END
+        )
+    }
+}
+
+// The SPSA inputs of all the tunables, one per line.
+pub fn spsa_inputs() -> String {
+    TUNABLES
+        .iter()
+        .map(|tunable| tunable.spsa_input() + "\n")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_in_range() {
+        let config = SearchConfig::default();
+        for tunable in &TUNABLES {
+            let value = tunable.value(&config);
+            assert!(
+                (tunable.min..=tunable.max).contains(&value),
+                "{}",
+                tunable.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_set_value() {
+        let mut config = SearchConfig::default();
+        let tunable = find("probcutreduction").unwrap();
+        assert_eq!(tunable.set_value(&mut config, 3), Ok(()));
+        assert_eq!(config.probcut_reduction, 3);
+        assert!(tunable.set_value(&mut config, 0).is_err());
+        assert_eq!(config.probcut_reduction, 3);
+        assert!(find("LmrBase").is_none());
+
+        // Each tunable changes its own field only.
+        for tunable in &TUNABLES {
+            let mut config = SearchConfig::default();
+            tunable.set_value(&mut config, tunable.max).unwrap();
+            assert_eq!(tunable.value(&config), tunable.max);
+            for other in TUNABLES.iter().filter(|other| other.name != tunable.name) {
+                assert_eq!(other.value(&config), other.value(&SearchConfig::default()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let tunable = find("RazorMargin").unwrap();
+        assert_eq!(
+            tunable.uci_option(),
+            "name RazorMargin type spin default 250 min 0 max 2000"
+        );
+        assert_eq!(
+            tunable.spsa_input(),
+            "RazorMargin, int, 250, 0, 2000, 20, 0.002"
+        );
+        assert_eq!(spsa_inputs().lines().count(), TUNABLES.len());
+    }
+}
@@ -0,0 +1,309 @@
+//! Fuzzing of the parsers of untrusted input: FEN strings, UCI commands and moves in pure notation.
+//! Each input is either random bytes, or a valid input of the corpus with a few random mutations,
+//! so that most inputs get deep into the parsers. Inputs must be rejected with an error, never
+//! with a panic, and the accepted positions must be consistent.
+//! The same seed gives the same inputs, to reproduce a failure.
+//! Run with the release profile: cargo r --release -- fuzz fen --iterations 1000000
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    str::FromStr,
+};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+
+use crate::{
+    board::Board,
+    common::{Move, NULL_MOVE_PURE},
+    uci::{self, UciCommand},
+    utils::fen,
+};
+
+const FEN_CORPUS: [&str; 8] = [
+    fen::START_POSITION,
+    fen::KIWIPETE,
+    fen::POSITION_3,
+    fen::POSITION_4,
+    fen::POSITION_5,
+    fen::POSITION_6,
+    "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+    "4k3/8/8/8/8/8/8/4K3 b - - 99 120",
+];
+
+const UCI_CORPUS: [&str; 12] = [
+    "uci",
+    "debug on",
+    "isready",
+    "setoption name Hash value 32",
+    "setoption name Clear Hash",
+    "ucinewgame",
+    "position startpos moves e2e4 e7e5 g1f3",
+    "position fen r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1 moves e1g1",
+    "go wtime 300000 btime 300000 winc 2000 binc 2000 movestogo 40",
+    "go depth 6 movetime 1000",
+    "d attacks flip",
+    "edit put e4 N",
+];
+
+const MOVES_CORPUS: [&str; 10] = [
+    "e2e4",
+    "e7e5",
+    "g1f3",
+    "e1g1",
+    "e8c8",
+    "a7a8q",
+    "b2b1N",
+    "d5e6",
+    NULL_MOVE_PURE,
+    "h7h8",
+];
+
+// Characters that matter to the parsers, more likely to find bugs than random ones.
+const ALPHABET: &[u8] = b"pnbrqkPNBRQK0123456789/ -abcdefghwx";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Fen,
+    Uci,
+    Moves,
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fen" => Ok(Target::Fen),
+            "uci" => Ok(Target::Uci),
+            "moves" => Ok(Target::Moves),
+            _ => Err(format!("Unknown fuzzing target {s}")),
+        }
+    }
+}
+
+impl Target {
+    fn corpus(self) -> &'static [&'static str] {
+        match self {
+            Target::Fen => &FEN_CORPUS,
+            Target::Uci => &UCI_CORPUS,
+            Target::Moves => &MOVES_CORPUS,
+        }
+    }
+
+    fn check(self, input: &str, rng: &mut StdRng) -> Result<(), String> {
+        match self {
+            Target::Fen => check_fen(input),
+            Target::Uci => check_uci(input),
+            Target::Moves => {
+                let fen = FEN_CORPUS.choose(rng).unwrap();
+                check_moves(&Board::from_fen(fen), input, rng)
+            }
+        }
+    }
+}
+
+// An input that panicked or broke a consistency check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub input: String,
+    pub error: String,
+}
+
+// Runs the target on that many random inputs, returning the failures.
+pub fn run(target: Target, iterations: usize, rng: &mut StdRng) -> Vec<Failure> {
+    // The default hook would print each panic, they are reported with their input instead.
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut failures = Vec::new();
+    for _ in 0..iterations {
+        let input = random_input(target.corpus(), rng);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| target.check(&input, rng)));
+        let error = match result {
+            Ok(Ok(())) => continue,
+            Ok(Err(err)) => err,
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(ToString::to_string)
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                format!("panic: {message}")
+            }
+        };
+        failures.push(Failure { input, error });
+    }
+
+    panic::set_hook(default_hook);
+    failures
+}
+
+fn random_input(corpus: &[&str], rng: &mut StdRng) -> String {
+    if rng.gen_ratio(1, 10) {
+        let bytes: Vec<u8> = (0..rng.gen_range(0..64)).map(|_| rng.gen()).collect();
+        return String::from_utf8_lossy(&bytes).into_owned();
+    }
+    let mut input = corpus.choose(rng).unwrap().as_bytes().to_vec();
+    // Mostly few mutations, so that many inputs are still accepted.
+    mutate(&mut input, corpus, rng);
+    while rng.gen_ratio(1, 3) {
+        mutate(&mut input, corpus, rng);
+    }
+    String::from_utf8_lossy(&input).into_owned()
+}
+
+fn mutate(input: &mut Vec<u8>, corpus: &[&str], rng: &mut StdRng) {
+    let byte = |rng: &mut StdRng| {
+        if rng.gen_ratio(1, 8) {
+            rng.gen()
+        } else {
+            *ALPHABET.choose(rng).unwrap()
+        }
+    };
+    let len = input.len();
+    match rng.gen_range(0..6) {
+        0 => input.insert(rng.gen_range(0..=len), byte(rng)),
+        1 if len > 0 => {
+            input.remove(rng.gen_range(0..len));
+        }
+        2 if len > 0 => input[rng.gen_range(0..len)] = byte(rng),
+        // Keeps the length, like the ranks of a FEN.
+        3 if len > 0 => input.swap(rng.gen_range(0..len), rng.gen_range(0..len)),
+        // Duplicates or drops a part of the input.
+        4 if len > 0 => {
+            let start = rng.gen_range(0..len);
+            let end = rng.gen_range(start..=len);
+            if rng.gen() {
+                let part = input[start..end].to_vec();
+                input.splice(end..end, part);
+            } else {
+                input.drain(start..end);
+            }
+        }
+        // Appends another input of the corpus.
+        _ => {
+            input.push(b' ');
+            input.extend_from_slice(corpus.choose(rng).unwrap().as_bytes());
+        }
+    }
+}
+
+// A valid FEN must give a consistent board, the same after writing it back and parsing it again.
+// The moves from the position must keep the board consistent too.
+fn check_fen(fen: &str) -> Result<(), String> {
+    let Ok(board) = Board::try_from_fen(fen) else {
+        return Ok(());
+    };
+    board.check_invariants()?;
+    let written = board.as_fen();
+    if Board::try_from_fen(&written) != Ok(board) {
+        return Err(format!(
+            "The board isn't the same after writing it as {written}"
+        ));
+    }
+    for mv in board.generate_legal_moves() {
+        if let Some(next) = board.copy_with_move(mv) {
+            next.check_invariants()
+                .map_err(|err| format!("{err} after {}", mv.pure()))?;
+        }
+    }
+    Ok(())
+}
+
+// The position of a valid position command is checked like a FEN, and its moves like the moves
+// target.
+fn check_uci(line: &str) -> Result<(), String> {
+    if let Ok(Some(UciCommand::Position(position, moves))) = uci::parse_command(line) {
+        let board = match position {
+            Some(fen) => {
+                check_fen(&fen)?;
+                Board::try_from_fen(&fen).ok()
+            }
+            None => Some(Board::initial_board()),
+        };
+        if let Some(board) = board {
+            play_moves(board, &moves, None)?;
+        }
+    }
+    Ok(())
+}
+
+// The moves of the input are played when legal, with random legal moves in between when the
+// rng is given, so that the moves of the corpus have a chance to be legal.
+fn check_moves(board: &Board, moves: &str, rng: &mut StdRng) -> Result<(), String> {
+    let moves: Vec<String> = moves.split_ascii_whitespace().map(String::from).collect();
+    play_moves(*board, &moves, Some(rng))
+}
+
+// The moves are played like the ones of the command-line, which must accept only the legal ones.
+fn play_moves(
+    mut board: Board,
+    moves: &[String],
+    mut rng: Option<&mut StdRng>,
+) -> Result<(), String> {
+    for s in moves {
+        if let Some(rng) = rng.as_deref_mut() {
+            if let Some(&mv) = board.generate_legal_moves().choose(rng) {
+                board.update_by_move(mv);
+                board.check_invariants()?;
+            }
+        }
+        let legal = board.generate_legal_moves();
+        // Only parsing must not fail: The move may be illegal or invalid.
+        if let Ok(mv) = board.try_new_move_from_pure(s) {
+            if (board.why_illegal(mv).is_none()) != legal.contains(&mv) {
+                return Err(format!("Wrong legality of {s} in {}", board.as_fen()));
+            }
+        }
+        let before = board;
+        if crate::apply_moves(&mut board, s).is_err() {
+            if board != before {
+                return Err(format!("Rejected move {s} changed {}", before.as_fen()));
+            }
+            continue;
+        }
+        // The board must be the one after a legal move.
+        let reached = |&mv: &Move| before.copy_with_move(mv) == Some(board);
+        if s != NULL_MOVE_PURE && !legal.iter().any(reached) {
+            return Err(format!("Illegal move {s} accepted in {}", before.as_fen()));
+        }
+        board
+            .check_invariants()
+            .map_err(|err| format!("{err} after {s}"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn test_corpus_is_valid() {
+        for fen in FEN_CORPUS {
+            assert_eq!(check_fen(fen), Ok(()));
+            assert!(Board::try_from_fen(fen).is_ok(), "{fen}");
+        }
+        for line in UCI_CORPUS {
+            assert!(matches!(uci::parse_command(line), Ok(Some(_))), "{line}");
+        }
+    }
+
+    #[test]
+    fn test_fuzz() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for target in [Target::Fen, Target::Uci, Target::Moves] {
+            assert_eq!(run(target, 300, &mut rng), vec![]);
+        }
+    }
+
+    #[test]
+    fn test_check_moves() {
+        let board = Board::initial_board();
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(check_moves(&board, "e2e4 x e1e2 e7e8k", &mut rng), Ok(()));
+        assert_eq!(play_moves(board, &["e2e4".to_string()], None), Ok(()));
+    }
+}
@@ -0,0 +1,356 @@
+//! Lichess bot: Plays on Lichess through its Bot API <https://lichess.org/api#tag/Bot>,
+//! without an external bridge.
+//! The bot listens to the event stream of its account, accepts the challenges it can play and
+//! plays each game in its own thread, with its own Game. The game streams tell the moves and
+//! clocks, the search runs like for a "go" command with the clocks of the game.
+//! The account must already be upgraded to a bot account.
+
+use std::{
+    io::{BufRead, BufReader, Read},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use serde_json::Value;
+
+use crate::{
+    common::Color,
+    engine::{
+        config::Config,
+        game::{Event, Game, SearchParams},
+    },
+};
+
+pub const DEFAULT_URL: &str = "https://lichess.org";
+// Environment variable with the API token, if not set on the command line.
+pub const TOKEN_ENV_VAR: &str = "LICHESS_BOT_TOKEN";
+
+// Variants we can play. "fromPosition" games are standard chess from a custom position.
+const SUPPORTED_VARIANTS: [&str; 2] = ["standard", "fromPosition"];
+
+pub struct Settings {
+    pub url: String,
+    // Personal API token, with the bot:play scope.
+    pub token: String,
+    // Number of games played at the same time, other challenges are declined.
+    pub max_games: usize,
+    pub config: Config,
+}
+
+// A Lichess Bot API client.
+#[derive(Clone)]
+struct Client {
+    agent: ureq::Agent,
+    url: String,
+    token: String,
+}
+
+impl Client {
+    fn new(url: &str, token: &str) -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new().build(),
+            url: url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<ureq::Response, String> {
+        self.agent
+            .get(&format!("{}{path}", self.url))
+            .set("Authorization", &format!("Bearer {}", self.token))
+            .call()
+            .map_err(|err| format!("GET {path}: {err}"))
+    }
+
+    fn post(&self, path: &str, form: &[(&str, &str)]) -> Result<(), String> {
+        self.agent
+            .post(&format!("{}{path}", self.url))
+            .set("Authorization", &format!("Bearer {}", self.token))
+            .send_form(form)
+            .map(|_| ())
+            .map_err(|err| format!("POST {path}: {err}"))
+    }
+
+    // The account the token belongs to.
+    fn account_id(&self) -> Result<String, String> {
+        let account: Value = self
+            .get("/api/account")?
+            .into_json()
+            .map_err(|err| err.to_string())?;
+        account["id"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| "No account id".to_string())
+    }
+
+    // Calls the handler for each JSON object of a ndjson stream, until the stream ends.
+    fn stream<F>(&self, path: &str, mut handler: F) -> Result<(), String>
+    where
+        F: FnMut(&Value),
+    {
+        read_ndjson(self.get(path)?.into_reader(), |value| handler(&value))
+    }
+}
+
+// Parses a newline delimited JSON stream. Lichess sends empty lines to keep the connection alive.
+fn read_ndjson<R, F>(reader: R, mut handler: F) -> Result<(), String>
+where
+    R: Read,
+    F: FnMut(Value),
+{
+    for line in BufReader::new(reader).lines() {
+        let line = line.map_err(|err| err.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(value) => handler(value),
+            Err(err) => warn!("Invalid JSON {line}: {err}"),
+        }
+    }
+    Ok(())
+}
+
+// Returns the reason to decline the challenge, as expected by the API, or None to accept it.
+fn decline_reason(
+    challenge: &Value,
+    active_games: usize,
+    max_games: usize,
+) -> Option<&'static str> {
+    let variant = challenge["variant"]["key"].as_str().unwrap_or_default();
+    if !SUPPORTED_VARIANTS.contains(&variant) {
+        Some("variant")
+    } else if challenge["speed"] == "correspondence" {
+        Some("timeControl")
+    } else if active_games >= max_games {
+        Some("later")
+    } else {
+        None
+    }
+}
+
+// State of a game, from the "gameState" objects of the game stream.
+#[derive(Debug)]
+struct GameState {
+    moves: Vec<String>,
+    search_params: SearchParams,
+    // "started" while the game is running.
+    status: String,
+}
+
+impl GameState {
+    fn parse(state: &Value) -> Self {
+        let millis = |key: &str| state[key].as_u64().map(Duration::from_millis);
+        Self {
+            moves: state["moves"]
+                .as_str()
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(String::from)
+                .collect(),
+            search_params: SearchParams {
+                white_time: millis("wtime"),
+                black_time: millis("btime"),
+                white_increment: millis("winc"),
+                black_increment: millis("binc"),
+                ..Default::default()
+            },
+            status: state["status"].as_str().unwrap_or_default().to_string(),
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        self.status != "started"
+    }
+}
+
+// Plays a game until it's over.
+struct GamePlayer {
+    client: Client,
+    game_id: String,
+    game: Game,
+    // None until the "gameFull" object tells the players.
+    color: Option<Color>,
+    // FEN of the starting position, None for the standard one.
+    initial_fen: Option<String>,
+}
+
+impl GamePlayer {
+    fn new(client: Client, game_id: &str, config: Config) -> Self {
+        let mut game = Game::new();
+        game.set_config(config);
+        Self {
+            client,
+            game_id: game_id.to_string(),
+            game,
+            color: None,
+            initial_fen: None,
+        }
+    }
+
+    fn play(&mut self, bot_id: &str) -> Result<(), String> {
+        let client = self.client.clone();
+        client.stream(&format!("/api/bot/game/stream/{}", self.game_id), |value| {
+            let result = match value["type"].as_str() {
+                Some("gameFull") => {
+                    self.color = if value["white"]["id"] == bot_id {
+                        Some(Color::White)
+                    } else {
+                        Some(Color::Black)
+                    };
+                    self.initial_fen = value["initialFen"]
+                        .as_str()
+                        .filter(|&fen| fen != "startpos")
+                        .map(String::from);
+                    self.on_state(&GameState::parse(&value["state"]))
+                }
+                Some("gameState") => self.on_state(&GameState::parse(value)),
+                _ => Ok(()),
+            };
+            if let Err(err) = result {
+                warn!("Game {}: {err}", self.game_id);
+            }
+        })
+    }
+
+    fn on_state(&mut self, state: &GameState) -> Result<(), String> {
+        if state.is_over() {
+            info!("Game {} over: {}", self.game_id, state.status);
+            return Ok(());
+        }
+        self.game
+            .set_position(self.initial_fen.as_deref(), &state.moves);
+        if Some(self.game.get_board().get_side_to_move()) != self.color {
+            return Ok(());
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        self.game.start_search(state.search_params, &sender);
+        self.game.wait_for_search();
+        let best_move = receiver.try_iter().find_map(|event| {
+            if let Event::BestMove(mv, _) = event {
+                mv
+            } else {
+                None
+            }
+        });
+        if let Some(mv) = best_move {
+            info!("Game {}: Playing {}", self.game_id, mv.pure());
+            self.client.post(
+                &format!("/api/bot/game/{}/move/{}", self.game_id, mv.pure()),
+                &[],
+            )
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// Listens to the account events until the stream ends.
+pub fn run(settings: &Settings) -> Result<(), String> {
+    let client = Client::new(&settings.url, &settings.token);
+    let bot_id = client.account_id()?;
+    info!("Connected to {} as {bot_id}", settings.url);
+    let active_games = Arc::new(AtomicUsize::new(0));
+
+    client.stream("/api/stream/event", |event| {
+        let result = match event["type"].as_str() {
+            Some("challenge") => {
+                let challenge = &event["challenge"];
+                let id = challenge["id"].as_str().unwrap_or_default();
+                // Our own challenges are listed as well.
+                if challenge["challenger"]["id"] == bot_id.as_str() {
+                    Ok(())
+                } else if let Some(reason) = decline_reason(
+                    challenge,
+                    active_games.load(Ordering::Relaxed),
+                    settings.max_games,
+                ) {
+                    info!("Declining challenge {id}: {reason}");
+                    client.post(
+                        &format!("/api/challenge/{id}/decline"),
+                        &[("reason", reason)],
+                    )
+                } else {
+                    info!("Accepting challenge {id}");
+                    client.post(&format!("/api/challenge/{id}/accept"), &[])
+                }
+            }
+            Some("gameStart") => {
+                let game_id = event["game"]["gameId"].as_str().unwrap_or_default();
+                info!("Game {game_id} started");
+                let mut player = GamePlayer::new(client.clone(), game_id, settings.config);
+                let bot_id = bot_id.clone();
+                let active_games = active_games.clone();
+                active_games.fetch_add(1, Ordering::Relaxed);
+                thread::spawn(move || {
+                    if let Err(err) = player.play(&bot_id) {
+                        warn!("Game {}: {err}", player.game_id);
+                    }
+                    active_games.fetch_sub(1, Ordering::Relaxed);
+                });
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        if let Err(err) = result {
+            warn!("{err}");
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(s: &str) -> Value {
+        serde_json::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_read_ndjson() {
+        let stream = "{\"type\":\"gameStart\"}\n\n\n{\"type\":\"gameFinish\"}\nnot json\n";
+        let mut types = Vec::new();
+        read_ndjson(stream.as_bytes(), |value| {
+            types.push(value["type"].as_str().unwrap().to_string());
+        })
+        .unwrap();
+        assert_eq!(types, ["gameStart", "gameFinish"]);
+    }
+
+    #[test]
+    fn test_decline_reason() {
+        let challenge =
+            json(r#"{"id":"abc","variant":{"key":"standard"},"speed":"blitz","rated":true}"#);
+        assert_eq!(decline_reason(&challenge, 0, 1), None);
+        assert_eq!(decline_reason(&challenge, 1, 1), Some("later"));
+
+        let challenge = json(r#"{"id":"abc","variant":{"key":"chess960"},"speed":"blitz"}"#);
+        assert_eq!(decline_reason(&challenge, 0, 1), Some("variant"));
+        let challenge =
+            json(r#"{"id":"abc","variant":{"key":"fromPosition"},"speed":"correspondence"}"#);
+        assert_eq!(decline_reason(&challenge, 0, 1), Some("timeControl"));
+    }
+
+    #[test]
+    fn test_game_state() {
+        let state = GameState::parse(&json(
+            r#"{"type":"gameState","moves":"e2e4 c7c5","wtime":60000,"btime":59000,"winc":2000,"binc":2000,"status":"started"}"#,
+        ));
+        assert_eq!(state.moves, ["e2e4", "c7c5"]);
+        assert_eq!(state.search_params.white_time, Some(Duration::from_mins(1)));
+        assert_eq!(
+            state.search_params.black_increment,
+            Some(Duration::from_secs(2))
+        );
+        assert!(!state.is_over());
+
+        let state = GameState::parse(&json(r#"{"moves":"","status":"mate"}"#));
+        assert!(state.moves.is_empty());
+        assert!(state.is_over());
+    }
+}
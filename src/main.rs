@@ -3,43 +3,79 @@
 #[macro_use]
 extern crate log;
 
-use clap::{Parser, Subcommand};
-use flexi_logger::{FileSpec, Logger};
+use clap::{builder::FalseyValueParser, Args, Parser, Subcommand};
+use flexi_logger::{Cleanup, Criterion, FileSpec, Logger, LoggerHandle, Naming};
 use std::{
+    fs,
     io::{self, BufReader},
+    net::TcpListener,
     sync::{
-        atomic::AtomicBool,
+        atomic::{AtomicBool, Ordering},
         mpsc::{self, Receiver, Sender},
         Arc, Mutex,
     },
-    time::Instant,
+    thread,
+    time::{Duration, Instant},
 };
 
-use board::Board;
-use common::Move;
-use common::Square;
+use board::{Board, BoardEdit, Theme};
+use common::{Color, Move, Score};
+use common::{ENGINE_BUILD_INFO, ENGINE_VERSION, NULL_MOVE_PURE};
 use engine::{
-    game::{Event, Game, SearchParams},
-    search,
+    config::Config,
+    difficulty::Difficulty,
+    eval::{self, EvalBackend},
+    game::{Event, Game, InfoData, SearchParams},
+    search::{self, trace::Trace, SearchAlgorithm},
+    transposition::TranspositionTable,
+    tunables,
 };
+use itertools::Itertools;
+use rand::{rngs::StdRng, SeedableRng};
+use tournament::{adjudication::Adjudication, stats::Sprt, TimeControl};
+use uci::transcript::{self, RecordingReader, RecordingWriter, Transcript};
+use utils::{chess960, fen, pgn, uci_engine::UciEngine};
 
+mod analysis;
+mod bench;
 mod board;
 mod common;
+#[cfg(feature = "differential")]
+mod differential;
 mod engine;
+mod fuzz;
+mod lichess;
 mod perft;
+mod play;
+mod puzzles;
+mod selftest;
+mod signals;
+mod tournament;
 mod uci;
 mod utils;
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(version = ENGINE_VERSION, long_version = ENGINE_BUILD_INFO, about, long_about = None)]
 struct Arguments {
-    /// Disable logging (default is on)
-    #[arg(short, long)]
-    nolog: bool,
+    #[command(flatten)]
+    log: LogArgs,
 
-    /// Log file discrimant. Useful if multiple instances are running simultaneously.
-    #[arg(short, long)]
-    log_discriminant: Option<String>,
+    /// Records the UCI dialogue with timestamps to this file, to replay it later.
+    #[arg(long)]
+    transcript: Option<String>,
+
+    /// TOML file with engine parameters, to test them without recompiling.
+    #[arg(long, global = true)]
+    config: Option<String>,
+
+    /// Strength of the engine when playing: beginner, club or max. Overrides the config.
+    #[arg(long, global = true)]
+    difficulty: Option<Difficulty>,
+
+    /// How boards are printed, as a comma separated list of: ascii or unicode pieces, color or
+    /// plain (no ANSI codes), coords or nocoords, flip to see the board from Black's side.
+    #[arg(long, global = true)]
+    theme: Option<Theme>,
 
     #[command(subcommand)]
     command: Option<Commands>,
@@ -50,99 +86,498 @@ enum Commands {
     /// Runs divide command.
     Divide {
         depth: usize,
-        position: String,
-        moves: Option<String>,
+        #[command(flatten)]
+        position: PositionArgs,
+        /// Prints the results as JSON, for scripts.
+        #[arg(long)]
+        json: bool,
     },
-    /// Runs Perft command with result only.
+    /// Runs Perft command with result only, or with timing information in JSON.
     Perft {
         depth: usize,
-        position: String,
-        moves: Option<String>,
+        #[command(flatten)]
+        position: PositionArgs,
+        /// Prints the results as JSON, for scripts.
+        #[arg(long)]
+        json: bool,
     },
     /// Runs Perft command with timing information.
     PerftTime {
         depth: usize,
-        position: String,
-        moves: Option<String>,
+        #[command(flatten)]
+        position: PositionArgs,
+    },
+    /// Compares divide results with a reference engine supporting "go perft", and finds
+    /// the position where they differ.
+    PerftDiff {
+        depth: usize,
+        #[command(flatten)]
+        position: PositionArgs,
+        /// Command starting the reference engine, with its arguments if any.
+        #[arg(short, long, default_value = "stockfish")]
+        engine: String,
+    },
+    /// Plays a round robin tournament between Kaik and other UCI engines.
+    Tournament(TournamentArgs),
+    /// Runs the UCI protocol with async I/O on Tokio, instead of the threaded loop.
+    #[cfg(feature = "async")]
+    AsyncUci,
+    /// Runs the UCI protocol on TCP connections instead of stdin and stdout.
+    Serve {
+        /// Address to listen on. Use 0.0.0.0 to accept connections from other machines.
+        #[arg(default_value = "127.0.0.1:4000")]
+        address: String,
+        /// Number of sessions running at the same time, each with its own game.
+        #[arg(long, default_value_t = 4)]
+        max_sessions: usize,
+    },
+    /// Plays on Lichess with a bot account.
+    LichessBot {
+        /// API token with the bot:play scope. Default is the `LICHESS_BOT_TOKEN` environment variable.
+        #[arg(long)]
+        token: Option<String>,
+        #[arg(long, default_value = lichess::DEFAULT_URL)]
+        url: String,
+        /// Number of games played at the same time.
+        #[arg(long, default_value_t = 1)]
+        max_games: usize,
+    },
+    /// Plays a game against Kaik in the terminal.
+    Play(PlayArgs),
+    /// Measures the speed of move generation, evaluation and perft.
+    BenchInternal {
+        /// Number of times each benchmark runs over the positions.
+        #[arg(short, long, default_value_t = 100_000)]
+        iterations: usize,
+        #[arg(short, long, default_value_t = 4)]
+        perft_depth: usize,
     },
     /// Runs a search.
-    Search {
+    Search(SearchArgs),
+    /// Analyses a position, or annotates the moves of a PGN file with evaluations.
+    Analyse {
+        #[command(flatten)]
+        position: PositionArgs,
+        /// PGN file whose games are annotated, instead of the position.
+        #[arg(long, conflicts_with_all = ["position", "fen_file"])]
+        pgn: Option<String>,
+        #[arg(short, long, default_value_t = 4, value_parser = at_least_one)]
+        depth: usize,
+        /// Number of lines to show when analysing a position.
+        #[arg(short, long, default_value_t = 1, value_parser = at_least_one)]
+        multipv: usize,
+    },
+    /// Solves a mate puzzle: Prints the forced mating line in SAN, or fails if there is none
+    /// within the number of moves.
+    Solve {
+        #[command(flatten)]
+        position: PositionArgs,
+        /// Maximum number of moves of the mate.
+        #[arg(short, long, default_value_t = 3)]
+        mate: usize,
+    },
+    /// Finds tactics puzzles in games: Positions where the evaluation swings after a move, and only
+    /// one move takes advantage of it. Prints them as EPD records with the solution.
+    Puzzles(PuzzlesArgs),
+    /// Replays the GUI commands of a UCI transcript, and compares the best moves with the
+    /// recorded ones.
+    Replay {
+        file: String,
+        /// Sends each command as soon as possible, instead of at its recorded time.
+        #[arg(long)]
+        fast: bool,
+    },
+    /// Runs internal consistency checks: perft, evaluation symmetry, Zobrist keys, FEN and binary
+    /// round trips.
+    Selftest {
+        /// Maximum depth of the perft checks, up to 4.
+        #[arg(short, long, default_value_t = selftest::MAX_PERFT_DEPTH)]
         depth: usize,
-        position: String,
-        moves: Option<String>,
+    },
+    /// Fuzzes the parsers of FEN strings, UCI commands or moves with random inputs, reporting
+    /// the ones that panic.
+    Fuzz {
+        /// "fen", "uci" or "moves".
+        target: fuzz::Target,
+        #[arg(short, long, default_value_t = 100_000)]
+        iterations: usize,
+        /// Seed of the random inputs, to reproduce a failure. Random by default.
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Prints the tunable search parameters in the `OpenBench` SPSA input format. The engine must
+    /// be built with the tune feature for them to be UCI options.
+    Tunables,
+    /// Generates random Chess960 starting positions, one FEN per line.
+    ///
+    /// Without Chess960 castling, they only get the castling rights of standard chess, with the
+    /// king on the e-file and the rook in the corner: Most have none, so the games from them aren't
+    /// Chess960 games.
+    Chess960 {
+        /// Number of positions to generate.
+        #[arg(short, long, default_value_t = 1)]
+        count: usize,
+        /// Double Fischer Random Chess: White and Black get different setups.
+        #[arg(short, long)]
+        double: bool,
+        /// Seed of the random generator, to get reproducible positions.
+        #[arg(long)]
+        seed: Option<u64>,
     },
 }
 
-fn create_board(position: &String, moves: &Option<String>) -> Board {
-    let mut b: Board = if position == "startpos" {
-        Board::initial_board()
-    } else {
-        position.as_str().into()
+// The log options can also be set with environment variables, for engines started by tournament
+// managers that don't allow passing arguments.
+#[derive(Args)]
+struct LogArgs {
+    /// Disable logging (default is on)
+    #[arg(short, long, env = "KAIK_NOLOG", value_parser = FalseyValueParser::new())]
+    nolog: bool,
+
+    /// Log file discrimant. Useful if multiple instances are running simultaneously.
+    #[arg(short, long, env = "KAIK_LOG_DISCRIMINANT")]
+    log_discriminant: Option<String>,
+
+    /// Directory of the log files, instead of the working directory.
+    #[arg(long, env = "KAIK_LOG_DIR")]
+    log_dir: Option<String>,
+
+    /// Maximum size of the log file in MB. Above it, the file is rotated to a numbered one.
+    #[arg(long, env = "KAIK_LOG_MAX_SIZE")]
+    log_max_size: Option<u64>,
+
+    /// Number of rotated log files kept, the older ones are deleted.
+    #[arg(
+        long,
+        env = "KAIK_LOG_KEEP",
+        default_value_t = 5,
+        requires = "log_max_size"
+    )]
+    log_keep: usize,
+
+    /// Logs to stderr instead of a file.
+    #[arg(
+        long,
+        env = "KAIK_LOG_STDERR",
+        value_parser = FalseyValueParser::new(),
+        conflicts_with_all = ["log_dir", "log_max_size"]
+    )]
+    log_stderr: bool,
+}
+
+#[derive(Args)]
+struct PositionArgs {
+    /// A FEN string, a named position (startpos, kiwipete, pos2 to pos6), or - to read the FEN
+    /// from stdin. Default is the starting position.
+    position: Option<String>,
+    /// Moves played from the position, in pure notation like "e2e4 e7e5".
+    moves: Option<String>,
+    /// File with the FEN of the position on its first line, instead of the position argument.
+    #[arg(long, conflicts_with = "position")]
+    fen_file: Option<String>,
+    /// Edits applied after the moves: "put e4 N", "remove e4", "side b" or "castling Kq".
+    #[arg(long = "edit")]
+    edits: Vec<BoardEdit>,
+}
+
+#[derive(Args)]
+struct SearchArgs {
+    /// Maximum depth, the other limits may stop the search before.
+    depth: usize,
+    #[command(flatten)]
+    position: PositionArgs,
+    /// Time to search, in milliseconds.
+    #[arg(long)]
+    movetime: Option<u64>,
+    /// Maximum number of nodes to search.
+    #[arg(long)]
+    nodes: Option<usize>,
+    /// Searches without a depth limit, until stopped.
+    #[arg(long, requires = "stop_after")]
+    infinite: bool,
+    /// Stops the search after this time in milliseconds, like the stop command of a GUI.
+    #[arg(long)]
+    stop_after: Option<u64>,
+    /// "alphabeta" or "negamax". Default is the one of the config.
+    #[arg(long)]
+    algorithm: Option<SearchAlgorithm>,
+    /// "material", "classical" or "pst". Default is the one of the config.
+    #[arg(long)]
+    eval: Option<EvalBackend>,
+    /// Prints the results as JSON, for scripts.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args)]
+struct PlayArgs {
+    /// Play with the black pieces.
+    #[arg(long)]
+    black: bool,
+    /// Initial time on each clock, in seconds. Default is to play without clocks.
+    #[arg(long)]
+    time: Option<u64>,
+    /// Time added to the clock after each move, in seconds.
+    #[arg(long, default_value_t = 0)]
+    increment: u64,
+    /// Search depth of the engine, when playing without clocks.
+    #[arg(short, long, default_value_t = play::DEFAULT_DEPTH)]
+    depth: usize,
+    /// File where to save the game, with the engine evaluations and the clock times.
+    #[arg(long)]
+    pgn: Option<String>,
+}
+
+#[derive(Args)]
+struct TournamentArgs {
+    /// Commands starting the other engines, with their arguments if any, like "kaik --nolog".
+    engines: Vec<String>,
+    /// File with the starting positions, one FEN per line. Default is the standard position.
+    #[arg(short, long)]
+    openings: Option<String>,
+    /// Number of times each opening is played by each pair of engines, with both colors.
+    #[arg(short, long, default_value_t = 1)]
+    rounds: usize,
+    /// Search depth for each move. Takes precedence over the move time.
+    #[arg(short, long)]
+    depth: Option<usize>,
+    /// Time for each move, in milliseconds.
+    #[arg(long, default_value_t = 100)]
+    movetime: u64,
+    /// Number of games played in parallel.
+    #[arg(short, long, default_value_t = 1)]
+    concurrency: usize,
+    /// Games are drawn after this number of moves.
+    #[arg(long, default_value_t = 200)]
+    max_moves: usize,
+    /// Games are won when both engines agree one side is ahead by this score, in centipawns,
+    /// for --win-moves moves.
+    #[arg(long, default_value_t = 1000)]
+    win_score: Score,
+    /// Number of moves for the win adjudication, 0 to disable it.
+    #[arg(long, default_value_t = 0)]
+    win_moves: usize,
+    /// Games are drawn when both scores stay within this score of 0 for --draw-moves moves.
+    #[arg(long, default_value_t = 10)]
+    draw_score: Score,
+    /// Number of moves for the draw adjudication, 0 to disable it.
+    #[arg(long, default_value_t = 0)]
+    draw_moves: usize,
+    /// Move number from which draws are adjudicated.
+    #[arg(long, default_value_t = 40)]
+    draw_min_moves: usize,
+    /// Runs a sequential probability ratio test of Kaik against a single other engine, between
+    /// the Elo differences of H0 and H1. The match stops when one of them is accepted.
+    #[arg(long, num_args = 2, value_names = ["ELO0", "ELO1"], allow_negative_numbers = true)]
+    sprt: Option<Vec<f64>>,
+    /// Probability of accepting H1 when H0 is true, for the SPRT.
+    #[arg(long, default_value_t = 0.05)]
+    alpha: f64,
+    /// Probability of accepting H0 when H1 is true, for the SPRT.
+    #[arg(long, default_value_t = 0.05)]
+    beta: f64,
+    /// File where to save the games.
+    #[arg(long)]
+    pgn: Option<String>,
+    /// Adds the evaluations of the engines to the saved games, as %eval annotations.
+    #[arg(long)]
+    pgn_eval: bool,
+}
+
+#[derive(Args)]
+struct PuzzlesArgs {
+    /// PGN file of the games. Without it, the engine plays the games against itself.
+    #[arg(long)]
+    pgn: Option<String>,
+    /// Number of games the engine plays against itself.
+    #[arg(short, long, default_value_t = 10)]
+    games: usize,
+    #[arg(short, long, default_value_t = 4)]
+    depth: usize,
+    /// Minimum swing of the evaluation after the move, in centipawns. The evaluation must be at
+    /// least as much after it.
+    #[arg(long, default_value_t = 200)]
+    min_swing: Score,
+    /// Minimum gap between the best move and the second best one, in centipawns.
+    #[arg(long, default_value_t = 150)]
+    min_gap: Score,
+    /// Maximum number of moves in the solutions.
+    #[arg(long, default_value_t = 3)]
+    max_moves: usize,
+    /// Seed of the random openings of the games played by the engine. Random by default.
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+// For the arguments the search can't do with 0, like the depth.
+fn at_least_one(arg: &str) -> Result<usize, String> {
+    match arg.parse() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(value) => Ok(value),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn create_board(args: &PositionArgs) -> Board {
+    let fen = match read_fen(args) {
+        Ok(fen) => fen,
+        Err(err) => {
+            eprintln!("Invalid position {err}");
+            std::process::exit(1);
+        }
     };
-    if let Some(m) = moves {
-        apply_moves(&mut b, m);
+    let mut b = Board::from_fen(&fen);
+    if let Some(m) = &args.moves {
+        if let Err(err) = apply_moves(&mut b, m) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+    for edit in &args.edits {
+        b.apply_edit(edit);
+    }
+    if !b.has_kings() {
+        eprintln!("Invalid position {b}: each side needs a king");
+        std::process::exit(1);
     }
     b
 }
 
+fn read_fen(args: &PositionArgs) -> Result<String, String> {
+    let first_line = |content: &str| {
+        content
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(String::from)
+    };
+    if let Some(path) = &args.fen_file {
+        let content = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+        return first_line(&content).ok_or(format!("{path}: No FEN found"));
+    }
+    let position = args.position.as_deref().unwrap_or("startpos");
+    if position == "-" {
+        let content = io::read_to_string(io::stdin()).map_err(|err| format!("stdin: {err}"))?;
+        return first_line(&content).ok_or("stdin: No FEN found".to_string());
+    }
+    Ok(fen::named_position(position)
+        .unwrap_or(position)
+        .to_string())
+}
+
+// The handle must be kept until the end, it flushes the logs when dropped.
+fn start_logger(args: &LogArgs) -> LoggerHandle {
+    let logger = Logger::try_with_str("info").unwrap();
+    let logger = if args.log_stderr {
+        logger.log_to_stderr()
+    } else {
+        let mut file_spec = FileSpec::default() // write logs to file
+            .o_discriminant(args.log_discriminant.clone())
+            .suppress_timestamp();
+        if let Some(dir) = &args.log_dir {
+            file_spec = file_spec.directory(dir);
+        }
+        let logger = logger.log_to_file(file_spec);
+        match args.log_max_size {
+            Some(size) => logger.rotate(
+                Criterion::Size(size * 1024 * 1024),
+                Naming::Numbers,
+                Cleanup::KeepLogFiles(args.log_keep),
+            ),
+            None => logger,
+        }
+    };
+    // .duplicate_to_stderr(Duplicate::Warn)     // print warnings and errors also to the console
+    // .append() // do not truncate the log file when the program is restarted
+    logger.start().unwrap_or_else(|err| {
+        eprintln!("Could not start the logger: {err}");
+        std::process::exit(1);
+    })
+}
+
 fn main() {
     let args = Arguments::parse();
 
-    if !args.nolog {
-        Logger::try_with_str("info")
-            .unwrap()
-            .log_to_file(
-                FileSpec::default() // write logs to file
-                    .o_discriminant(args.log_discriminant)
-                    .suppress_timestamp(),
-            )
-            // .duplicate_to_stderr(Duplicate::Warn)     // print warnings and errors also to the console
-            // .append() // do not truncate the log file when the program is restarted
-            .start()
-            .unwrap();
+    let _logger = (!args.log.nolog).then(|| start_logger(&args.log));
+    let mut config = load_config(args.config.as_ref());
+    if let Some(difficulty) = args.difficulty {
+        config.difficulty = difficulty;
+    }
+    if let Some(theme) = args.theme {
+        board::set_theme(theme);
     }
 
     match &args.command {
         Some(Commands::Divide {
             depth,
             position,
-            moves,
-        }) => {
-            divide(&create_board(position, moves), *depth);
-            return;
-        }
+            json,
+        }) => divide(&create_board(position), *depth, *json),
         Some(Commands::Perft {
             depth,
             position,
-            moves,
-        }) => {
-            let nodes_cnt = perft::perft(&create_board(position, moves), *depth);
-            println!("{nodes_cnt}");
-            return;
-        }
-        Some(Commands::PerftTime {
+            json,
+        }) => perft_count(&create_board(position), *depth, *json),
+        Some(Commands::PerftTime { depth, position }) => perft(&create_board(position), *depth),
+        Some(Commands::PerftDiff {
             depth,
             position,
-            moves,
-        }) => {
-            perft(&create_board(position, moves), *depth);
-            return;
+            engine,
+        }) => perft_diff(&create_board(position), *depth, engine),
+        #[cfg(feature = "async")]
+        Some(Commands::AsyncUci) => {
+            let mut game = Game::new();
+            game.set_config(config);
+            if let Err(err) = uci::async_loop::run_stdio(&mut game) {
+                eprintln!("UCI loop failed: {err}");
+            }
         }
-        Some(Commands::Search {
-            depth,
+        Some(Commands::Serve {
+            address,
+            max_sessions,
+        }) => run_server(address, *max_sessions, config),
+        Some(Commands::LichessBot {
+            token,
+            url,
+            max_games,
+        }) => run_lichess_bot(token.as_ref(), url, *max_games, config),
+        Some(Commands::Tournament(tournament_args)) => run_tournament(tournament_args, &config),
+        Some(Commands::Play(play_args)) => run_play(play_args, config),
+        Some(Commands::BenchInternal {
+            iterations,
+            perft_depth,
+        }) => print!("{}", bench::report(&bench::run(*iterations, *perft_depth))),
+        Some(Commands::Search(search_args)) => search(search_args, config),
+        Some(Commands::Analyse {
             position,
-            moves,
-        }) => {
-            search(&create_board(position, moves), *depth);
-            return;
+            pgn,
+            depth,
+            multipv,
+        }) => match pgn {
+            Some(pgn) => annotate(pgn, *depth, &config),
+            None => analysis::analyse_position(&create_board(position), *depth, *multipv, &config),
+        },
+        Some(Commands::Solve { position, mate }) => solve(&create_board(position), *mate, &config),
+        Some(Commands::Puzzles(puzzles_args)) => find_puzzles(puzzles_args, &config),
+        Some(Commands::Chess960 {
+            count,
+            double,
+            seed,
+        }) => chess960(*count, *double, *seed),
+        Some(Commands::Replay { file, fast }) => replay(file, *fast, config),
+        Some(Commands::Selftest { depth }) => run_selftest(*depth),
+        Some(Commands::Fuzz {
+            target,
+            iterations,
+            seed,
+        }) => run_fuzz(*target, *iterations, *seed),
+        Some(Commands::Tunables) => print!("{}", tunables::spsa_inputs()),
+        None => {
+            info!("Kaik Chess Engine");
+            start_uci_loop(config, args.transcript.as_deref());
         }
-        _ => {}
     }
 
-    info!("Kaik Chess Engine");
-
-    start_uci_loop();
-
     // hacks();
 }
 
@@ -164,7 +599,23 @@ fn hacks() {
     }
 }
 
-fn start_uci_loop() {
+fn load_config(path: Option<&String>) -> Config {
+    let Some(path) = path else {
+        return Config::default();
+    };
+    match Config::load(path) {
+        Ok(config) => {
+            info!("Loaded config {path}: {config:?}");
+            config
+        }
+        Err(err) => {
+            eprintln!("Invalid config {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn start_uci_loop(config: Config, transcript: Option<&str>) {
     let stdio = io::stdin();
     let input = BufReader::new(stdio);
 
@@ -172,12 +623,48 @@ fn start_uci_loop() {
     // let output = BufWriter::new(output);
 
     let mut game = Game::new();
+    game.set_config(config);
 
-    uci::run(
-        &mut game,
-        Arc::new(Mutex::new(input)),
-        Arc::new(Mutex::new(output)),
-    );
+    match transcript.map(Transcript::create) {
+        Some(Ok(transcript)) => uci::run_until_signal(
+            &mut game,
+            Arc::new(Mutex::new(RecordingReader::new(input, &transcript))),
+            Arc::new(Mutex::new(RecordingWriter::new(output, &transcript))),
+        ),
+        Some(Err(err)) => eprintln!("Could not create the transcript: {err}"),
+        None => uci::run_until_signal(
+            &mut game,
+            Arc::new(Mutex::new(input)),
+            Arc::new(Mutex::new(output)),
+        ),
+    }
+}
+
+fn replay(path: &str, fast: bool, config: Config) {
+    let entries = match fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|transcript| transcript::parse(&transcript))
+    {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("Could not read the transcript {path}: {err}");
+            return;
+        }
+    };
+    let mut game = Game::new();
+    game.set_config(config);
+    let mismatches = transcript::replay(&mut game, &entries, !fast, io::stdout());
+    if mismatches.is_empty() {
+        eprintln!("Best moves match the transcript");
+    }
+    for (recorded, replayed) in mismatches {
+        let none = || "none".to_string();
+        eprintln!(
+            "Best move differs: recorded {}, replayed {}",
+            recorded.unwrap_or_else(none),
+            replayed.unwrap_or_else(none)
+        );
+    }
 }
 
 fn perft(board: &Board, depth: usize) {
@@ -191,38 +678,345 @@ fn perft(board: &Board, depth: usize) {
     println!("Time: {elapsed:.2?} secs. \t{nodes_secs} millions nodes / secs.");
 }
 
-fn divide(board: &Board, depth: usize) {
-    // Output format is the same as Stockfish "go perft <depth>" command.
-    let nodes = perft::divide(board, depth);
+fn perft_count(board: &Board, depth: usize, json: bool) {
+    let now = Instant::now();
+    let nodes_count = perft::perft(board, depth);
+    if json {
+        println!(
+            "{}",
+            perft::json_report(depth, nodes_count, None, now.elapsed())
+        );
+    } else {
+        println!("{nodes_count}");
+    }
+}
 
+fn divide(board: &Board, depth: usize, json: bool) {
+    let now = Instant::now();
+    let nodes = perft::divide(board, depth);
     let total_nodes: usize = nodes.iter().map(|(_, count)| *count).sum();
+    if json {
+        let report = perft::json_report(depth, total_nodes, Some(&nodes), now.elapsed());
+        println!("{report}");
+        return;
+    }
+
+    // Output format is the same as Stockfish "go perft <depth>" command.
 
     for (mv, count) in &nodes {
         println!("{}: {count}", mv.pure());
     }
     println!();
-    println!("Nodes searched: {total_nodes}",);
+    println!("Nodes searched: {total_nodes}");
+}
+
+fn perft_diff(board: &Board, depth: usize, engine_path: &str) {
+    let command = engine_path
+        .split_whitespace()
+        .map(String::from)
+        .collect_vec();
+    let mut engine = match UciEngine::start(&command) {
+        Ok(engine) => engine,
+        Err(err) => {
+            eprintln!("Failed to start {engine_path}: {err}");
+            return;
+        }
+    };
+    println!("Comparing with {}", engine.get_name());
+    match perft::diff::find_culprit(board, depth, |fen, depth| engine.divide(fen, depth)) {
+        Ok(Some(culprit)) => println!("{culprit}"),
+        Ok(None) => println!("No differences"),
+        Err(err) => eprintln!("Communication with {engine_path} failed: {err}"),
+    }
+}
+
+fn run_play(args: &PlayArgs, config: Config) {
+    let settings = play::Settings {
+        human: if args.black {
+            Color::Black
+        } else {
+            Color::White
+        },
+        clock: args.time.map(|time| play::TimeControl {
+            initial: Duration::from_secs(time),
+            increment: Duration::from_secs(args.increment),
+        }),
+        depth: args.depth,
+        config,
+    };
+    match play::run(&settings, &mut io::stdin().lock(), &mut io::stdout()) {
+        Ok(game) => {
+            if let Some(path) = &args.pgn {
+                fs::write(path, pgn::create(&game)).expect("Could not write PGN file");
+            }
+        }
+        Err(err) => eprintln!("{err}"),
+    }
+}
+
+fn run_tournament(args: &TournamentArgs, config: &Config) {
+    if args.sprt.is_some() && args.engines.len() != 1 {
+        eprintln!("The SPRT needs a match between Kaik and a single other engine");
+        return;
+    }
+    let mut settings = tournament::Settings {
+        engines: Vec::new(),
+        openings: Vec::new(),
+        rounds: args.rounds,
+        time_control: args.depth.map_or(
+            TimeControl::MoveTime(Duration::from_millis(args.movetime)),
+            TimeControl::Depth,
+        ),
+        concurrency: args.concurrency,
+        adjudication: Adjudication {
+            win_score: args.win_score,
+            win_moves: args.win_moves,
+            draw_score: args.draw_score,
+            draw_moves: args.draw_moves,
+            draw_min_moves: args.draw_min_moves,
+            max_moves: args.max_moves,
+            resign: config.resign,
+        },
+        sprt: args.sprt.as_deref().map(|elos| Sprt {
+            elo0: elos[0],
+            elo1: elos[1],
+            alpha: args.alpha,
+            beta: args.beta,
+        }),
+        eval_annotations: args.pgn_eval,
+    };
+
+    // Kaik is always playing.
+    let kaik = std::env::current_exe().expect("Could not find Kaik executable");
+    settings.engines.push(vec![
+        kaik.to_string_lossy().to_string(),
+        "--nolog".to_string(),
+    ]);
+    settings.engines.extend(
+        args.engines
+            .iter()
+            .map(|e| e.split_whitespace().map(String::from).collect()),
+    );
+    settings.openings = if let Some(path) = &args.openings {
+        let content = fs::read_to_string(path).expect("Could not read openings file");
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(String::from)
+            .collect()
+    } else {
+        vec![fen::START_POSITION.to_string()]
+    };
+
+    match tournament::run(&settings) {
+        Ok(records) => {
+            println!();
+            print!("{}", tournament::standings(&records));
+            println!();
+            print!(
+                "{}",
+                tournament::match_stats(&records, settings.sprt.as_ref())
+            );
+            println!();
+            print!("{}", tournament::terminations(&records));
+            if let Some(path) = &args.pgn {
+                let pgn = records.iter().map(|r| pgn::create(&r.pgn)).join("\n");
+                fs::write(path, pgn).expect("Could not write PGN file");
+            }
+        }
+        Err(err) => eprintln!("Tournament failed: {err}"),
+    }
+}
+
+fn run_server(address: &str, max_sessions: usize, config: Config) {
+    match TcpListener::bind(address) {
+        Ok(listener) => uci::serve(&listener, config, max_sessions),
+        Err(err) => eprintln!("Could not listen on {address}: {err}"),
+    }
 }
 
-fn search(board: &Board, depth: usize) {
+fn run_lichess_bot(token: Option<&String>, url: &str, max_games: usize, config: Config) {
+    let Some(token) = token
+        .cloned()
+        .or_else(|| std::env::var(lichess::TOKEN_ENV_VAR).ok())
+    else {
+        eprintln!("Missing Lichess token");
+        return;
+    };
+    let settings = lichess::Settings {
+        url: url.to_string(),
+        token,
+        max_games,
+        config,
+    };
+    if let Err(err) = lichess::run(&settings) {
+        eprintln!("Lichess bot failed: {err}");
+    }
+}
+
+fn search(args: &SearchArgs, mut config: Config) {
+    if let Some(algorithm) = args.algorithm {
+        config.search.algorithm = algorithm;
+    }
+    if let Some(backend) = args.eval {
+        config.eval.backend = backend;
+    }
+    let board = create_board(&args.position);
     let stop_flag = Arc::new(AtomicBool::new(false));
-    let sp = SearchParams { depth: Some(depth) };
-    let (event_sender, _event_receiver): (Sender<Event>, Receiver<Event>) = mpsc::channel();
+    let sp = SearchParams {
+        depth: (!args.infinite).then_some(args.depth),
+        move_time: args.movetime.map(Duration::from_millis),
+        nodes: args.nodes,
+        search: config.search,
+        ..Default::default()
+    };
+    if let Some(stop_after) = args.stop_after {
+        let stop_flag = stop_flag.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(stop_after));
+            stop_flag.store(true, Ordering::Relaxed);
+        });
+    }
+    let (event_sender, event_receiver): (Sender<Event>, Receiver<Event>) = mpsc::channel();
+    // Streams the progress of the iterative deepening like in UCI mode, and keeps the info of the
+    // last completed depth.
+    let json = args.json;
+    let event_sink = thread::spawn(move || {
+        let mut last_info = Vec::new();
+        for event in event_receiver {
+            if let Event::Info(info) = event {
+                if !json {
+                    println!("{}", uci::format_info(&info));
+                }
+                if info.iter().any(|data| matches!(data, InfoData::Depth(_))) {
+                    last_info = info;
+                }
+            }
+        }
+        last_info
+    });
 
     let now = Instant::now();
-    let result = search::run(board, &sp, &event_sender, &stop_flag);
+    let tt = TranspositionTable::new(config.search.hash);
+    let evaluator = eval::new_evaluator(&config.eval);
+    let result = search::run(
+        &board,
+        &sp,
+        evaluator.as_ref(),
+        &event_sender,
+        &stop_flag,
+        &tt,
+        &mut Trace::disabled(),
+    );
     let elapsed = now.elapsed();
-
+    // Closes the channel, ending the sink.
+    drop(event_sender);
+    let info = event_sink.join().unwrap();
+    // The limits may stop the search before the requested depth.
+    let depth = info
+        .iter()
+        .find_map(|data| match data {
+            InfoData::Depth(depth) => Some(*depth),
+            _ => None,
+        })
+        .unwrap_or_default();
+    if args.json {
+        println!("{}", search::json_report(depth, &result, &info, elapsed));
+        return;
+    }
     println!("Search({depth}) {elapsed:.2?} secs: {result}");
     if let search::Result::BestMove(mv, _score) = result {
         board.print_with_move(Some(mv));
     }
 }
 
+fn annotate(path: &str, depth: usize, config: &Config) {
+    let pgn = fs::read_to_string(path).expect("Could not read PGN file");
+    for game in pgn::parse(&pgn) {
+        match analysis::annotate_game(&game, depth, config) {
+            Ok(annotated) => println!("{}", pgn::create(&annotated)),
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+}
+
+// Exits with an error when there is no mate, for puzzle validation scripts.
+fn solve(board: &Board, max_moves: usize, config: &Config) {
+    let Some(line) = analysis::solve_mate(board, max_moves, config) else {
+        println!("No mate in {max_moves} moves");
+        std::process::exit(1);
+    };
+    println!(
+        "Mate in {}: {}",
+        line.score.mate_moves().unwrap_or_default(),
+        board.format_moves_as_san_string(&line.pv)
+    );
+}
+
+fn find_puzzles(args: &PuzzlesArgs, config: &Config) {
+    let settings = puzzles::Settings {
+        depth: args.depth.max(1),
+        min_swing: args.min_swing,
+        min_gap: args.min_gap,
+        max_moves: args.max_moves.max(1),
+    };
+    let games = if let Some(file) = &args.pgn {
+        pgn::parse(&fs::read_to_string(file).expect("Could not read PGN file"))
+    } else {
+        let mut rng = args
+            .seed
+            .map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+        (1..=args.games)
+            .map(|round| puzzles::self_play(&mut rng, round, settings.depth, config))
+            .collect()
+    };
+    let mut count = 0;
+    for game in &games {
+        match puzzles::find_in_game(game, &settings, config) {
+            Ok(found) => {
+                for puzzle in found {
+                    count += 1;
+                    println!("{}", puzzle.to_epd(&format!("puzzle.{count}")));
+                }
+            }
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+}
+
+fn chess960(count: usize, double: bool, seed: Option<u64>) {
+    let mut rng = seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+    for _ in 0..count {
+        println!("{}", chess960::random_fen(&mut rng, double));
+    }
+}
+
+fn run_selftest(depth: usize) {
+    let results = selftest::run(depth);
+    print!("{}", selftest::report(&results));
+    if !selftest::all_passed(&results) {
+        std::process::exit(1);
+    }
+}
+
+fn run_fuzz(target: fuzz::Target, iterations: usize, seed: Option<u64>) {
+    let seed = seed.unwrap_or_else(rand::random);
+    println!("Fuzzing {target:?} with {iterations} inputs, seed {seed}");
+    let failures = fuzz::run(target, iterations, &mut StdRng::seed_from_u64(seed));
+    for failure in &failures {
+        println!("{:?}: {}", failure.input, failure.error);
+    }
+    println!("{} failures", failures.len());
+    if !failures.is_empty() {
+        std::process::exit(1);
+    }
+}
+
 fn print_moves_with_board(board: &Board, moves: &[Move]) {
     println!();
     for mv in moves {
-        println!("{mv}");
+        println!("{}", mv.annotated(board));
         board.print_with_move(Some(*mv));
     }
 }
@@ -235,11 +1029,62 @@ fn print_moves_statistics(moves: &[Move]) {
     );
 }
 
-fn apply_moves(board: &mut Board, moves: &str) {
+// Moves are in pure notation, like UCI ones.
+// Plays moves in pure notation, failing on the first one that isn't legal.
+fn apply_moves(board: &mut Board, moves: &str) -> Result<(), String> {
     for mv in moves.split_ascii_whitespace() {
-        assert_eq!(mv.len(), 4);
-        let from: Square = mv[0..2].try_into().unwrap();
-        let to: Square = mv[2..4].try_into().unwrap();
-        board.update_by_move(board.new_move(from, to));
+        if mv == NULL_MOVE_PURE {
+            // Passing when in check would let the opponent capture the king.
+            if board.in_check() {
+                return Err(format!("Illegal move {mv}: the king is in check"));
+            }
+            board.make_null_move();
+        } else {
+            let parsed = board
+                .parse_pure_move(mv)
+                .map_err(|reason| format!("Illegal move {mv}: {reason}"))?;
+            board.update_by_move(parsed);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn test_arguments() {
+        // Catches clashes between the options of the subcommands and the global ones.
+        Arguments::command().debug_assert();
+    }
+
+    #[test]
+    fn test_apply_moves() {
+        let mut board = Board::initial_board();
+        assert_eq!(apply_moves(&mut board, "e2e4 0000 d2d4"), Ok(()));
+        assert_eq!(
+            board.as_fen(),
+            "rnbqkbnr/pppppppp/8/8/3PP3/8/PPP2PPP/RNBQKBNR b KQkq - 0 2"
+        );
+
+        for moves in ["e2e9", "e7e5", "e1e2"] {
+            assert!(apply_moves(&mut Board::initial_board(), moves).is_err());
+        }
+        let mut board = Board::from_fen("8/P7/8/8/8/8/8/k6K w - - 0 1");
+        assert!(apply_moves(&mut board, "a7a8").is_err());
+        assert_eq!(apply_moves(&mut board, "a7a8n"), Ok(()));
+        // Passing in check.
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 b - - 0 1");
+        assert!(apply_moves(&mut board, "e8e7 a1a7 0000").is_err());
+    }
+
+    #[test]
+    fn test_analyse_arguments() {
+        assert!(Arguments::try_parse_from(["kaik", "analyse", "--depth", "2"]).is_ok());
+        assert!(Arguments::try_parse_from(["kaik", "analyse", "--depth", "0"]).is_err());
+        assert!(Arguments::try_parse_from(["kaik", "analyse", "--multipv", "0"]).is_err());
     }
 }
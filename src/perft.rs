@@ -1,20 +1,25 @@
 //! Perft <https://www.chessprogramming.org/Perft>
 
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
 use crate::{board::Board, common::Move};
 
+pub mod diff;
+
 pub fn perft(board: &Board, depth: usize) -> usize {
     if depth == 0 {
         return 1;
     }
 
+    // Bulk counting: The moves of the last ply don't need to be made.
+    if depth == 1 {
+        return board.count_legal_moves();
+    }
+
     let mut nodes = 0;
     let move_list = board.generate_moves();
-
-    // If we had a legal move generator, we could do following optimization:
-    // if depth == 1 {
-    //     return move_list.len();
-    // }
-
     for mv in move_list {
         if let Some(board_copy) = board.copy_with_move(mv) {
             nodes += perft(&board_copy, depth - 1);
@@ -36,6 +41,33 @@ pub fn divide(board: &Board, depth: usize) -> Vec<(Move, usize)> {
     nodes
 }
 
+// Nodes per second, for reports.
+pub fn nps(nodes: usize, elapsed: Duration) -> u128 {
+    nodes as u128 * 1_000_000 / elapsed.as_micros().max(1)
+}
+
+// The results as JSON for scripts, with the counts of each move for divide.
+pub fn json_report(
+    depth: usize,
+    nodes: usize,
+    divided: Option<&[(Move, usize)]>,
+    elapsed: Duration,
+) -> Value {
+    let mut report = json!({
+        "depth": depth,
+        "nodes": nodes,
+        "time_ms": elapsed.as_millis(),
+        "nps": nps(nodes, elapsed),
+    });
+    if let Some(divided) = divided {
+        report["moves"] = divided
+            .iter()
+            .map(|(mv, count)| json!({"move": mv.pure().to_string(), "nodes": count}))
+            .collect();
+    }
+    report
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -52,6 +84,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_json_report() {
+        let board = Board::initial_board();
+        let divided = divide(&board, 1);
+        let report = json_report(1, 20, Some(&divided), Duration::from_millis(2));
+        assert_eq!(report["nodes"], 20);
+        assert_eq!(report["time_ms"], 2);
+        assert_eq!(report["nps"], 10_000);
+        assert_eq!(report["moves"].as_array().unwrap().len(), 20);
+        assert_eq!(report["moves"][0], json!({"move": "a2a3", "nodes": 1}));
+
+        let report = json_report(3, 8902, None, Duration::ZERO);
+        assert!(report.get("moves").is_none());
+    }
+
     #[test]
     fn test_perft_initial() {
         let board = Board::initial_board();
@@ -88,7 +135,7 @@ mod tests {
     }
 
     #[test]
-    #[cfg_attr(not(feature = "perft"), ignore)]
+    #[cfg_attr(not(feature = "perft"), ignore = "slow, enable with the perft feature")]
     fn test_peterellisjones_slow() {
         // Slower tests, not enabled by default.
         let b: Board = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8".into();
@@ -99,35 +146,35 @@ mod tests {
         assert_eq!(perft(&b, 3), 89890);
 
         let b: Board = "3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1".into();
-        assert_eq!(perft(&b, 6), 1134888);
+        assert_eq!(perft(&b, 6), 1_134_888);
 
         // Push and en-passant leaving king in check.
         let b: Board = "8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1".into();
-        assert_eq!(perft(&b, 6), 1015133);
+        assert_eq!(perft(&b, 6), 1_015_133);
 
         let b: Board = "8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1".into();
-        assert_eq!(perft(&b, 6), 1440467);
+        assert_eq!(perft(&b, 6), 1_440_467);
 
         let b: Board = "5k2/8/8/8/8/8/8/4K2R w K - 0 1".into();
-        assert_eq!(perft(&b, 6), 661072);
+        assert_eq!(perft(&b, 6), 661_072);
 
         let b: Board = "3k4/8/8/8/8/8/8/R3K3 w Q - 0 1".into();
-        assert_eq!(perft(&b, 6), 803711);
+        assert_eq!(perft(&b, 6), 803_711);
 
         let b: Board = "r3k2r/1b4bq/8/8/8/8/7B/R3K2R w KQkq - 0 1".into();
-        assert_eq!(perft(&b, 4), 1274206);
+        assert_eq!(perft(&b, 4), 1_274_206);
 
         let b: Board = "r3k2r/8/3Q4/8/8/5q2/8/R3K2R b KQkq - 0 1".into();
-        assert_eq!(perft(&b, 4), 1720476);
+        assert_eq!(perft(&b, 4), 1_720_476);
 
         let b: Board = "2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1".into();
-        assert_eq!(perft(&b, 6), 3821001);
+        assert_eq!(perft(&b, 6), 3_821_001);
 
         let b: Board = "8/8/1P2K3/8/2n5/1q6/8/5k2 b - - 0 1".into();
-        assert_eq!(perft(&b, 5), 1004658);
+        assert_eq!(perft(&b, 5), 1_004_658);
 
         let b: Board = "4k3/1P6/8/8/8/8/K7/8 w - - 0 1".into();
-        assert_eq!(perft(&b, 6), 217342);
+        assert_eq!(perft(&b, 6), 217_342);
 
         let b: Board = "8/P1k5/K7/8/8/8/8/8 w - - 0 1".into();
         assert_eq!(perft(&b, 6), 92683);
@@ -136,7 +183,7 @@ mod tests {
         assert_eq!(perft(&b, 6), 2217);
 
         let b: Board = "8/k1P5/8/1K6/8/8/8/8 w - - 0 1".into();
-        assert_eq!(perft(&b, 7), 567584);
+        assert_eq!(perft(&b, 7), 567_584);
 
         let b: Board = "8/8/2k5/5q2/5n2/8/5K2/8 b - - 0 1".into();
         assert_eq!(perft(&b, 4), 23527);
@@ -0,0 +1,158 @@
+//! Compares perft results with a reference engine, to find move generation bugs.
+//! When the counts differ for a move, we play it and compare again one level deeper,
+//! until reaching the position where one engine generates a move the other doesn't.
+
+use std::{collections::BTreeMap, fmt::Display, io};
+
+use crate::board::Board;
+
+use super::divide;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Difference {
+    ExtraMove(String),   // Kaik generates a move the reference doesn't.
+    MissingMove(String), // Kaik doesn't generate a move the reference does.
+}
+
+// The position where the engines disagree, and how we got there from the initial position.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Culprit {
+    pub moves: Vec<String>,
+    pub fen: String,
+    pub difference: Difference,
+}
+
+impl Display for Culprit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Moves: {}", self.moves.join(" "))?;
+        writeln!(f, "Position: {}", self.fen)?;
+        match &self.difference {
+            Difference::ExtraMove(mv) => write!(f, "Kaik generates {mv}, the reference doesn't"),
+            Difference::MissingMove(mv) => write!(f, "Kaik doesn't generate {mv}"),
+        }
+    }
+}
+
+// Finds where Kaik and the reference disagree. Returns None if they agree.
+// The reference takes a FEN and a depth, and returns the perft count of each move in pure format.
+pub fn find_culprit<F>(board: &Board, depth: usize, mut reference: F) -> io::Result<Option<Culprit>>
+where
+    F: FnMut(&str, usize) -> io::Result<Vec<(String, usize)>>,
+{
+    let mut board = *board;
+    let mut moves = Vec::new();
+    for depth in (1..=depth).rev() {
+        let fen = board.as_fen();
+        let kaik: BTreeMap<String, usize> = divide(&board, depth)
+            .iter()
+            .map(|(mv, count)| (mv.pure().to_string(), *count))
+            .collect();
+        let reference: BTreeMap<String, usize> = reference(&fen, depth)?.into_iter().collect();
+
+        let difference = if let Some(mv) = kaik.keys().find(|mv| !reference.contains_key(*mv)) {
+            Some(Difference::ExtraMove(mv.clone()))
+        } else {
+            reference
+                .keys()
+                .find(|mv| !kaik.contains_key(*mv))
+                .map(|mv| Difference::MissingMove(mv.clone()))
+        };
+        if let Some(difference) = difference {
+            return Ok(Some(Culprit {
+                moves,
+                fen,
+                difference,
+            }));
+        }
+
+        // Same moves, so the difference, if any, is deeper.
+        let Some((mv, _)) = kaik.iter().find(|(mv, count)| reference[*mv] != **count) else {
+            return Ok(None);
+        };
+        let played = board
+            .parse_pure_move(mv)
+            .map_err(|reason| io::Error::new(io::ErrorKind::InvalidData, reason.to_string()))?;
+        board.update_by_move(played);
+        moves.push(mv.clone());
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A reference that is Kaik itself, but that never generates the king moving to the square.
+    fn buggy_reference(
+        forbidden_square: &'static str,
+    ) -> impl FnMut(&str, usize) -> io::Result<Vec<(String, usize)>> {
+        fn perft_without(board: &Board, depth: usize, forbidden_square: &str) -> usize {
+            if depth == 0 {
+                return 1;
+            }
+            board
+                .generate_legal_moves()
+                .iter()
+                .filter(|mv| {
+                    !(mv.get_piece().is_king() && mv.get_to().to_string() == forbidden_square)
+                })
+                .map(|mv| {
+                    perft_without(
+                        &board.copy_with_move(*mv).unwrap(),
+                        depth - 1,
+                        forbidden_square,
+                    )
+                })
+                .sum()
+        }
+
+        move |fen, depth| {
+            let board = Board::from_fen(fen);
+            Ok(board
+                .generate_legal_moves()
+                .iter()
+                .filter(|mv| {
+                    !(mv.get_piece().is_king() && mv.get_to().to_string() == forbidden_square)
+                })
+                .map(|mv| {
+                    let count = perft_without(
+                        &board.copy_with_move(*mv).unwrap(),
+                        depth - 1,
+                        forbidden_square,
+                    );
+                    (mv.pure().to_string(), count)
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_no_difference() {
+        let board = Board::initial_board();
+        let reference = |fen: &str, depth| {
+            Ok(divide(&Board::from_fen(fen), depth)
+                .iter()
+                .map(|(mv, count)| (mv.pure().to_string(), *count))
+                .collect())
+        };
+        assert_eq!(find_culprit(&board, 3, reference).unwrap(), None);
+    }
+
+    #[test]
+    fn test_find_culprit() {
+        let board = Board::initial_board();
+        let culprit = find_culprit(&board, 3, buggy_reference("e2"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(culprit.moves.len(), 2);
+        assert_eq!(
+            culprit.difference,
+            Difference::ExtraMove("e1e2".to_string())
+        );
+        let board = Board::from_fen(&culprit.fen);
+        assert!(board
+            .generate_legal_moves()
+            .iter()
+            .any(|mv| mv.pure().to_string() == "e1e2"));
+    }
+}
@@ -0,0 +1,494 @@
+//! Console play mode: A human plays against the engine in the terminal.
+//! Moves are entered in SAN or in pure notation. The board is shown from the human's side.
+//! The human can also ask for a hint, or take back the last moves.
+//! The engine resigns and offers draws following the resign settings of the config.
+//! Games can be played with clocks for both sides, the engine managing its own time with them.
+//! The game is returned as PGN, with the engine evaluations and the clock times as annotations.
+
+use std::{
+    io::{self, BufRead, Write},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    board::{outcome::Outcome, IllegalReason, Theme},
+    common::{Color, Move, ENGINE_NAME, ENGINE_VERSION},
+    engine::{
+        config::Config,
+        game::{Event, Game, SearchParams},
+        resign::{self, ScoreHistory},
+    },
+    utils::{
+        fen,
+        pgn::{self, Eval, PgnGame, PgnMove},
+    },
+};
+
+// Depth of the engine search in games without clocks.
+pub const DEFAULT_DEPTH: usize = 6;
+// Hints come from a quick search.
+const HINT_DEPTH: usize = 4;
+
+pub struct Settings {
+    pub human: Color,
+    pub clock: Option<TimeControl>,
+    // Depth of the engine search in games without clocks.
+    pub depth: usize,
+    pub config: Config,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeControl {
+    pub initial: Duration,
+    pub increment: Duration,
+}
+
+// Chess clock: The time left of each side, running for the side to move.
+#[derive(Debug)]
+pub struct Clock {
+    remaining: [Duration; 2],
+    increment: Duration,
+    // When the running clock was started.
+    started: Option<Instant>,
+}
+
+impl Clock {
+    pub fn new(time_control: TimeControl) -> Self {
+        Self {
+            remaining: [time_control.initial; 2],
+            increment: time_control.increment,
+            started: None,
+        }
+    }
+
+    // Starts the clock of the side to move, if it's not already running.
+    pub fn start(&mut self) {
+        self.started.get_or_insert_with(Instant::now);
+    }
+
+    // Stops the clock of the side that just moved. Returns false if its flag fell.
+    pub fn stop(&mut self, color: Color) -> bool {
+        let elapsed = self.started.take().map_or(Duration::ZERO, |s| s.elapsed());
+        self.record(color, elapsed)
+    }
+
+    // Takes the time of a move off the clock, adding the increment if the flag didn't fall.
+    fn record(&mut self, color: Color, elapsed: Duration) -> bool {
+        let remaining = &mut self.remaining[color as usize];
+        match remaining.checked_sub(elapsed) {
+            Some(left) if !left.is_zero() => {
+                *remaining = left + self.increment;
+                true
+            }
+            _ => {
+                *remaining = Duration::ZERO;
+                false
+            }
+        }
+    }
+
+    pub fn remaining(&self, color: Color) -> Duration {
+        self.remaining[color as usize]
+    }
+
+    // The clocks as given to the engine time manager.
+    pub fn search_params(&self) -> SearchParams {
+        SearchParams {
+            white_time: Some(self.remaining(Color::White)),
+            black_time: Some(self.remaining(Color::Black)),
+            white_increment: Some(self.increment),
+            black_increment: Some(self.increment),
+            ..Default::default()
+        }
+    }
+}
+
+// Clock time as minutes and seconds, with tenths in the last 10 seconds.
+fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    if secs < 10 {
+        format!("0:{:04.1}", time.as_secs_f64())
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+// The move entered by the human, in SAN or pure notation, or why it's illegal.
+fn parse_move(game: &Game, input: &str) -> Result<Move, String> {
+    let board = game.get_board();
+    if let Some(mv) = board.new_move_from_san(input) {
+        return Ok(mv);
+    }
+    match board.parse_pure_move(input) {
+        Ok(mv) => Ok(mv),
+        // Probably an illegal move in SAN, which can't be explained as easily.
+        Err(IllegalReason::InvalidNotation) => Err(format!("Illegal move {input}")),
+        Err(reason) => Err(format!("Illegal move {input}: {reason}")),
+    }
+}
+
+// The best move and its evaluation.
+fn engine_move(game: &mut Game, search_params: SearchParams) -> (Option<Move>, Option<Eval>) {
+    let (sender, receiver) = mpsc::channel();
+    game.start_search(search_params, &sender);
+    game.wait_for_search();
+    let mut eval = None;
+    for event in receiver.try_iter() {
+        match event {
+            Event::Info(info) => eval = resign::eval_of(&info).or(eval),
+            Event::BestMove(mv, _) => return (mv, eval),
+        }
+    }
+    (None, eval)
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "White",
+        Color::Black => "Black",
+    }
+}
+
+// What to do after a command of the human.
+enum Action {
+    // The move, with the engine evaluation for its moves.
+    Play(Move, Option<Eval>),
+    // Wait for the next command.
+    Continue,
+    // The result and the reason, or none if the game was interrupted.
+    GameOver(Option<(Outcome, &'static str)>),
+}
+
+struct ConsoleGame<'a> {
+    settings: &'a Settings,
+    game: Game,
+    clock: Option<Clock>,
+    theme: Theme,
+    // Moves in pure notation, to replay the game when taking back moves.
+    moves: Vec<String>,
+    pgn: PgnGame,
+    // Scores of the engine moves, for resigning and draw offers.
+    engine_scores: ScoreHistory,
+    draw_offered: bool,
+}
+
+impl ConsoleGame<'_> {
+    fn human_command<W: Write>(&mut self, command: &str, output: &mut W) -> io::Result<Action> {
+        let board = self.game.get_board();
+        let resign_config = &self.settings.config.resign;
+        match command {
+            "quit" => return Ok(Action::GameOver(None)),
+            "hint" => {
+                let search_params = SearchParams {
+                    depth: Some(HINT_DEPTH),
+                    ..Default::default()
+                };
+                if let (Some(mv), _) = engine_move(&mut self.game, search_params) {
+                    writeln!(output, "Hint: {}", board.move_to_san(mv))?;
+                }
+            }
+            "undo" | "takeback" => {
+                // Takes back the last move of each side. The clocks keep their time.
+                if self.moves.len() < 2 {
+                    writeln!(output, "No move to take back")?;
+                } else {
+                    self.moves.truncate(self.moves.len() - 2);
+                    self.pgn.moves.truncate(self.moves.len());
+                    self.engine_scores.pop();
+                    self.draw_offered = false;
+                    self.game.set_position(None, &self.moves);
+                    let _ = self.game.get_board().write_with_theme(output, self.theme);
+                }
+            }
+            "draw" => {
+                let full_move = board.get_full_move_counter();
+                if self.draw_offered || self.engine_scores.accepts_draw(resign_config, full_move) {
+                    return Ok(Action::GameOver(Some((Outcome::Draw, "draw agreed"))));
+                }
+                writeln!(output, "Kaik declines the draw")?;
+            }
+            "resign" => {
+                let outcome = Outcome::win_for(self.settings.human.opposite());
+                return Ok(Action::GameOver(Some((outcome, "resignation"))));
+            }
+            line => match parse_move(&self.game, line) {
+                Ok(mv) => {
+                    self.draw_offered = false;
+                    return Ok(Action::Play(mv, None));
+                }
+                Err(err) => writeln!(output, "{err}")?,
+            },
+        }
+        Ok(Action::Continue)
+    }
+
+    fn engine_turn<W: Write>(&mut self, output: &mut W) -> io::Result<Action> {
+        let board = self.game.get_board();
+        let resign_config = &self.settings.config.resign;
+        let search_params = self.clock.as_ref().map_or(
+            SearchParams {
+                depth: Some(self.settings.depth),
+                ..Default::default()
+            },
+            Clock::search_params,
+        );
+        let (Some(mv), eval) = engine_move(&mut self.game, search_params) else {
+            return Ok(Action::GameOver(None));
+        };
+        if let Some(eval) = eval {
+            self.engine_scores.push(resign::score_of(eval));
+        }
+        if self.engine_scores.should_resign(resign_config) {
+            writeln!(output, "Kaik resigns")?;
+            let outcome = Outcome::win_for(self.settings.human);
+            return Ok(Action::GameOver(Some((outcome, "resignation"))));
+        }
+        writeln!(output, "Kaik plays {}", board.move_to_san(mv))?;
+        if !self.draw_offered
+            && self
+                .engine_scores
+                .offers_draw(resign_config, board.get_full_move_counter())
+        {
+            writeln!(output, "Kaik offers a draw, enter draw to accept")?;
+            self.draw_offered = true;
+        }
+        Ok(Action::Play(mv, eval))
+    }
+
+    // Plays the move on the board and adds it to the PGN, with its annotations.
+    fn play_move(&mut self, mv: Move, eval: Option<Eval>) {
+        let board = self.game.get_board();
+        let side = board.get_side_to_move();
+        let annotations = [
+            eval.map(|eval| eval.for_white(side).annotation()),
+            self.clock
+                .as_ref()
+                .map(|clock| pgn::clock_annotation(clock.remaining(side))),
+        ];
+        let comment = annotations
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.pgn.moves.push(PgnMove {
+            san: board.move_to_san(mv),
+            comment: (!comment.is_empty()).then_some(comment),
+        });
+        self.moves.push(mv.pure().to_string());
+        self.game.apply_moves(&self.moves[self.moves.len() - 1..]);
+    }
+
+    // Ends the game, showing the result if it's not interrupted.
+    fn game_over<W: Write>(
+        mut self,
+        result: Option<(Outcome, &'static str)>,
+        output: &mut W,
+    ) -> io::Result<PgnGame> {
+        if let Some((outcome, reason)) = result {
+            writeln!(output, "Game over: {} ({reason})", outcome.as_pgn_result())?;
+            self.pgn.set_result(outcome.as_pgn_result());
+            self.pgn.set_tag("Termination", reason);
+        }
+        Ok(self.pgn)
+    }
+}
+
+// Plays a game, reading the human moves from the input, until it's over or the input ends.
+// Returns the game, with a result only if it's over.
+pub fn run<R, W>(settings: &Settings, input: &mut R, output: &mut W) -> io::Result<PgnGame>
+where
+    R: BufRead,
+    W: Write,
+{
+    let engine = format!("{ENGINE_NAME} {ENGINE_VERSION}");
+    let mut names = ["Human", engine.as_str()];
+    if settings.human == Color::Black {
+        names.reverse();
+    }
+    let mut console = ConsoleGame {
+        settings,
+        game: Game::new(),
+        clock: settings.clock.map(Clock::new),
+        theme: Theme {
+            flip: settings.human == Color::Black,
+            ..Theme::for_print()
+        },
+        moves: Vec::new(),
+        pgn: PgnGame::new(
+            "Kaik console game",
+            "-",
+            names[0],
+            names[1],
+            fen::START_POSITION,
+        ),
+        engine_scores: ScoreHistory::new(),
+        draw_offered: false,
+    };
+    console.game.set_config(settings.config);
+
+    writeln!(
+        output,
+        "Enter moves in SAN or pure notation, or hint, undo, draw, resign, quit."
+    )?;
+    let _ = console
+        .game
+        .get_board()
+        .write_with_theme(output, console.theme);
+    loop {
+        if let Some(result) = console.game.get_outcome() {
+            return console.game_over(Some(result), output);
+        }
+
+        let side = console.game.get_board().get_side_to_move();
+        if let Some(clock) = &mut console.clock {
+            clock.start();
+        }
+        let action = if side == settings.human {
+            write!(output, "Your move: ")?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return console.game_over(None, output);
+            }
+            console.human_command(line.trim(), output)?
+        } else {
+            console.engine_turn(output)?
+        };
+        let (mv, eval) = match action {
+            Action::Play(mv, eval) => (mv, eval),
+            Action::Continue => continue,
+            Action::GameOver(result) => return console.game_over(result, output),
+        };
+
+        if let Some(clock) = &mut console.clock {
+            if !clock.stop(side) {
+                writeln!(output, "{} lost on time", color_name(side))?;
+                let outcome = Outcome::win_for(side.opposite());
+                return console.game_over(Some((outcome, "time forfeit")), output);
+            }
+        }
+        console.play_move(mv, eval);
+        let _ = console
+            .game
+            .get_board()
+            .write_with_theme(output, console.theme);
+        if let Some(clock) = &console.clock {
+            writeln!(
+                output,
+                "White {}  Black {}",
+                format_time(clock.remaining(Color::White)),
+                format_time(clock.remaining(Color::Black))
+            )?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_clock() {
+        let mut clock = Clock::new(TimeControl {
+            initial: Duration::from_mins(1),
+            increment: Duration::from_secs(2),
+        });
+        assert!(clock.record(Color::White, Duration::from_secs(10)));
+        assert_eq!(clock.remaining(Color::White), Duration::from_secs(52));
+        assert_eq!(clock.remaining(Color::Black), Duration::from_mins(1));
+        assert_eq!(
+            clock.search_params().white_time,
+            Some(Duration::from_secs(52))
+        );
+
+        // The flag falls, no increment then.
+        assert!(!clock.record(Color::Black, Duration::from_mins(1)));
+        assert_eq!(clock.remaining(Color::Black), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(Duration::from_mins(5)), "5:00");
+        assert_eq!(format_time(Duration::from_secs(62)), "1:02");
+        assert_eq!(format_time(Duration::from_millis(9_450)), "0:09.4");
+    }
+
+    #[test]
+    fn test_hint_and_undo() {
+        let settings = Settings {
+            human: Color::Black,
+            clock: None,
+            depth: 1,
+            config: Config::default(),
+        };
+        let mut input = Cursor::new("undo\nhint\ne5\nundo\n");
+        let mut output = Vec::new();
+        run(&settings, &mut input, &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("No move to take back"));
+        assert!(output.contains("Hint: "));
+        // Back to the position after the first engine move.
+        let fens: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with("FEN: "))
+            .collect();
+        assert_eq!(fens.len(), 5);
+        assert_eq!(fens[1], fens[4]);
+        assert_ne!(fens[1], fens[3]);
+    }
+
+    #[test]
+    fn test_draw_and_resign() {
+        let settings = Settings {
+            human: Color::White,
+            clock: None,
+            depth: 1,
+            config: Config::default(),
+        };
+        let mut input = Cursor::new("draw\nresign\n");
+        let mut output = Vec::new();
+        let game = run(&settings, &mut input, &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Kaik declines the draw"));
+        assert!(output.ends_with("Game over: 0-1 (resignation)\n"));
+        assert_eq!(game.result, "0-1");
+        assert_eq!(game.get_tag("Termination"), Some("resignation"));
+        assert_eq!(game.get_tag("White"), Some("Human"));
+    }
+
+    #[test]
+    fn test_play() {
+        let settings = Settings {
+            human: Color::White,
+            clock: Some(TimeControl {
+                initial: Duration::from_secs(5),
+                increment: Duration::ZERO,
+            }),
+            depth: 1,
+            config: Config::default(),
+        };
+        let mut input = Cursor::new("e5\ne2e5\ne4\nNf3\n");
+        let mut output = Vec::new();
+        let game = run(&settings, &mut input, &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Illegal move e5\n"));
+        assert!(output.contains("Illegal move e2e5: the piece doesn't move that way\n"));
+        assert_eq!(output.matches("Kaik plays").count(), 2);
+        assert!(output.contains("White 0:05.0  Black 0:0"));
+
+        // The game isn't over, the engine moves have evaluations.
+        assert_eq!(game.result, "*");
+        assert_eq!(game.moves.len(), 4);
+        assert_eq!(game.moves[0].san, "e4");
+        let comment = |i: usize| game.moves[i].comment.clone().unwrap_or_default();
+        assert!(comment(0).starts_with("[%clk 0:00:0"));
+        assert!(comment(1).starts_with("[%eval "));
+        assert!(comment(1).contains("] [%clk 0:00:0"));
+    }
+}
@@ -0,0 +1,278 @@
+//! Tactics puzzles found in games: Positions where a move gave the opponent a winning advantage,
+//! which only one move takes. The games come from a PGN file, or are played by the engine
+//! against itself.
+//! Each position is searched for its two best lines: The best one gives the swing of the
+//! evaluation, and the gap to the second one shows that the best move is the only good one.
+//! The solution goes on as long as the moves of the solver stay the only good ones.
+//! Puzzles are printed as EPD records, with the first move of the solution as best move and the
+//! whole solution as predicted variation.
+
+use std::sync::{atomic::AtomicBool, Arc};
+
+use itertools::Itertools;
+use rand::{rngs::StdRng, seq::SliceRandom};
+
+use crate::{
+    board::Board,
+    common::{Move, Score, Value, ENGINE_NAME},
+    engine::{
+        config::Config,
+        eval::{self, Evaluator},
+        search::{self, Line},
+        transposition::TranspositionTable,
+    },
+    utils::{
+        epd::{self, Epd},
+        fen,
+        pgn::{PgnGame, PgnMove},
+    },
+};
+
+// Games of the engine against itself are stopped there, if not over before.
+const MAX_SELF_PLAY_PLIES: usize = 300;
+// The self-play games start with random moves, so that they are all different.
+const RANDOM_OPENING_PLIES: usize = 4;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    pub depth: usize,
+    // Minimum increase of the evaluation caused by the opponent's move, which is also the minimum
+    // evaluation after it, in centipawns.
+    pub min_swing: Score,
+    // Minimum gap between the best move and the second best one, in centipawns.
+    pub min_gap: Score,
+    // Maximum number of moves of the solver in the solution.
+    pub max_moves: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Puzzle {
+    pub board: Board,
+    // Moves of the solver, with the replies of the opponent between them.
+    pub solution: Vec<Move>,
+    pub score: Value,
+}
+
+impl Puzzle {
+    // The puzzle as an EPD record, the evaluation being a direct mate or centipawns.
+    pub fn to_epd(&self, id: &str) -> String {
+        let mut epd = Epd::from_fen(&self.board.as_fen()).expect("Invalid FEN");
+        let mut board = self.board;
+        let pv = self
+            .solution
+            .iter()
+            .map(|&mv| {
+                let san = board.move_to_san(mv);
+                board.update_by_move(mv);
+                san
+            })
+            .collect_vec();
+        epd.set_operation("bm", &[&pv[0]]);
+        epd.set_operation("pv", &pv.iter().map(String::as_str).collect_vec());
+        match self.score.mate_moves() {
+            Some(moves) => epd.set_operation("dm", &[&moves.to_string()]),
+            None => epd.set_operation("ce", &[&self.score.score().to_string()]),
+        }
+        epd.set_operation("id", &[id]);
+        epd::create(&epd)
+    }
+}
+
+// The best line, if its move is the only good one.
+fn unique_best(lines: &[Line], min_gap: Score) -> Option<&Line> {
+    match lines {
+        [best, second] if best.score.score() - second.score.score() >= min_gap => Some(best),
+        _ => None,
+    }
+}
+
+struct Finder {
+    settings: Settings,
+    stop_flag: Arc<AtomicBool>,
+    tt: TranspositionTable,
+    evaluator: Box<dyn Evaluator>,
+}
+
+impl Finder {
+    fn new(settings: &Settings, config: &Config) -> Self {
+        Self {
+            settings: *settings,
+            stop_flag: Arc::new(AtomicBool::new(false)),
+            tt: TranspositionTable::new(config.search.hash),
+            evaluator: eval::new_evaluator(&config.eval),
+        }
+    }
+
+    // The two best lines.
+    fn lines(&mut self, board: &Board) -> Vec<Line> {
+        search::multi_pv(
+            board,
+            self.settings.depth,
+            2,
+            &self.stop_flag,
+            &self.tt,
+            self.evaluator.as_ref(),
+        )
+    }
+
+    // Follows the best line while the moves of the solver are the only good ones.
+    fn solution(&mut self, board: &Board, best: &Line) -> Vec<Move> {
+        let mut board = *board;
+        let mut pv = best.pv.clone();
+        let mut solution = vec![pv[0]];
+        while solution.len() < 2 * self.settings.max_moves - 1 {
+            let Some(&reply) = pv.get(1) else {
+                break;
+            };
+            let mut next_board = board;
+            next_board.update_by_move(pv[0]);
+            next_board.update_by_move(reply);
+            let lines = self.lines(&next_board);
+            let Some(next) = unique_best(&lines, self.settings.min_gap) else {
+                break;
+            };
+            solution.extend([reply, next.pv[0]]);
+            pv.clone_from(&next.pv);
+            board = next_board;
+        }
+        solution
+    }
+}
+
+// Finds the puzzles of the game, in the order of its moves.
+pub fn find_in_game(
+    game: &PgnGame,
+    settings: &Settings,
+    config: &Config,
+) -> Result<Vec<Puzzle>, String> {
+    let mut board = game
+        .get_fen()
+        .map_or_else(Board::initial_board, Board::from_fen);
+    let mut finder = Finder::new(settings, config);
+    let mut puzzles = Vec::new();
+
+    // Evaluation of the position before the move, from the point of view of the side moving.
+    let mut previous = finder.lines(&board).first().map(|line| line.score);
+    for pgn_move in &game.moves {
+        let mv = board
+            .new_move_from_san(&pgn_move.san)
+            .ok_or_else(|| format!("Invalid move {} in position {board}", pgn_move.san))?;
+        board.update_by_move(mv);
+
+        let lines = finder.lines(&board);
+        if let (Some(best), Some(previous)) = (unique_best(&lines, settings.min_gap), previous) {
+            let before = -previous.score();
+            let after = best.score.score();
+            if after >= settings.min_swing && after - before >= settings.min_swing {
+                puzzles.push(Puzzle {
+                    board,
+                    solution: finder.solution(&board, best),
+                    score: best.score,
+                });
+            }
+        }
+        previous = lines.first().map(|line| line.score);
+    }
+    Ok(puzzles)
+}
+
+// Plays a game of the engine against itself from the starting position, the first moves being
+// random.
+pub fn self_play(rng: &mut StdRng, round: usize, depth: usize, config: &Config) -> PgnGame {
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let tt = TranspositionTable::new(config.search.hash);
+    let evaluator = eval::new_evaluator(&config.eval);
+    let mut board = Board::initial_board();
+    let mut history = Vec::new();
+    let mut moves = Vec::new();
+
+    let outcome = loop {
+        if let Some((outcome, _reason)) = board.game_over(&history) {
+            break Some(outcome);
+        }
+        if moves.len() >= MAX_SELF_PLAY_PLIES {
+            break None;
+        }
+        let mv = if moves.len() < RANDOM_OPENING_PLIES {
+            *board
+                .generate_legal_moves()
+                .choose(rng)
+                .expect("No legal move")
+        } else {
+            search::multi_pv(&board, depth, 1, &stop_flag, &tt, evaluator.as_ref())[0].pv[0]
+        };
+        moves.push(PgnMove {
+            san: board.move_to_san(mv),
+            comment: None,
+        });
+        history.push(board.get_zobrist_key());
+        board.update_by_move(mv);
+    };
+
+    let mut game = PgnGame::new(
+        "Kaik self-play",
+        &round.to_string(),
+        ENGINE_NAME,
+        ENGINE_NAME,
+        fen::START_POSITION,
+    );
+    game.moves = moves;
+    if let Some(outcome) = outcome {
+        game.set_result(outcome.as_pgn_result());
+    }
+    game
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use crate::utils::pgn;
+
+    use super::*;
+
+    const SETTINGS: Settings = Settings {
+        depth: 3,
+        min_swing: 200,
+        min_gap: 150,
+        max_moves: 3,
+    };
+
+    #[test]
+    fn test_find_in_game() {
+        let games = pgn::parse("1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 4. Qxf7# 1-0");
+        let puzzles = find_in_game(&games[0], &SETTINGS, &Config::default()).unwrap();
+        assert_eq!(puzzles.len(), 1);
+        assert_eq!(
+            puzzles[0].to_epd("puzzle.1"),
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - hmvc 4; fmvn 4; \
+             bm Qxf7#; pv Qxf7#; dm 1; id \"puzzle.1\";"
+        );
+    }
+
+    #[test]
+    fn test_to_epd() {
+        let board: Board = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1".into();
+        let puzzle = Puzzle {
+            board,
+            solution: vec![board.new_move_from_pure("a1a8")],
+            score: Value::mate_in(1),
+        };
+        assert_eq!(
+            puzzle.to_epd("mate"),
+            "6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; pv Ra8#; dm 1; id \"mate\";"
+        );
+    }
+
+    #[test]
+    fn test_self_play() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let game = self_play(&mut rng, 1, 1, &Config::default());
+        assert!(game.moves.len() > RANDOM_OPENING_PLIES);
+        let mut board = Board::initial_board();
+        for pgn_move in &game.moves {
+            board.update_by_move(board.new_move_from_san(&pgn_move.san).unwrap());
+        }
+        assert_eq!(game.get_tag("Round"), Some("1"));
+    }
+}
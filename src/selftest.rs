@@ -0,0 +1,220 @@
+//! Internal consistency checks, as a quick sanity check after changing the move generation,
+//! the board updates or the evaluation: Perft against the known node counts, evaluation symmetry
+//! between a position and its mirror, Zobrist keys updated incrementally against the ones
+//! computed from scratch, and FEN and binary round trips.
+//! The positions are the perft ones, and the positions of random games played from them.
+//! Run with the release profile: cargo r --release -- selftest
+
+use std::fmt::Write;
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    board::Board,
+    engine::{
+        config::EvalConfig,
+        eval::{self, EvalBackend},
+    },
+    perft,
+    utils::{fen, random_game::random_game},
+};
+
+// Positions from <https://www.chessprogramming.org/Perft_Results>, with their perft at depths 1 to 4.
+const PERFT_POSITIONS: [(&str, [usize; 4]); 6] = [
+    (fen::START_POSITION, [20, 400, 8902, 197_281]),
+    (fen::KIWIPETE, [48, 2039, 97862, 4_085_603]),
+    (fen::POSITION_3, [14, 191, 2812, 43238]),
+    (fen::POSITION_4, [6, 264, 9467, 422_333]),
+    (fen::POSITION_5, [44, 1486, 62379, 2_103_487]),
+    (fen::POSITION_6, [46, 2079, 89890, 3_894_594]),
+];
+
+pub const MAX_PERFT_DEPTH: usize = 4;
+
+const BACKENDS: [EvalBackend; 3] = [
+    EvalBackend::Material,
+    EvalBackend::Classical,
+    EvalBackend::Pst,
+];
+
+// Random games played from each perft position, always the same ones.
+const RANDOM_GAMES: usize = 5;
+const RANDOM_GAME_PLIES: usize = 80;
+const RANDOM_SEED: u64 = 0;
+
+pub struct CheckResult {
+    pub name: &'static str,
+    pub checked: usize,
+    pub failures: Vec<String>,
+}
+
+impl CheckResult {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            checked: 0,
+            failures: Vec::new(),
+        }
+    }
+
+    // Counts a check, keeping the failure if the condition is false.
+    fn check(&mut self, condition: bool, failure: impl FnOnce() -> String) {
+        self.checked += 1;
+        if !condition {
+            self.failures.push(failure());
+        }
+    }
+}
+
+// Runs all the checks, perft up to that depth.
+pub fn run(perft_depth: usize) -> Vec<CheckResult> {
+    let boards = boards();
+    vec![
+        check_perft(perft_depth),
+        check_eval_symmetry(&boards),
+        check_zobrist_keys(&boards),
+        check_fen_round_trip(&boards),
+        check_binary_round_trip(&boards),
+    ]
+}
+
+pub fn all_passed(results: &[CheckResult]) -> bool {
+    results.iter().all(|result| result.failures.is_empty())
+}
+
+// The perft positions, and the ones of the random games played from them.
+fn boards() -> Vec<Board> {
+    let mut rng = StdRng::seed_from_u64(RANDOM_SEED);
+    let mut boards = Vec::new();
+    for (fen, _) in PERFT_POSITIONS {
+        let board = Board::from_fen(fen);
+        boards.push(board);
+        for _ in 0..RANDOM_GAMES {
+            boards.extend(random_game(board, RANDOM_GAME_PLIES, &mut rng).skip(1));
+        }
+    }
+    boards
+}
+
+fn check_perft(max_depth: usize) -> CheckResult {
+    let mut result = CheckResult::new("Perft");
+    for (fen, counts) in PERFT_POSITIONS {
+        let board = Board::from_fen(fen);
+        for (depth, &expected) in counts.iter().enumerate().take(max_depth) {
+            let nodes = perft::perft(&board, depth + 1);
+            result.check(nodes == expected, || {
+                format!(
+                    "{fen}: {nodes} nodes at depth {} instead of {expected}",
+                    depth + 1
+                )
+            });
+        }
+    }
+    result
+}
+
+fn check_eval_symmetry(boards: &[Board]) -> CheckResult {
+    let mut result = CheckResult::new("Evaluation symmetry");
+    for backend in BACKENDS {
+        let evaluator = eval::new_evaluator(&EvalConfig {
+            backend,
+            ..Default::default()
+        });
+        for board in boards {
+            let (score, mirrored) = (evaluator.eval(board), evaluator.eval(&board.mirror()));
+            result.check(score == mirrored, || {
+                format!(
+                    "{backend}: {} evaluates to {score}, its mirror to {mirrored}",
+                    board.as_fen()
+                )
+            });
+        }
+    }
+    result
+}
+
+fn check_zobrist_keys(boards: &[Board]) -> CheckResult {
+    let mut result = CheckResult::new("Zobrist keys");
+    for board in boards {
+        let key = Board::gen_zobrist_key(board);
+        result.check(board.get_zobrist_key() == key, || {
+            format!("{}: incremental key differs", board.as_fen())
+        });
+        let state = board.check_invariants();
+        result.check(state.is_ok(), || {
+            format!("{}: {}", board.as_fen(), state.unwrap_err())
+        });
+    }
+    result
+}
+
+fn check_fen_round_trip(boards: &[Board]) -> CheckResult {
+    let mut result = CheckResult::new("FEN round trip");
+    for board in boards {
+        let fen = board.as_fen();
+        let parsed = Board::try_from_fen(&fen);
+        result.check(parsed == Ok(*board), || {
+            format!("{fen}: parsed to a different board")
+        });
+    }
+    result
+}
+
+fn check_binary_round_trip(boards: &[Board]) -> CheckResult {
+    let mut result = CheckResult::new("Binary round trip");
+    for board in boards {
+        let decoded = Board::from_bytes(&board.to_bytes());
+        result.check(decoded == Ok(*board), || {
+            format!("{}: decoded to a different board", board.as_fen())
+        });
+    }
+    result
+}
+
+pub fn report(results: &[CheckResult]) -> String {
+    let mut out = String::new();
+    for result in results {
+        let status = if result.failures.is_empty() {
+            "ok".to_string()
+        } else {
+            format!("{} FAILED", result.failures.len())
+        };
+        writeln!(
+            out,
+            "{:<24}{:>8} checked  {status}",
+            result.name, result.checked
+        )
+        .unwrap();
+        for failure in &result.failures {
+            writeln!(out, "    {failure}").unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let results = run(2);
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].checked, 12);
+        assert!(all_passed(&results), "{}", report(&results));
+        assert_eq!(report(&results).lines().count(), 5);
+    }
+
+    #[test]
+    fn test_report_failure() {
+        let mut result = CheckResult::new("Test");
+        result.check(true, String::new);
+        result.check(false, || "wrong".to_string());
+        let results = [result];
+        assert!(!all_passed(&results));
+        assert_eq!(
+            report(&results),
+            "Test                           2 checked  1 FAILED\n    wrong\n"
+        );
+    }
+}
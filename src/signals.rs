@@ -0,0 +1,49 @@
+//! Termination signals: Tournament managers stop the engine with SIGTERM, users with Ctrl-C.
+//! Killed in the middle of a search, the engine would leave a truncated log and a search thread
+//! writing to a closed output. Instead, the first signal runs a handler, which quits like the UCI
+//! command does. A second signal terminates the engine at once, if the first one wasn't enough.
+//! Only on Unix, elsewhere the signals keep their default behavior.
+
+#[cfg(unix)]
+pub fn on_termination(handler: impl FnOnce() + Send + 'static) {
+    use signal_hook::{consts::TERM_SIGNALS, iterator::Signals, low_level};
+
+    let mut signals = match Signals::new(TERM_SIGNALS) {
+        Ok(signals) => signals,
+        Err(err) => {
+            warn!("Could not handle the termination signals: {err}");
+            return;
+        }
+    };
+    std::thread::spawn(move || {
+        let mut signals = signals.forever();
+        if let Some(signal) = signals.next() {
+            info!("Received signal {signal}, quitting");
+            handler();
+        }
+        if let Some(signal) = signals.next() {
+            let _ = low_level::emulate_default_handler(signal);
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn on_termination(_handler: impl FnOnce() + Send + 'static) {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use signal_hook::{consts::SIGTERM, low_level};
+
+    use super::*;
+
+    #[test]
+    fn test_on_termination() {
+        let (sender, receiver) = mpsc::channel();
+        on_termination(move || sender.send(()).unwrap());
+        low_level::raise(SIGTERM).unwrap();
+        // Handled instead of terminating the tests.
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(()));
+    }
+}
@@ -0,0 +1,523 @@
+//! Tournaments between UCI engines, for quick tests without external tools.
+//! The engines are started as child processes and play a round robin, where each pair
+//! of engines plays each opening twice, swapping colors.
+//! Games end by the rules (mate, stalemate, repetition, fifty moves, insufficient material),
+//! or are adjudicated, see the adjudication module.
+//! With two engines, a sequential probability ratio test can stop the match once its result
+//! is significant, see the stats module.
+
+use std::{fmt::Write, io, sync::Mutex, thread, time::Duration};
+
+use itertools::Itertools;
+
+use adjudication::{Adjudication, Adjudicator};
+use stats::{Results, Sprt, SprtStatus};
+
+use crate::{
+    board::{outcome::Outcome, Board},
+    common::Color,
+    engine::resign,
+    utils::{
+        pgn::{Eval, PgnGame, PgnMove},
+        uci_engine::{self, UciEngine},
+    },
+};
+
+pub mod adjudication;
+pub mod stats;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeControl {
+    Depth(usize),
+    // Implemented with "go infinite" followed by "stop", which all engines support.
+    MoveTime(Duration),
+}
+
+pub struct Settings {
+    // Commands starting the engines: The program followed by its arguments.
+    pub engines: Vec<Vec<String>>,
+    // FEN of the starting positions.
+    pub openings: Vec<String>,
+    pub rounds: usize,
+    pub time_control: TimeControl,
+    // Number of games played in parallel.
+    pub concurrency: usize,
+    pub adjudication: Adjudication,
+    // Stops the match when the test concludes. Only for tournaments between two engines.
+    pub sprt: Option<Sprt>,
+    // Adds the evaluations of the engines to the PGN moves, as %eval annotations.
+    pub eval_annotations: bool,
+}
+
+pub struct GameRecord {
+    pub white: usize, // Index of the engine in the settings.
+    pub black: usize,
+    pub outcome: Outcome,
+    // Why the game ended, like "checkmate" or "adjudication: win".
+    pub reason: String,
+    pub pgn: PgnGame,
+}
+
+// Asks the engine for its move, in pure format, with the last evaluation it reported.
+fn get_move(
+    engine: &mut UciEngine,
+    fen: &str,
+    moves: &[String],
+    time_control: TimeControl,
+) -> io::Result<(String, Option<Eval>)> {
+    if moves.is_empty() {
+        engine.send(&format!("position fen {fen}"))?;
+    } else {
+        engine.send(&format!("position fen {fen} moves {}", moves.join(" ")))?;
+    }
+    match time_control {
+        TimeControl::Depth(depth) => engine.send(&format!("go depth {depth}"))?,
+        TimeControl::MoveTime(time) => {
+            engine.send("go infinite")?;
+            thread::sleep(time);
+            engine.send("stop")?;
+        }
+    }
+    let lines = engine.read_until("bestmove")?;
+    let best_move = lines
+        .last()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or_default()
+        .to_string();
+    let eval = lines
+        .iter()
+        .rev()
+        .find_map(|line| uci_engine::parse_eval(line));
+    Ok((best_move, eval))
+}
+
+// Plays a game, the first engine having White.
+// Returns the outcome, the reason of the game end and the moves in SAN.
+fn play_game(
+    mut engines: [&mut UciEngine; 2],
+    fen: &str,
+    settings: &Settings,
+) -> io::Result<(Outcome, String, Vec<PgnMove>)> {
+    for engine in &mut engines {
+        engine.send("ucinewgame")?;
+        engine.wait_ready()?;
+    }
+
+    let mut board = Board::from_fen(fen);
+    let mut history = Vec::new();
+    let mut moves = Vec::new();
+    let mut pgn_moves = Vec::new();
+    let mut adjudicator = Adjudicator::new(&settings.adjudication);
+    loop {
+        if let Some((outcome, reason)) = board.game_over(&history) {
+            return Ok((outcome, reason.to_string(), pgn_moves));
+        }
+
+        let side = board.get_side_to_move();
+        let engine = &mut *engines[side as usize];
+        let (pure, eval) = get_move(engine, fen, &moves, settings.time_control)?;
+        let Some(mv) = board
+            .generate_legal_moves()
+            .into_iter()
+            .find(|mv| mv.pure().to_string() == pure)
+        else {
+            warn!("Illegal move {pure} by {}", engine.get_name());
+            let outcome = Outcome::win_for(side.opposite());
+            return Ok((outcome, "illegal move".to_string(), pgn_moves));
+        };
+        if let Some((outcome, reason)) = adjudicator.before_move(side, eval.map(resign::score_of)) {
+            return Ok((outcome, reason.to_string(), pgn_moves));
+        }
+
+        pgn_moves.push(PgnMove {
+            san: board.move_to_san(mv),
+            comment: eval
+                .filter(|_| settings.eval_annotations)
+                .map(|eval| eval.for_white(side).annotation()),
+        });
+        history.push(board.get_zobrist_key());
+        board.update_by_move(mv);
+        moves.push(pure);
+
+        let adjudication = adjudicator.after_move(board.get_full_move_counter(), moves.len());
+        if let Some((outcome, reason)) = adjudication {
+            return Ok((outcome, reason.to_string(), pgn_moves));
+        }
+    }
+}
+
+fn create_pgn(
+    round: usize,
+    names: [&str; 2],
+    fen: &str,
+    outcome: Outcome,
+    reason: &str,
+    moves: Vec<PgnMove>,
+) -> PgnGame {
+    let mut game = PgnGame::new(
+        "Kaik tournament",
+        &round.to_string(),
+        names[0],
+        names[1],
+        fen,
+    );
+    game.set_result(outcome.as_pgn_result());
+    game.set_tag("Termination", reason);
+    game.moves = moves;
+    game
+}
+
+// Plays games from the queue until it's empty.
+// Each worker has its own instances of the engines, started when first needed.
+fn worker<I>(
+    settings: &Settings,
+    queue: &Mutex<I>,
+    records: &Mutex<Vec<GameRecord>>,
+) -> io::Result<()>
+where
+    I: Iterator<Item = (usize, usize, usize, usize, String)>,
+{
+    let mut engines: Vec<Option<UciEngine>> = settings.engines.iter().map(|_| None).collect();
+    let games_count = settings.rounds
+        * settings.openings.len()
+        * settings.engines.len()
+        * (settings.engines.len() - 1);
+
+    loop {
+        if let Some(sprt) = &settings.sprt {
+            let results = results(&records.lock().unwrap(), 0, 1);
+            if sprt.status(&results) != SprtStatus::Continue {
+                return Ok(());
+            }
+        }
+        let Some((game_number, round, white, black, fen)) = queue.lock().unwrap().next() else {
+            return Ok(());
+        };
+        for index in [white, black] {
+            if engines[index].is_none() {
+                engines[index] = Some(UciEngine::start(&settings.engines[index])?);
+            }
+        }
+        let [Some(white_engine), Some(black_engine)] = engines
+            .get_disjoint_mut([white, black])
+            .expect("Engines of a game are different")
+        else {
+            unreachable!("Engines are started");
+        };
+
+        let (outcome, reason, moves) =
+            play_game([&mut *white_engine, &mut *black_engine], &fen, settings)?;
+        let names = [white_engine.get_name(), black_engine.get_name()];
+        println!(
+            "Game {game_number}/{games_count}: {} vs {}: {} ({reason})",
+            names[0],
+            names[1],
+            outcome.as_pgn_result()
+        );
+        let pgn = create_pgn(round, names, &fen, outcome, &reason, moves);
+        let mut records = records.lock().unwrap();
+        records.push(GameRecord {
+            white,
+            black,
+            outcome,
+            reason,
+            pgn,
+        });
+        if let Some(sprt) = &settings.sprt {
+            println!("SPRT: {}", sprt.report(&results(&records, 0, 1)));
+        }
+    }
+}
+
+// Plays all the games of the tournament and returns them in the order they were played.
+pub fn run(settings: &Settings) -> io::Result<Vec<GameRecord>> {
+    assert!(
+        settings.engines.len() >= 2,
+        "A tournament needs at least two engines"
+    );
+    let engines_count = settings.engines.len();
+    let games = (1..=settings.rounds)
+        .flat_map(|round| {
+            settings.openings.iter().flat_map(move |fen| {
+                (0..engines_count)
+                    .tuple_combinations()
+                    .flat_map(move |(a, b)| {
+                        [(round, a, b, fen.clone()), (round, b, a, fen.clone())]
+                    })
+            })
+        })
+        .enumerate()
+        .map(|(i, (round, white, black, fen))| (i + 1, round, white, black, fen));
+
+    let queue = Mutex::new(games);
+    let records = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        let workers = (0..settings.concurrency.max(1))
+            .map(|_| scope.spawn(|| worker(settings, &queue, &records)))
+            .collect_vec();
+        workers
+            .into_iter()
+            .try_for_each(|w| w.join().expect("Tournament worker panicked"))
+    })?;
+    Ok(records.into_inner().unwrap())
+}
+
+// Results table of the tournament, best engines first.
+pub fn standings(records: &[GameRecord]) -> String {
+    struct Standing<'a> {
+        name: &'a str,
+        wins: usize,
+        draws: usize,
+        losses: usize,
+    }
+
+    let mut standings: Vec<Standing> = Vec::new();
+    for record in records {
+        for (index, color) in [(record.white, Color::White), (record.black, Color::Black)] {
+            if standings.len() <= index {
+                standings.resize_with(index + 1, || Standing {
+                    name: "",
+                    wins: 0,
+                    draws: 0,
+                    losses: 0,
+                });
+            }
+            let standing = &mut standings[index];
+            let tag = if color == Color::White {
+                "White"
+            } else {
+                "Black"
+            };
+            standing.name = record.pgn.get_tag(tag).unwrap_or_default();
+            match record.outcome {
+                Outcome::Draw => standing.draws += 1,
+                outcome if outcome == Outcome::win_for(color) => standing.wins += 1,
+                _ => standing.losses += 1,
+            }
+        }
+    }
+
+    // Points are counted in half points to stay in integers.
+    let half_points = |s: &Standing| 2 * s.wins + s.draws;
+    let mut table = String::new();
+    writeln!(
+        table,
+        "{:>4} {:<30} {:>5} {:>5} {:>5} {:>6} {:>7}",
+        "Rank", "Engine", "Games", "Wins", "Draws", "Losses", "Score"
+    )
+    .unwrap();
+    for (rank, standing) in standings
+        .iter()
+        .filter(|s| !s.name.is_empty())
+        .sorted_by_key(|s| std::cmp::Reverse(half_points(s)))
+        .enumerate()
+    {
+        let games = standing.wins + standing.draws + standing.losses;
+        #[allow(clippy::cast_precision_loss)]
+        let score = 50.0 * half_points(standing) as f64 / games as f64;
+        writeln!(
+            table,
+            "{:>4} {:<30} {:>5} {:>5} {:>5} {:>6} {:>6.1}%",
+            rank + 1,
+            standing.name,
+            games,
+            standing.wins,
+            standing.draws,
+            standing.losses,
+            score
+        )
+        .unwrap();
+    }
+    table
+}
+
+// Results of the engine against the opponent, given by their index in the settings.
+pub fn results(records: &[GameRecord], engine: usize, opponent: usize) -> Results {
+    let mut results = Results::default();
+    for record in records {
+        let color = if (record.white, record.black) == (engine, opponent) {
+            Color::White
+        } else if (record.white, record.black) == (opponent, engine) {
+            Color::Black
+        } else {
+            continue;
+        };
+        match record.outcome {
+            Outcome::Draw => results.draws += 1,
+            outcome if outcome == Outcome::win_for(color) => results.wins += 1,
+            _ => results.losses += 1,
+        }
+    }
+    results
+}
+
+// Statistics of the first engine against each of the others.
+pub fn match_stats(records: &[GameRecord], sprt: Option<&Sprt>) -> String {
+    let name = |index: usize| {
+        records.iter().find_map(|r| {
+            if r.white == index {
+                r.pgn.get_tag("White")
+            } else if r.black == index {
+                r.pgn.get_tag("Black")
+            } else {
+                None
+            }
+        })
+    };
+    let opponents = records
+        .iter()
+        .flat_map(|r| [r.white, r.black])
+        .filter(|&index| index != 0)
+        .unique()
+        .sorted();
+    let mut summary = String::new();
+    for opponent in opponents {
+        let results = results(records, 0, opponent);
+        writeln!(
+            summary,
+            "{} vs {}: {results}",
+            name(0).unwrap_or_default(),
+            name(opponent).unwrap_or_default()
+        )
+        .unwrap();
+        if let Some(sprt) = sprt {
+            writeln!(summary, "SPRT: {}", sprt.report(&results)).unwrap();
+        }
+    }
+    summary
+}
+
+// Number of games ended by each rule, most frequent first.
+pub fn terminations(records: &[GameRecord]) -> String {
+    let mut summary = String::new();
+    for (reason, count) in records
+        .iter()
+        .counts_by(|r| r.reason.as_str())
+        .into_iter()
+        .sorted_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)))
+    {
+        writeln!(summary, "{count:>5} {reason}").unwrap();
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fen;
+
+    #[test]
+    fn test_standings() {
+        let record = |white, black, outcome| GameRecord {
+            white,
+            black,
+            outcome,
+            reason: "checkmate".to_string(),
+            pgn: create_pgn(
+                1,
+                [["A", "B", "C"][white], ["A", "B", "C"][black]],
+                fen::START_POSITION,
+                outcome,
+                "",
+                Vec::new(),
+            ),
+        };
+        let records = [
+            record(0, 1, Outcome::WhiteWins),
+            record(1, 0, Outcome::Draw),
+            record(0, 2, Outcome::BlackWins),
+            record(2, 0, Outcome::BlackWins),
+        ];
+        let table = standings(&records);
+        let lines = table.lines().collect_vec();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].starts_with("   1 A "));
+        assert!(lines[1].ends_with("     4     2     1      1   62.5%"));
+        assert!(lines[2].starts_with("   2 C "));
+        assert!(lines[3].ends_with("     2     0     1      1   25.0%"));
+    }
+
+    #[test]
+    fn test_match_stats() {
+        let record = |white, black, outcome| GameRecord {
+            white,
+            black,
+            outcome,
+            reason: "checkmate".to_string(),
+            pgn: create_pgn(
+                1,
+                [["A", "B", "C"][white], ["A", "B", "C"][black]],
+                fen::START_POSITION,
+                outcome,
+                "",
+                Vec::new(),
+            ),
+        };
+        let records = [
+            record(0, 1, Outcome::WhiteWins),
+            record(1, 0, Outcome::Draw),
+            record(1, 0, Outcome::WhiteWins),
+            record(2, 1, Outcome::BlackWins),
+            record(2, 0, Outcome::BlackWins),
+        ];
+        assert_eq!(
+            results(&records, 0, 1),
+            Results {
+                wins: 1,
+                draws: 1,
+                losses: 1
+            }
+        );
+        assert_eq!(
+            results(&records, 1, 2),
+            Results {
+                wins: 1,
+                draws: 0,
+                losses: 0
+            }
+        );
+
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+        let summary = match_stats(&records, Some(&sprt));
+        let lines = summary.lines().collect_vec();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("A vs B: +1 =1 -1, Elo 0.0 +/- "));
+        assert!(lines[0].ends_with(", LOS 50.0%"));
+        assert!(lines[1].starts_with("SPRT: LLR "));
+        assert!(lines[1].ends_with("[0, 5]: continue"));
+        assert!(lines[2].starts_with("A vs C: +1 =0 -0, Elo n/a"));
+    }
+
+    #[test]
+    fn test_terminations() {
+        let record = |reason: &str| GameRecord {
+            white: 0,
+            black: 1,
+            outcome: Outcome::Draw,
+            reason: reason.to_string(),
+            pgn: create_pgn(
+                1,
+                ["A", "B"],
+                fen::START_POSITION,
+                Outcome::Draw,
+                reason,
+                Vec::new(),
+            ),
+        };
+        let records = [
+            record("move limit"),
+            record("adjudication: draw"),
+            record("adjudication: draw"),
+            record("threefold repetition"),
+        ];
+        assert_eq!(
+            terminations(&records),
+            "    2 adjudication: draw\n    1 move limit\n    1 threefold repetition\n"
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Adjudication of tournament games, to end them once their result is clear.
+//! The rules use the scores reported by the engines, from their own point of view:
+//! - An engine resigns, or both agree to a draw, following the resign settings of the config.
+//! - A game is won when both engines agree one side is ahead by a margin for several moves.
+//! - A game is drawn when both scores stayed close to 0 for several moves, late enough.
+//! - A game is drawn when it exceeds the move limit.
+//!
+//! There is no tablebase support yet, so no tablebase adjudication.
+
+use crate::{
+    board::outcome::Outcome,
+    common::{Color, Score},
+    engine::{config::ResignConfig, resign::ScoreHistory},
+};
+
+// Numbers of moves are counted for each engine. A number of moves of 0 disables the rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Adjudication {
+    pub win_score: Score,
+    pub win_moves: usize,
+    pub draw_score: Score,
+    pub draw_moves: usize,
+    // Full move number from which draws are adjudicated.
+    pub draw_min_moves: usize,
+    // Games longer than this number of moves are drawn.
+    pub max_moves: usize,
+    pub resign: ResignConfig,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Self {
+            win_score: 1000,
+            win_moves: 0,
+            draw_score: 10,
+            draw_moves: 0,
+            draw_min_moves: 40,
+            max_moves: 200,
+            resign: ResignConfig::default(),
+        }
+    }
+}
+
+// Follows the scores of a game.
+pub struct Adjudicator<'a> {
+    settings: &'a Adjudication,
+    scores: [ScoreHistory; 2],
+}
+
+impl<'a> Adjudicator<'a> {
+    pub fn new(settings: &'a Adjudication) -> Self {
+        Self {
+            settings,
+            scores: [ScoreHistory::new(), ScoreHistory::new()],
+        }
+    }
+
+    // Records the score of the engine about to play. Returns the result if it resigns instead.
+    pub fn before_move(
+        &mut self,
+        side: Color,
+        score: Option<Score>,
+    ) -> Option<(Outcome, &'static str)> {
+        let scores = &mut self.scores[side as usize];
+        if let Some(score) = score {
+            scores.push(score);
+        }
+        scores
+            .should_resign(&self.settings.resign)
+            .then(|| (Outcome::win_for(side.opposite()), "resignation"))
+    }
+
+    // Returns the result if the game should end after the move. The full move number is the
+    // one of the new position, the number of plies the one played in the game.
+    pub fn after_move(&self, full_move: usize, plies: usize) -> Option<(Outcome, &'static str)> {
+        let settings = self.settings;
+        let [white, black] = &self.scores;
+        if white.offers_draw(&settings.resign, full_move)
+            && black.offers_draw(&settings.resign, full_move)
+        {
+            return Some((Outcome::Draw, "draw agreed"));
+        }
+
+        for (color, winner, loser) in [(Color::White, white, black), (Color::Black, black, white)] {
+            if winner.sustained(settings.win_moves, |score| score >= settings.win_score)
+                && loser.sustained(settings.win_moves, |score| score <= -settings.win_score)
+            {
+                return Some((Outcome::win_for(color), "adjudication: win"));
+            }
+        }
+
+        let level = |score: Score| score.abs() <= settings.draw_score;
+        if full_move >= settings.draw_min_moves
+            && white.sustained(settings.draw_moves, level)
+            && black.sustained(settings.draw_moves, level)
+        {
+            return Some((Outcome::Draw, "adjudication: draw"));
+        }
+
+        (plies >= 2 * settings.max_moves).then_some((Outcome::Draw, "move limit"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Plays the scores, White's and Black's alternately, and returns the adjudication.
+    fn adjudicate(settings: &Adjudication, scores: &[Score]) -> Option<(Outcome, &'static str)> {
+        let mut adjudicator = Adjudicator::new(settings);
+        for (ply, &score) in scores.iter().enumerate() {
+            let side = if ply % 2 == 0 {
+                Color::White
+            } else {
+                Color::Black
+            };
+            if let Some(result) = adjudicator.before_move(side, Some(score)) {
+                return Some(result);
+            }
+            if let Some(result) = adjudicator.after_move(50, ply + 1) {
+                return Some(result);
+            }
+        }
+        None
+    }
+
+    fn settings() -> Adjudication {
+        Adjudication {
+            resign: ResignConfig {
+                resign_moves: 0,
+                draw_moves: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_win() {
+        let settings = Adjudication {
+            win_score: 500,
+            win_moves: 2,
+            ..settings()
+        };
+        assert_eq!(
+            adjudicate(&settings, &[-600, 550, -700, 600]),
+            Some((Outcome::BlackWins, "adjudication: win"))
+        );
+        // The engines don't agree.
+        assert_eq!(adjudicate(&settings, &[600, 550, 700, 600]), None);
+    }
+
+    #[test]
+    fn test_draw() {
+        let settings = Adjudication {
+            draw_moves: 2,
+            ..settings()
+        };
+        assert_eq!(
+            adjudicate(&settings, &[30, 0, 5, -10, 0]),
+            Some((Outcome::Draw, "adjudication: draw"))
+        );
+        assert_eq!(adjudicate(&settings, &[30, 0, 5, -10, 50]), None);
+    }
+
+    #[test]
+    fn test_move_limit() {
+        let settings = Adjudication {
+            max_moves: 2,
+            ..settings()
+        };
+        assert_eq!(adjudicate(&settings, &[0, 0, 0]), None);
+        assert_eq!(
+            adjudicate(&settings, &[0, 0, 0, 0]),
+            Some((Outcome::Draw, "move limit"))
+        );
+    }
+
+    #[test]
+    fn test_resign() {
+        let settings = Adjudication {
+            resign: ResignConfig {
+                resign_score: 500,
+                resign_moves: 2,
+                ..Default::default()
+            },
+            ..settings()
+        };
+        assert_eq!(
+            adjudicate(&settings, &[0, -600, 0, -600]),
+            Some((Outcome::WhiteWins, "resignation"))
+        );
+    }
+}
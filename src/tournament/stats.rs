@@ -0,0 +1,255 @@
+//! Statistics of a match between two engines: Elo difference with its error margin,
+//! likelihood of superiority (LOS), and the sequential probability ratio test (SPRT).
+//! The SPRT tells after each game if the results are enough to decide between the hypotheses
+//! H0 (the Elo difference is elo0) and H1 (it is elo1), so that feature tests can stop early.
+//! The log-likelihood ratio uses the normal approximation of the game results, as in Fishtest.
+//! <https://www.chessprogramming.org/Match_Statistics>
+
+use std::fmt;
+
+// Quantile of the normal distribution for the 95% confidence interval.
+const CONFIDENCE_95: f64 = 1.959_964;
+
+// Results of the games of an engine against another one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Results {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl Results {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    // Variance of the score of one game.
+    #[allow(clippy::cast_precision_loss)]
+    fn variance(&self) -> f64 {
+        let score = self.score();
+        let deviation = |result: f64, count: usize| count as f64 * (result - score).powi(2);
+        (deviation(1.0, self.wins) + deviation(0.5, self.draws) + deviation(0.0, self.losses))
+            / self.games() as f64
+    }
+
+    // Elo difference and the margin of its 95% confidence interval.
+    // None when there are no games, or when they were all won or all lost.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        if self.wins == self.games() || self.losses == self.games() {
+            return None;
+        }
+        let score = self.score();
+        let margin = CONFIDENCE_95 * (self.variance() / self.games() as f64).sqrt();
+        let low = elo_from_score((score - margin).max(f64::EPSILON));
+        let high = elo_from_score((score + margin).min(1.0 - f64::EPSILON));
+        Some((elo_from_score(score), (high - low) / 2.0))
+    }
+
+    // Probability that the engine is stronger, draws not counting.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn los(&self) -> f64 {
+        if self.wins + self.losses == 0 {
+            return 0.5;
+        }
+        let (wins, losses) = (self.wins as f64, self.losses as f64);
+        0.5 * (1.0 + erf((wins - losses) / (2.0 * (wins + losses)).sqrt()))
+    }
+}
+
+impl fmt::Display for Results {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "+{} ={} -{}, ", self.wins, self.draws, self.losses)?;
+        match self.elo() {
+            Some((elo, margin)) => write!(f, "Elo {elo:.1} +/- {margin:.1}")?,
+            None => write!(f, "Elo n/a")?,
+        }
+        write!(f, ", LOS {:.1}%", 100.0 * self.los())
+    }
+}
+
+fn elo_from_score(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+fn score_from_elo(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+// Error function, with the approximation 7.1.26 of Abramowitz and Stegun (error below 1.5e-7).
+fn erf(x: f64) -> f64 {
+    const P: f64 = 0.327_591_1;
+    const A: [f64; 5] = [
+        0.254_829_592,
+        -0.284_496_736,
+        1.421_413_741,
+        -1.453_152_027,
+        1.061_405_429,
+    ];
+    let t = 1.0 / (1.0 + P * x.abs());
+    let polynomial = A.iter().rev().fold(0.0, |acc, a| acc * t + a) * t;
+    let y = 1.0 - polynomial * (-x * x).exp();
+    y.copysign(x)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtStatus {
+    Continue,
+    AcceptH0,
+    AcceptH1,
+}
+
+impl fmt::Display for SprtStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SprtStatus::Continue => write!(f, "continue"),
+            SprtStatus::AcceptH0 => write!(f, "H0 accepted"),
+            SprtStatus::AcceptH1 => write!(f, "H1 accepted"),
+        }
+    }
+}
+
+// Hypotheses H0 and H1 as Elo differences, and the probabilities of accepting H1 when H0 is
+// true (alpha) and H0 when H1 is true (beta).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    // The test stops when the log-likelihood ratio gets out of these bounds.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn llr(&self, results: &Results) -> f64 {
+        if results.games() == 0 {
+            return 0.0;
+        }
+        let variance = results.variance();
+        if variance == 0.0 {
+            return 0.0;
+        }
+        let (score0, score1) = (score_from_elo(self.elo0), score_from_elo(self.elo1));
+        results.games() as f64 * (score1 - score0) * (2.0 * results.score() - score0 - score1)
+            / (2.0 * variance)
+    }
+
+    pub fn status(&self, results: &Results) -> SprtStatus {
+        let llr = self.llr(results);
+        let (lower, upper) = self.bounds();
+        if llr <= lower {
+            SprtStatus::AcceptH0
+        } else if llr >= upper {
+            SprtStatus::AcceptH1
+        } else {
+            SprtStatus::Continue
+        }
+    }
+
+    // Summary line, like "LLR 1.23 (-2.94, 2.94) [0, 5]: continue".
+    pub fn report(&self, results: &Results) -> String {
+        let (lower, upper) = self.bounds();
+        format!(
+            "LLR {:.2} ({lower:.2}, {upper:.2}) [{}, {}]: {}",
+            self.llr(results),
+            self.elo0,
+            self.elo1,
+            self.status(results)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(wins: usize, draws: usize, losses: usize) -> Results {
+        Results {
+            wins,
+            draws,
+            losses,
+        }
+    }
+
+    fn assert_near(value: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (value - expected).abs() < tolerance,
+            "{value} is not {expected}"
+        );
+    }
+
+    #[test]
+    fn test_erf() {
+        assert_near(erf(0.0), 0.0, 1e-7);
+        assert_near(erf(0.5), 0.520_499_9, 1e-6);
+        assert_near(erf(-1.0), -0.842_700_8, 1e-6);
+        assert_near(erf(3.0), 0.999_977_9, 1e-6);
+    }
+
+    #[test]
+    fn test_elo() {
+        let (elo, margin) = results(40, 20, 40).elo().unwrap();
+        assert_near(elo, 0.0, 1e-9);
+        // The standard error of the score is 0.0447, giving +/- 0.0876 on the score.
+        assert_near(margin, 61.5, 0.5);
+
+        // A 75% score.
+        let (elo, _) = results(60, 30, 10).elo().unwrap();
+        assert_near(elo, 190.8, 0.1);
+
+        assert_eq!(results(0, 0, 0).elo(), None);
+        assert_eq!(results(3, 0, 0).elo(), None);
+    }
+
+    #[test]
+    fn test_los() {
+        assert_near(results(10, 5, 10).los(), 0.5, 1e-9);
+        assert_near(results(0, 10, 0).los(), 0.5, 1e-9);
+        assert!(results(30, 10, 10).los() > 0.99);
+        assert!(results(10, 10, 30).los() < 0.01);
+    }
+
+    #[test]
+    fn test_sprt() {
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 10.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+        let (lower, upper) = sprt.bounds();
+        assert_near(lower, -2.944, 1e-3);
+        assert_near(upper, 2.944, 1e-3);
+
+        assert_eq!(sprt.status(&results(0, 0, 0)), SprtStatus::Continue);
+        assert_eq!(sprt.status(&results(0, 20, 0)), SprtStatus::Continue);
+        assert_eq!(sprt.status(&results(12, 10, 10)), SprtStatus::Continue);
+        assert_eq!(sprt.status(&results(600, 400, 400)), SprtStatus::AcceptH1);
+        assert_eq!(sprt.status(&results(400, 400, 600)), SprtStatus::AcceptH0);
+        assert!(sprt
+            .report(&results(12, 10, 10))
+            .ends_with("[0, 10]: continue"));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            results(40, 20, 40).to_string(),
+            "+40 =20 -40, Elo 0.0 +/- 61.5, LOS 50.0%"
+        );
+        assert_eq!(results(2, 0, 0).to_string(), "+2 =0 -0, Elo n/a, LOS 92.1%");
+    }
+}
@@ -1,20 +1,42 @@
 //! Handles communication with a UI over UCI.
+//! The protocol runs on any reader and writer: stdin and stdout, or a TCP connection in server mode.
 
 use std::{
     collections::VecDeque,
     fmt::Display,
-    io::{BufRead, Write},
+    io::{self, BufRead, BufReader, Write},
+    net::{Shutdown, TcpListener, TcpStream},
     sync::{
+        atomic::{AtomicUsize, Ordering},
         mpsc::{self, Receiver, Sender},
         Arc, Mutex,
     },
+    thread::JoinHandle,
+    time::Duration,
 };
 
 use itertools::Itertools;
 
+#[cfg(feature = "async")]
+pub mod async_loop;
+pub mod transcript;
+
 use crate::{
-    common::{format_moves_as_pure_string, Move, ENGINE_AUTHOR, ENGINE_NAME},
-    engine::game::{Event, Game, InfoData, SearchParams},
+    board::{Board, BoardEdit},
+    common::{
+        format_moves_as_pure_string, Move, ENGINE_AUTHOR, ENGINE_NAME, ENGINE_VERSION,
+        NULL_MOVE_PURE,
+    },
+    engine::{
+        config::{Config, DEFAULT_RAZOR_MARGIN},
+        difficulty::Difficulty,
+        eval::EvalBackend,
+        experience,
+        game::{Event, Game, InfoData, SearchParams, SearchState},
+        search::{trace::TraceFormat, SearchAlgorithm},
+        tunables,
+    },
+    signals,
 };
 
 // Writes the UCI output to the writer and logs it.
@@ -30,7 +52,7 @@ macro_rules! outputln {
 
 // GUI to Engine
 #[derive(Debug)]
-enum UciCommand {
+pub enum UciCommand {
     Uci,
     Debug(bool),
     IsReady,
@@ -42,7 +64,9 @@ enum UciCommand {
     Stop,
     PonderHit,
     Quit,
-    Print, // Non-standard: "d"
+    Print { attacks: bool, flip: bool }, // Non-standard: "d", "d attacks", "d flip"
+    Edit(BoardEdit),                     // Non-standard: "edit put e4 N", see BoardEdit.
+    Invalid(String), // A line that couldn't be parsed, with the error to report to the GUI.
 }
 
 // Engine to GUI
@@ -52,15 +76,33 @@ enum UciEvent {
     UciOk,
     ReadyOk,
     BestMove(Option<Move>, Option<Move>), // move, ponder
-    CopyProtection,
-    Registration,
     Info(Vec<InfoData>),
-    Option,
+    Option(String),
     DisplayBoard(String), // Non-standard (response to d)
 }
 
+// What to send as best move when there is none, on checkmate or stalemate.
+// The protocol doesn't specify it, and GUIs handle it differently.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum NoMoveOutput {
+    // This is what Stockfish seems to do.
+    // <https://github.com/official-stockfish/Stockfish/discussions/5075>
+    #[default]
+    Empty, // "(none)"
+    NullMove, // "0000", the null move of the protocol.
+}
+
+impl NoMoveOutput {
+    fn as_str(self) -> &'static str {
+        match self {
+            NoMoveOutput::Empty => "(none)",
+            NoMoveOutput::NullMove => NULL_MOVE_PURE,
+        }
+    }
+}
+
 #[derive(Debug)]
-enum GoCommand {
+pub enum GoCommand {
     SearchMoves(Vec<Move>),
     Ponder,
     WTime(u32),
@@ -80,19 +122,134 @@ pub fn run<R, W>(game: &mut Game, reader: Arc<Mutex<R>>, writer: Arc<Mutex<W>>)
 where
     R: BufRead + Send + 'static,
     W: Write + Send + 'static,
+{
+    run_session(game, reader, writer, false);
+}
+
+// Like run, but the termination signals are handled as a quit command: The search is stopped and
+// its best move sent before returning. For the engine on stdin and stdout, as signals are global.
+pub fn run_until_signal<R, W>(game: &mut Game, reader: Arc<Mutex<R>>, writer: Arc<Mutex<W>>)
+where
+    R: BufRead + Send + 'static,
+    W: Write + Send + 'static,
+{
+    run_session(game, reader, writer, true);
+}
+
+fn run_session<R, W>(
+    game: &mut Game,
+    reader: Arc<Mutex<R>>,
+    writer: Arc<Mutex<W>>,
+    quit_on_signal: bool,
+) where
+    R: BufRead + Send + 'static,
+    W: Write + Send + 'static,
 {
     let (cmd_sender, cmd_receiver): (Sender<UciCommand>, Receiver<UciCommand>) = mpsc::channel();
+    if quit_on_signal {
+        let cmd_sender = cmd_sender.clone();
+        // The commands handler is gone if a quit command was received before.
+        signals::on_termination(move || {
+            let _ = cmd_sender.send(UciCommand::Quit);
+        });
+    }
     let (evt_sender, evt_receiver): (Sender<UciEvent>, Receiver<UciEvent>) = mpsc::channel();
     let (game_event_sender, game_event_receiver): (Sender<Event>, Receiver<Event>) =
         mpsc::channel();
 
+    // Set on the game commands thread, used when writing the best move.
+    let no_move_output = Arc::new(Mutex::new(NoMoveOutput::default()));
+
     spawn_ui_input_handler(reader, cmd_sender);
-    spawn_ui_event_handler(writer, evt_receiver);
+    let ui_event_handler =
+        spawn_ui_event_handler(writer, evt_receiver, Arc::clone(&no_move_output));
     spawn_game_event_handler(game_event_receiver, evt_sender.clone());
-    spawn_game_commands_handler(game, cmd_receiver, evt_sender, game_event_sender);
+    spawn_game_commands_handler(
+        game,
+        cmd_receiver,
+        evt_sender,
+        game_event_sender,
+        &no_move_output,
+    );
+    // Once the game commands are done, the events still queued must be written before returning,
+    // otherwise the output of the last commands could be lost when the input ends right after them.
+    let _ = ui_event_handler.join();
+}
+
+// Runs the UCI loop on each connection to the listener, so that remote GUIs and test harnesses
+// can use the engine over the network.
+// Each connection is a session with its own game, and so its own search thread and hash table.
+// Connections beyond the maximum number of sessions are refused.
+pub fn serve(listener: &TcpListener, config: Config, max_sessions: usize) {
+    let sessions = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let result = stream.and_then(|stream| {
+            if sessions.load(Ordering::Relaxed) >= max_sessions {
+                refuse_connection(stream)
+            } else {
+                start_session(stream, config, &sessions)
+            }
+        });
+        if let Err(err) = result {
+            warn!("Connection failed: {err}");
+        }
+    }
+}
+
+fn refuse_connection(mut stream: TcpStream) -> io::Result<()> {
+    warn!("Too many sessions, refusing connection");
+    writeln!(stream, "info string too many sessions, try again later")?;
+    stream.shutdown(Shutdown::Write)?;
+    // Closing with unread input would reset the connection, and the client could miss the message.
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    std::thread::spawn(move || io::copy(&mut stream, &mut io::sink()));
+    Ok(())
+}
+
+// Counts a running session. Dropping it ends the session, even on a panic.
+struct SessionCount(Arc<AtomicUsize>);
+
+impl SessionCount {
+    fn new(sessions: &Arc<AtomicUsize>) -> Self {
+        sessions.fetch_add(1, Ordering::Relaxed);
+        Self(Arc::clone(sessions))
+    }
+}
+
+impl Drop for SessionCount {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// The session runs on its own thread, so that a client disconnecting in the middle of a search
+// can't bring the server down.
+fn start_session(stream: TcpStream, config: Config, sessions: &Arc<AtomicUsize>) -> io::Result<()> {
+    let peer = stream.peer_addr()?;
+    info!("Connection from {peer}");
+    let reader = BufReader::new(stream.try_clone()?);
+    let connection = stream.try_clone()?;
+    let count = SessionCount::new(sessions);
+    std::thread::Builder::new()
+        .name(format!("session {peer}"))
+        .spawn(move || {
+            let mut game = Game::new();
+            game.set_config(config);
+            run(
+                &mut game,
+                Arc::new(Mutex::new(reader)),
+                Arc::new(Mutex::new(stream)),
+            );
+            info!("Connection from {peer} closed");
+            // The client only sees the connection closing once the session isn't counted anymore.
+            drop(count);
+            drop(connection);
+        })?;
+    Ok(())
 }
 
 // Spawn a thread to handle UI input.
+// The end of the input is handled as a quit command, as nobody is left to send one.
 fn spawn_ui_input_handler<R>(reader: Arc<Mutex<R>>, cmd_sender: Sender<UciCommand>)
 where
     R: BufRead + Send + 'static,
@@ -100,181 +257,151 @@ where
     std::thread::spawn(move || {
         loop {
             let mut line = String::new();
-            reader
-                .lock()
-                .unwrap()
-                .read_line(&mut line)
-                .expect("Could not read line");
-            if line.is_empty() {
-                continue;
+            if let Ok(0) | Err(_) = reader.lock().unwrap().read_line(&mut line) {
+                // The commands handler is gone if a quit command was received before.
+                let _ = cmd_sender.send(UciCommand::Quit);
+                break;
             }
 
             info!("< {}", line.trim());
 
-            // Split the input into tokens
-            let mut tokens: VecDeque<_> = line.split_ascii_whitespace().collect();
-            if tokens.is_empty() {
-                continue;
+            match parse_command(&line) {
+                Ok(Some(cmd)) => cmd_sender.send(cmd).unwrap(),
+                Ok(None) => {}
+                // Reported through the commands handler, to keep the order of the responses.
+                Err(err) => cmd_sender.send(UciCommand::Invalid(err)).unwrap(),
             }
+        }
+    });
+}
 
-            while let Some(cmd) = tokens.pop_front() {
-                match cmd.to_lowercase().as_str() {
-                    // Standard commands
-                    "uci" => cmd_sender.send(UciCommand::Uci).unwrap(),
-                    "debug" => {
-                        let val = *tokens.front().expect("No debug value provided");
-                        let debug = match val {
-                            "on" => true,
-                            "off" => false,
-                            _ => panic!("Invalid debug value"),
-                        };
-                        cmd_sender.send(UciCommand::Debug(debug)).unwrap();
-                    }
-                    "isready" => cmd_sender.send(UciCommand::IsReady).unwrap(),
-                    "setoptions" => {
-                        assert_eq!(tokens.pop_front().unwrap(), "name");
-                        let name = tokens.pop_front().unwrap().to_string();
-                        let value = if let Some(v) = tokens.pop_front() {
-                            assert_eq!(v, "value");
-                            Some(tokens.pop_front().unwrap().to_string())
-                        } else {
-                            None
-                        };
-                        cmd_sender.send(UciCommand::SetOption(name, value)).unwrap();
-                    }
-                    "ucinewgame" => cmd_sender.send(UciCommand::UciNewGame).unwrap(),
-                    "position" => {
-                        let pos = *tokens.front().expect("No position provided");
-
-                        let position = if pos == "startpos" {
-                            tokens.pop_front().unwrap();
-                            None // means start pos
-                        } else if pos == "fen" {
-                            tokens.pop_front().unwrap();
-                            // FEN string is always 6 tokens.
-                            // Not great to split the string to join it again..
-                            let fen = tokens.drain(0..6).join(" ");
-                            Some(fen)
-                        } else {
-                            panic!("Missing position")
-                        };
-
-                        let moves: Vec<String> = if matches!(tokens.pop_front(), Some("moves")) {
-                            tokens.into_iter().map(String::from).collect()
-                        } else {
-                            vec![]
-                        };
-
-                        cmd_sender
-                            .send(UciCommand::Position(position, moves))
-                            .unwrap();
-                    }
-                    "go" => {
-                        let mut go_cmds = Vec::new();
-                        while let Some(p) = tokens.pop_front() {
-                            match p {
-                                "infinite" => go_cmds.push(GoCommand::Infinite),
-                                "depth" => {
-                                    let d = tokens.pop_front().unwrap().parse().unwrap();
-                                    go_cmds.push(GoCommand::Depth(d));
-                                }
-                                _ => {}
-                            }
-                        }
-                        cmd_sender.send(UciCommand::Go(go_cmds)).unwrap();
-                    }
-                    "stop" => cmd_sender.send(UciCommand::Stop).unwrap(),
-                    "quit" | "q" => cmd_sender.send(UciCommand::Quit).unwrap(), // Only "quit" is standard.
-                    "register" | "ponderhit" => {} // Command not implemented
-                    // Non-standard commands
-                    "d" => cmd_sender.send(UciCommand::Print).unwrap(),
-                    _ => continue, // Command was unknown, try next token.
+// Parses a line of input. As the UCI protocol asks, unknown tokens before the command are skipped.
+// A line without any known command is an error, to be reported and otherwise ignored. None is
+// returned for empty lines and for the commands that aren't implemented.
+pub fn parse_command(line: &str) -> Result<Option<UciCommand>, String> {
+    let mut tokens: VecDeque<_> = line.split_ascii_whitespace().collect();
+    while let Some(cmd) = tokens.pop_front() {
+        let command = match cmd.to_lowercase().as_str() {
+            // Standard commands
+            "uci" => UciCommand::Uci,
+            "debug" => match tokens.front() {
+                Some(&"on") => UciCommand::Debug(true),
+                Some(&"off") => UciCommand::Debug(false),
+                _ => return Err(format!("Invalid debug value in {}", line.trim())),
+            },
+            "isready" => UciCommand::IsReady,
+            "setoption" => {
+                if tokens.pop_front() != Some("name") {
+                    return Err(format!("Missing option name in {}", line.trim()));
                 }
-                break; // Command was handled.
+                // Names and values may contain spaces.
+                let name = tokens.iter().take_while(|&&t| t != "value").join(" ");
+                let value = tokens
+                    .iter()
+                    .position(|&t| t == "value")
+                    .map(|i| tokens.range(i + 1..).join(" "));
+                UciCommand::SetOption(name, value)
             }
-        }
-    });
+            "ucinewgame" => UciCommand::UciNewGame,
+            "position" => {
+                let position = match tokens.pop_front() {
+                    Some("startpos") => None, // means start pos
+                    // FEN string is always 6 tokens.
+                    // Not great to split the string to join it again..
+                    Some("fen") if tokens.len() >= 6 => Some(tokens.drain(0..6).join(" ")),
+                    _ => return Err(format!("Missing position in {}", line.trim())),
+                };
+
+                let moves: Vec<String> = if matches!(tokens.pop_front(), Some("moves")) {
+                    tokens.into_iter().map(String::from).collect()
+                } else {
+                    vec![]
+                };
+                UciCommand::Position(position, moves)
+            }
+            "go" => UciCommand::Go(parse_go_cmd(&mut tokens)?),
+            "stop" => UciCommand::Stop,
+            "quit" | "q" => UciCommand::Quit, // Only "quit" is standard.
+            "register" | "ponderhit" => return Ok(None), // Command not implemented
+            // Non-standard commands
+            "d" => UciCommand::Print {
+                attacks: tokens.contains(&"attacks"),
+                flip: tokens.contains(&"flip"),
+            },
+            "edit" => UciCommand::Edit(tokens.iter().join(" ").parse()?),
+            _ => continue, // Command was unknown, try next token.
+        };
+        return Ok(Some(command));
+    }
+    if line.trim().is_empty() {
+        Ok(None)
+    } else {
+        Err(format!("Unknown command {}", line.trim()))
+    }
 }
 
 // Handle UCI commands..
-fn spawn_ui_event_handler<W>(writer: Arc<Mutex<W>>, evt_receiver: Receiver<UciEvent>)
+fn spawn_ui_event_handler<W>(
+    writer: Arc<Mutex<W>>,
+    evt_receiver: Receiver<UciEvent>,
+    no_move_output: Arc<Mutex<NoMoveOutput>>,
+) -> JoinHandle<()>
 where
     W: Write + Send + 'static,
 {
     std::thread::spawn(move || {
         let mut writer = writer.lock().unwrap();
-        loop {
-            while let Ok(cmd) = evt_receiver.recv() {
-                match cmd {
-                    UciEvent::Id(param, value) => {
-                        outputln!(&mut writer, "id {param} {value}");
-                    }
-                    UciEvent::UciOk => {
-                        outputln!(&mut writer, "uciok");
-                    }
-                    UciEvent::ReadyOk => {
-                        outputln!(&mut writer, "readyok");
-                    }
-                    UciEvent::BestMove(mv, ponder) => {
-                        // If best_move is None, it means we are in stale mate.
-                        if let Some(best_move) = mv {
-                            if let Some(ponder_move) = ponder {
-                                outputln!(
-                                    &mut writer,
-                                    "bestmove {} ponder {}",
-                                    best_move.pure(),
-                                    ponder_move.pure()
-                                );
-                            } else {
-                                outputln!(&mut writer, "bestmove {}", best_move.pure());
-                            }
-                        } else {
-                            // The protocol doesn't specify what do on stalemates.
-                            // This is what Stockfish seems to do.
-                            // <https://github.com/official-stockfish/Stockfish/discussions/5075>
-                            outputln!(&mut writer, "bestmove (none)");
-                        }
-                    }
-                    UciEvent::Info(infos) => {
-                        // Sorting the keys for readability.
-                        outputln!(
-                            &mut writer,
-                            "info {}",
-                            infos
-                                .iter()
-                                .sorted_unstable_by_key(|i| info_data_sort_order(i))
-                                .join(" ")
-                        );
-                    }
-                    UciEvent::Option => {
-                        // TODO
-                    }
-                    UciEvent::DisplayBoard(b) => {
-                        outputln!(&mut writer, "{b}");
-                    }
-                    UciEvent::CopyProtection | UciEvent::Registration => {
-                        unimplemented!();
-                    }
-                }
-            }
+        while let Ok(evt) = evt_receiver.recv() {
+            let no_move_output = *no_move_output.lock().unwrap();
+            outputln!(&mut writer, "{}", format_event(evt, no_move_output));
         }
-    });
+    })
+}
+
+// The line sent to the GUI for the event.
+fn format_event(evt: UciEvent, no_move_output: NoMoveOutput) -> String {
+    match evt {
+        UciEvent::Id(param, value) => format!("id {param} {value}"),
+        UciEvent::UciOk => "uciok".to_string(),
+        UciEvent::ReadyOk => "readyok".to_string(),
+        UciEvent::BestMove(mv, ponder) => format_best_move(mv, ponder, no_move_output),
+        UciEvent::Info(infos) => format_info(&infos),
+        UciEvent::Option(option) => format!("option {option}"),
+        UciEvent::DisplayBoard(b) => b,
+    }
+}
+
+// If the best move is None, it means we are mated or in stalemate.
+fn format_best_move(
+    mv: Option<Move>,
+    ponder: Option<Move>,
+    no_move_output: NoMoveOutput,
+) -> String {
+    match (mv, ponder) {
+        (Some(best_move), Some(ponder_move)) => {
+            format!(
+                "bestmove {} ponder {}",
+                best_move.pure(),
+                ponder_move.pure()
+            )
+        }
+        (Some(best_move), None) => format!("bestmove {}", best_move.pure()),
+        (None, _) => format!("bestmove {}", no_move_output.as_str()),
+    }
 }
 
 // Spawn a thread to handle game events.
 fn spawn_game_event_handler(game_event_receiver: Receiver<Event>, evt_sender: Sender<UciEvent>) {
     std::thread::spawn(move || {
-        loop {
-            // Receive messages from the Game thread (info messages, bestmove)
-            while let Ok(evt) = game_event_receiver.recv() {
-                // Convert to UCI event.
-                let uci_event = match evt {
-                    Event::BestMove(mv, ponder) => UciEvent::BestMove(mv, ponder),
-                    Event::Info(info) => UciEvent::Info(info),
-                };
-                // Send to UciCommand handler.
-                evt_sender.send(uci_event).unwrap();
-            }
+        // Receive messages from the Game thread (info messages, bestmove)
+        while let Ok(evt) = game_event_receiver.recv() {
+            // Convert to UCI event.
+            let uci_event = match evt {
+                Event::BestMove(mv, ponder) => UciEvent::BestMove(mv, ponder),
+                Event::Info(info) => UciEvent::Info(info),
+            };
+            // Send to UciCommand handler.
+            evt_sender.send(uci_event).unwrap();
         }
     });
 }
@@ -286,35 +413,62 @@ fn spawn_game_commands_handler(
     cmd_receiver: Receiver<UciCommand>,
     evt_sender: Sender<UciEvent>,
     game_event_sender: Sender<Event>,
+    no_move_output: &Mutex<NoMoveOutput>,
 ) {
     loop {
         // Receive messages from the Game thread (info messages, bestmove)
         while let Ok(cmd) = cmd_receiver.recv() {
-            match cmd {
-                // UI to Engine: Standard commands
-                UciCommand::Uci => handle_uci_cmd(&evt_sender),
-                UciCommand::Debug(val) => handle_debug_cmd(game, val),
-                UciCommand::IsReady => handle_isready_cmd(&evt_sender),
-                UciCommand::SetOption(name, value) => handle_setoptions_cmd(&name, &value),
-                UciCommand::UciNewGame => handle_ucinewgame_cmd(game),
-                UciCommand::Position(position, moves) => {
-                    handle_position_cmd(game, position, &moves);
-                }
-                UciCommand::Go(go_cmds) => handle_go_cmd(game, &go_cmds, &game_event_sender),
-                UciCommand::Stop => handle_stop_cmd(game),
-                UciCommand::Quit => return,
-                UciCommand::Register | UciCommand::PonderHit => {} // Command not implemented
-                // UI to Engine: Non-standard commands
-                UciCommand::Print => handle_d_cmd(game, &evt_sender),
+            if !handle_command(game, cmd, &evt_sender, &game_event_sender, no_move_output) {
+                return;
             }
         }
     }
 }
 
+// Runs the command, returns false once the session must end.
+fn handle_command(
+    game: &mut Game,
+    cmd: UciCommand,
+    evt_sender: &Sender<UciEvent>,
+    game_event_sender: &Sender<Event>,
+    no_move_output: &Mutex<NoMoveOutput>,
+) -> bool {
+    match cmd {
+        // UI to Engine: Standard commands
+        UciCommand::Uci => handle_uci_cmd(evt_sender),
+        UciCommand::Debug(val) => handle_debug_cmd(game, val),
+        UciCommand::IsReady => handle_isready_cmd(evt_sender),
+        UciCommand::SetOption(name, value) => {
+            handle_setoptions_cmd(game, &name, value.as_ref(), no_move_output);
+        }
+        UciCommand::UciNewGame => handle_ucinewgame_cmd(game),
+        UciCommand::Position(position, moves) => {
+            handle_position_cmd(game, evt_sender, position.as_deref(), &moves);
+        }
+        UciCommand::Go(go_cmds) => handle_go_cmd(game, &go_cmds, game_event_sender),
+        UciCommand::Stop => handle_stop_cmd(game),
+        UciCommand::Quit => {
+            handle_quit_cmd(game);
+            return false;
+        }
+        UciCommand::Register | UciCommand::PonderHit => {} // Command not implemented
+        // UI to Engine: Non-standard commands
+        UciCommand::Print { attacks, flip } => {
+            handle_d_cmd(game, evt_sender, attacks, flip);
+        }
+        UciCommand::Edit(edit) => game.edit_board(&edit),
+        UciCommand::Invalid(err) => handle_invalid_cmd(evt_sender, err),
+    }
+    true
+}
+
 fn handle_uci_cmd(evt_sender: &Sender<UciEvent>) {
     // Identify.
     evt_sender
-        .send(UciEvent::Id("name".to_string(), ENGINE_NAME.to_string()))
+        .send(UciEvent::Id(
+            "name".to_string(),
+            format!("{ENGINE_NAME} {ENGINE_VERSION}"),
+        ))
         .unwrap();
     evt_sender
         .send(UciEvent::Id(
@@ -324,11 +478,66 @@ fn handle_uci_cmd(evt_sender: &Sender<UciEvent>) {
         .unwrap();
 
     // Send the options that can be changed.
+    for option in uci_options() {
+        evt_sender.send(UciEvent::Option(option)).unwrap();
+    }
 
     // Ready
     evt_sender.send(UciEvent::UciOk).unwrap();
 }
 
+fn uci_options() -> Vec<String> {
+    let mut options = vec![
+        "name UCI_AnalyseMode type check default false".to_string(),
+        "name UCI_ShowWDL type check default false".to_string(),
+        "name Config File type string default <empty>".to_string(),
+        "name Clear Hash type button".to_string(),
+        // Learning from previous games, see engine::experience.
+        "name Experience type check default false".to_string(),
+        format!(
+            "name Experience File type string default {}",
+            experience::DEFAULT_FILE
+        ),
+        "name Clear Experience type button".to_string(),
+        format!(
+            "name Search Algorithm type combo default {} var {} var {}",
+            SearchAlgorithm::default(),
+            SearchAlgorithm::AlphaBeta,
+            SearchAlgorithm::Negamax
+        ),
+        format!(
+            "name Evaluation type combo default {} var {} var {} var {}",
+            EvalBackend::default(),
+            EvalBackend::Material,
+            EvalBackend::Classical,
+            EvalBackend::Pst
+        ),
+        "name No Move Output type combo default (none) var (none) var 0000".to_string(),
+        format!(
+            "name Difficulty type combo default {} var {}",
+            Difficulty::default(),
+            Difficulty::ALL.iter().join(" var ")
+        ),
+        format!("name Razor Margin type spin default {DEFAULT_RAZOR_MARGIN} min 0 max 2000"),
+        // Without an opening book, randomizes the first moves a little so that games don't repeat.
+        "name Opening Variety type spin default 0 min 0 max 40".to_string(),
+        "name Random Seed type spin default 0 min 0 max 1000000".to_string(),
+        // Debugging: Stops with an error if the fast legal move count of a node differs from the
+        // moves generated, slowing the search down.
+        "name VerifyMoveGen type check default false".to_string(),
+        // Debugging: Dumps the first plies of the search tree to a file after each search.
+        "name Trace Plies type spin default 0 min 0 max 16".to_string(),
+        "name Trace Format type combo default text var text var dot".to_string(),
+        // Analysis: Appends a JSON summary of each search to a file, see search::summary.
+        "name Search Log type string default <empty>".to_string(),
+    ];
+    // For SPSA tuning only, as they change the strength.
+    if cfg!(feature = "tune") {
+        options.extend(tunables::TUNABLES.iter().map(tunables::Tunable::uci_option));
+    }
+    options
+}
+
 fn handle_debug_cmd(game: &mut Game, debug: bool) {
     game.set_debug(debug);
 }
@@ -340,8 +549,113 @@ fn handle_isready_cmd(evt_sender: &Sender<UciEvent>) {
     evt_sender.send(UciEvent::ReadyOk).unwrap();
 }
 
-fn handle_setoptions_cmd(name: &str, value: &Option<String>) {
+fn handle_setoptions_cmd(
+    game: &mut Game,
+    name: &str,
+    value: Option<&String>,
+    no_move_output: &Mutex<NoMoveOutput>,
+) {
     info!("Setting option {name} to {:?}", value);
+    // Option names are not case sensitive.
+    match name.to_lowercase().as_str() {
+        "uci_analysemode" => game.set_analyse_mode(is_true(value)),
+        "uci_showwdl" => game.set_show_wdl(is_true(value)),
+        "config file" => match value.filter(|path| *path != "<empty>") {
+            Some(path) => match Config::load(path) {
+                Ok(config) => game.set_config(config),
+                Err(err) => warn!("Invalid config {err}"),
+            },
+            None => game.set_config(Config::default()),
+        },
+        "clear hash" => game.clear_hash(),
+        "experience" => game.set_experience(is_true(value)),
+        "experience file" => {
+            if let Some(path) = value {
+                game.set_experience_file(path);
+            } else {
+                warn!("Missing experience file");
+            }
+        }
+        "clear experience" => game.clear_experience(),
+        "search algorithm" => {
+            if let Some(Ok(algorithm)) = value.map(|v| v.parse()) {
+                game.set_search_algorithm(algorithm);
+            } else {
+                warn!("Invalid search algorithm {:?}", value);
+            }
+        }
+        "evaluation" => {
+            if let Some(Ok(backend)) = value.map(|v| v.parse()) {
+                game.set_eval_backend(backend);
+            } else {
+                warn!("Invalid evaluation {:?}", value);
+            }
+        }
+        "difficulty" => {
+            if let Some(Ok(difficulty)) = value.map(|v| v.parse()) {
+                game.set_difficulty(difficulty);
+            } else {
+                warn!("Invalid difficulty {:?}", value);
+            }
+        }
+        "razor margin" => {
+            if let Some(Ok(margin)) = value.map(|v| v.parse()) {
+                game.set_razor_margin(margin);
+            } else {
+                warn!("Invalid razor margin {:?}", value);
+            }
+        }
+        "opening variety" => {
+            if let Some(Ok(moves)) = value.map(|v| v.parse()) {
+                game.set_opening_variety(moves);
+            } else {
+                warn!("Invalid opening variety {:?}", value);
+            }
+        }
+        "random seed" => {
+            if let Some(Ok(seed)) = value.map(|v| v.parse()) {
+                game.set_random_seed(seed);
+            } else {
+                warn!("Invalid random seed {:?}", value);
+            }
+        }
+        "verifymovegen" => game.set_verify_move_gen(is_true(value)),
+        "trace plies" => {
+            if let Some(Ok(plies)) = value.map(|v| v.parse()) {
+                game.set_trace_plies(plies);
+            } else {
+                warn!("Invalid trace plies {:?}", value);
+            }
+        }
+        "no move output" => match value.map(String::as_str) {
+            Some("(none)") => *no_move_output.lock().unwrap() = NoMoveOutput::Empty,
+            Some(NULL_MOVE_PURE) => *no_move_output.lock().unwrap() = NoMoveOutput::NullMove,
+            _ => warn!("Invalid no move output {:?}", value),
+        },
+        "trace format" => match value.map(|v| v.to_lowercase()).as_deref() {
+            Some("text") => game.set_trace_format(TraceFormat::Text),
+            Some("dot") => game.set_trace_format(TraceFormat::Dot),
+            _ => warn!("Invalid trace format {:?}", value),
+        },
+        "search log" => {
+            game.set_search_log(value.map(String::as_str).filter(|path| *path != "<empty>"));
+        }
+        name if cfg!(feature = "tune") && tunables::find(name).is_some() => {
+            let result = match value.map(|v| v.parse()) {
+                Some(Ok(value)) => game.set_tunable(name, value),
+                _ => Err(format!("Invalid {name} {value:?}")),
+            };
+            if let Err(err) = result {
+                warn!("{err}");
+            }
+        }
+        _ => warn!("Unknown option {name}"),
+    }
+}
+
+// Check options are set with "true" or "false".
+fn is_true(value: Option<&String>) -> bool {
+    value.is_some_and(|v| v.eq_ignore_ascii_case("true"))
 }
 
 fn handle_ucinewgame_cmd(game: &mut Game) {
@@ -349,16 +663,82 @@ fn handle_ucinewgame_cmd(game: &mut Game) {
     game.new_game();
 }
 
-fn handle_position_cmd(game: &mut Game, position: Option<String>, moves: &[String]) {
-    if let Some(fen) = position {
-        game.set_to_fen(&fen);
-    } else {
-        game.set_to_startpos();
+// An invalid FEN is reported to the UI, keeping the current position.
+// An illegal move is reported to the UI, and the position is set up to the move before it.
+fn handle_position_cmd(
+    game: &mut Game,
+    evt_sender: &Sender<UciEvent>,
+    position: Option<&str>,
+    moves: &[String],
+) {
+    let board = position.map_or_else(|| Ok(Board::initial_board()), Board::try_from_fen);
+    let mut board = match board {
+        Ok(board) => board,
+        Err(err) => {
+            warn!("{err}");
+            evt_sender
+                .send(UciEvent::Info(vec![InfoData::String(err)]))
+                .unwrap();
+            return;
+        }
+    };
+    for (i, mv) in moves.iter().enumerate() {
+        if mv == NULL_MOVE_PURE {
+            // Ignored by the game when in check.
+            if !board.in_check() {
+                board.make_null_move();
+            }
+            continue;
+        }
+        match board.parse_pure_move(mv) {
+            Ok(mv) => board.update_by_move(mv),
+            Err(reason) => {
+                let message = format!("Illegal move {mv}: {reason}, ignoring the next moves");
+                warn!("{message}");
+                evt_sender
+                    .send(UciEvent::Info(vec![InfoData::String(message)]))
+                    .unwrap();
+                game.set_position(position, &moves[..i]);
+                return;
+            }
+        }
     }
+    game.set_position(position, moves);
+}
 
-    if !moves.is_empty() {
-        game.apply_moves(moves);
+fn parse_go_cmd(tokens: &mut VecDeque<&str>) -> Result<Vec<GoCommand>, String> {
+    let mut go_cmds = Vec::new();
+    while let Some(p) = tokens.pop_front() {
+        match p {
+            "infinite" => go_cmds.push(GoCommand::Infinite),
+            "depth" => go_cmds.push(GoCommand::Depth(parse_number(p, tokens)?)),
+            "wtime" => go_cmds.push(GoCommand::WTime(parse_millis(p, tokens)?)),
+            "btime" => go_cmds.push(GoCommand::BTime(parse_millis(p, tokens)?)),
+            "winc" => go_cmds.push(GoCommand::WInc(parse_millis(p, tokens)?)),
+            "binc" => go_cmds.push(GoCommand::BInc(parse_millis(p, tokens)?)),
+            "movetime" => go_cmds.push(GoCommand::MoveTime(parse_millis(p, tokens)?)),
+            "movestogo" => go_cmds.push(GoCommand::MovesToGo(parse_number(p, tokens)?)),
+            "nodes" => go_cmds.push(GoCommand::Nodes(parse_number(p, tokens)?)),
+            _ => {}
+        }
     }
+    Ok(go_cmds)
+}
+
+fn parse_number<T: std::str::FromStr>(
+    name: &str,
+    tokens: &mut VecDeque<&str>,
+) -> Result<T, String> {
+    tokens
+        .pop_front()
+        .and_then(|t| t.parse().ok())
+        .ok_or_else(|| format!("Invalid {name} value"))
+}
+
+// Times are in milliseconds. Some GUIs send negative times when the engine is late, we treat them as 0.
+fn parse_millis(name: &str, tokens: &mut VecDeque<&str>) -> Result<u32, String> {
+    let millis: i64 = parse_number(name, tokens)?;
+    Ok(u32::try_from(millis.max(0)).unwrap_or(u32::MAX))
 }
 
 fn handle_go_cmd(game: &mut Game, go_cmds: &[GoCommand], game_event_sender: &Sender<Event>) {
@@ -369,26 +749,48 @@ fn handle_go_cmd(game: &mut Game, go_cmds: &[GoCommand], game_event_sender: &Sen
             GoCommand::Depth(d) => sp.depth = Some(*d),
             GoCommand::SearchMoves(_) => todo!(),
             GoCommand::Ponder => todo!(),
-            GoCommand::WTime(_) => todo!(),
-            GoCommand::BTime(_) => todo!(),
-            GoCommand::WInc(_) => todo!(),
-            GoCommand::BInc(_) => todo!(),
-            GoCommand::MovesToGo(_) => todo!(),
-            GoCommand::Nodes(_) => todo!(),
+            GoCommand::WTime(t) => sp.white_time = Some(Duration::from_millis(u64::from(*t))),
+            GoCommand::BTime(t) => sp.black_time = Some(Duration::from_millis(u64::from(*t))),
+            GoCommand::WInc(t) => sp.white_increment = Some(Duration::from_millis(u64::from(*t))),
+            GoCommand::BInc(t) => sp.black_increment = Some(Duration::from_millis(u64::from(*t))),
+            GoCommand::MovesToGo(n) => sp.moves_to_go = Some(*n),
+            GoCommand::MoveTime(t) => sp.move_time = Some(Duration::from_millis(u64::from(*t))),
+            GoCommand::Nodes(n) => sp.nodes = Some(usize::try_from(*n).unwrap()),
             GoCommand::Mate(_) => todo!(),
-            GoCommand::MoveTime(_) => todo!(),
         }
     }
     game.start_search(sp, game_event_sender);
 }
 
+// The search must not outlive the session.
+fn handle_quit_cmd(game: &mut Game) {
+    if game.get_search_state() != SearchState::Idle {
+        game.stop_search();
+    }
+    game.wait_for_search();
+    game.save_experience();
+}
+
 fn handle_stop_cmd(game: &mut Game) {
     game.stop_search();
 }
 
-fn handle_d_cmd(game: &mut Game, evt_sender: &Sender<UciEvent>) {
+// The GUI is told, but the session goes on as if the line wasn't sent.
+fn handle_invalid_cmd(evt_sender: &Sender<UciEvent>, err: String) {
+    warn!("{err}");
+    evt_sender
+        .send(UciEvent::Info(vec![InfoData::String(err)]))
+        .unwrap();
+}
+
+// The flip option shows the board from Black's side.
+fn handle_d_cmd(game: &mut Game, evt_sender: &Sender<UciEvent>, attacks: bool, flip: bool) {
     let mut out = Vec::new();
-    game.display_board(&mut out);
+    if attacks {
+        game.display_attacks(&mut out, flip);
+    } else {
+        game.display_board(&mut out, flip);
+    }
     let output = String::from_utf8(out).expect("Invalid UTF-8 sequence");
     evt_sender.send(UciEvent::DisplayBoard(output)).unwrap();
 }
@@ -397,31 +799,51 @@ impl Display for InfoData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             InfoData::Depth(x) => write!(f, "depth {x}"),
-            InfoData::Score(x) => write!(f, "score cp {x}"),
-            InfoData::ScoreMate(y) => write!(f, "score mate {y}"),
+            InfoData::SelDepth(x) => write!(f, "seldepth {x}"),
+            InfoData::Score(x) => write!(f, "score {}", x.to_uci_string()),
+            InfoData::Wdl(wdl) => write!(f, "wdl {} {} {}", wdl.win, wdl.draw, wdl.loss),
             InfoData::Nodes(x) => write!(f, "nodes {x}"),
+            InfoData::HashFull(x) => write!(f, "hashfull {x}"),
             InfoData::Pv(moves) => write!(f, "pv {}", format_moves_as_pure_string(moves)),
             InfoData::String(s) => write!(f, "string {s}"),
         }
     }
 }
 
+// The info line as sent to the GUI, with the keys sorted for readability.
+pub fn format_info(infos: &[InfoData]) -> String {
+    format!(
+        "info {}",
+        infos
+            .iter()
+            .sorted_unstable_by_key(|i| info_data_sort_order(i))
+            .join(" ")
+    )
+}
+
 fn info_data_sort_order(info: &InfoData) -> u8 {
     match info {
         InfoData::Score(_) => 1,
-        InfoData::ScoreMate(_) => 2,
+        InfoData::Wdl(_) => 2,
         InfoData::Depth(_) => 3,
-        InfoData::Nodes(_) => 4,
-        InfoData::Pv(_) => 5,
-        InfoData::String(_) => 6,
+        InfoData::SelDepth(_) => 4,
+        InfoData::Nodes(_) => 5,
+        InfoData::HashFull(_) => 6,
+        InfoData::Pv(_) => 7,
+        InfoData::String(_) => 8,
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::Cursor;
+    use std::io::{Cursor, Read};
 
-    use crate::{board::Board, uci};
+    use crate::{
+        board::{outcome::Outcome, Board},
+        common::{Piece::*, Square::*},
+        uci,
+        utils::fen,
+    };
 
     use super::*;
 
@@ -458,6 +880,135 @@ mod tests {
         );
     }
 
+    // Sends the commands on a new connection and returns the output once the server closes it.
+    fn send_to_server(address: std::net::SocketAddr, commands: &str) -> String {
+        let mut stream = std::net::TcpStream::connect(address).unwrap();
+        stream.write_all(commands.as_bytes()).unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        let mut output = String::new();
+        stream.read_to_string(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_serve() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || uci::serve(&listener, Config::default(), 2));
+
+        // Each connection gets its own session, even when the client leaves without quitting.
+        for commands in ["uci\nisready\nquit\n", "isready\n"] {
+            let output = send_to_server(address, commands);
+            assert!(output.ends_with("readyok\n"), "{output}");
+        }
+
+        // Sessions run at the same time, with their own position.
+        let mut first = std::net::TcpStream::connect(address).unwrap();
+        first
+            .write_all(b"position startpos moves e2e4\nisready\n")
+            .unwrap();
+        let mut reader = BufReader::new(first.try_clone().unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "readyok\n");
+        let output = send_to_server(address, "d\n");
+        assert!(
+            output.contains(&format!("FEN: {}", fen::START_POSITION)),
+            "{output}"
+        );
+
+        // The first session is still running, so there is room for one more only.
+        let mut second = std::net::TcpStream::connect(address).unwrap();
+        second.write_all(b"isready\n").unwrap();
+        reader = BufReader::new(second.try_clone().unwrap());
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "readyok\n");
+        let output = send_to_server(address, "isready\n");
+        assert_eq!(output, "info string too many sessions, try again later\n");
+    }
+
+    #[test]
+    fn test_setoption_analyse_mode() {
+        let input = "setoption name UCI_AnalyseMode value true\nquit\n";
+        let mut game = Game::new();
+        let input = Cursor::new(input);
+        let output = Vec::new();
+        uci::run(
+            &mut game,
+            Arc::new(Mutex::new(input)),
+            Arc::new(Mutex::new(output)),
+        );
+
+        assert!(game.is_analyse_mode());
+    }
+
+    #[test]
+    fn test_setoption_show_wdl() {
+        let input = "setoption name UCI_ShowWDL value true\nquit\n";
+        let mut game = Game::new();
+        let input = Cursor::new(input);
+        let output = Vec::new();
+        uci::run(
+            &mut game,
+            Arc::new(Mutex::new(input)),
+            Arc::new(Mutex::new(output)),
+        );
+
+        assert!(game.is_show_wdl());
+    }
+
+    #[test]
+    fn test_setoption_evaluation() {
+        let input = "setoption name Evaluation value PST\nquit\n";
+        let mut game = Game::new();
+        uci::run(
+            &mut game,
+            Arc::new(Mutex::new(Cursor::new(input))),
+            Arc::new(Mutex::new(Vec::new())),
+        );
+
+        assert_eq!(game.get_config().eval.backend, EvalBackend::Pst);
+    }
+
+    #[test]
+    fn test_setoption_tunable() {
+        let input =
+            "setoption name ProbCutMargin value 300\nsetoption name RazorMaxDepth value 9\nquit\n";
+        let mut game = Game::new();
+        uci::run(
+            &mut game,
+            Arc::new(Mutex::new(Cursor::new(input))),
+            Arc::new(Mutex::new(Vec::new())),
+        );
+
+        // Only with the tune feature, and within range.
+        let config = game.get_config().search;
+        let expected = if cfg!(feature = "tune") { 300 } else { 200 };
+        assert_eq!(config.probcut_margin, expected);
+        assert_eq!(config.razor_max_depth, 3);
+    }
+
+    #[test]
+    fn test_setoption_config_file() {
+        let path = std::env::temp_dir().join("kaik_test_config.toml");
+        std::fs::write(&path, "[search]\nhash = 1\n[eval]\nknight = 300\n").unwrap();
+        let input = format!(
+            "setoption name Config File value {}\nquit\n",
+            path.display()
+        );
+        let mut game = Game::new();
+        uci::run(
+            &mut game,
+            Arc::new(Mutex::new(Cursor::new(input))),
+            Arc::new(Mutex::new(Vec::new())),
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(game.get_config().search.hash, 1);
+        assert_eq!(game.get_config().eval.knight, 300);
+    }
+
     #[test]
     fn test_position_moves() {
         let input = "position startpos moves e2e4 e7e5\nquit\n";
@@ -475,4 +1026,280 @@ mod tests {
             Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2")
         );
     }
+
+    #[test]
+    fn test_position_null_move() {
+        let input = "position startpos moves e2e4 0000 d2d4\nquit\n";
+        let mut game = Game::new();
+        uci::run(
+            &mut game,
+            Arc::new(Mutex::new(Cursor::new(input))),
+            Arc::new(Mutex::new(Vec::new())),
+        );
+
+        assert_eq!(
+            game.get_board(),
+            Board::from_fen("rnbqkbnr/pppppppp/8/8/3PP3/8/PPP2PPP/RNBQKBNR b KQkq - 0 2")
+        );
+    }
+
+    #[test]
+    fn test_position_illegal_move() {
+        let input = "position startpos moves e2e4 e7e5 e1e3 g8f6\nquit\n";
+        let mut game = Game::new();
+        let output = Arc::new(Mutex::new(Vec::new()));
+        uci::run(
+            &mut game,
+            Arc::new(Mutex::new(Cursor::new(input))),
+            Arc::clone(&output),
+        );
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            output,
+            "info string Illegal move e1e3: the piece doesn't move that way, ignoring the next moves\n"
+        );
+        assert_eq!(
+            game.get_board(),
+            Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2")
+        );
+    }
+
+    #[test]
+    fn test_position_invalid_fen() {
+        let input =
+            "position startpos moves e2e4\nposition fen P3k3/8/8/8/8/8/8/4K3 w - - 0 1\nquit\n";
+        let mut game = Game::new();
+        let output = Arc::new(Mutex::new(Vec::new()));
+        uci::run(
+            &mut game,
+            Arc::new(Mutex::new(Cursor::new(input))),
+            Arc::clone(&output),
+        );
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert!(output.starts_with("info string Invalid FEN P3k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert_eq!(
+            game.get_board(),
+            Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")
+        );
+    }
+
+    #[test]
+    fn test_end_of_input() {
+        // Without a quit command, like when piping commands to the engine.
+        let input = "position startpos\ngo infinite\n";
+        let mut game = Game::new();
+        let output = Arc::new(Mutex::new(Vec::new()));
+        uci::run(
+            &mut game,
+            Arc::new(Mutex::new(Cursor::new(input))),
+            Arc::clone(&output),
+        );
+
+        // The search is stopped, and its move still sent.
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        let best_move = output.lines().find(|line| line.starts_with("bestmove "));
+        assert!(
+            best_move.is_some_and(|line| line != "bestmove (none)"),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn test_go_infinite_until_stop() {
+        // Checkmated: The search is over at once, but the best move is only sent on stop.
+        let input =
+            "position fen k7/1Q6/1K6/8/8/8/8/8 b - - 0 1\ngo infinite\nisready\nstop\nquit\n";
+        let mut game = Game::new();
+        let output = Arc::new(Mutex::new(Vec::new()));
+        uci::run(
+            &mut game,
+            Arc::new(Mutex::new(Cursor::new(input))),
+            Arc::clone(&output),
+        );
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        let ready = output.find("readyok").unwrap();
+        let best_move = output.find("bestmove (none)").unwrap();
+        assert!(ready < best_move, "{output}");
+    }
+
+    #[test]
+    fn test_clear_hash_during_search() {
+        let input =
+            "position startpos\ngo infinite\nsetoption name Clear Hash\nstop\nisready\nquit\n";
+        let mut game = Game::new();
+        let output = Arc::new(Mutex::new(Vec::new()));
+        uci::run(
+            &mut game,
+            Arc::new(Mutex::new(Cursor::new(input))),
+            Arc::clone(&output),
+        );
+
+        // The search goes on with the cleared table, until the stop command.
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("readyok"), "{output}");
+        let best_move = output.lines().last().unwrap();
+        assert!(best_move.starts_with("bestmove "), "{output}");
+        assert_ne!(best_move, "bestmove (none)");
+    }
+
+    #[test]
+    fn test_unknown_commands() {
+        let input = "xyzzy\nisready\nregister later\nposition startpos moves e2e4\nxyzzy 42\nisready\nquit\n";
+        let mut game = Game::new();
+        let output = Arc::new(Mutex::new(Vec::new()));
+        uci::run(
+            &mut game,
+            Arc::new(Mutex::new(Cursor::new(input))),
+            Arc::clone(&output),
+        );
+
+        // Reported in order, and the session goes on.
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            output,
+            "info string Unknown command xyzzy\nreadyok\ninfo string Unknown command xyzzy 42\nreadyok\n"
+        );
+        assert_eq!(
+            game.get_board(),
+            Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")
+        );
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert!(matches!(
+            parse_command("isready"),
+            Ok(Some(UciCommand::IsReady))
+        ));
+        assert!(matches!(parse_command("  "), Ok(None)));
+        assert!(matches!(parse_command("ponderhit"), Ok(None)));
+        assert!(matches!(parse_command("register later"), Ok(None)));
+        // Unknown tokens are skipped.
+        assert!(matches!(
+            parse_command("joho debug on"),
+            Ok(Some(UciCommand::Debug(true)))
+        ));
+        assert!(matches!(
+            parse_command("go wtime -20 depth 3"),
+            Ok(Some(UciCommand::Go(cmds))) if matches!(cmds[..], [GoCommand::WTime(0), GoCommand::Depth(3)])
+        ));
+        assert!(matches!(
+            parse_command("go nodes 5000"),
+            Ok(Some(UciCommand::Go(cmds))) if matches!(cmds[..], [GoCommand::Nodes(5000)])
+        ));
+        for invalid in [
+            "debug",
+            "debug maybe",
+            "setoption Hash value 1",
+            "position",
+            "position fen 8/8/8 w",
+            "go depth",
+            "go movetime x",
+            "go nodes -1",
+            "edit put",
+            "xyzzy",
+            "copyprotection ok",
+        ] {
+            assert!(parse_command(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_edit() {
+        let input = "position startpos\nedit remove d1\nedit put d4 q\nedit castling Kk\nedit side b\nedit swap e2 e4\nquit\n";
+        let mut game = Game::new();
+        uci::run(
+            &mut game,
+            Arc::new(Mutex::new(Cursor::new(input))),
+            Arc::new(Mutex::new(Vec::new())),
+        );
+
+        assert_eq!(
+            game.get_board(),
+            Board::from_fen("rnbqkbnr/pppppppp/8/8/3q4/8/PPPPPPPP/RNB1KBNR b Kk - 0 1")
+        );
+    }
+
+    #[test]
+    fn test_d_flip() {
+        let input = "position fen 4k3/8/8/8/8/8/4P3/4K3 b - - 0 1\nd flip\nd attacks flip\nquit\n";
+        let mut game = Game::new();
+        let output = Arc::new(Mutex::new(Vec::new()));
+        uci::run(
+            &mut game,
+            Arc::new(Mutex::new(Cursor::new(input))),
+            Arc::clone(&output),
+        );
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert!(output.contains(
+            "  1  . . . K . . . .
+  2  . . . P . . . .
+  3  . . . . . . . .
+  4  . . . . . . . .
+  5  . . . . . . . .
+  6  . . . . . . . .
+  7  . . . . . . . .
+  8  . . . k . . . .
+ =>  h g f e d c b a
+"
+        ));
+        assert!(output.contains(
+            "  1  .  .  .+ K  .+ .  .  .
+  2  .  .  .+ P+ .+ .  .  .
+  3  .  .  .+ .  .+ .  .  .
+"
+        ));
+        assert!(output.contains(" =>  h  g  f  e  d  c  b  a\n"));
+    }
+
+    #[test]
+    fn test_position_game_over() {
+        let input = "position startpos moves f2f3 e7e5 g2g4 d8h4\nquit\n";
+        let mut game = Game::new();
+        let input = Cursor::new(input);
+        let output = Vec::new();
+        uci::run(
+            &mut game,
+            Arc::new(Mutex::new(input)),
+            Arc::new(Mutex::new(output)),
+        );
+        assert_eq!(game.get_outcome(), Some((Outcome::BlackWins, "checkmate")));
+
+        // Threefold repetition needs the moves history.
+        game.set_to_startpos();
+        assert_eq!(game.get_outcome(), None);
+        game.apply_moves(&["g1f3", "g8f6", "f3g1", "f6g8"].map(String::from));
+        assert_eq!(game.get_outcome(), None);
+        game.apply_moves(&["g1f3", "g8f6", "f3g1", "f6g8"].map(String::from));
+        assert_eq!(
+            game.get_outcome(),
+            Some((Outcome::Draw, "threefold repetition"))
+        );
+    }
+
+    #[test]
+    fn test_format_best_move() {
+        let e4 = Move::double_push(E2, E4, WhitePawn);
+        let e5 = Move::double_push(E7, E5, BlackPawn);
+        assert_eq!(
+            format_best_move(Some(e4), Some(e5), NoMoveOutput::Empty),
+            "bestmove e2e4 ponder e7e5"
+        );
+        assert_eq!(
+            format_best_move(Some(e4), None, NoMoveOutput::NullMove),
+            "bestmove e2e4"
+        );
+        assert_eq!(
+            format_best_move(None, None, NoMoveOutput::Empty),
+            "bestmove (none)"
+        );
+        assert_eq!(
+            format_best_move(None, None, NoMoveOutput::NullMove),
+            "bestmove 0000"
+        );
+    }
 }
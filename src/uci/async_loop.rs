@@ -0,0 +1,158 @@
+//! The UCI loop on Tokio, for servers embedding the engine in an async runtime: The commands are
+//! read and the responses written with async I/O, so a session doesn't need threads of its own
+//! besides the search. The commands are handled as in the threaded loop, which stays the default.
+//! Only built with the async feature: cargo r --release --features async -- async-uci
+
+use std::sync::{mpsc, Mutex};
+
+use tokio::{
+    io::{self, AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc::unbounded_channel,
+    task,
+};
+
+use crate::engine::game::{Event, Game};
+
+use super::{format_event, handle_command, parse_command, NoMoveOutput, UciCommand, UciEvent};
+
+// Stops the search when the session ends, including when its future is dropped before the end,
+// for example when the server cancels the task of a closed connection.
+struct SearchGuard<'a>(&'a mut Game);
+
+impl Drop for SearchGuard<'_> {
+    fn drop(&mut self) {
+        self.0.stop_search();
+        self.0.wait_for_search();
+    }
+}
+
+async fn write_event<W>(
+    writer: &mut W,
+    evt: UciEvent,
+    no_move_output: &Mutex<NoMoveOutput>,
+) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let no_move_output = *no_move_output.lock().unwrap();
+    let msg = format_event(evt, no_move_output);
+    info!("> {msg}");
+    writer.write_all(format!("{msg}\n").as_bytes()).await?;
+    writer.flush().await
+}
+
+// Runs the UCI protocol until the quit command or the end of the input.
+// The search runs on its own thread as in the threaded loop, its events are forwarded to the
+// session by a blocking task. The quit command waits for the search to end, blocking the task.
+pub async fn run<R, W>(game: &mut Game, reader: R, mut writer: W) -> io::Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let game = SearchGuard(game);
+    // The responses to the commands are sent before the command handler returns.
+    let (evt_sender, evt_receiver) = mpsc::channel();
+    let (game_event_sender, game_event_receiver) = mpsc::channel::<Event>();
+    let (search_event_sender, mut search_event_receiver) = unbounded_channel();
+    task::spawn_blocking(move || {
+        while let Ok(evt) = game_event_receiver.recv() {
+            let uci_event = match evt {
+                Event::BestMove(mv, ponder) => UciEvent::BestMove(mv, ponder),
+                Event::Info(info) => UciEvent::Info(info),
+            };
+            if search_event_sender.send(uci_event).is_err() {
+                break;
+            }
+        }
+    });
+    let no_move_output = Mutex::new(NoMoveOutput::default());
+
+    let mut lines = reader.lines();
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                // The end of the input is handled as a quit command, as in the threaded loop.
+                let cmd = match line? {
+                    Some(line) => {
+                        info!("< {}", line.trim());
+                        match parse_command(&line) {
+                            Ok(Some(cmd)) => cmd,
+                            Ok(None) => continue,
+                            Err(err) => UciCommand::Invalid(err),
+                        }
+                    }
+                    None => UciCommand::Quit,
+                };
+                let go_on = handle_command(game.0, cmd, &evt_sender, &game_event_sender, &no_move_output);
+                for evt in evt_receiver.try_iter() {
+                    write_event(&mut writer, evt, &no_move_output).await?;
+                }
+                if !go_on {
+                    break;
+                }
+            }
+            Some(evt) = search_event_receiver.recv() => {
+                write_event(&mut writer, evt, &no_move_output).await?;
+            }
+        }
+    }
+
+    // The search is over, its last events must be written before returning.
+    drop(game_event_sender);
+    while let Some(evt) = search_event_receiver.recv().await {
+        write_event(&mut writer, evt, &no_move_output).await?;
+    }
+    Ok(())
+}
+
+// The UCI loop on stdin and stdout, on a runtime of its own.
+pub fn run_stdio(game: &mut Game) -> io::Result<()> {
+    tokio::runtime::Builder::new_current_thread()
+        .build()?
+        .block_on(run(game, io::BufReader::new(io::stdin()), io::stdout()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::Board, utils::fen};
+
+    use super::*;
+
+    fn run_session(game: &mut Game, input: &str) -> String {
+        let mut output = Vec::new();
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(run(game, input.as_bytes(), &mut output))
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_session() {
+        let mut game = Game::new();
+        let output = run_session(
+            &mut game,
+            "isready\nxyzzy\nposition startpos moves e2e4\ngo depth 3\nquit\n",
+        );
+
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines[..2], ["readyok", "info string Unknown command xyzzy"]);
+        assert!(lines.last().unwrap().starts_with("bestmove "));
+        assert_eq!(
+            game.get_board(),
+            Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")
+        );
+    }
+
+    #[test]
+    fn test_end_of_input_stops_search() {
+        let mut game = Game::new();
+        let output = run_session(
+            &mut game,
+            &format!("position fen {}\ngo infinite\n", fen::KIWIPETE),
+        );
+        // Stopped by the end of the input, the best move is still sent.
+        assert!(output.lines().last().unwrap().starts_with("bestmove "));
+    }
+}
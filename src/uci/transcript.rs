@@ -0,0 +1,374 @@
+//! Transcripts of UCI sessions, to reproduce exactly the problems reported with GUIs.
+//! Recording wraps the reader and the writer of a session. Each line is written with the
+//! milliseconds since the start of the session and its direction, like in the log:
+//! "1520 < go movetime 1000" for a GUI command, "2521 > bestmove e2e4" for the engine output.
+//! Replaying sends the GUI commands of a transcript to the engine, at the same times,
+//! and compares the best moves with the recorded ones. Like the GUI did, a command is sent only
+//! once the engine gave the best moves recorded before it.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufRead, BufWriter, Read, Write},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::engine::game::Game;
+
+// How long to wait for a best move missing from the replay.
+const BEST_MOVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Transcript {
+    file: BufWriter<File>,
+    start: Instant,
+}
+
+impl Transcript {
+    pub fn create(path: &str) -> io::Result<Arc<Mutex<Self>>> {
+        Ok(Arc::new(Mutex::new(Self {
+            file: BufWriter::new(File::create(path)?),
+            start: Instant::now(),
+        })))
+    }
+
+    // Failing to record isn't a reason to stop the session, so errors are only logged.
+    fn record(&mut self, direction: char, line: &str) {
+        let millis = self.start.elapsed().as_millis();
+        if let Err(err) = writeln!(self.file, "{millis} {direction} {}", line.trim_end())
+            .and_then(|()| self.file.flush())
+        {
+            warn!("Could not write the transcript: {err}");
+        }
+    }
+}
+
+// Reader recording the lines read from the GUI.
+pub struct RecordingReader<R> {
+    inner: R,
+    transcript: Arc<Mutex<Transcript>>,
+}
+
+impl<R> RecordingReader<R> {
+    pub fn new(inner: R, transcript: &Arc<Mutex<Transcript>>) -> Self {
+        Self {
+            inner,
+            transcript: Arc::clone(transcript),
+        }
+    }
+}
+
+impl<R: Read> Read for RecordingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+// The UCI loop reads with read_line() only, so it's the only method recording.
+impl<R: BufRead> BufRead for RecordingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+    }
+
+    fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
+        let start = buf.len();
+        let count = self.inner.read_line(buf)?;
+        if count > 0 {
+            self.transcript.lock().unwrap().record('<', &buf[start..]);
+        }
+        Ok(count)
+    }
+}
+
+// Writer recording the lines sent to the GUI.
+pub struct RecordingWriter<W> {
+    inner: W,
+    transcript: Arc<Mutex<Transcript>>,
+    // Output not ended by a new line yet.
+    pending: Vec<u8>,
+}
+
+impl<W> RecordingWriter<W> {
+    pub fn new(inner: W, transcript: &Arc<Mutex<Transcript>>) -> Self {
+        Self {
+            inner,
+            transcript: Arc::clone(transcript),
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for RecordingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.pending.extend_from_slice(&buf[..count]);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            self.transcript
+                .lock()
+                .unwrap()
+                .record('>', &String::from_utf8_lossy(&line));
+        }
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// A line of a transcript.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub time: Duration,
+    pub from_gui: bool,
+    pub line: String,
+}
+
+pub fn parse(transcript: &str) -> Result<Vec<Entry>, String> {
+    transcript
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let invalid = || format!("Invalid transcript line: {line}");
+            let (millis, rest) = line.split_once(' ').ok_or_else(invalid)?;
+            let time = Duration::from_millis(millis.parse().map_err(|_| invalid())?);
+            let (direction, line) = rest.split_at_checked(1).ok_or_else(invalid)?;
+            let from_gui = match direction {
+                "<" => true,
+                ">" => false,
+                _ => return Err(invalid()),
+            };
+            Ok(Entry {
+                time,
+                from_gui,
+                line: line.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+fn is_best_move(entry: &Entry) -> bool {
+    !entry.from_gui && entry.line.starts_with("bestmove")
+}
+
+// Reader sending the GUI commands of a transcript, waiting for their time unless replaying fast.
+struct ReplayReader {
+    // The commands, with the number of best moves recorded before them.
+    commands: VecDeque<(Entry, usize)>,
+    total_best_moves: usize,
+    // Signaled for each best move of the replay.
+    best_moves: Receiver<()>,
+    best_moves_count: usize,
+    timed: bool,
+    start: Instant,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl ReplayReader {
+    fn wait_for_best_moves(&mut self, count: usize) {
+        while self.best_moves_count < count {
+            if self.best_moves.recv_timeout(BEST_MOVE_TIMEOUT).is_err() {
+                warn!("Best move missing in the replay");
+                return;
+            }
+            self.best_moves_count += 1;
+        }
+    }
+}
+
+impl Read for ReplayReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl BufRead for ReplayReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.position == self.buffer.len() {
+            if let Some((entry, best_moves_before)) = self.commands.pop_front() {
+                self.wait_for_best_moves(best_moves_before);
+                if self.timed {
+                    thread::sleep(entry.time.saturating_sub(self.start.elapsed()));
+                }
+                self.buffer = format!("{}\n", entry.line).into_bytes();
+                self.position = 0;
+            } else {
+                // The end of the input stops the engine.
+                self.wait_for_best_moves(self.total_best_moves);
+            }
+        }
+        Ok(&self.buffer[self.position..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position += amt;
+    }
+}
+
+// Writer passing the engine output through, keeping the best moves.
+struct ReplayWriter<W> {
+    inner: W,
+    pending: Vec<u8>,
+    best_moves: Vec<String>,
+    best_move_sender: Sender<()>,
+}
+
+impl<W: Write> Write for ReplayWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.pending.extend_from_slice(&buf[..count]);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if line.starts_with("bestmove") {
+                self.best_moves.push(line.trim().to_string());
+                // The reader is gone once the input is over.
+                let _ = self.best_move_sender.send(());
+            }
+        }
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Best move lines of the recording and of the replay, None when one has less of them.
+pub type Mismatch = (Option<String>, Option<String>);
+
+// Replays the GUI commands of the transcript on the game, writing the engine output to the
+// writer. Returns the best moves that differ from the recorded ones.
+pub fn replay<W>(game: &mut Game, entries: &[Entry], timed: bool, writer: W) -> Vec<Mismatch>
+where
+    W: Write + Send + 'static,
+{
+    let mut commands = VecDeque::new();
+    let mut recorded = Vec::new();
+    for entry in entries {
+        if entry.from_gui {
+            commands.push_back((entry.clone(), recorded.len()));
+        } else if is_best_move(entry) {
+            recorded.push(entry.line.clone());
+        }
+    }
+    let (best_move_sender, best_moves) = mpsc::channel();
+    let reader = ReplayReader {
+        commands,
+        total_best_moves: recorded.len(),
+        best_moves,
+        best_moves_count: 0,
+        timed,
+        start: Instant::now(),
+        buffer: Vec::new(),
+        position: 0,
+    };
+    let writer = Arc::new(Mutex::new(ReplayWriter {
+        inner: writer,
+        pending: Vec::new(),
+        best_moves: Vec::new(),
+        best_move_sender,
+    }));
+    super::run(game, Arc::new(Mutex::new(reader)), Arc::clone(&writer));
+
+    let replayed = std::mem::take(&mut writer.lock().unwrap().best_moves);
+    (0..recorded.len().max(replayed.len()))
+        .map(|i| (recorded.get(i).cloned(), replayed.get(i).cloned()))
+        .filter(|(recorded, replayed)| recorded != replayed)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let entries = parse("0 < uci\n3 > id name Kaik\n\n1520 < go movetime 1000\n").unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[2],
+            Entry {
+                time: Duration::from_millis(1520),
+                from_gui: true,
+                line: "go movetime 1000".to_string(),
+            }
+        );
+        assert!(!entries[1].from_gui);
+        assert!(parse("12 uci").is_err());
+        assert!(parse("x < uci").is_err());
+    }
+
+    // Input waiting before its end, as the end of the input stops the search.
+    struct SlowEndReader(Cursor<&'static str>);
+
+    impl Read for SlowEndReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl BufRead for SlowEndReader {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            if self.0.position() == self.0.get_ref().len() as u64 {
+                thread::sleep(Duration::from_secs(1));
+            }
+            self.0.fill_buf()
+        }
+
+        fn consume(&mut self, amt: usize) {
+            self.0.consume(amt);
+        }
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join("kaik_test_transcript.txt");
+        let path = path.to_str().unwrap();
+        let transcript = Transcript::create(path).unwrap();
+        let input = SlowEndReader(Cursor::new(
+            "isready\nposition startpos moves e2e4\ngo depth 1\n",
+        ));
+        super::super::run(
+            &mut Game::new(),
+            Arc::new(Mutex::new(RecordingReader::new(input, &transcript))),
+            Arc::new(Mutex::new(RecordingWriter::new(Vec::new(), &transcript))),
+        );
+        drop(transcript);
+
+        let entries = parse(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert!(entries.iter().any(|e| e.from_gui && e.line == "go depth 1"));
+        assert!(entries.iter().any(|e| !e.from_gui && e.line == "readyok"));
+        assert!(entries.iter().any(is_best_move));
+
+        assert_eq!(
+            replay(&mut Game::new(), &entries, false, Vec::new()),
+            vec![]
+        );
+        // Without the search, the best move is missing.
+        let without_go = entries
+            .iter()
+            .filter(|e| !e.line.starts_with("go"))
+            .cloned()
+            .collect::<Vec<_>>();
+        let mismatches = replay(&mut Game::new(), &without_go, false, Vec::new());
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].1, None);
+    }
+}
@@ -1 +1,6 @@
+pub mod chess960;
+pub mod epd;
 pub mod fen;
+pub mod pgn;
+pub mod random_game;
+pub mod uci_engine;
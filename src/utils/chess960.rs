@@ -0,0 +1,196 @@
+//! Chess960 (Fischer Random Chess) starting positions.
+//! Without support for Chess960 castling, most of them have no castling rights, so they are only
+//! the openings of standard chess games, not Chess960 games.
+//! Positions are identified by their number from 0 to 959, in Scharnagl numbering.
+//! Doc: <https://www.chessprogramming.org/Chess960>
+
+use itertools::Itertools;
+use rand::Rng;
+
+use crate::common::{Color, Piece, PieceListBoard};
+use crate::utils::fen;
+
+pub const POSITIONS_COUNT: usize = 960;
+
+// Number of the standard chess starting position.
+pub const STANDARD_POSITION_ID: usize = 518;
+
+// Knights placement on the 5 squares left after the bishops and queen are placed.
+const KNIGHTS_TABLE: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+// Places the piece on the n-th empty square of the rank.
+fn place_on_empty(rank: &mut [Option<Piece>; 8], n: usize, piece: Piece) {
+    let file = rank.iter().positions(Option::is_none).nth(n).unwrap();
+    rank[file] = Some(piece);
+}
+
+// Returns the back rank (from file A to H) of the position, as white pieces.
+// <https://www.chessprogramming.org/Reinhard_Scharnagl#Chess960_Enumeration>
+pub fn back_rank(id: usize) -> [Piece; 8] {
+    assert!(id < POSITIONS_COUNT);
+    let mut rank: [Option<Piece>; 8] = [None; 8];
+
+    let mut n = id;
+    rank[2 * (n % 4) + 1] = Some(Piece::WhiteBishop); // light squared bishop
+    n /= 4;
+    rank[2 * (n % 4)] = Some(Piece::WhiteBishop); // dark squared bishop
+    n /= 4;
+    place_on_empty(&mut rank, n % 6, Piece::WhiteQueen);
+    n /= 6;
+    let (knight_1, knight_2) = KNIGHTS_TABLE[n];
+    // Placing the second knight first, so that the index of the first one isn't affected.
+    place_on_empty(&mut rank, knight_2, Piece::WhiteKnight);
+    place_on_empty(&mut rank, knight_1, Piece::WhiteKnight);
+    // The king is always between the rooks.
+    place_on_empty(&mut rank, 0, Piece::WhiteRook);
+    place_on_empty(&mut rank, 0, Piece::WhiteKing);
+    place_on_empty(&mut rank, 0, Piece::WhiteRook);
+
+    rank.map(Option::unwrap)
+}
+
+fn piece_of(piece: Piece, color: Color) -> Piece {
+    match piece {
+        Piece::WhiteKnight => Piece::get_knight_of(color),
+        Piece::WhiteBishop => Piece::get_bishop_of(color),
+        Piece::WhiteRook => Piece::get_rook_of(color),
+        Piece::WhiteQueen => Piece::get_queen_of(color),
+        Piece::WhiteKing => Piece::get_king_of(color),
+        _ => panic!("Invalid back rank piece"),
+    }
+}
+
+// Castling rights, only where castling is the same as in standard chess, with the king on the
+// e-file and the rook in the corner, as the board doesn't support Chess960 castling yet.
+fn castling_ability(white_rank: [Piece; 8], black_rank: [Piece; 8]) -> Vec<Piece> {
+    [(white_rank, Color::White), (black_rank, Color::Black)]
+        .into_iter()
+        .filter(|(rank, _)| rank[4].is_king())
+        .flat_map(|(rank, color)| {
+            [
+                (7, Piece::get_king_of(color)),
+                (0, Piece::get_queen_of(color)),
+            ]
+            .into_iter()
+            .filter(move |(file, _)| rank[*file].is_rook())
+            .map(|(_, side)| side)
+        })
+        .collect()
+}
+
+// Creates the FEN of a Double Fischer Random Chess position, where each side has its own setup.
+// With same ids, it's a regular Chess960 position.
+pub fn create_fen(white_id: usize, black_id: usize) -> String {
+    let white_rank = back_rank(white_id);
+    let black_rank = back_rank(black_id);
+
+    let mut piece_placement: PieceListBoard = Vec::with_capacity(64);
+    piece_placement.extend(black_rank.iter().map(|p| Some(piece_of(*p, Color::Black))));
+    piece_placement.extend([Some(Piece::BlackPawn); 8]);
+    piece_placement.extend([None; 32]);
+    piece_placement.extend([Some(Piece::WhitePawn); 8]);
+    piece_placement.extend(white_rank.iter().map(|p| Some(*p)));
+
+    fen::create(
+        &piece_placement,
+        Color::White,
+        &castling_ability(white_rank, black_rank),
+        None,
+        0,
+        1,
+    )
+}
+
+// Creates a random Chess960 position, or a Double Fischer Random Chess one.
+pub fn random_fen<R: Rng>(rng: &mut R, double: bool) -> String {
+    let white_id = rng.gen_range(0..POSITIONS_COUNT);
+    let black_id = if double {
+        rng.gen_range(0..POSITIONS_COUNT)
+    } else {
+        white_id
+    };
+    create_fen(white_id, black_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::board::Board;
+
+    fn back_rank_as_string(id: usize) -> String {
+        back_rank(id).iter().map(|p| char::from(*p)).collect()
+    }
+
+    #[test]
+    fn test_back_rank() {
+        assert_eq!(back_rank_as_string(0), "BBQNNRKR");
+        assert_eq!(back_rank_as_string(STANDARD_POSITION_ID), "RNBQKBNR");
+        assert_eq!(back_rank_as_string(959), "RKRNNQBB");
+    }
+
+    #[test]
+    fn test_back_rank_rules() {
+        for id in 0..POSITIONS_COUNT {
+            let rank = back_rank(id);
+            let bishops = rank.iter().positions(|p| p.is_bishop()).collect_vec();
+            assert_eq!(bishops.len(), 2);
+            assert_ne!(bishops[0] % 2, bishops[1] % 2);
+            let rooks = rank.iter().positions(|p| p.is_rook()).collect_vec();
+            let king = rank.iter().position(|p| p.is_king()).unwrap();
+            assert!(rooks[0] < king && king < rooks[1]);
+        }
+        // All positions are different.
+        assert_eq!(
+            (0..POSITIONS_COUNT).map(back_rank).unique().count(),
+            POSITIONS_COUNT
+        );
+    }
+
+    #[test]
+    fn test_create_fen() {
+        assert_eq!(
+            create_fen(STANDARD_POSITION_ID, STANDARD_POSITION_ID),
+            fen::START_POSITION
+        );
+        assert_eq!(
+            create_fen(0, 959),
+            "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w - - 0 1"
+        );
+    }
+
+    #[test]
+    fn test_castling_ability() {
+        // Only the castling of standard chess: The king on e1 with the rook on h1.
+        let id = (0..POSITIONS_COUNT)
+            .find(|&id| back_rank_as_string(id) == "BBNRKNQR")
+            .unwrap();
+        let fen = create_fen(id, STANDARD_POSITION_ID);
+        assert_eq!(
+            fen,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/BBNRKNQR w Kkq - 0 1"
+        );
+        assert_eq!(Board::from_fen(&fen).as_fen(), fen);
+    }
+
+    #[test]
+    fn test_random_fen() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let fen = random_fen(&mut rng, false);
+        // Black mirrors White.
+        let ranks = fen.split(['/', ' ']).collect_vec();
+        assert_eq!(ranks[0].to_ascii_uppercase(), ranks[7]);
+    }
+}
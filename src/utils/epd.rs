@@ -0,0 +1,300 @@
+//! Parsing and creation of EPD records, as used by test suites and tuning data sets.
+//! A record is a FEN without the move clocks, followed by operations, each an opcode with its
+//! operands and ending with a semicolon: `... w - - bm Nf3; id "WAC.001";`
+//! The clocks of a FEN are kept in the hmvc and fmvn operations, unless they are the defaults.
+//! Doc: <https://www.chessprogramming.org/Extended_Position_Description>
+
+use itertools::Itertools;
+
+use crate::common::Score;
+
+// Opcodes whose operands are strings, always written quoted.
+const STRING_OPCODES: [&str; 11] = [
+    "id", "c0", "c1", "c2", "c3", "c4", "c5", "c6", "c7", "c8", "c9",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Epd {
+    // The first four fields of the FEN: Pieces, side to move, castling and en passant.
+    pub position: String,
+    // Opcodes with their operands, in the order of the record. Quotes are removed.
+    pub operations: Vec<(String, Vec<String>)>,
+}
+
+impl Epd {
+    // Converts a FEN, the clocks becoming the hmvc and fmvn operations.
+    pub fn from_fen(fen: &str) -> Result<Self, String> {
+        let fields = fen.split_whitespace().collect_vec();
+        if fields.len() != 6 {
+            return Err(format!("Invalid FEN {fen}"));
+        }
+        let mut epd = Self {
+            position: fields[..4].join(" "),
+            operations: Vec::new(),
+        };
+        if fields[4] != "0" {
+            epd.set_operation("hmvc", &[fields[4]]);
+        }
+        if fields[5] != "1" {
+            epd.set_operation("fmvn", &[fields[5]]);
+        }
+        Ok(epd)
+    }
+
+    // The FEN of the position, with the clocks of the hmvc and fmvn operations if any.
+    pub fn to_fen(&self) -> String {
+        let clock = |opcode, default| {
+            self.operation(opcode)
+                .and_then(<[String]>::first)
+                .map_or(default, String::as_str)
+        };
+        format!(
+            "{} {} {}",
+            self.position,
+            clock("hmvc", "0"),
+            clock("fmvn", "1")
+        )
+    }
+
+    pub fn operation(&self, opcode: &str) -> Option<&[String]> {
+        self.operations
+            .iter()
+            .find(|(op, _)| op == opcode)
+            .map(|(_, operands)| operands.as_slice())
+    }
+
+    // Replaces the operands of the opcode, or adds the operation at the end.
+    pub fn set_operation(&mut self, opcode: &str, operands: &[&str]) {
+        let operands = operands.iter().map(ToString::to_string).collect();
+        if let Some((_, ops)) = self.operations.iter_mut().find(|(op, _)| op == opcode) {
+            *ops = operands;
+        } else {
+            self.operations.push((opcode.to_string(), operands));
+        }
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.operation("id")?.first().map(String::as_str)
+    }
+
+    // Best moves, in SAN.
+    pub fn best_moves(&self) -> &[String] {
+        self.operation("bm").unwrap_or_default()
+    }
+
+    // Moves to avoid, in SAN.
+    pub fn avoid_moves(&self) -> &[String] {
+        self.operation("am").unwrap_or_default()
+    }
+
+    // Centipawn evaluation, from the side to move point of view.
+    pub fn centipawn_eval(&self) -> Option<Score> {
+        self.operation("ce")?.first()?.parse().ok()
+    }
+}
+
+// Splits the operations into tokens: Quoted strings are single tokens, and semicolons are
+// tokens of their own. Returns None if a quote isn't closed.
+fn tokenize(operations: &str) -> Option<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut chars = operations.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            ';' => {
+                chars.next();
+                tokens.push(";".to_string());
+            }
+            '"' => {
+                chars.next();
+                let mut token = String::new();
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        c => token.push(c),
+                    }
+                }
+                // Marks the token as a string, so that it's never taken for an opcode.
+                tokens.push(format!("\"{token}"));
+            }
+            _ => {
+                let mut token = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' || c == '"' {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                tokens.push(token);
+            }
+        }
+    }
+    Some(tokens)
+}
+
+// Parses an EPD record. Records with move clocks, like a FEN, are accepted too.
+pub fn parse(record: &str) -> Result<Epd, String> {
+    let invalid = || format!("Invalid EPD {record}");
+    let mut fields = record.trim().splitn(5, char::is_whitespace);
+    let position = fields.by_ref().take(4).collect_vec();
+    if position.len() != 4 || position[0].split('/').count() != 8 {
+        return Err(invalid());
+    }
+    let mut epd = Epd {
+        position: position.join(" "),
+        operations: Vec::new(),
+    };
+
+    let mut tokens = tokenize(fields.next().unwrap_or_default())
+        .ok_or_else(invalid)?
+        .into_iter()
+        .peekable();
+    // FEN clocks, when the record is a full FEN.
+    let is_number = |token: Option<&String>| token.is_some_and(|t| t.parse::<usize>().is_ok());
+    if is_number(tokens.peek()) {
+        let half_move = tokens.next().unwrap();
+        let full_move = tokens.next_if(|t| is_number(Some(t))).ok_or_else(invalid)?;
+        epd = Epd::from_fen(&format!("{} {half_move} {full_move}", epd.position))?;
+    }
+
+    while let Some(opcode) = tokens.next() {
+        if opcode == ";" || opcode.starts_with('"') {
+            return Err(invalid());
+        }
+        let mut operands = Vec::new();
+        loop {
+            match tokens.next() {
+                Some(token) if token == ";" => break,
+                Some(token) => operands.push(token.trim_start_matches('"').to_string()),
+                None => return Err(invalid()),
+            }
+        }
+        epd.operations.push((opcode, operands));
+    }
+    Ok(epd)
+}
+
+fn create_operand(opcode: &str, operand: &str) -> String {
+    if STRING_OPCODES.contains(&opcode)
+        || operand.is_empty()
+        || operand.contains(|c: char| c.is_whitespace() || c == ';')
+    {
+        format!("\"{operand}\"")
+    } else {
+        operand.to_string()
+    }
+}
+
+// Creates the EPD record.
+pub fn create(epd: &Epd) -> String {
+    let mut record = epd.position.clone();
+    for (opcode, operands) in &epd.operations {
+        record.push(' ');
+        record.push_str(opcode);
+        for operand in operands {
+            record.push(' ');
+            record.push_str(&create_operand(opcode, operand));
+        }
+        record.push(';');
+    }
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fen;
+
+    #[test]
+    fn test_parse() {
+        let epd = parse(
+            r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";"#,
+        )
+        .unwrap();
+        assert_eq!(
+            epd.position,
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - -"
+        );
+        assert_eq!(epd.best_moves(), ["Qg6"]);
+        assert!(epd.avoid_moves().is_empty());
+        assert_eq!(epd.id(), Some("WAC.001"));
+        assert_eq!(
+            epd.to_fen(),
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+
+        let epd = parse(r#"8/8/8/8/8/8/8/K1k5 b - - am Kb2 Kb1; ce -25; c0 "a; b";"#).unwrap();
+        assert_eq!(epd.avoid_moves(), ["Kb2", "Kb1"]);
+        assert_eq!(epd.centipawn_eval(), Some(-25));
+        assert_eq!(epd.operation("c0"), Some(&["a; b".to_string()][..]));
+
+        // No operations.
+        let epd = parse("8/8/8/8/8/8/8/K1k5 b - -").unwrap();
+        assert!(epd.operations.is_empty());
+    }
+
+    #[test]
+    fn test_parse_with_clocks() {
+        let epd = parse(&format!("{} bm e4;", fen::POSITION_5)).unwrap();
+        assert_eq!(epd.to_fen(), fen::POSITION_5);
+        assert_eq!(epd.best_moves(), ["e4"]);
+        assert_eq!(epd.operation("hmvc"), Some(&["1".to_string()][..]));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for invalid in [
+            "",
+            "8/8/8 w - -",
+            "8/8/8/8/8/8/8/K1k5 b -",
+            "8/8/8/8/8/8/8/K1k5 b - - bm Kb2",
+            r#"8/8/8/8/8/8/8/K1k5 b - - id "unclosed;"#,
+            r#"8/8/8/8/8/8/8/K1k5 b - - "id" x;"#,
+            "8/8/8/8/8/8/8/K1k5 b - - 3 bm Kb2;",
+        ] {
+            assert!(parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_create() {
+        let mut epd = Epd::from_fen(fen::START_POSITION).unwrap();
+        assert_eq!(
+            create(&epd),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -"
+        );
+        epd.set_operation("bm", &["e4", "d4"]);
+        epd.set_operation("id", &["start"]);
+        epd.set_operation("c0", &["two words"]);
+        assert_eq!(
+            create(&epd),
+            r#"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e4 d4; id "start"; c0 "two words";"#
+        );
+        epd.set_operation("bm", &["Nf3"]);
+        assert_eq!(epd.best_moves(), ["Nf3"]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for fen in [
+            fen::START_POSITION,
+            fen::KIWIPETE,
+            fen::POSITION_5,
+            fen::POSITION_6,
+        ] {
+            let epd = Epd::from_fen(fen).unwrap();
+            assert_eq!(epd.to_fen(), fen);
+            assert_eq!(parse(&create(&epd)).unwrap(), epd);
+        }
+
+        for record in [
+            r#"r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - bm Nf5; id "test";"#,
+            "8/8/8/8/8/8/8/K1k5 b - - am Kb2 Kb1; ce -25;",
+        ] {
+            assert_eq!(create(&parse(record).unwrap()), record);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 //! Parsing and creation of FEN strings.
-//! Only valid FEN strings are supported. Invalid will cause the code to assert.
+//! Invalid FEN strings are reported as errors.
 //! Doc: <https://www.chessprogramming.org/Forsyth-Edwards_Notation>
 
 use itertools::Itertools;
@@ -25,6 +25,19 @@ pub const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w K
 pub const POSITION_6: &str =
     "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
 
+// Short names for the positions above, to avoid typing them on the command line.
+pub fn named_position(name: &str) -> Option<&'static str> {
+    match name {
+        "startpos" => Some(START_POSITION),
+        "kiwipete" | "pos2" => Some(KIWIPETE),
+        "pos3" => Some(POSITION_3),
+        "pos4" => Some(POSITION_4),
+        "pos5" => Some(POSITION_5),
+        "pos6" => Some(POSITION_6),
+        _ => None,
+    }
+}
+
 fn create_rank(rank: &[Option<Piece>]) -> String {
     assert_eq!(rank.len(), 8);
     let mut result = String::with_capacity(8);
@@ -64,14 +77,13 @@ fn get_castling_ability(castling_ability: &[Piece]) -> String {
     }
 
     assert!(castling_ability.len() <= 4);
-    assert!([
+    assert!(castling_ability.iter().all(|piece| [
         Piece::WhiteKing,
         Piece::WhiteQueen,
         Piece::BlackKing,
         Piece::BlackQueen
     ]
-    .iter()
-    .all(|piece| castling_ability.contains(piece)));
+    .contains(piece)));
 
     castling_ability
         .iter()
@@ -116,91 +128,114 @@ pub fn create(
     )
 }
 
-fn parse_piece_placement(s: &str) -> PieceListBoard {
-    let pieces = s
-        .split('/')
-        .flat_map(|rank| {
-            rank.chars().flat_map(|c| {
-                if let Some(d) = c.to_digit(10) {
-                    assert!((1..=8).contains(&d));
-                    vec![None; d as usize]
-                } else {
-                    vec![c.try_into().ok()]
-                }
-            })
-        })
-        .collect_vec();
-    assert_eq!(pieces.len(), 64);
-    pieces
+// Piece placement, side to move, castling ability, en passant target square, half move clock
+// and full move counter.
+pub type Fields = (
+    PieceListBoard,
+    Color,
+    Vec<Piece>,
+    Option<Square>,
+    usize,
+    usize,
+);
+
+fn parse_piece_placement(s: &str) -> Result<PieceListBoard, String> {
+    let ranks = s.split('/').collect_vec();
+    if ranks.len() != 8 {
+        return Err(format!("Invalid piece placement {s}, it needs 8 ranks"));
+    }
+    let mut pieces = Vec::with_capacity(64);
+    for rank in ranks {
+        let start = pieces.len();
+        for c in rank.chars() {
+            match c {
+                '1'..='8' => pieces.extend([None].repeat(c as usize - '0' as usize)),
+                _ => pieces.push(Some(
+                    Piece::try_from(c).map_err(|_| format!("Invalid piece {c}"))?,
+                )),
+            }
+            if pieces.len() > start + 8 {
+                return Err(format!(
+                    "Invalid piece placement {s}, rank {rank} is too long"
+                ));
+            }
+        }
+        if pieces.len() != start + 8 {
+            return Err(format!(
+                "Invalid piece placement {s}, rank {rank} is too short"
+            ));
+        }
+    }
+    Ok(pieces)
 }
 
-fn parse_side_to_move(s: &str) -> Color {
+fn parse_side_to_move(s: &str) -> Result<Color, String> {
     match s {
-        "w" => Color::White,
-        "b" => Color::Black,
-        _ => panic!("Invalid side to move"),
+        "w" => Ok(Color::White),
+        "b" => Ok(Color::Black),
+        _ => Err(format!("Invalid side to move {s}")),
     }
 }
 
-fn parse_castling_ability(s: &str) -> Vec<Piece> {
+// Each right at most once, in any order.
+fn parse_castling_ability(s: &str) -> Result<Vec<Piece>, String> {
     if s == "-" {
-        Vec::new()
-    } else {
-        s.chars().map(|c| c.try_into().unwrap()).collect()
+        return Ok(Vec::new());
     }
+    let rights: Vec<Piece> = s
+        .chars()
+        .map(|c| match c {
+            'K' | 'Q' | 'k' | 'q' => Ok(Piece::try_from(c).unwrap()),
+            _ => Err(format!("Invalid castling ability {s}")),
+        })
+        .collect::<Result<_, _>>()?;
+    if !rights.iter().all_unique() {
+        return Err(format!("Invalid castling ability {s}"));
+    }
+    Ok(rights)
 }
 
-fn parse_en_passant_target_square(s: &str) -> Option<Square> {
+fn parse_en_passant_target_square(s: &str) -> Result<Option<Square>, String> {
     if s == "-" {
-        None
+        Ok(None)
     } else {
-        s.try_into().ok()
+        s.try_into()
+            .map(Some)
+            .map_err(|_| format!("Invalid en passant square {s}"))
     }
 }
 
-fn parse_half_move_clock(s: &str) -> usize {
-    s.parse().unwrap()
+fn parse_half_move_clock(s: &str) -> Result<usize, String> {
+    s.parse()
+        .map_err(|_| format!("Invalid half move clock {s}"))
 }
 
-fn parse_full_move_counter(s: &str) -> usize {
-    s.parse().unwrap()
+fn parse_full_move_counter(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(counter) if counter > 0 => Ok(counter),
+        _ => Err(format!("Invalid full move counter {s}")),
+    }
 }
 
-// Parses a FEN string.
-pub fn parse(
-    fen: &str,
-) -> (
-    PieceListBoard,
-    Color,
-    Vec<Piece>,
-    Option<Square>,
-    usize,
-    usize,
-) {
+// Parses a FEN string. Only the syntax is checked, not if the position is possible.
+pub fn parse(fen: &str) -> Result<Fields, String> {
     let parts = fen.split_ascii_whitespace().collect_vec();
-    assert_eq!(parts.len(), 6);
-    (
-        parse_piece_placement(parts[0]),
-        parse_side_to_move(parts[1]),
-        parse_castling_ability(parts[2]),
-        parse_en_passant_target_square(parts[3]),
-        parse_half_move_clock(parts[4]),
-        parse_full_move_counter(parts[5]),
-    )
+    if parts.len() != 6 {
+        return Err(format!("Invalid FEN {fen}, it needs 6 fields"));
+    }
+    Ok((
+        parse_piece_placement(parts[0])?,
+        parse_side_to_move(parts[1])?,
+        parse_castling_ability(parts[2])?,
+        parse_en_passant_target_square(parts[3])?,
+        parse_half_move_clock(parts[4])?,
+        parse_full_move_counter(parts[5])?,
+    ))
 }
 
 // Parses only a list of pieces, populating the rest with sensible defaults.
 // For writing tests mainly.
-pub fn parse_pieces(
-    pieces: &str,
-) -> (
-    PieceListBoard,
-    Color,
-    Vec<Piece>,
-    Option<Square>,
-    usize,
-    usize,
-) {
+pub fn parse_pieces(pieces: &str) -> Result<Fields, String> {
     parse(&format!("{pieces}  w KQkq - 0 1"))
 }
 
@@ -211,6 +246,15 @@ mod tests {
     use crate::common::Piece::*;
     use crate::common::Square;
 
+    #[test]
+    fn test_named_position() {
+        assert_eq!(named_position("startpos"), Some(START_POSITION));
+        assert_eq!(named_position("kiwipete"), named_position("pos2"));
+        assert_eq!(named_position("pos6"), Some(POSITION_6));
+        assert_eq!(named_position("pos7"), None);
+        assert_eq!(named_position(EMPTY_BOARD), None);
+    }
+
     #[test]
     fn test_create_rank() {
         let rank = [
@@ -281,10 +325,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_create_partial_castling() {
+        let piece_placement = Piece::build_list_board(
+            "r...k... ........ ........ ........ ........ ........ ........ ....K..R",
+        );
+        let fen = create(
+            &piece_placement,
+            Color::Black,
+            &[WhiteKing, BlackQueen],
+            None,
+            3,
+            20,
+        );
+        assert_eq!(fen, "r3k3/8/8/8/8/8/8/4K2R b Kq - 3 20");
+    }
+
     #[test]
     fn test_parse_starting_position() {
         let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-        let (pieces, side, castling, en_passant, half_move, full_move) = parse(fen);
+        let (pieces, side, castling, en_passant, half_move, full_move) = parse(fen).unwrap();
 
         assert_eq!(pieces.len(), 64);
         assert_eq!(
@@ -307,7 +367,7 @@ mod tests {
     #[test]
     fn test_parse_middle_game_position() {
         let fen = "r1bqkbnr/pppppppp/2n5/8/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq e3 0 3";
-        let (pieces, side, castling, en_passant, half_move, full_move) = parse(fen);
+        let (pieces, side, castling, en_passant, half_move, full_move) = parse(fen).unwrap();
 
         assert_eq!(pieces.len(), 64);
         assert_eq!(
@@ -330,10 +390,10 @@ mod tests {
     #[test]
     fn test_parse_end_game_position() {
         let fen = EMPTY_BOARD;
-        let (pieces, side, castling, en_passant, half_move, full_move) = parse(fen);
+        let (pieces, side, castling, en_passant, half_move, full_move) = parse(fen).unwrap();
 
         assert_eq!(pieces.len(), 64);
-        assert!(pieces.iter().all(|p| p.is_none()));
+        assert!(pieces.iter().all(Option::is_none));
         assert_eq!(side, Color::White);
         assert_eq!(castling.len(), 0);
         assert_eq!(en_passant, None);
@@ -343,8 +403,23 @@ mod tests {
 
     #[test]
     fn test_parse_invalid_fen() {
-        let fen = "invalid fen string";
-        let result = std::panic::catch_unwind(|| parse(fen));
-        assert!(result.is_err());
+        for fen in [
+            "invalid fen string",
+            "",
+            "8/8/8/8/8/8/8 w - - 0 1",
+            "8/8/8/8/8/8/8/9 w - - 0 1",
+            "8/8/8/8/8/8/8/7 w - - 0 1",
+            "8/8/8/8/8/8/8/71k w - - 0 1",
+            "8/8/8/8/8/8/8/7x w - - 0 1",
+            "8/8/8/8/8/8/8/8 x - - 0 1",
+            "8/8/8/8/8/8/8/8 w KK - 0 1",
+            "8/8/8/8/8/8/8/8 w A - 0 1",
+            "8/8/8/8/8/8/8/8 w - e9 0 1",
+            "8/8/8/8/8/8/8/8 w - - -1 1",
+            "8/8/8/8/8/8/8/8 w - - 0 0",
+            "8/8/8/8/8/8/8/8 w - - 0 1 extra",
+        ] {
+            assert!(parse(fen).is_err(), "{fen}");
+        }
     }
 }
@@ -0,0 +1,455 @@
+//! Parsing and creation of PGN games.
+//! Only the main line is kept: Variations and numeric annotation glyphs are skipped.
+//! Created games start with the seven tag roster, and their comments can hold the %eval and
+//! %clk annotations that Lichess and most chess databases import.
+//! Doc: <https://www.chessprogramming.org/Portable_Game_Notation>
+//! Annotations: <https://www.enpassant.dk/chess/palview/enhancedpgn.htm>
+
+use std::{
+    fmt::Write,
+    time::{Duration, SystemTime},
+};
+
+use itertools::Itertools;
+
+use crate::common::{Color, Score};
+use crate::utils::fen;
+
+// Maximum length of the movetext lines when creating a PGN.
+const LINE_LENGTH: usize = 80;
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+// Evaluation of a position, as given by an engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eval {
+    Centipawns(Score),
+    // Mate in that number of moves, negative when getting mated.
+    Mate(i32),
+}
+
+impl Eval {
+    // Converts an evaluation from the point of view of the side to move to White's one.
+    pub fn for_white(self, side_to_move: Color) -> Self {
+        match (self, side_to_move) {
+            (eval, Color::White) => eval,
+            (Eval::Centipawns(score), Color::Black) => Eval::Centipawns(-score),
+            (Eval::Mate(moves), Color::Black) => Eval::Mate(-moves),
+        }
+    }
+
+    // The %eval annotation, in pawns from White's point of view, like "[%eval -0.35]".
+    pub fn annotation(self) -> String {
+        match self {
+            Eval::Centipawns(score) => format!("[%eval {:.2}]", f64::from(score) / 100.0),
+            Eval::Mate(moves) => format!("[%eval #{moves}]"),
+        }
+    }
+}
+
+// The %clk annotation of the time left on the clock, like "[%clk 0:04:58]".
+pub fn clock_annotation(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!(
+        "[%clk {}:{:02}:{:02}]",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+// Date for the Date tag, like "2024.03.17", in UTC.
+pub fn date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    // Civil from days algorithm: <https://howardhinnant.github.io/date_algorithms.html>
+    let days = secs / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!("{year:04}.{month:02}.{day:02}")
+}
+
+// A move of the game, in SAN, with the comment that followed it if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnMove {
+    pub san: String,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<PgnMove>,
+    pub result: String,
+}
+
+impl PgnGame {
+    // A game with the seven tag roster, played today from the starting position.
+    // The result is set when the game is over.
+    pub fn new(event: &str, round: &str, white: &str, black: &str, fen: &str) -> Self {
+        let mut game = Self::default();
+        for (name, value) in [
+            ("Event", event),
+            ("Site", "?"),
+            ("Date", &date(SystemTime::now())),
+            ("Round", round),
+            ("White", white),
+            ("Black", black),
+            ("Result", "*"),
+        ] {
+            game.set_tag(name, value);
+        }
+        if fen != fen::START_POSITION {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", fen);
+        }
+        game
+    }
+
+    // Replaces the value of the tag, or adds it at the end.
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        if let Some((_, v)) = self.tags.iter_mut().find(|(n, _)| n == name) {
+            *v = value.to_string();
+        } else {
+            self.tags.push((name.to_string(), value.to_string()));
+        }
+    }
+
+    // Sets both the Result tag and the game termination marker.
+    pub fn set_result(&mut self, result: &str) {
+        self.set_tag("Result", result);
+        self.result = result.to_string();
+    }
+
+    pub fn get_tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // The FEN of the starting position, if the game doesn't start from the standard position.
+    pub fn get_fen(&self) -> Option<&str> {
+        self.get_tag("FEN")
+    }
+}
+
+impl Default for PgnGame {
+    fn default() -> Self {
+        Self {
+            tags: Vec::new(),
+            moves: Vec::new(),
+            result: "*".to_string(),
+        }
+    }
+}
+
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let line = line.trim().strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = line.split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((name.to_string(), value.replace("\\\"", "\"")))
+}
+
+// Removes the move number from tokens like "12." or "12...e5".
+fn strip_move_number(token: &str) -> &str {
+    let without_digits = token.trim_start_matches(|c: char| c.is_ascii_digit());
+    if without_digits.len() < token.len() && without_digits.starts_with('.') {
+        without_digits.trim_start_matches('.')
+    } else {
+        token
+    }
+}
+
+// Parses the movetext section of a game.
+fn parse_movetext(movetext: &str, game: &mut PgnGame) {
+    let mut chars = movetext.chars();
+    let mut token = String::new();
+    let mut tokens = Vec::new();
+
+    // Splits the movetext into tokens, extracting the comments at the same time.
+    // Comments are stored as tokens starting with '{'.
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                tokens.push(std::mem::take(&mut token));
+                tokens.push(format!("{{{}", comment.split_whitespace().join(" ")));
+            }
+            ';' => {
+                // Comment until the end of the line.
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' => {
+                // Variations can be nested.
+                let mut level = 1;
+                for c in chars.by_ref() {
+                    match c {
+                        '(' => level += 1,
+                        ')' => level -= 1,
+                        _ => {}
+                    }
+                    if level == 0 {
+                        break;
+                    }
+                }
+            }
+            c if c.is_whitespace() => tokens.push(std::mem::take(&mut token)),
+            c => token.push(c),
+        }
+    }
+    tokens.push(token);
+
+    for token in tokens.iter().filter(|t| !t.is_empty()) {
+        if let Some(comment) = token.strip_prefix('{') {
+            if let Some(last_move) = game.moves.last_mut() {
+                if !comment.is_empty() {
+                    last_move.comment = Some(comment.to_string());
+                }
+            }
+        } else if RESULTS.contains(&token.as_str()) {
+            game.result.clone_from(token);
+        } else if !token.starts_with('$') {
+            let san = strip_move_number(token);
+            if !san.is_empty() {
+                game.moves.push(PgnMove {
+                    san: san.to_string(),
+                    comment: None,
+                });
+            }
+        }
+    }
+}
+
+// Parses all the games of a PGN file.
+pub fn parse(pgn: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    let mut movetext = String::new();
+
+    for line in pgn.lines() {
+        if line.trim_start().starts_with('[') {
+            if !movetext.trim().is_empty() {
+                // A new game is starting.
+                parse_movetext(&movetext, &mut game);
+                games.push(std::mem::take(&mut game));
+                movetext.clear();
+            }
+            if let Some(tag) = parse_tag(line) {
+                game.tags.push(tag);
+            }
+        } else {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+    if !movetext.trim().is_empty() || !game.tags.is_empty() {
+        parse_movetext(&movetext, &mut game);
+        games.push(game);
+    }
+    games
+}
+
+// Creates the PGN text of a game.
+pub fn create(game: &PgnGame) -> String {
+    let mut pgn = String::new();
+    for (name, value) in &game.tags {
+        writeln!(pgn, "[{name} \"{}\"]", value.replace('"', "\\\"")).unwrap();
+    }
+    if !game.tags.is_empty() {
+        pgn.push('\n');
+    }
+
+    // Move numbers depend on the starting position.
+    let (mut side_to_move, mut move_number) =
+        game.get_fen().and_then(|f| fen::parse(f).ok()).map_or(
+            (Color::White, 1),
+            |(_, side_to_move, _, _, _, full_move_counter)| (side_to_move, full_move_counter),
+        );
+
+    let mut tokens = Vec::new();
+    let mut after_comment = false;
+    for (i, mv) in game.moves.iter().enumerate() {
+        if side_to_move == Color::White {
+            tokens.push(format!("{move_number}."));
+        } else if i == 0 || after_comment {
+            tokens.push(format!("{move_number}..."));
+        }
+        tokens.push(mv.san.clone());
+        after_comment = false;
+        if let Some(comment) = &mv.comment {
+            tokens.push(format!("{{{comment}}}"));
+            after_comment = true;
+        }
+
+        if side_to_move == Color::Black {
+            move_number += 1;
+        }
+        side_to_move = side_to_move.opposite();
+    }
+    tokens.push(game.result.clone());
+
+    // Wrap the movetext.
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_LENGTH {
+            pgn.push_str(&line);
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    pgn.push_str(&line);
+    pgn.push('\n');
+    pgn
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_PGN: &str = r#"[Event "Casual game"]
+[White "Kaik"]
+[Black "Human"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 {Developing} Nc6 (2... d6 3. d4 (3. Bc4)) 3. Bb5 $1 a6
+; Ruy Lopez
+4.Ba4 1-0
+
+[Event "Second game"]
+[FEN "4k3/8/8/8/8/8/8/R3K3 b Q - 0 12"]
+
+12... Kd7 13. O-O-O+ *
+"#;
+
+    #[test]
+    fn test_parse() {
+        let games = parse(SAMPLE_PGN);
+        assert_eq!(games.len(), 2);
+
+        let game = &games[0];
+        assert_eq!(game.get_tag("White"), Some("Kaik"));
+        assert_eq!(game.get_tag("Round"), None);
+        assert_eq!(game.get_fen(), None);
+        assert_eq!(
+            game.moves.iter().map(|m| m.san.as_str()).collect_vec(),
+            ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4"]
+        );
+        assert_eq!(game.moves[2].comment, Some("Developing".to_string()));
+        assert_eq!(game.moves[3].comment, None);
+        assert_eq!(game.result, "1-0");
+
+        let game = &games[1];
+        assert_eq!(game.get_fen(), Some("4k3/8/8/8/8/8/8/R3K3 b Q - 0 12"));
+        assert_eq!(
+            game.moves.iter().map(|m| m.san.as_str()).collect_vec(),
+            ["Kd7", "O-O-O+"]
+        );
+        assert_eq!(game.result, "*");
+    }
+
+    #[test]
+    fn test_create() {
+        let games = parse(SAMPLE_PGN);
+        assert_eq!(
+            create(&games[0]),
+            r#"[Event "Casual game"]
+[White "Kaik"]
+[Black "Human"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 {Developing} 2... Nc6 3. Bb5 a6 4. Ba4 1-0
+"#
+        );
+        assert_eq!(
+            create(&games[1]),
+            r#"[Event "Second game"]
+[FEN "4k3/8/8/8/8/8/8/R3K3 b Q - 0 12"]
+
+12... Kd7 13. O-O-O+ *
+"#
+        );
+    }
+
+    #[test]
+    fn test_new_game() {
+        let mut game = PgnGame::new("Test", "1", "Kaik", "Human", fen::START_POSITION);
+        game.set_result("0-1");
+        game.set_tag("Termination", "resignation");
+        assert_eq!(
+            game.tags
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect_vec(),
+            [
+                "Event",
+                "Site",
+                "Date",
+                "Round",
+                "White",
+                "Black",
+                "Result",
+                "Termination"
+            ]
+        );
+        assert_eq!(game.get_tag("Result"), Some("0-1"));
+        assert_eq!(game.result, "0-1");
+
+        let fen = "4k3/8/8/8/8/8/8/R3K3 b Q - 0 12";
+        let game = PgnGame::new("Test", "2", "Kaik", "Human", fen);
+        assert_eq!(game.get_tag("SetUp"), Some("1"));
+        assert_eq!(game.get_fen(), Some(fen));
+    }
+
+    #[test]
+    fn test_date() {
+        assert_eq!(date(SystemTime::UNIX_EPOCH), "1970.01.01");
+        let date_of = |secs| date(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(date_of(951_782_400), "2000.02.29");
+        assert_eq!(date_of(1_710_700_000), "2024.03.17");
+        assert_eq!(date_of(1_735_689_599), "2024.12.31");
+    }
+
+    #[test]
+    fn test_annotations() {
+        assert_eq!(Eval::Centipawns(35).annotation(), "[%eval 0.35]");
+        assert_eq!(
+            Eval::Centipawns(35).for_white(Color::Black).annotation(),
+            "[%eval -0.35]"
+        );
+        assert_eq!(Eval::Centipawns(-1234).annotation(), "[%eval -12.34]");
+        assert_eq!(
+            Eval::Mate(3).for_white(Color::Black).annotation(),
+            "[%eval #-3]"
+        );
+        assert_eq!(
+            clock_annotation(Duration::from_millis(298_700)),
+            "[%clk 0:04:58]"
+        );
+        assert_eq!(clock_annotation(Duration::from_mins(90)), "[%clk 1:30:00]");
+    }
+
+    #[test]
+    fn test_parse_create_round_trip() {
+        let games = parse(SAMPLE_PGN);
+        for game in &games {
+            assert_eq!(&parse(&create(game))[0], game);
+        }
+    }
+}
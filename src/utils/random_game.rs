@@ -0,0 +1,146 @@
+//! Random legal games, for property tests: Whatever the moves, the properties of the boards
+//! must hold, like the incremental Zobrist key being the one computed from scratch.
+//! The games start from any position and stop at mate, stalemate or after the maximum number of
+//! plies, so they are short of the draw rules.
+
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{board::Board, common::Move};
+
+// Iterator over the boards of a random game, starting with the initial one.
+pub struct RandomGame<'a, R: Rng> {
+    board: Option<Board>,
+    plies_left: usize,
+    rng: &'a mut R,
+}
+
+impl<R: Rng> Iterator for RandomGame<'_, R> {
+    type Item = Board;
+
+    fn next(&mut self) -> Option<Board> {
+        let board = self.board.take()?;
+        if self.plies_left > 0 {
+            if let Some(&mv) = board.generate_legal_moves().choose(self.rng) {
+                self.plies_left -= 1;
+                self.board = Some(board.copy_with_move(mv).expect("Legal move"));
+            }
+        }
+        Some(board)
+    }
+}
+
+// Plays a random game of at most `max_plies` moves from the board.
+pub fn random_game<R: Rng>(board: Board, max_plies: usize, rng: &mut R) -> RandomGame<'_, R> {
+    RandomGame {
+        board: Some(board),
+        plies_left: max_plies,
+        rng,
+    }
+}
+
+// The moves of a random game, with the board before each move.
+pub fn random_moves<R: Rng>(board: Board, max_plies: usize, rng: &mut R) -> Vec<(Board, Move)> {
+    let mut moves = Vec::new();
+    let mut board = board;
+    for _ in 0..max_plies {
+        let Some(&mv) = board.generate_legal_moves().choose(rng) else {
+            break;
+        };
+        moves.push((board, mv));
+        board.update_by_move(mv);
+    }
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::utils::fen;
+
+    const GAMES: usize = 20;
+    const MAX_PLIES: usize = 120;
+
+    fn starting_boards() -> Vec<Board> {
+        [
+            fen::START_POSITION,
+            fen::KIWIPETE,
+            fen::POSITION_3,
+            fen::POSITION_4,
+            fen::POSITION_5,
+        ]
+        .map(Board::from_fen)
+        .to_vec()
+    }
+
+    #[test]
+    fn test_random_game() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let boards = random_game(Board::initial_board(), 10, &mut rng).collect::<Vec<_>>();
+        assert_eq!(boards.len(), 11);
+        assert_eq!(boards[0], Board::initial_board());
+
+        // The game stops at mate.
+        let mated = Board::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1");
+        assert_eq!(random_game(mated, 10, &mut rng).count(), 1);
+
+        // Same seed, same game.
+        let game = |seed| {
+            random_moves(Board::initial_board(), 20, &mut StdRng::seed_from_u64(seed))
+                .into_iter()
+                .map(|(_, mv)| mv)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(game(7), game(7));
+        assert_ne!(game(7), game(8));
+    }
+
+    #[test]
+    fn test_zobrist_key_incremental() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for board in starting_boards() {
+            for _ in 0..GAMES {
+                for board in random_game(board, MAX_PLIES, &mut rng) {
+                    assert_eq!(board.get_zobrist_key(), Board::gen_zobrist_key(&board));
+                    assert_eq!(board.check_invariants(), Ok(()), "{}", board.as_fen());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fen_round_trip() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for board in starting_boards() {
+            for _ in 0..GAMES {
+                for board in random_game(board, MAX_PLIES, &mut rng) {
+                    assert_eq!(
+                        Board::from_fen(&board.as_fen()),
+                        board,
+                        "{}",
+                        board.as_fen()
+                    );
+                }
+            }
+        }
+    }
+
+    // The board is copied to make a move, so unmaking it is going back to the copy: Making the
+    // move on a copy or in place must give the same board.
+    #[test]
+    fn test_make_unmake() {
+        let mut rng = StdRng::seed_from_u64(4);
+        for board in starting_boards() {
+            for _ in 0..GAMES {
+                for (before, mv) in random_moves(board, MAX_PLIES, &mut rng) {
+                    let after = before.copy_with_move(mv).unwrap();
+                    let mut in_place = before;
+                    in_place.update_by_move(mv);
+                    assert_eq!(in_place, after);
+                    assert_ne!(after, before);
+                }
+            }
+        }
+    }
+}
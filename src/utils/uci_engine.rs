@@ -0,0 +1,149 @@
+//! Client side of UCI: Runs an external engine and talks to it.
+//! Used to compare Kaik with other engines.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use crate::utils::pgn::Eval;
+
+pub struct UciEngine {
+    name: String,
+    process: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl UciEngine {
+    // Starts the engine and waits until it's ready to receive commands.
+    // The command is the path of the engine followed by its arguments.
+    pub fn start(command: &[String]) -> io::Result<Self> {
+        let (path, args) = command.split_first().expect("Empty engine command");
+        let mut process = Command::new(path)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = process.stdin.take().expect("Engine stdin is piped");
+        let stdout = BufReader::new(process.stdout.take().expect("Engine stdout is piped"));
+        let mut engine = Self {
+            name: path.clone(),
+            process,
+            stdin,
+            stdout,
+        };
+
+        engine.send("uci")?;
+        for line in engine.read_until("uciok")? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.to_string();
+            }
+        }
+        engine.wait_ready()?;
+        Ok(engine)
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn send(&mut self, command: &str) -> io::Result<()> {
+        debug!("{} < {command}", self.name);
+        writeln!(self.stdin, "{command}")?;
+        self.stdin.flush()
+    }
+
+    pub fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} closed its output", self.name),
+            ));
+        }
+        let line = line.trim_end().to_string();
+        debug!("{} > {line}", self.name);
+        Ok(line)
+    }
+
+    // Reads lines until one starts with the prefix, returning all the lines read.
+    pub fn read_until(&mut self, prefix: &str) -> io::Result<Vec<String>> {
+        let mut lines = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            let found = line.starts_with(prefix);
+            lines.push(line);
+            if found {
+                return Ok(lines);
+            }
+        }
+    }
+
+    pub fn wait_ready(&mut self) -> io::Result<()> {
+        self.send("isready")?;
+        self.read_until("readyok").map(|_| ())
+    }
+
+    // Runs the non-standard "go perft" command, supported by Stockfish and many other engines.
+    // Returns the count for each move, in pure format.
+    pub fn divide(&mut self, fen: &str, depth: usize) -> io::Result<Vec<(String, usize)>> {
+        self.send(&format!("position fen {fen}"))?;
+        self.send(&format!("go perft {depth}"))?;
+        let lines = self.read_until("Nodes searched")?;
+        Ok(lines
+            .iter()
+            .filter_map(|line| {
+                let (mv, count) = line.split_once(": ")?;
+                if mv.contains(' ') {
+                    return None; // The total count line.
+                }
+                Some((mv.to_string(), count.trim().parse().ok()?))
+            })
+            .collect())
+    }
+}
+
+// The evaluation of an "info" line, from the engine's point of view, like
+// "info depth 5 score cp 23".
+pub fn parse_eval(line: &str) -> Option<Eval> {
+    let mut tokens = line.split_whitespace();
+    if tokens.next() != Some("info") {
+        return None;
+    }
+    tokens.find(|&t| t == "score")?;
+    match (tokens.next()?, tokens.next()?.parse().ok()?) {
+        ("cp", score) => Some(Eval::Centipawns(score)),
+        ("mate", moves) => Some(Eval::Mate(moves)),
+        _ => None,
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        if self.send("quit").is_err() {
+            let _ = self.process.kill();
+        }
+        let _ = self.process.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_eval() {
+        assert_eq!(
+            parse_eval("info depth 5 seldepth 7 score cp -23 nodes 1000 pv e2e4"),
+            Some(Eval::Centipawns(-23))
+        );
+        assert_eq!(
+            parse_eval("info depth 9 score mate -2 pv e8d8"),
+            Some(Eval::Mate(-2))
+        );
+        assert_eq!(parse_eval("info string score cp"), None);
+        assert_eq!(parse_eval("bestmove e2e4"), None);
+    }
+}